@@ -0,0 +1,47 @@
+//! Fetch and stream market data through the polygon.io adapter.
+//!
+//! The adapter runs against the bundled polygon stand-in, so this example
+//! needs neither network access nor an API key.
+//!
+//!     cargo run --features mock --example market_data
+
+use tiger_trade_connector::market_data::{MarketDataProvider, Polygon, Timespan};
+use tiger_trade_connector::mock;
+
+use chrono::{Duration, Utc};
+use tokio::net::TcpListener;
+
+#[tokio::main]
+pub async fn main() -> tiger_trade_connector::Result<()> {
+    // Start the stand-in on a free port
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(mock::polygon::serve(listener));
+
+    let polygon = Polygon::new(
+        "example",
+        &format!("http://{}", addr),
+        &format!("ws://{}/stocks", addr),
+    )?;
+
+    println!("quote: {:?}", polygon.last_quote("MSFT").await?);
+    println!("trade: {:?}", polygon.last_trade("MSFT").await?);
+    println!("previous close: {:?}", polygon.previous_close("MSFT").await?);
+    println!("details: {:?}", polygon.ticker_details("MSFT").await?);
+
+    let now = Utc::now();
+    let bars = polygon.bars("MSFT", Timespan::Day, now - Duration::days(7), now).await?;
+    println!("{} daily bars", bars.len());
+
+    // Stream a few updates
+    let mut subscription = polygon.subscribe("MSFT").await?;
+
+    for _ in 0..5 {
+        match subscription.recv().await {
+            Some(event) => println!("event: {:?}", event),
+            None => break,
+        }
+    }
+
+    Ok(())
+}
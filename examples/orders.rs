@@ -0,0 +1,74 @@
+//! Walk an order lifecycle through the Alpaca adapter.
+//!
+//! The adapter runs against the bundled alpaca stand-in, so this example
+//! needs neither network access nor an account.
+//!
+//!     cargo run --features mock --example orders
+
+use tiger_trade_connector::broker::{
+    Alpaca, Broker, OrderChanges, OrderFilter, OrderRequest, OrderType, Side, TimeInForce,
+};
+use tiger_trade_connector::mock;
+
+use tokio::net::TcpListener;
+use tokio::time::{self, Duration};
+
+#[tokio::main]
+pub async fn main() -> tiger_trade_connector::Result<()> {
+    // Start the stand-in on a free port
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(mock::alpaca::serve(listener));
+
+    let alpaca = Alpaca::new(
+        "example",
+        "example",
+        &format!("http://{}", addr),
+        &format!("ws://{}/stream", addr),
+    )?;
+    let mut updates = alpaca.updates();
+
+    // Give the stream time to connect
+    time::sleep(Duration::from_millis(500)).await;
+
+    // A limit order below the market waits...
+    let order = alpaca
+        .submit_order(&OrderRequest {
+            client_order_id: "example-1".to_string(),
+            symbol: "MSFT".to_string(),
+            side: Side::Buy,
+            quantity: 150.0,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Day,
+            limit_price: Some(95.0),
+            stop_price: None,
+            trail: None,
+            extended_hours: false,
+        })
+        .await?;
+    println!("submitted: {} {:?}", order.id, order.status);
+
+    // ...until its limit is raised to the market price.
+    let changes = OrderChanges {
+        client_order_id: Some("example-2".to_string()),
+        limit_price: Some(100.0),
+        ..OrderChanges::default()
+    };
+    let order = alpaca.replace_order(&order.id, &changes).await?;
+    println!("replaced by: {} {:?}", order.id, order.status);
+
+    // new, replaced, new, partial fill and fill
+    for _ in 0..5 {
+        let update = updates.recv().await?;
+        println!(
+            "update: {} {:?} {:?}",
+            update.order.id, update.order.status, update.fill
+        );
+    }
+
+    println!("account: {:?}", alpaca.account().await?);
+    println!("positions: {:?}", alpaca.positions().await?);
+    println!("open orders: {}", alpaca.orders(OrderFilter::Open).await?.len());
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! Local alpaca.markets stand-in.
+//!
+//! Simulates a trading account, see `tiger_trade_connector::mock::alpaca`.
+//! To use it, point an account's broker settings at it:
+//!
+//! ```toml
+//! [accounts.broker]
+//! kind = "alpaca"
+//! key_id = "any"
+//! secret_key = "any"
+//! base_url = "http://127.0.0.1:8766"
+//! stream_url = "ws://127.0.0.1:8766/stream"
+//! ```
+
+use tiger_trade_connector::mock;
+
+use clap::Parser;
+use tokio::net::TcpListener;
+use tracing::info;
+
+#[tokio::main]
+pub async fn main() -> tiger_trade_connector::Result<()> {
+    tracing_subscriber::fmt::try_init()?;
+
+    let cli = Cli::parse();
+    let listener = TcpListener::bind(("127.0.0.1", cli.port)).await?;
+
+    info!(port = cli.port, "serving the alpaca stand-in");
+
+    mock::alpaca::serve(listener).await
+}
+
+#[derive(Parser, Debug)]
+#[clap(name = "alpaca-stand-in", version, about = "A local alpaca.markets stand-in")]
+struct Cli {
+    #[clap(long, default_value_t = 8766)]
+    port: u16,
+}
@@ -0,0 +1,37 @@
+//! Local polygon.io stand-in.
+//!
+//! Serves canned market data, see `tiger_trade_connector::mock::polygon`. To
+//! use it, point the connector's `market_data` settings at it:
+//!
+//! ```toml
+//! [market_data]
+//! kind = "polygon"
+//! api_key = "any"
+//! rest_url = "http://127.0.0.1:8765"
+//! ws_url = "ws://127.0.0.1:8765/stocks"
+//! ```
+
+use tiger_trade_connector::mock;
+
+use clap::Parser;
+use tokio::net::TcpListener;
+use tracing::info;
+
+#[tokio::main]
+pub async fn main() -> tiger_trade_connector::Result<()> {
+    tracing_subscriber::fmt::try_init()?;
+
+    let cli = Cli::parse();
+    let listener = TcpListener::bind(("127.0.0.1", cli.port)).await?;
+
+    info!(port = cli.port, "serving the polygon stand-in");
+
+    mock::polygon::serve(listener).await
+}
+
+#[derive(Parser, Debug)]
+#[clap(name = "polygon-stand-in", version, about = "A local polygon.io stand-in")]
+struct Cli {
+    #[clap(long, default_value_t = 8765)]
+    port: u16,
+}
@@ -0,0 +1,809 @@
+//! alpaca.markets adapter.
+//!
+//! Orders and balances go through the trading REST API v2, order changes and
+//! fills arrive on the `trade_updates` stream. See
+//! <https://docs.alpaca.markets/reference>.
+
+use crate::broker::{
+    Account, Asset, Broker, Error, Fill, Order, OrderChanges, OrderFilter, OrderRequest, OrderStatus,
+    OrderType, OrderUpdate, Position, Side, TimeInForce, Trail,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::{Method, StatusCode};
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+/// Number of order updates a subscriber may fall behind before it misses
+/// some.
+const UPDATE_CAPACITY: usize = 1024;
+
+/// Longest wait between two attempts to reconnect the stream, in seconds.
+const MAX_BACKOFF: u64 = 64;
+
+/// Most orders Alpaca returns for one list request.
+const PAGE_SIZE: usize = 500;
+
+/// Seconds the clocks of the connector and Alpaca may differ by.
+const CLOCK_SKEW: i64 = 5;
+
+/// An Alpaca trading account.
+#[derive(Debug)]
+pub struct Alpaca {
+    rest: Rest,
+    updates: broadcast::Sender<OrderUpdate>,
+
+    /// Task reading the `trade_updates` stream, stopped on drop.
+    stream: JoinHandle<()>,
+}
+
+impl Alpaca {
+    /// Create an adapter for the account of `key_id` against the REST API at
+    /// `base_url` and the stream at `stream_url`.
+    ///
+    /// Must be called from within a Tokio runtime, the stream is connected
+    /// right away.
+    pub fn new(key_id: &str, secret_key: &str, base_url: &str, stream_url: &str) -> crate::Result<Alpaca> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        let rest = Rest {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            key_id: key_id.to_string(),
+            secret_key: secret_key.to_string(),
+        };
+        let updates = broadcast::channel(UPDATE_CAPACITY).0;
+
+        let stream = tokio::spawn(run_stream(stream_url.to_string(), rest.clone(), updates.clone()));
+
+        Ok(Alpaca {
+            rest,
+            updates,
+            stream,
+        })
+    }
+}
+
+/// The REST API of the account, shared with the stream to catch up on the
+/// updates it missed.
+#[derive(Clone, Debug)]
+struct Rest {
+    http: reqwest::Client,
+    base_url: String,
+    key_id: String,
+    secret_key: String,
+}
+
+impl Rest {
+    /// Send a request for `path` and decode the JSON response, if any.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<Value>,
+    ) -> Result<T, Error> {
+        let url = format!("{}{}", self.base_url, path);
+        debug!(%method, %url, ?body, "alpaca request");
+
+        let mut request = self
+            .http
+            .request(method, &url)
+            .header("APCA-API-KEY-ID", &self.key_id)
+            .header("APCA-API-SECRET-KEY", &self.secret_key)
+            .query(query);
+
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+
+        if status.is_success() {
+            // Cancellations answer with an empty body.
+            let text = response.text().await?;
+            let text = if text.is_empty() { "null" } else { &text };
+
+            return serde_json::from_str(text).map_err(|err| Error::Other(err.into()));
+        }
+
+        // Errors carry `{"code": ..., "message": ...}`.
+        let message = response
+            .json::<Value>()
+            .await
+            .ok()
+            .and_then(|body| body["message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| status.to_string());
+
+        match status {
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            StatusCode::FORBIDDEN | StatusCode::UNPROCESSABLE_ENTITY => Err(Error::Rejected(message)),
+            _ => Err(format!("alpaca request {} failed with {}: {}", url, status, message).into()),
+        }
+    }
+
+    /// Returns the orders matching `filter`, oldest first.
+    async fn orders(&self, filter: OrderFilter) -> Result<Vec<Order>, Error> {
+        let mut query = vec![
+            ("limit", PAGE_SIZE.to_string()),
+            ("direction", "asc".to_string()),
+            ("nested", "false".to_string()),
+        ];
+
+        let mut after = match filter {
+            OrderFilter::Open => {
+                query.push(("status", "open".to_string()));
+                None
+            }
+            OrderFilter::Closed { after } => {
+                query.push(("status", "closed".to_string()));
+                Some(after)
+            }
+        };
+
+        let mut orders = Vec::new();
+
+        // Pages are walked by moving `after` to the last order received.
+        loop {
+            let mut page_query = query.clone();
+            if let Some(after) = after {
+                page_query.push(("after", after.to_rfc3339()));
+            }
+
+            let page: Vec<RestOrder> = self.request(Method::GET, "/v2/orders", &page_query, None).await?;
+            let full = page.len() == PAGE_SIZE;
+
+            for order in page {
+                orders.push(order.into_order()?);
+            }
+
+            match orders.last() {
+                Some(last) if full => after = Some(last.created_at),
+                _ => return Ok(orders),
+            }
+        }
+    }
+}
+
+impl Drop for Alpaca {
+    fn drop(&mut self) {
+        self.stream.abort();
+    }
+}
+
+#[async_trait]
+impl Broker for Alpaca {
+    async fn account(&self) -> Result<Account, Error> {
+        let account: RestAccount = self.rest.request(Method::GET, "/v2/account", &[], None).await?;
+
+        Ok(Account {
+            currency: account.currency,
+            cash: account.cash,
+            equity: account.equity,
+            last_equity: account.last_equity,
+            long_market_value: account.long_market_value,
+            short_market_value: account.short_market_value,
+            initial_margin: account.initial_margin,
+            maintenance_margin: account.maintenance_margin,
+            buying_power: account.buying_power,
+            sma: account.sma,
+            // Pattern day trader rules allow 3 day trades in 5 business days
+            // to accounts under $25,000.
+            day_trades_remaining: if account.equity < 25_000.0 {
+                Some(3u32.saturating_sub(account.daytrade_count))
+            } else {
+                None
+            },
+        })
+    }
+
+    async fn positions(&self) -> Result<Vec<Position>, Error> {
+        let positions: Vec<RestPosition> = self.rest.request(Method::GET, "/v2/positions", &[], None).await?;
+
+        Ok(positions
+            .into_iter()
+            .map(|position| Position {
+                symbol: position.symbol,
+                exchange: position.exchange,
+                quantity: position.qty,
+                avg_cost: position.avg_entry_price,
+                market_price: position.current_price.unwrap_or_default(),
+                market_value: position.market_value.unwrap_or_default(),
+                unrealized_pnl: position.unrealized_pl.unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn asset(&self, symbol: &str) -> Result<Asset, Error> {
+        let path = format!("/v2/assets/{}", symbol);
+        let asset: RestAsset = self.rest.request(Method::GET, &path, &[], None).await?;
+
+        Ok(Asset {
+            symbol: asset.symbol,
+            tradable: asset.tradable,
+            shortable: asset.shortable,
+            easy_to_borrow: asset.easy_to_borrow,
+        })
+    }
+
+    async fn submit_order(&self, request: &OrderRequest) -> Result<Order, Error> {
+        let mut body = Map::new();
+        body.insert("client_order_id".into(), json!(request.client_order_id));
+        body.insert("symbol".into(), json!(request.symbol));
+        body.insert("qty".into(), json!(request.quantity.to_string()));
+        body.insert("side".into(), json!(side_name(request.side)));
+        body.insert("type".into(), json!(order_type_name(request.order_type)));
+        body.insert(
+            "time_in_force".into(),
+            json!(time_in_force_name(request.time_in_force)),
+        );
+        body.insert("extended_hours".into(), json!(request.extended_hours));
+        insert_prices(&mut body, request.limit_price, request.stop_price, request.trail);
+
+        let order: RestOrder = self.rest
+            .request(Method::POST, "/v2/orders", &[], Some(Value::Object(body)))
+            .await?;
+
+        order.into_order()
+    }
+
+    async fn replace_order(&self, id: &str, changes: &OrderChanges) -> Result<Order, Error> {
+        let mut body = Map::new();
+
+        if let Some(client_order_id) = &changes.client_order_id {
+            body.insert("client_order_id".into(), json!(client_order_id));
+        }
+        if let Some(quantity) = changes.quantity {
+            body.insert("qty".into(), json!(quantity.to_string()));
+        }
+        if let Some(time_in_force) = changes.time_in_force {
+            body.insert("time_in_force".into(), json!(time_in_force_name(time_in_force)));
+        }
+        insert_prices(&mut body, changes.limit_price, changes.stop_price, changes.trail);
+
+        // A replace takes a single trail amount, whatever its kind.
+        if let Some(trail) = body
+            .remove("trail_price")
+            .or_else(|| body.remove("trail_percent"))
+        {
+            body.insert("trail".into(), trail);
+        }
+
+        let order: RestOrder = self.rest
+            .request(
+                Method::PATCH,
+                &format!("/v2/orders/{}", id),
+                &[],
+                Some(Value::Object(body)),
+            )
+            .await?;
+
+        order.into_order()
+    }
+
+    async fn cancel_order(&self, id: &str) -> Result<(), Error> {
+        let _: Value = self.rest
+            .request(Method::DELETE, &format!("/v2/orders/{}", id), &[], None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn orders(&self, filter: OrderFilter) -> Result<Vec<Order>, Error> {
+        self.rest.orders(filter).await
+    }
+
+    fn updates(&self) -> broadcast::Receiver<OrderUpdate> {
+        self.updates.subscribe()
+    }
+}
+
+/// Add the price fields of an order to a request body.
+fn insert_prices(body: &mut Map<String, Value>, limit: Option<f64>, stop: Option<f64>, trail: Option<Trail>) {
+    if let Some(limit) = limit {
+        body.insert("limit_price".into(), json!(limit.to_string()));
+    }
+    if let Some(stop) = stop {
+        body.insert("stop_price".into(), json!(stop.to_string()));
+    }
+    match trail {
+        Some(Trail::Price(price)) => body.insert("trail_price".into(), json!(price.to_string())),
+        Some(Trail::Percent(percent)) => body.insert("trail_percent".into(), json!(percent.to_string())),
+        None => None,
+    };
+}
+
+/// Keep the `trade_updates` stream connected, reconnecting with an
+/// exponential backoff when it drops.
+///
+/// Every time the stream listens, the orders are listed to catch up on the
+/// updates sent while it was not connected.
+async fn run_stream(url: String, rest: Rest, updates: broadcast::Sender<OrderUpdate>) {
+    let mut seen = Seen::new(Utc::now());
+    let mut backoff = 1;
+
+    loop {
+        if let Err(err) = stream_session(&url, &rest, &updates, &mut seen, &mut backoff).await {
+            warn!(cause = %err, backoff, "alpaca stream disconnected");
+        }
+
+        time::sleep(Duration::from_secs(backoff)).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Connect, authenticate, listen to `trade_updates` and forward them until the
+/// connection fails.
+async fn stream_session(
+    url: &str,
+    rest: &Rest,
+    updates: &broadcast::Sender<OrderUpdate>,
+    seen: &mut Seen,
+    backoff: &mut u64,
+) -> crate::Result<()> {
+    let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+    let (mut sink, mut source) = socket.split();
+
+    let auth = json!({ "action": "auth", "key": rest.key_id, "secret": rest.secret_key });
+    sink.send(Message::text(auth.to_string())).await?;
+
+    while let Some(message) = source.next().await {
+        // Alpaca sends binary frames on the paper endpoint and text frames on
+        // the live one.
+        let message: StreamMessage = match message? {
+            Message::Text(text) => serde_json::from_str(&text)?,
+            Message::Binary(data) => serde_json::from_slice(&data)?,
+            _ => continue,
+        };
+
+        match message.stream.as_str() {
+            "authorization" if message.data["status"] == "authorized" => {
+                let listen = json!({ "action": "listen", "data": { "streams": ["trade_updates"] } });
+                sink.send(Message::text(listen.to_string())).await?;
+            }
+            "authorization" => return Err(format!("alpaca authentication failed: {}", message.data).into()),
+            "listening" => {
+                info!(%url, "alpaca stream listening");
+
+                // The updates that arrive meanwhile wait in the socket.
+                catch_up(rest, updates, seen).await?;
+                *backoff = 1;
+            }
+            "trade_updates" => {
+                let update: TradeUpdate = serde_json::from_value(message.data)?;
+                debug!(event = %update.event, order = %update.order.id, "alpaca trade update");
+
+                // Fails only when nobody is subscribed.
+                let _ = updates.send(seen.streamed(update.into_update()?));
+            }
+            _ => {}
+        }
+    }
+
+    Err("connection closed by alpaca".into())
+}
+
+/// List the orders that may have changed while the stream was not listening
+/// and forward their changes, with what they filled meanwhile as one fill.
+async fn catch_up(rest: &Rest, updates: &broadcast::Sender<OrderUpdate>, seen: &mut Seen) -> Result<(), Error> {
+    let checked = Utc::now();
+
+    let mut orders = rest.orders(OrderFilter::Open).await?;
+    orders.extend(rest.orders(OrderFilter::Closed { after: seen.closed_after() }).await?);
+
+    let mut caught_up = 0;
+    for update in orders.into_iter().filter_map(|order| seen.listed(order)) {
+        caught_up += 1;
+        let _ = updates.send(update);
+    }
+
+    seen.checked(checked);
+    debug!(caught_up, "alpaca orders listed");
+
+    Ok(())
+}
+
+/// What the stream forwarded of every order it heard of, to tell what it
+/// missed while it was not listening.
+#[derive(Debug)]
+struct Seen {
+    /// When the adapter was created. What the orders created before filled
+    /// until they are first heard of is not forwarded, the positions include
+    /// it.
+    started: DateTime<Utc>,
+
+    /// When the orders were last listed. The orders created before and still
+    /// open then are in `orders`.
+    checked: DateTime<Utc>,
+
+    orders: HashMap<String, Progress>,
+}
+
+/// How far an order got, as forwarded.
+#[derive(Debug)]
+struct Progress {
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    status: OrderStatus,
+
+    /// Quantity filled, and its value.
+    filled: f64,
+    value: f64,
+}
+
+impl Seen {
+    fn new(started: DateTime<Utc>) -> Seen {
+        Seen {
+            started,
+            checked: started,
+            orders: HashMap::new(),
+        }
+    }
+
+    /// Take in `update`, which arrived on the stream.
+    ///
+    /// Returns it without its fill if a listing forwarded the fill already.
+    fn streamed(&mut self, mut update: OrderUpdate) -> OrderUpdate {
+        let order = &update.order;
+        let progress = self.orders.get(&order.id);
+
+        if progress.is_some_and(|progress| order.filled_quantity <= progress.filled) {
+            update.fill = None;
+        }
+
+        if progress.is_none_or(|progress| order.updated_at >= progress.updated_at) {
+            self.orders.insert(order.id.clone(), Progress::new(order));
+        }
+        update
+    }
+
+    /// Take in `order`, which a listing returned.
+    ///
+    /// Returns the update to forward if the order changed since it was last
+    /// forwarded, with what it filled meanwhile as one fill.
+    fn listed(&mut self, order: Order) -> Option<OrderUpdate> {
+        let Some(progress) = self.orders.get(&order.id) else {
+            let known = order.created_at < self.started;
+            self.orders.insert(order.id.clone(), Progress::new(&order));
+
+            // What the orders of before filled is in the positions already.
+            return (!known).then(|| {
+                let fill = fill(&order, 0.0, 0.0);
+                OrderUpdate { order, fill }
+            });
+        };
+
+        if order.updated_at <= progress.updated_at || order.filled_quantity < progress.filled {
+            return None;
+        }
+
+        let fill = fill(&order, progress.filled, progress.value);
+        self.orders.insert(order.id.clone(), Progress::new(&order));
+
+        Some(OrderUpdate { order, fill })
+    }
+
+    /// Returns the time to list the closed orders from: the orders created
+    /// before were open at the last listing, or closed already.
+    fn closed_after(&self) -> DateTime<Utc> {
+        let oldest_open = self
+            .orders
+            .values()
+            .filter(|progress| !progress.status.is_terminal())
+            .map(|progress| progress.created_at)
+            .min();
+
+        let after = oldest_open.map_or(self.checked, |created_at| created_at.min(self.checked));
+        after - chrono::Duration::seconds(CLOCK_SKEW)
+    }
+
+    /// Record that the orders were listed at `checked`, forgetting the closed
+    /// orders the next listing leaves out.
+    fn checked(&mut self, checked: DateTime<Utc>) {
+        self.checked = checked;
+
+        let after = self.closed_after();
+        self.orders
+            .retain(|_, progress| !progress.status.is_terminal() || progress.created_at > after);
+    }
+}
+
+impl Progress {
+    fn new(order: &Order) -> Progress {
+        Progress {
+            created_at: order.created_at,
+            updated_at: order.updated_at,
+            status: order.status,
+            filled: order.filled_quantity,
+            value: order.filled_quantity * order.filled_avg_price.unwrap_or_default(),
+        }
+    }
+}
+
+/// Returns what `order` filled since it had filled `filled` worth `value`,
+/// as one fill, or `None` if it filled nothing more.
+///
+/// The position after the fill is not known.
+fn fill(order: &Order, filled: f64, value: f64) -> Option<Fill> {
+    let quantity = order.filled_quantity - filled;
+
+    if quantity <= 0.0 {
+        return None;
+    }
+
+    let total = order.filled_quantity * order.filled_avg_price.unwrap_or_default();
+
+    Some(Fill {
+        // Stands for the executions it sums up, the same on every listing.
+        execution_id: format!("{}-{}", order.id, order.filled_quantity),
+        price: (total - value) / quantity,
+        quantity,
+        position_quantity: 0.0,
+        timestamp: order.updated_at,
+    })
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+fn order_type_name(order_type: OrderType) -> &'static str {
+    match order_type {
+        OrderType::Market => "market",
+        OrderType::Limit => "limit",
+        OrderType::Stop => "stop",
+        OrderType::StopLimit => "stop_limit",
+        OrderType::TrailingStop => "trailing_stop",
+    }
+}
+
+fn time_in_force_name(time_in_force: TimeInForce) -> &'static str {
+    match time_in_force {
+        TimeInForce::Day => "day",
+        TimeInForce::GoodTillCancel => "gtc",
+        TimeInForce::AtTheOpening => "opg",
+        TimeInForce::AtTheClose => "cls",
+        TimeInForce::ImmediateOrCancel => "ioc",
+        TimeInForce::FillOrKill => "fok",
+    }
+}
+
+/// A message of the stream.
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    stream: String,
+    #[serde(default)]
+    data: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct TradeUpdate {
+    event: String,
+    order: RestOrder,
+    execution_id: Option<String>,
+    #[serde(default, deserialize_with = "opt_number")]
+    price: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    qty: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    position_qty: Option<f64>,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl TradeUpdate {
+    fn into_update(self) -> crate::Result<OrderUpdate> {
+        let fill = match (self.event.as_str(), self.execution_id, self.price, self.qty) {
+            ("fill" | "partial_fill", Some(execution_id), Some(price), Some(quantity)) => Some(Fill {
+                execution_id,
+                price,
+                quantity,
+                position_quantity: self.position_qty.unwrap_or_default(),
+                timestamp: self.timestamp.unwrap_or_else(Utc::now),
+            }),
+            _ => None,
+        };
+
+        Ok(OrderUpdate {
+            order: self.order.into_order()?,
+            fill,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RestAccount {
+    currency: String,
+    #[serde(deserialize_with = "number")]
+    cash: f64,
+    #[serde(deserialize_with = "number")]
+    equity: f64,
+    #[serde(deserialize_with = "number")]
+    last_equity: f64,
+    #[serde(deserialize_with = "number")]
+    long_market_value: f64,
+    #[serde(deserialize_with = "number")]
+    short_market_value: f64,
+    #[serde(deserialize_with = "number")]
+    initial_margin: f64,
+    #[serde(deserialize_with = "number")]
+    maintenance_margin: f64,
+    #[serde(deserialize_with = "number")]
+    buying_power: f64,
+    #[serde(deserialize_with = "number")]
+    sma: f64,
+    #[serde(default)]
+    daytrade_count: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestPosition {
+    symbol: String,
+    exchange: String,
+    #[serde(deserialize_with = "number")]
+    qty: f64,
+    #[serde(deserialize_with = "number")]
+    avg_entry_price: f64,
+    #[serde(default, deserialize_with = "opt_number")]
+    current_price: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    market_value: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    unrealized_pl: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestAsset {
+    symbol: String,
+    tradable: bool,
+    #[serde(default)]
+    shortable: bool,
+    #[serde(default)]
+    easy_to_borrow: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestOrder {
+    id: String,
+    client_order_id: String,
+    symbol: String,
+    side: String,
+    #[serde(rename = "type")]
+    order_type: String,
+    time_in_force: String,
+    #[serde(default, deserialize_with = "opt_number")]
+    qty: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    filled_qty: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    filled_avg_price: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    limit_price: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    stop_price: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    trail_price: Option<f64>,
+    #[serde(default, deserialize_with = "opt_number")]
+    trail_percent: Option<f64>,
+    #[serde(default)]
+    extended_hours: bool,
+    status: String,
+    replaces: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl RestOrder {
+    fn into_order(self) -> Result<Order, Error> {
+        let invalid =
+            |field: &str, value: &str| Error::from(format!("alpaca order has unknown {} `{}`", field, value));
+
+        let side = match self.side.as_str() {
+            "buy" => Side::Buy,
+            "sell" => Side::Sell,
+            other => return Err(invalid("side", other)),
+        };
+
+        let order_type = match self.order_type.as_str() {
+            "market" => OrderType::Market,
+            "limit" => OrderType::Limit,
+            "stop" => OrderType::Stop,
+            "stop_limit" => OrderType::StopLimit,
+            "trailing_stop" => OrderType::TrailingStop,
+            other => return Err(invalid("type", other)),
+        };
+
+        let time_in_force = match self.time_in_force.as_str() {
+            "day" => TimeInForce::Day,
+            "gtc" => TimeInForce::GoodTillCancel,
+            "opg" => TimeInForce::AtTheOpening,
+            "cls" => TimeInForce::AtTheClose,
+            "ioc" => TimeInForce::ImmediateOrCancel,
+            "fok" => TimeInForce::FillOrKill,
+            other => return Err(invalid("time in force", other)),
+        };
+
+        let status = match self.status.as_str() {
+            "pending_new" | "accepted_for_bidding" => OrderStatus::PendingNew,
+            "new" | "accepted" | "calculated" => OrderStatus::New,
+            "partially_filled" => OrderStatus::PartiallyFilled,
+            "filled" => OrderStatus::Filled,
+            "done_for_day" | "expired" => OrderStatus::Expired,
+            "canceled" => OrderStatus::Canceled,
+            "replaced" => OrderStatus::Replaced,
+            "pending_cancel" => OrderStatus::PendingCancel,
+            "pending_replace" => OrderStatus::PendingReplace,
+            "rejected" => OrderStatus::Rejected,
+            "stopped" | "suspended" | "held" => OrderStatus::Held,
+            other => return Err(invalid("status", other)),
+        };
+
+        let trail = match (self.trail_price, self.trail_percent) {
+            (Some(price), _) => Some(Trail::Price(price)),
+            (None, Some(percent)) => Some(Trail::Percent(percent)),
+            (None, None) => None,
+        };
+
+        Ok(Order {
+            id: self.id,
+            client_order_id: self.client_order_id,
+            symbol: self.symbol,
+            side,
+            quantity: self.qty.unwrap_or_default(),
+            order_type,
+            time_in_force,
+            limit_price: self.limit_price,
+            stop_price: self.stop_price,
+            trail,
+            extended_hours: self.extended_hours,
+            status,
+            filled_quantity: self.filled_qty.unwrap_or_default(),
+            filled_avg_price: self.filled_avg_price,
+            replaces: self.replaces,
+            created_at: self.created_at,
+            updated_at: self.updated_at.unwrap_or(self.created_at),
+        })
+    }
+}
+
+/// Alpaca sends amounts as strings, accept them as numbers too.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Text(String),
+    Value(f64),
+}
+
+impl Number {
+    fn value<E: de::Error>(self) -> Result<f64, E> {
+        match self {
+            Number::Text(text) => text
+                .parse()
+                .map_err(|_| E::custom(format!("invalid number `{}`", text))),
+            Number::Value(value) => Ok(value),
+        }
+    }
+}
+
+fn number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Number::deserialize(deserializer)?.value()
+}
+
+fn opt_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    Option::<Number>::deserialize(deserializer)?
+        .map(Number::value)
+        .transpose()
+}
@@ -0,0 +1,363 @@
+//! Brokers that hold the accounts routed through the connector.
+//!
+//! Every configured account is backed by one `Broker`. Commands only talk to
+//! the trait, the adapters translate to the broker's own API.
+
+mod alpaca;
+pub use alpaca::Alpaca;
+
+mod paper;
+pub use paper::PaperBroker;
+
+use crate::config::{AccountConfig, BrokerConfig};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Balances and margin of an account, in its base currency.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Account {
+    /// Base currency of all the amounts below.
+    pub currency: String,
+
+    /// Cash balance. Negative when trading on margin.
+    pub cash: f64,
+
+    /// Cash plus the market value of all positions.
+    pub equity: f64,
+
+    /// `equity` at the previous close.
+    pub last_equity: f64,
+
+    /// Market value of the long positions.
+    pub long_market_value: f64,
+
+    /// Market value of the short positions, a negative amount.
+    pub short_market_value: f64,
+
+    /// Margin required to open the current positions.
+    pub initial_margin: f64,
+
+    /// Margin required to keep the current positions.
+    pub maintenance_margin: f64,
+
+    /// Amount available to open new positions.
+    pub buying_power: f64,
+
+    /// Special Memorandum Account balance of a Reg T margin account.
+    pub sma: f64,
+
+    /// Day trades left before the account is flagged as a pattern day
+    /// trader, or `None` if it is not limited.
+    pub day_trades_remaining: Option<u32>,
+}
+
+/// A position held in an account.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Position {
+    pub symbol: String,
+
+    /// Exchange the instrument is listed on, e.g. `NASDAQ`.
+    pub exchange: String,
+
+    /// Number of shares. Negative for a short position.
+    pub quantity: f64,
+
+    /// Average price paid per share.
+    pub avg_cost: f64,
+
+    /// Last price of the instrument.
+    pub market_price: f64,
+
+    /// `quantity` valued at `market_price`.
+    pub market_value: f64,
+
+    /// Profit or loss of the open quantity.
+    pub unrealized_pnl: f64,
+}
+
+/// How an instrument can be traded at the broker.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Asset {
+    pub symbol: String,
+    pub tradable: bool,
+
+    /// Whether the broker allows short sales of the instrument.
+    pub shortable: bool,
+
+    /// Whether shares to borrow are readily available. Short sales of hard
+    /// to borrow instruments may be refused.
+    pub easy_to_borrow: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OrderType {
+    Market,
+    Limit,
+    Stop,
+    StopLimit,
+    TrailingStop,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimeInForce {
+    Day,
+    GoodTillCancel,
+    AtTheOpening,
+    AtTheClose,
+    ImmediateOrCancel,
+    FillOrKill,
+}
+
+/// Trailing amount of a trailing stop order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trail {
+    /// Distance to the high water mark, in currency.
+    Price(f64),
+
+    /// Distance to the high water mark, in percent.
+    Percent(f64),
+}
+
+/// A new order to submit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderRequest {
+    /// Identifier chosen by the connector, unique per account. The broker
+    /// returns it with every update of the order.
+    pub client_order_id: String,
+
+    pub symbol: String,
+    pub side: Side,
+    pub quantity: f64,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub limit_price: Option<f64>,
+    pub stop_price: Option<f64>,
+    pub trail: Option<Trail>,
+
+    /// Allow the order to fill outside regular trading hours.
+    pub extended_hours: bool,
+}
+
+/// Changes to a working order. `None` leaves the value as it is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrderChanges {
+    /// Identifier of the order that replaces the changed one.
+    pub client_order_id: Option<String>,
+
+    /// Quantity of the replacement, not counting what the changed order
+    /// filled.
+    pub quantity: Option<f64>,
+    pub time_in_force: Option<TimeInForce>,
+    pub limit_price: Option<f64>,
+    pub stop_price: Option<f64>,
+    pub trail: Option<Trail>,
+}
+
+/// Where an order is in its lifecycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OrderStatus {
+    /// Received by the broker, not yet accepted by the venue.
+    PendingNew,
+
+    /// Working at the venue.
+    New,
+    PartiallyFilled,
+    Filled,
+    PendingCancel,
+    PendingReplace,
+    Canceled,
+
+    /// Replaced by another order as the result of a change.
+    Replaced,
+    Expired,
+    Rejected,
+
+    /// Accepted, but held until a trigger condition or the next session.
+    Held,
+}
+
+impl OrderStatus {
+    /// Returns `true` if the order can no longer change.
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            OrderStatus::Filled
+                | OrderStatus::Canceled
+                | OrderStatus::Replaced
+                | OrderStatus::Expired
+                | OrderStatus::Rejected
+        )
+    }
+}
+
+/// An order as known by the broker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Order {
+    /// Identifier assigned by the broker.
+    pub id: String,
+    pub client_order_id: String,
+    pub symbol: String,
+    pub side: Side,
+    pub quantity: f64,
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    pub limit_price: Option<f64>,
+    pub stop_price: Option<f64>,
+    pub trail: Option<Trail>,
+    pub extended_hours: bool,
+    pub status: OrderStatus,
+    pub filled_quantity: f64,
+
+    /// Average price of the fills so far.
+    pub filled_avg_price: Option<f64>,
+
+    /// Broker id of the order this one replaced.
+    pub replaces: Option<String>,
+
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An execution of part or all of an order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    /// Identifier assigned by the broker, unique per account.
+    pub execution_id: String,
+    pub price: f64,
+    pub quantity: f64,
+
+    /// Size of the position in the symbol after the fill.
+    pub position_quantity: f64,
+
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A change of an order, pushed by the broker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderUpdate {
+    /// The order after the change.
+    pub order: Order,
+
+    /// The execution that caused the change, if any.
+    pub fill: Option<Fill>,
+}
+
+/// Which orders `Broker::orders` lists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OrderFilter {
+    /// Orders that can still be filled.
+    Open,
+
+    /// Orders that reached a terminal state, submitted since `after`.
+    Closed { after: DateTime<Utc> },
+}
+
+/// Error returned by broker operations.
+#[derive(Debug)]
+pub enum Error {
+    /// The order or asset does not exist.
+    NotFound,
+
+    /// The broker refused the operation, e.g. because the order is already
+    /// filled or the account lacks buying power. Holds the broker's reason.
+    Rejected(String),
+
+    /// The broker cannot carry out the operation at all, e.g. replace this
+    /// kind of order.
+    NotSupported,
+
+    /// Any other failure, such as a network error.
+    Other(crate::Error),
+}
+
+/// Operations every broker adapter provides.
+#[async_trait]
+pub trait Broker: fmt::Debug + Send + Sync {
+    /// Returns the current balances of the account.
+    async fn account(&self) -> Result<Account, Error>;
+
+    /// Returns the open positions of the account.
+    async fn positions(&self) -> Result<Vec<Position>, Error>;
+
+    /// Returns how `symbol` can be traded, `Error::NotFound` if the broker
+    /// does not know it.
+    async fn asset(&self, symbol: &str) -> Result<Asset, Error>;
+
+    /// Submit a new order.
+    async fn submit_order(&self, order: &OrderRequest) -> Result<Order, Error>;
+
+    /// Change the working order `id`. Returns the order that replaces it,
+    /// which has a new id and starts unfilled: the fills so far stay with
+    /// the replaced order. `Error::NotSupported` if the order cannot be
+    /// replaced, only cancelled.
+    async fn replace_order(&self, id: &str, changes: &OrderChanges) -> Result<Order, Error>;
+
+    /// Request the cancellation of order `id`. The outcome is reported
+    /// through `updates`.
+    async fn cancel_order(&self, id: &str) -> Result<(), Error>;
+
+    /// Returns the orders matching `filter`, oldest first.
+    async fn orders(&self, filter: OrderFilter) -> Result<Vec<Order>, Error>;
+
+    /// Subscribe to the changes of the account's orders, fills included.
+    fn updates(&self) -> broadcast::Receiver<OrderUpdate>;
+}
+
+/// Create the broker adapter for `account`.
+///
+/// Must be called from within a Tokio runtime, adapters that stream updates
+/// start doing so right away.
+pub fn connect(account: &AccountConfig) -> crate::Result<Arc<dyn Broker>> {
+    let broker: Arc<dyn Broker> = match &account.broker {
+        BrokerConfig::Paper { cash } => Arc::new(PaperBroker::new(&account.currency, *cash)),
+        BrokerConfig::Alpaca {
+            key_id,
+            secret_key,
+            base_url,
+            stream_url,
+        } => Arc::new(Alpaca::new(key_id, secret_key, base_url, stream_url)?),
+    };
+
+    Ok(broker)
+}
+
+impl From<String> for Error {
+    fn from(src: String) -> Error {
+        Error::Other(src.into())
+    }
+}
+
+impl From<&str> for Error {
+    fn from(src: &str) -> Error {
+        src.to_string().into()
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(src: reqwest::Error) -> Error {
+        Error::Other(src.into())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotFound => "not found".fmt(fmt),
+            Error::Rejected(reason) => reason.fmt(fmt),
+            Error::NotSupported => "not supported".fmt(fmt),
+            Error::Other(err) => err.fmt(fmt),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::broker::{
+    Account, Asset, Broker, Error, Order, OrderChanges, OrderFilter, OrderRequest, OrderStatus, OrderUpdate,
+    Position,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// Share of the market value of a position required as initial margin, as
+/// under Reg T.
+const INITIAL_MARGIN: f64 = 0.5;
+
+/// Share of the market value of a position required as maintenance margin.
+const MAINTENANCE_MARGIN: f64 = 0.25;
+
+/// Number of order updates a subscriber may fall behind before it misses
+/// some.
+const UPDATE_CAPACITY: usize = 1024;
+
+/// A simulated account kept in memory.
+///
+/// Useful to try the connector without a broker. Orders are accepted and
+/// can be changed and cancelled, but never fill, as the account has no
+/// prices to fill them at. Everything is lost when the connector stops.
+#[derive(Debug)]
+pub struct PaperBroker {
+    currency: String,
+    state: Mutex<State>,
+    updates: broadcast::Sender<OrderUpdate>,
+}
+
+#[derive(Debug)]
+struct State {
+    cash: f64,
+
+    /// Open positions by symbol.
+    positions: HashMap<String, Position>,
+
+    /// Every order placed, by id.
+    orders: HashMap<String, Order>,
+
+    /// Used to assign order ids.
+    next_order_id: u64,
+}
+
+impl PaperBroker {
+    /// Create an account with no positions and `cash` in `currency`.
+    pub fn new(currency: &str, cash: f64) -> PaperBroker {
+        PaperBroker {
+            currency: currency.to_string(),
+            state: Mutex::new(State {
+                cash,
+                positions: HashMap::new(),
+                orders: HashMap::new(),
+                next_order_id: 1,
+            }),
+            updates: broadcast::channel(UPDATE_CAPACITY).0,
+        }
+    }
+
+    /// Store `order` and tell the subscribers about it.
+    fn publish(&self, state: &mut State, order: Order) {
+        state.orders.insert(order.id.clone(), order.clone());
+
+        // Fails only when nobody is subscribed.
+        let _ = self.updates.send(OrderUpdate { order, fill: None });
+    }
+}
+
+impl State {
+    fn order_id(&mut self) -> String {
+        let id = format!("paper-{}", self.next_order_id);
+        self.next_order_id += 1;
+        id
+    }
+
+    /// Returns the order `id` if it can still be changed.
+    fn working_order(&self, id: &str) -> Result<&Order, Error> {
+        let order = self.orders.get(id).ok_or(Error::NotFound)?;
+
+        if order.status.is_terminal() {
+            return Err(Error::Rejected(format!("order is {:?}", order.status)));
+        }
+
+        Ok(order)
+    }
+}
+
+#[async_trait]
+impl Broker for PaperBroker {
+    async fn account(&self) -> Result<Account, Error> {
+        let state = self.state.lock().unwrap();
+
+        let (mut long_market_value, mut short_market_value) = (0.0, 0.0);
+
+        for position in state.positions.values() {
+            if position.market_value >= 0.0 {
+                long_market_value += position.market_value;
+            } else {
+                short_market_value += position.market_value;
+            }
+        }
+
+        let equity = state.cash + long_market_value + short_market_value;
+        let gross = long_market_value - short_market_value;
+        let initial_margin = gross * INITIAL_MARGIN;
+
+        Ok(Account {
+            currency: self.currency.clone(),
+            cash: state.cash,
+            equity,
+            last_equity: equity,
+            long_market_value,
+            short_market_value,
+            initial_margin,
+            maintenance_margin: gross * MAINTENANCE_MARGIN,
+            buying_power: ((equity - initial_margin) / INITIAL_MARGIN).max(0.0),
+            sma: (equity - initial_margin).max(0.0),
+            day_trades_remaining: None,
+        })
+    }
+
+    async fn positions(&self) -> Result<Vec<Position>, Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.positions.values().cloned().collect())
+    }
+
+    async fn asset(&self, symbol: &str) -> Result<Asset, Error> {
+        // The account has no market to borrow from, so anything goes.
+        Ok(Asset {
+            symbol: symbol.to_uppercase(),
+            tradable: true,
+            shortable: true,
+            easy_to_borrow: true,
+        })
+    }
+
+    async fn submit_order(&self, request: &OrderRequest) -> Result<Order, Error> {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now();
+
+        let order = Order {
+            id: state.order_id(),
+            client_order_id: request.client_order_id.clone(),
+            symbol: request.symbol.clone(),
+            side: request.side,
+            quantity: request.quantity,
+            order_type: request.order_type,
+            time_in_force: request.time_in_force,
+            limit_price: request.limit_price,
+            stop_price: request.stop_price,
+            trail: request.trail,
+            extended_hours: request.extended_hours,
+            status: OrderStatus::New,
+            filled_quantity: 0.0,
+            filled_avg_price: None,
+            replaces: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.publish(&mut state, order.clone());
+        Ok(order)
+    }
+
+    async fn replace_order(&self, id: &str, changes: &OrderChanges) -> Result<Order, Error> {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now();
+
+        let mut replaced = state.working_order(id)?.clone();
+        let mut order = replaced.clone();
+
+        replaced.status = OrderStatus::Replaced;
+        replaced.updated_at = now;
+
+        order.id = state.order_id();
+        order.replaces = Some(id.to_string());
+        order.created_at = now;
+        order.updated_at = now;
+
+        if let Some(client_order_id) = &changes.client_order_id {
+            order.client_order_id = client_order_id.clone();
+        }
+
+        order.quantity = changes.quantity.unwrap_or(order.quantity);
+        order.time_in_force = changes.time_in_force.unwrap_or(order.time_in_force);
+        order.limit_price = changes.limit_price.or(order.limit_price);
+        order.stop_price = changes.stop_price.or(order.stop_price);
+        order.trail = changes.trail.or(order.trail);
+
+        self.publish(&mut state, replaced);
+        self.publish(&mut state, order.clone());
+        Ok(order)
+    }
+
+    async fn cancel_order(&self, id: &str) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+
+        let mut order = state.working_order(id)?.clone();
+        order.status = OrderStatus::Canceled;
+        order.updated_at = Utc::now();
+
+        self.publish(&mut state, order);
+        Ok(())
+    }
+
+    async fn orders(&self, filter: OrderFilter) -> Result<Vec<Order>, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut orders: Vec<_> = state
+            .orders
+            .values()
+            .filter(|order| match filter {
+                OrderFilter::Open => !order.status.is_terminal(),
+                OrderFilter::Closed { after } => order.status.is_terminal() && order.created_at > after,
+            })
+            .cloned()
+            .collect();
+
+        orders.sort_by_key(|order| order.created_at);
+        Ok(orders)
+    }
+
+    fn updates(&self) -> broadcast::Receiver<OrderUpdate> {
+        self.updates.subscribe()
+    }
+}
@@ -1,84 +1,76 @@
-use crate::{Connection, Db, Frame, Parse, API_VERSION};
+use crate::messages::errors;
+use crate::{Connection, Frame, API_VERSION, MIN_API_VERSION};
 
-use bytes::Bytes;
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
 
 use chrono::prelude::*;
 
-/// Get the value of key.
+/// The initial handshake.
 ///
-/// If the key does not exist the special value nil is returned. An error is
-/// returned if the value stored at key is not a string, because GET only
-/// handles string values.
+/// The client sends the `API\0` prelude followed by the range of versions it
+/// supports and, optionally, connection options. The connector answers with
+/// the highest version both sides support and the connection time.
 #[derive(Debug)]
 pub struct Api {
-    /// Name of the key to get
+    /// The client's supported version range and options, e.g.
+    /// `v100..176 +PACEAPI`
     key: String,
 }
 
 impl Api {
-    /// Create a new `Get` command which fetches `key`.
+    /// Create a new `Api` command for the version range `key`.
     pub fn new(key: impl ToString) -> Api {
         Api {
             key: key.to_string(),
         }
     }
 
-    /// Get the key
+    /// Get the version range
     pub fn key(&self) -> &str {
         &self.key
     }
 
-    /// Parse a `Get` instance from a received frame.
+    /// Apply the `Api` command.
     ///
-    /// The `Parse` argument provides a cursor-like API to read fields from the
-    /// `Frame`. At this point, the entire frame has already been received from
-    /// the socket.
-    ///
-    /// The `GET` string has already been consumed.
-    ///
-    /// # Returns
-    ///
-    /// Returns the `Get` value on success. If the frame is malformed, `Err` is
-    /// returned.
-    ///
-    /// # Format
-    ///
-    /// Expects an array frame containing two entries.
-    ///
-    /// ```text
-    /// GET key
-    /// ```
-    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Api> {
-        // The `GET` string has already been consumed. The next value is the
-        // name of the key to get. If the next value is not a string or the
-        // input is fully consumed, then an error is returned.
-        let key = parse.next_string()?;
-
-        Ok(Api { key })
-    }
-
-    /// Apply the `Get` command to the specified `Db` instance.
-    ///
-    /// The response is written to `dst`. This is called by the server in order
-    /// to execute a received command.
+    /// On success the negotiated version and connection options are stored on
+    /// `dst`, and every later message on the connection is encoded for that
+    /// version. If the client's range does not overlap with the connector's,
+    /// the client is told to upgrade and `dst` is left without a version,
+    /// which closes the session.
     #[instrument(skip(self, dst))]
     pub(crate) async fn apply(self, dst: &mut Connection) -> crate::Result<()> {
-        // Here I need to call a function that can remove "v" from the key and then split
-        // the rest using ".." as a separator. If api version in the connector is between
-        // min and max valuesm then return <version>\0<date time>0\  ex: "176\x0020240209 22:23:12 EST\x00"
-        // Get the value from the shared database state
-        let response = if let Some(value) = self.get_version(&self.key) {
-            // If a value is present, it is written to the client in "bulk"
-            // format.
-            info!("Inside response bulk");
-            Frame::Bulk(value)
-        } else {
-            info!("Inside response null");
-            // If there is no value, `Null` is written.
-            Frame::Null
+        let (min, max, options) = self.parse_key()?;
+
+        let version = match negotiate(min, max) {
+            Some(version) => version,
+            None => {
+                warn!(
+                    "client version range v{}..{} does not overlap with v{}..{}",
+                    min, max, MIN_API_VERSION, API_VERSION
+                );
+
+                let response = errors::UPDATE_TWS.to_frame(-1, dst.server_version());
+
+                debug!(?response);
+                dst.write_frame(&response).await?;
+
+                return Ok(());
+            }
         };
 
+        info!(version, ?options, "negotiated server version");
+
+        dst.set_server_version(version);
+        dst.set_pace_api(options.iter().any(|option| option == "+PACEAPI"));
+
+        // The reply is the version followed by the connection time,
+        // ex: "176\x0020240209 22:23:12 EST\x00"
+        let timestamp = Local::now().format("%Y%m%d %H:%M:%S %Z").to_string();
+
+        let mut response = Frame::message();
+        response.push_int(version.into());
+        response.push_str(&timestamp);
+
         debug!(?response);
 
         // Write the response back to the client
@@ -87,28 +79,103 @@ impl Api {
         Ok(())
     }
 
-    /// New function that returns the API version
-    pub(crate) fn get_version(&self, key: &str) -> Option<Bytes> {
-        info!("Inside get_version");
+    /// Split the key into the client's minimum and maximum version and its
+    /// connection options.
+    ///
+    /// The version part is either a range, `v100..176`, or a single version,
+    /// `v176`. Options follow it separated by whitespace.
+    fn parse_key(&self) -> crate::Result<(u16, u16, Vec<String>)> {
+        let mut parts = self.key.split_whitespace();
+        let invalid = || format!("protocol error; invalid API version `{}`", self.key);
+
+        let range = parts.next().ok_or_else(invalid)?;
+        let range = range.strip_prefix('v').ok_or_else(invalid)?;
+
+        let (min, max) = match range.split_once("..") {
+            Some((min, max)) => (min, max),
+            None => (range, range),
+        };
+
+        let min = min.parse::<u16>().map_err(|_| invalid())?;
+        let max = max.parse::<u16>().map_err(|_| invalid())?;
+
+        Ok((min, max, parts.map(str::to_string).collect()))
+    }
+}
+
+/// Returns the highest version supported by both a client accepting
+/// `min..=max` and the connector, if there is one.
+fn negotiate(min: u16, max: u16) -> Option<u16> {
+    let version = max.min(API_VERSION);
 
-        let mut values = key.trim_start_matches('v').split("..");
+    if version >= min && version >= MIN_API_VERSION {
+        Some(version)
+    } else {
+        None
+    }
+}
 
-        info!("values is: {:?}", values);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
 
-        let min = values.next()?.parse::<u16>().unwrap();
-        let max = values.next()?.parse::<u16>().unwrap();
-        info!("min is: {}", min);
-        info!("max is: {}", max);
+    /// Returns the connector's end of a connection and the client's.
+    async fn connected() -> (Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
 
-        match min <= API_VERSION && API_VERSION <= max {
-            true => {
-                let now = Local::now();
-                let timestamp = now.format("%Y%m%d %H:%M:%S %Z").to_string();
-                let value = format!("{}\0{}\0", API_VERSION.to_string(), timestamp);
-                Some(Bytes::copy_from_slice(&value.into_bytes()))
-            }
-            // true => Some(Bytes::copy_from_slice(&API_VERSION.to_string().into_bytes())),
-            false => None,
+        (Connection::new(server), Connection::new(client))
+    }
+
+    #[test]
+    fn key_is_a_range_or_a_version_with_options() {
+        assert_eq!(Api::new("v100..176").parse_key().unwrap(), (100, 176, vec![]));
+        assert_eq!(
+            Api::new("v100..176 +PACEAPI").parse_key().unwrap(),
+            (100, 176, vec!["+PACEAPI".to_string()])
+        );
+        assert_eq!(Api::new("v140").parse_key().unwrap(), (140, 140, vec![]));
+
+        for invalid in ["", "100..176", "vx..176", "v100.."] {
+            assert!(Api::new(invalid).parse_key().is_err(), "{:?}", invalid);
         }
     }
+
+    #[test]
+    fn negotiate_picks_the_highest_common_version() {
+        assert_eq!(negotiate(100, 176), Some(API_VERSION));
+        assert_eq!(negotiate(140, 140), Some(140));
+
+        // Above what the connector supports, or below what it accepts.
+        assert_eq!(negotiate(API_VERSION + 1, 176), None);
+        assert_eq!(negotiate(20, MIN_API_VERSION - 1), None);
+    }
+
+    #[tokio::test]
+    async fn negotiated_version_and_pace_api_are_kept() {
+        let (mut server, mut client) = connected().await;
+
+        Api::new("v100..176 +PACEAPI").apply(&mut server).await.unwrap();
+        assert!(server.is_negotiated());
+        assert_eq!((server.server_version(), server.pace_api()), (API_VERSION, true));
+
+        let Some(Frame::Message(fields)) = client.read_frame().await.unwrap() else {
+            panic!("no reply");
+        };
+        assert_eq!(fields[0], API_VERSION.to_string());
+        assert_eq!(fields.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn range_below_the_minimum_asks_for_an_update() {
+        let (mut server, mut client) = connected().await;
+
+        Api::new("v20..90").apply(&mut server).await.unwrap();
+        assert!(!server.is_negotiated());
+
+        let reply = client.read_frame().await.unwrap().unwrap();
+        assert_eq!(reply, errors::UPDATE_TWS.to_frame(-1, MIN_API_VERSION));
+    }
 }
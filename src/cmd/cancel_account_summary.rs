@@ -0,0 +1,54 @@
+use crate::messages::{Fields, IncomingMessage};
+use crate::subscriptions::Topic;
+use crate::{Parse, Subscriptions};
+
+use tracing::{debug, instrument};
+
+/// Stop an account summary stream (`cancelAccountSummary`, message 63).
+#[derive(Debug)]
+pub struct CancelAccountSummary {
+    /// Message version
+    version: String,
+
+    /// Id of the `reqAccountSummary` request to stop.
+    req_id: i64,
+}
+
+impl CancelAccountSummary {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    /// Parse a `CancelAccountSummary` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 63 version reqId
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<CancelAccountSummary> {
+        let fields = Fields::decode(IncomingMessage::CancelAccountSummary, server_version, parse)?;
+        let version = fields.string("version");
+        let req_id = fields.parse("reqId")?;
+
+        Ok(CancelAccountSummary { version, req_id })
+    }
+
+    /// Apply the `CancelAccountSummary` command.
+    ///
+    /// TWS does not answer cancel requests, and neither does the connector,
+    /// not even when no such stream exists.
+    #[instrument(skip(self, subscriptions))]
+    pub(crate) fn apply(self, subscriptions: &mut Subscriptions) {
+        if !subscriptions.cancel(Topic::AccountSummary, self.req_id) {
+            debug!(req_id = self.req_id, "no account summary to cancel");
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::messages::{Fields, IncomingMessage};
+use crate::subscriptions::Topic;
+use crate::{Parse, Subscriptions};
+
+use tracing::{debug, instrument};
+
+/// Stop a market data stream (`cancelMktData`, message 2).
+#[derive(Debug)]
+pub struct CancelMktData {
+    /// Message version
+    version: String,
+
+    /// Id of the `reqMktData` request to stop.
+    req_id: i64,
+}
+
+impl CancelMktData {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    /// Parse a `CancelMktData` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 2 version reqId
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<CancelMktData> {
+        let fields = Fields::decode(IncomingMessage::CancelMktData, server_version, parse)?;
+        let version = fields.string("version");
+        let req_id = fields.parse("reqId")?;
+
+        Ok(CancelMktData { version, req_id })
+    }
+
+    /// Apply the `CancelMktData` command.
+    ///
+    /// TWS does not answer cancel requests, and neither does the connector,
+    /// not even when no such stream exists.
+    #[instrument(skip(self, subscriptions))]
+    pub(crate) fn apply(self, subscriptions: &mut Subscriptions) {
+        if !subscriptions.cancel(Topic::MktData, self.req_id) {
+            debug!(req_id = self.req_id, "no market data to cancel");
+        }
+    }
+}
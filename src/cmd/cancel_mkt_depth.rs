@@ -0,0 +1,64 @@
+use crate::messages::{Fields, IncomingMessage};
+use crate::subscriptions::Topic;
+use crate::{Parse, Subscriptions};
+
+use tracing::{debug, instrument};
+
+/// Stop a market depth stream (`cancelMktDepth`, message 11).
+#[derive(Debug)]
+pub struct CancelMktDepth {
+    /// Message version
+    version: String,
+
+    /// Id of the `reqMktDepth` request to stop.
+    req_id: i64,
+    is_smart_depth: bool,
+}
+
+impl CancelMktDepth {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    pub fn is_smart_depth(&self) -> bool {
+        self.is_smart_depth
+    }
+
+    /// Parse a `CancelMktDepth` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 11 version reqId [isSmartDepth]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<CancelMktDepth> {
+        let fields = Fields::decode(IncomingMessage::CancelMktDepth, server_version, parse)?;
+        let version = fields.string("version");
+        let req_id = fields.parse("reqId")?;
+        let is_smart_depth = fields.flag("isSmartDepth")?;
+
+        Ok(CancelMktDepth {
+            version,
+            req_id,
+            is_smart_depth,
+        })
+    }
+
+    /// Apply the `CancelMktDepth` command.
+    ///
+    /// TWS does not answer cancel requests, and neither does the connector,
+    /// not even when no such stream exists.
+    #[instrument(skip(self, subscriptions))]
+    pub(crate) fn apply(self, subscriptions: &mut Subscriptions) {
+        if !subscriptions.cancel(Topic::MktDepth, self.req_id) {
+            debug!(req_id = self.req_id, "no market depth to cancel");
+        }
+    }
+}
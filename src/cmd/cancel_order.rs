@@ -0,0 +1,287 @@
+use crate::broker;
+use crate::messages::{errors, Fields, IncomingMessage};
+use crate::orders;
+use crate::{Connection, Db, Parse};
+
+use tracing::{debug, instrument, warn};
+
+/// Cancel an order (`cancelOrder`, message 4).
+///
+/// Only orders placed by the same client can be cancelled. The outcome is
+/// reported with `orderStatus`, `PendingCancel` first if the broker has to
+/// confirm, then `Cancelled` along with error 202.
+#[derive(Debug)]
+pub struct CancelOrder {
+    /// Message version
+    version: String,
+    order_id: i32,
+}
+
+impl CancelOrder {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn order_id(&self) -> i32 {
+        self.order_id
+    }
+
+    /// Parse a `CancelOrder` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 4 version orderId [manualOrderCancelTime]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<CancelOrder> {
+        let fields = Fields::decode(IncomingMessage::CancelOrder, server_version, parse)?;
+        let version = fields.string("version");
+        let order_id = fields.parse("orderId")?;
+
+        Ok(CancelOrder { version, order_id })
+    }
+
+    /// Apply the `CancelOrder` command to the specified `Db` instance.
+    ///
+    /// Errors are written to `dst`, the status changes are reported through
+    /// the order events of `db`.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let server_version = dst.server_version();
+        let client_id = dst.client_id().ok_or("cancelOrder before startApi")?;
+        let id = self.order_id.into();
+
+        let response = match db.order(client_id, self.order_id) {
+            None => errors::ORDER_TO_CANCEL_NOT_FOUND.to_frame_with(
+                id,
+                server_version,
+                &format!(
+                    "OrderId {} that needs to be cancelled is not found.",
+                    self.order_id
+                ),
+            ),
+            Some(order) if order.status.is_done() => not_cancellable(order.perm_id, id, server_version),
+            Some(order) => match orders::cancel(db, &order).await {
+                Ok(()) => return Ok(()),
+                Err(broker::Error::NotFound | broker::Error::Rejected(_)) => {
+                    warn!(
+                        client_id,
+                        order_id = self.order_id,
+                        "broker refused to cancel order"
+                    );
+                    not_cancellable(order.perm_id, id, server_version)
+                }
+                Err(err) => {
+                    warn!(client_id, order_id = self.order_id, cause = %err, "failed to cancel order");
+                    errors::PROCESSING_FAILED.to_frame_with(
+                        id,
+                        server_version,
+                        &format!("Error processing request: {}", err),
+                    )
+                }
+            },
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
+
+/// Error 161, for an order that already filled or was cancelled.
+fn not_cancellable(perm_id: i32, id: i64, server_version: u16) -> crate::Frame {
+    let message = format!("{}{}", errors::NOT_CANCELLABLE.message, perm_id);
+    errors::NOT_CANCELLABLE.to_frame_with(id, server_version, &message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::NextValidOrderId;
+    use crate::config::{AccountConfig, BrokerConfig};
+    use crate::orders::{Contract, Order, Status, TrackedOrder};
+    use crate::{Config, Frame, Subscriptions};
+
+    use std::fs;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+    use tokio::time::{timeout, Duration};
+
+    const ACCOUNT: &str = "DU1";
+
+    /// A session of client 1 on a `Db` whose only account is a paper one,
+    /// with the connection of the client, the one of the server and what the
+    /// subscriptions of the session send.
+    struct Session {
+        db: Db,
+        client: Connection,
+        server: Connection,
+        _subscriptions: Subscriptions,
+        streamed: mpsc::Receiver<Frame>,
+    }
+
+    async fn start(name: &str) -> Session {
+        let data_dir = std::env::temp_dir().join(format!("cancel_order_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let config = Config {
+            data_dir,
+            accounts: vec![AccountConfig {
+                id: ACCOUNT.to_string(),
+                currency: "USD".to_string(),
+                broker: BrokerConfig::Paper { cash: 100_000.0 },
+            }],
+            ..Config::default()
+        };
+        let db = Db::open(config).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = Connection::new(TcpStream::connect(listener.local_addr().unwrap()).await.unwrap());
+        let mut server = Connection::new(listener.accept().await.unwrap().0);
+        let (mut subscriptions, streamed) = Subscriptions::new();
+
+        NextValidOrderId::new(2, 1)
+            .apply(&db, &mut server, &mut subscriptions)
+            .await
+            .unwrap();
+
+        // nextValidId and managedAccounts.
+        for _ in 0..2 {
+            client.read_frame().await.unwrap().unwrap();
+        }
+
+        Session {
+            db,
+            client,
+            server,
+            _subscriptions: subscriptions,
+            streamed,
+        }
+    }
+
+    /// Place order `order_id` of `client_id`, a limit buy of 100 MSFT at
+    /// 10.00 the paper account never fills, and wait until it works.
+    async fn place(db: &Db, client_id: i32, order_id: i32) -> TrackedOrder {
+        let contract = Contract {
+            symbol: "MSFT".to_string(),
+            sec_type: "STK".to_string(),
+            ..Contract::default()
+        };
+        let order = Order {
+            action: "BUY".to_string(),
+            total_quantity: 100.0,
+            order_type: "LMT".to_string(),
+            lmt_price: Some(10.0),
+            account: ACCOUNT.to_string(),
+            transmit: true,
+            ..Order::default()
+        };
+
+        db.insert_order(TrackedOrder::new(client_id, order_id, ACCOUNT.to_string(), contract, order));
+        orders::transmit(db, client_id, order_id).await;
+
+        let order = db.order(client_id, order_id).unwrap();
+        assert_eq!(order.status, Status::Submitted);
+        order
+    }
+
+    fn cancel(order_id: i32) -> CancelOrder {
+        CancelOrder {
+            version: "1".to_string(),
+            order_id,
+        }
+    }
+
+    /// Returns the statuses streamed to the client up to error 202 for
+    /// `order_id`.
+    async fn statuses_until_cancelled(session: &mut Session, order_id: i32) -> Vec<String> {
+        let cancelled = errors::ORDER_CANCELLED.to_frame(order_id.into(), session.server.server_version());
+        let mut statuses = Vec::new();
+
+        loop {
+            let frame = timeout(Duration::from_secs(5), session.streamed.recv())
+                .await
+                .expect("error 202 was not sent")
+                .unwrap();
+
+            if frame == cancelled {
+                return statuses;
+            }
+
+            // orderStatus, with its version field before 131.
+            if let Frame::Message(fields) = &frame {
+                if fields[0] == "3" {
+                    statuses.push(String::from_utf8_lossy(&fields[3]).into_owned());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn cancels_a_working_order() {
+        let mut session = start("working").await;
+        place(&session.db, 1, 1).await;
+
+        cancel(1).apply(&session.db, &mut session.server).await.unwrap();
+
+        let statuses = statuses_until_cancelled(&mut session, 1).await;
+        assert_eq!(
+            statuses,
+            ["PreSubmitted", "PendingSubmit", "Submitted", "PendingCancel", "Cancelled"]
+        );
+        assert_eq!(session.db.order(1, 1).unwrap().status, Status::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn cancelling_an_unknown_order_fails_with_10147() {
+        let mut session = start("unknown").await;
+
+        // Only orders of the same client can be cancelled.
+        place(&session.db, 2, 4).await;
+
+        for order_id in [3, 4] {
+            cancel(order_id).apply(&session.db, &mut session.server).await.unwrap();
+
+            let response = session.client.read_frame().await.unwrap().unwrap();
+            assert_eq!(
+                response,
+                errors::ORDER_TO_CANCEL_NOT_FOUND.to_frame_with(
+                    order_id.into(),
+                    session.server.server_version(),
+                    &format!("OrderId {} that needs to be cancelled is not found.", order_id)
+                )
+            );
+        }
+
+        assert_eq!(session.db.order(2, 4).unwrap().status, Status::Submitted);
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_cancelled_order_fails_with_161() {
+        let mut session = start("done").await;
+        let order = place(&session.db, 1, 1).await;
+
+        cancel(1).apply(&session.db, &mut session.server).await.unwrap();
+        statuses_until_cancelled(&mut session, 1).await;
+
+        cancel(1).apply(&session.db, &mut session.server).await.unwrap();
+
+        let response = session.client.read_frame().await.unwrap().unwrap();
+        assert_eq!(
+            response,
+            errors::NOT_CANCELLABLE.to_frame_with(
+                1,
+                session.server.server_version(),
+                &format!(
+                    "Cancel attempted when order is not in a cancellable state. Order permId ={}",
+                    order.perm_id
+                )
+            )
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use crate::messages::{Fields, IncomingMessage};
+use crate::subscriptions::Topic;
+use crate::{Parse, Subscriptions};
+
+use tracing::{debug, instrument};
+
+/// Stop an account P&L stream (`cancelPnL`, message 93).
+#[derive(Debug)]
+pub struct CancelPnL {
+    /// Id of the `reqPnL` request to stop.
+    req_id: i64,
+}
+
+impl CancelPnL {
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    /// Parse a `CancelPnL` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 93 reqId
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<CancelPnL> {
+        let fields = Fields::decode(IncomingMessage::CancelPnL, server_version, parse)?;
+        let req_id = fields.parse("reqId")?;
+
+        Ok(CancelPnL { req_id })
+    }
+
+    /// Apply the `CancelPnL` command.
+    ///
+    /// TWS does not answer cancel requests, and neither does the connector.
+    #[instrument(skip(self, subscriptions))]
+    pub(crate) fn apply(self, subscriptions: &mut Subscriptions) {
+        if !subscriptions.cancel(Topic::Pnl, self.req_id) {
+            debug!(req_id = self.req_id, "no P&L to cancel");
+        }
+    }
+}
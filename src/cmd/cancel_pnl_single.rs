@@ -0,0 +1,44 @@
+use crate::messages::{Fields, IncomingMessage};
+use crate::subscriptions::Topic;
+use crate::{Parse, Subscriptions};
+
+use tracing::{debug, instrument};
+
+/// Stop a position P&L stream (`cancelPnLSingle`, message 95).
+#[derive(Debug)]
+pub struct CancelPnLSingle {
+    /// Id of the `reqPnLSingle` request to stop.
+    req_id: i64,
+}
+
+impl CancelPnLSingle {
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    /// Parse a `CancelPnLSingle` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 95 reqId
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<CancelPnLSingle> {
+        let fields = Fields::decode(IncomingMessage::CancelPnLSingle, server_version, parse)?;
+        let req_id = fields.parse("reqId")?;
+
+        Ok(CancelPnLSingle { req_id })
+    }
+
+    /// Apply the `CancelPnLSingle` command.
+    ///
+    /// TWS does not answer cancel requests, and neither does the connector.
+    #[instrument(skip(self, subscriptions))]
+    pub(crate) fn apply(self, subscriptions: &mut Subscriptions) {
+        if !subscriptions.cancel(Topic::PnlSingle, self.req_id) {
+            debug!(req_id = self.req_id, "no P&L to cancel");
+        }
+    }
+}
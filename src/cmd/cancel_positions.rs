@@ -0,0 +1,45 @@
+use crate::messages::{Fields, IncomingMessage};
+use crate::subscriptions::Topic;
+use crate::{Parse, Subscriptions};
+
+use tracing::{debug, instrument};
+
+/// Stop the position stream (`cancelPositions`, message 64).
+#[derive(Debug)]
+pub struct CancelPositions {
+    /// Message version
+    version: String,
+}
+
+impl CancelPositions {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Parse a `CancelPositions` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 64 version
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<CancelPositions> {
+        let fields = Fields::decode(IncomingMessage::CancelPositions, server_version, parse)?;
+        let version = fields.string("version");
+
+        Ok(CancelPositions { version })
+    }
+
+    /// Apply the `CancelPositions` command.
+    ///
+    /// TWS does not answer cancel requests, and neither does the connector.
+    #[instrument(skip(self, subscriptions))]
+    pub(crate) fn apply(self, subscriptions: &mut Subscriptions) {
+        if !subscriptions.cancel(Topic::Positions, 0) {
+            debug!("no positions to cancel");
+        }
+    }
+}
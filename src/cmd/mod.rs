@@ -1,105 +1,247 @@
 mod api;
 pub use api::Api;
 
+mod cancel_account_summary;
+pub use cancel_account_summary::CancelAccountSummary;
+
+mod cancel_mkt_data;
+pub use cancel_mkt_data::CancelMktData;
+
+mod cancel_mkt_depth;
+pub use cancel_mkt_depth::CancelMktDepth;
+
+mod cancel_order;
+pub use cancel_order::CancelOrder;
+
+mod cancel_pnl;
+pub use cancel_pnl::CancelPnL;
+
+mod cancel_pnl_single;
+pub use cancel_pnl_single::CancelPnLSingle;
+
+mod cancel_positions;
+pub use cancel_positions::CancelPositions;
+
 mod next_valid_order_id;
 pub use next_valid_order_id::NextValidOrderId;
 
+mod place_order;
+pub use place_order::PlaceOrder;
+
 mod req_account_summary;
 pub use req_account_summary::ReqAccountSummary;
 
+mod req_account_updates;
+pub use req_account_updates::ReqAccountUpdates;
+
+mod req_all_open_orders;
+pub use req_all_open_orders::ReqAllOpenOrders;
+
+mod req_auto_open_orders;
+pub use req_auto_open_orders::ReqAutoOpenOrders;
+
+mod req_completed_orders;
+pub use req_completed_orders::ReqCompletedOrders;
+
+mod req_executions;
+pub use req_executions::ReqExecutions;
+
+mod req_global_cancel;
+pub use req_global_cancel::ReqGlobalCancel;
+
+mod req_ids;
+pub use req_ids::ReqIds;
+
+mod req_managed_accts;
+pub use req_managed_accts::ReqManagedAccts;
+
+mod req_market_data_type;
+pub use req_market_data_type::ReqMarketDataType;
 
-// mod publish;
-// pub use publish::Publish;
+mod req_mkt_data;
+pub use req_mkt_data::ReqMktData;
 
-// mod set;
-// pub use set::Set;
+mod req_mkt_depth;
+pub use req_mkt_depth::ReqMktDepth;
 
-// mod subscribe;
-// pub use subscribe::{Subscribe, Unsubscribe};
+mod req_mkt_depth_exchanges;
+pub use req_mkt_depth_exchanges::ReqMktDepthExchanges;
 
-// mod ping;
-// pub use ping::Ping;
+mod req_open_orders;
+pub use req_open_orders::ReqOpenOrders;
+
+mod req_pnl;
+pub use req_pnl::ReqPnL;
+
+mod req_pnl_single;
+pub use req_pnl_single::ReqPnLSingle;
+
+mod req_positions;
+pub use req_positions::ReqPositions;
 
 mod unknown;
-use tracing::info;
+use tracing::trace;
 pub use unknown::Unknown;
 
-use crate::{Connection, Frame, Parse, ParseError, Shutdown};
+use crate::messages::IncomingMessage;
+use crate::{Connection, Db, Frame, Parse, Subscriptions};
 
-/// Enumeration of supported Redis commands.
+/// Enumeration of supported TWS API requests.
 ///
 /// Methods called on `Command` are delegated to the command implementation.
 #[derive(Debug)]
 pub enum Command {
     Api(Api),
+    CancelAccountSummary(CancelAccountSummary),
+    CancelMktData(CancelMktData),
+    CancelMktDepth(CancelMktDepth),
+    CancelOrder(CancelOrder),
+    CancelPnL(CancelPnL),
+    CancelPnLSingle(CancelPnLSingle),
+    CancelPositions(CancelPositions),
     NextValidOrderId(NextValidOrderId),
+    PlaceOrder(Box<PlaceOrder>),
     ReqAccountSummary(ReqAccountSummary),
-    // Get(Get),
-    // Publish(Publish),
-    // Set(Set),
-    // Subscribe(Subscribe),
-    // Unsubscribe(Unsubscribe),
-    // Ping(Ping),
+    ReqAccountUpdates(ReqAccountUpdates),
+    ReqAllOpenOrders(ReqAllOpenOrders),
+    ReqAutoOpenOrders(ReqAutoOpenOrders),
+    ReqCompletedOrders(ReqCompletedOrders),
+    ReqExecutions(ReqExecutions),
+    ReqGlobalCancel(ReqGlobalCancel),
+    ReqIds(ReqIds),
+    ReqManagedAccts(ReqManagedAccts),
+    ReqMarketDataType(ReqMarketDataType),
+    ReqMktData(Box<ReqMktData>),
+    ReqMktDepth(Box<ReqMktDepth>),
+    ReqMktDepthExchanges(ReqMktDepthExchanges),
+    ReqOpenOrders(ReqOpenOrders),
+    ReqPnL(ReqPnL),
+    ReqPnLSingle(ReqPnLSingle),
+    ReqPositions(ReqPositions),
     Unknown(Unknown),
 }
 
 impl Command {
     /// Parse a command from a received frame.
     ///
-    /// The `Frame` must represent a Redis command supported by `mini-redis` and
-    /// be the array variant.
+    /// A `Handshake` frame is always the `Api` command. Any other frame must
+    /// be a `Message` whose first field is the request's message id.
     ///
     /// # Returns
     ///
     /// On success, the command value is returned, otherwise, `Err` is returned.
-    pub fn from_frame(frame: Frame) -> crate::Result<Command> {
-        // The frame value is decorated with `Parse`. `Parse` provides a
-        // "cursor" like API which makes parsing the command easier.
-        //
-        // The frame value must be an array variant. Any other frame variants
-        // result in an error being returned.
+    /// Fields are decoded following their layout at `server_version`, the
+    /// version negotiated for the connection the frame arrived on.
+    pub fn from_frame(frame: Frame, server_version: u16) -> crate::Result<Command> {
+        trace!("in Command::from_frame");
 
-        info!("in Command::from_frame");
+        // The version negotiation is not a regular message, it carries the
+        // client's supported version range as its whole body.
+        if let Frame::Handshake(version) = frame {
+            return Ok(Command::Api(Api::new(String::from_utf8(version.to_vec())?)));
+        }
 
+        // The frame value is decorated with `Parse`. `Parse` provides a
+        // "cursor" like API which makes parsing the command easier.
         let mut parse = Parse::new(frame)?;
 
-        info!("parse is: {:?}", parse);
+        trace!(?parse);
 
-        // All redis commands begin with the command name as a string. The name
-        // is read and converted to lower cases in order to do case sensitive
-        // matching.
-        let command_name = parse.next_string()?.to_lowercase();
+        // All TWS requests begin with the message id.
+        let command_name = parse.next_string()?;
 
-        info!("command_name is: {:?}", command_name);
+        trace!(%command_name);
 
-        // Match the command name, delegating the rest of the parsing to the
+        let message = command_name.parse().ok().and_then(IncomingMessage::from_id);
+
+        // Match the message id, delegating the rest of the parsing to the
         // specific command.
-        let command = match &command_name[..] {
-            "api" => Command::Api(Api::parse_frames(&mut parse)?),
-            "71" => Command::NextValidOrderId(NextValidOrderId::parse_frames(&mut parse)?),
-            "62" => Command::ReqAccountSummary(ReqAccountSummary::parse_frames(&mut parse)?), // b"9\08\0215\00\0IBM\0STK\0\00.0\0\0\0SMART\0\0USD\0\0\00\0\0\0\0"
-            // "get" => Command::Get(Get::parse_frames(&mut parse)?),
-            // "publish" => Command::Publish(Publish::parse_frames(&mut parse)?),
-            // "set" => Command::Set(Set::parse_frames(&mut parse)?),
-            // "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parse)?),
-            // "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parse)?),
-            // "ping" => Command::Ping(Ping::parse_frames(&mut parse)?),
+        let command = match message {
+            Some(IncomingMessage::CancelAccountSummary) => {
+                Command::CancelAccountSummary(CancelAccountSummary::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::CancelMktData) => {
+                Command::CancelMktData(CancelMktData::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::CancelMktDepth) => {
+                Command::CancelMktDepth(CancelMktDepth::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::CancelOrder) => {
+                Command::CancelOrder(CancelOrder::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::CancelPnL) => {
+                Command::CancelPnL(CancelPnL::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::CancelPnLSingle) => {
+                Command::CancelPnLSingle(CancelPnLSingle::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::CancelPositions) => {
+                Command::CancelPositions(CancelPositions::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::StartApi) => {
+                Command::NextValidOrderId(NextValidOrderId::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::PlaceOrder) => {
+                Command::PlaceOrder(Box::new(PlaceOrder::parse_frames(&mut parse, server_version)?))
+            }
+            Some(IncomingMessage::ReqAccountSummary) => {
+                Command::ReqAccountSummary(ReqAccountSummary::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqAccountUpdates) => {
+                Command::ReqAccountUpdates(ReqAccountUpdates::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqAllOpenOrders) => {
+                Command::ReqAllOpenOrders(ReqAllOpenOrders::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqAutoOpenOrders) => {
+                Command::ReqAutoOpenOrders(ReqAutoOpenOrders::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqCompletedOrders) => {
+                Command::ReqCompletedOrders(ReqCompletedOrders::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqExecutions) => {
+                Command::ReqExecutions(ReqExecutions::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqGlobalCancel) => {
+                Command::ReqGlobalCancel(ReqGlobalCancel::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqIds) => {
+                Command::ReqIds(ReqIds::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqManagedAccts) => {
+                Command::ReqManagedAccts(ReqManagedAccts::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqMarketDataType) => {
+                Command::ReqMarketDataType(ReqMarketDataType::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqMktData) => {
+                Command::ReqMktData(Box::new(ReqMktData::parse_frames(&mut parse, server_version)?))
+            }
+            Some(IncomingMessage::ReqMktDepth) => {
+                Command::ReqMktDepth(Box::new(ReqMktDepth::parse_frames(&mut parse, server_version)?))
+            }
+            Some(IncomingMessage::ReqMktDepthExchanges) => {
+                Command::ReqMktDepthExchanges(ReqMktDepthExchanges::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqOpenOrders) => {
+                Command::ReqOpenOrders(ReqOpenOrders::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqPnL) => {
+                Command::ReqPnL(ReqPnL::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqPnLSingle) => {
+                Command::ReqPnLSingle(ReqPnLSingle::parse_frames(&mut parse, server_version)?)
+            }
+            Some(IncomingMessage::ReqPositions) => {
+                Command::ReqPositions(ReqPositions::parse_frames(&mut parse, server_version)?)
+            }
             _ => {
                 // The command is not recognized and an Unknown command is
                 // returned.
-                //
-                // `return` is called here to skip the `finish()` call below. As
-                // the command is not recognized, there is most likely
-                // unconsumed fields remaining in the `Parse` instance.
                 return Ok(Command::Unknown(Unknown::new(command_name)));
             }
         };
 
-        // Check if there is any remaining unconsumed fields in the `Parse`
-        // value. If fields remain, this indicates an unexpected frame format
-        // and an error is returned.
-        //parse.finish()?;
-
         // The command has been successfully parsed
         Ok(command)
     }
@@ -107,43 +249,66 @@ impl Command {
     /// Apply the command to the specified `Db` instance.
     ///
     /// The response is written to `dst`. This is called by the server in order
-    /// to execute a received command.
+    /// to execute a received command. Requests that stream data register the
+    /// stream in `subscriptions`, which outlives the call.
     pub(crate) async fn apply(
         self,
+        db: &Db,
         dst: &mut Connection,
-        shutdown: &mut Shutdown,
+        subscriptions: &mut Subscriptions,
     ) -> crate::Result<()> {
         use Command::*;
 
         match self {
             Api(cmd) => cmd.apply(dst).await,
-            NextValidOrderId(cmd) => cmd.apply(dst).await,
-            ReqAccountSummary(cmd) => cmd.apply(dst).await,
-            // Get(cmd) => cmd.apply(db, dst).await,
-            // Publish(cmd) => cmd.apply(db, dst).await,
-            // Set(cmd) => cmd.apply(db, dst).await,
-            // Subscribe(cmd) => cmd.apply(db, dst, shutdown).await,
-            // Ping(cmd) => cmd.apply(dst).await,
+            CancelAccountSummary(cmd) => {
+                cmd.apply(subscriptions);
+                Ok(())
+            }
+            CancelMktData(cmd) => {
+                cmd.apply(subscriptions);
+                Ok(())
+            }
+            CancelMktDepth(cmd) => {
+                cmd.apply(subscriptions);
+                Ok(())
+            }
+            CancelOrder(cmd) => cmd.apply(db, dst).await,
+            CancelPnL(cmd) => {
+                cmd.apply(subscriptions);
+                Ok(())
+            }
+            CancelPnLSingle(cmd) => {
+                cmd.apply(subscriptions);
+                Ok(())
+            }
+            CancelPositions(cmd) => {
+                cmd.apply(subscriptions);
+                Ok(())
+            }
+            NextValidOrderId(cmd) => cmd.apply(db, dst, subscriptions).await,
+            PlaceOrder(cmd) => cmd.apply(db, dst).await,
+            ReqAccountSummary(cmd) => cmd.apply(db, dst, subscriptions).await,
+            ReqAccountUpdates(cmd) => cmd.apply(db, dst, subscriptions).await,
+            ReqAllOpenOrders(cmd) => cmd.apply(db, dst).await,
+            ReqAutoOpenOrders(cmd) => cmd.apply(db, dst, subscriptions).await,
+            ReqCompletedOrders(cmd) => cmd.apply(db, dst).await,
+            ReqExecutions(cmd) => cmd.apply(db, dst).await,
+            ReqGlobalCancel(cmd) => {
+                cmd.apply(db).await;
+                Ok(())
+            }
+            ReqIds(cmd) => cmd.apply(db, dst).await,
+            ReqManagedAccts(cmd) => cmd.apply(db, dst).await,
+            ReqMarketDataType(cmd) => cmd.apply(dst, subscriptions).await,
+            ReqMktData(cmd) => cmd.apply(db, dst, subscriptions).await,
+            ReqMktDepth(cmd) => cmd.apply(db, dst, subscriptions).await,
+            ReqMktDepthExchanges(cmd) => cmd.apply(db, dst).await,
+            ReqOpenOrders(cmd) => cmd.apply(db, dst).await,
+            ReqPnL(cmd) => cmd.apply(db, dst, subscriptions).await,
+            ReqPnLSingle(cmd) => cmd.apply(db, dst, subscriptions).await,
+            ReqPositions(cmd) => cmd.apply(db, dst, subscriptions).await,
             Unknown(cmd) => cmd.apply(dst).await,
-            // `Unsubscribe` cannot be applied. It may only be received from the
-            // context of a `Subscribe` command.
-            // Unsubscribe(_) => Err("`Unsubscribe` is unsupported in this context".into()),
-        }
-    }
-
-    /// Returns the command name
-    pub(crate) fn get_name(&self) -> &str {
-        match self {
-            Command::Api(_) => "api",
-            Command::NextValidOrderId(_) => "next_valid_order_id",
-            Command::ReqAccountSummary(_) => "req_account_summary",
-            // Command::Get(_) => "get",
-            // Command::Publish(_) => "pub",
-            // Command::Set(_) => "set",
-            // Command::Subscribe(_) => "subscribe",
-            // Command::Unsubscribe(_) => "unsubscribe",
-            // Command::Ping(_) => "ping",
-            Command::Unknown(cmd) => cmd.get_name(),
         }
     }
 }
@@ -1,26 +1,28 @@
-use crate::{Connection, Frame, Parse, API_VERSION};
+use crate::messages::{errors, Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::orders;
+use crate::subscriptions::Topic;
+use crate::{Connection, Db, Frame, Parse, Subscriptions};
 
-use bytes::Bytes;
-use tracing::{debug, info, instrument};
+use tracing::{debug, instrument, warn};
 
-/// Get the value of key.
+/// Start the API session (`startApi`, message 71).
 ///
-/// If the key does not exist the special value nil is returned. An error is
-/// returned if the value stored at key is not a string, because GET only
-/// handles string values.
+/// The client identifies itself with a client id, which must be unique among
+/// the connected sessions. TWS answers with the next valid order id and the
+/// list of managed accounts.
 #[derive(Debug)]
 pub struct NextValidOrderId {
-    /// Name of the key to get
+    /// Message version
     version: String,
-    client_id: String,
+    client_id: i32,
 }
 
-impl  NextValidOrderId {
-    /// Create a new `Get` command which fetches `version` and `id`.
-    pub fn new(version: impl ToString, client_id: impl ToString) -> NextValidOrderId {
+impl NextValidOrderId {
+    /// Create a new `NextValidOrderId` command for `client_id`.
+    pub fn new(version: impl ToString, client_id: i32) -> NextValidOrderId {
         NextValidOrderId {
             version: version.to_string(),
-            client_id: client_id.to_string(),
+            client_id,
         }
     }
 
@@ -29,88 +31,112 @@ impl  NextValidOrderId {
         &self.version
     }
 
-    pub fn client_id(&self) -> &str {
-        &self.client_id
+    pub fn client_id(&self) -> i32 {
+        self.client_id
     }
 
-    /// Parse a `Get` instance from a received frame.
+    /// Parse a `NextValidOrderId` instance from a received frame.
     ///
     /// The `Parse` argument provides a cursor-like API to read fields from the
     /// `Frame`. At this point, the entire frame has already been received from
     /// the socket.
     ///
-    /// The `GET` string has already been consumed.
+    /// The message id has already been consumed.
     ///
     /// # Returns
     ///
-    /// Returns the `Get` value on success. If the frame is malformed, `Err` is
-    /// returned.
+    /// Returns the `NextValidOrderId` value on success. If the frame is
+    /// malformed, `Err` is returned.
     ///
     /// # Format
     ///
-    /// Expects an array frame containing two entries.
-    ///
     /// ```text
-    /// GET key
+    /// 71 version clientId [optionalCapabilities]
     /// ```
-    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<NextValidOrderId> {
-        // The `GET` string has already been consumed. The next value is the
-        // name of the key to get. If the next value is not a string or the
-        // input is fully consumed, then an error is returned.
-        let version = parse.next_string()?;
-        let client_id = parse.next_string()?;
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<NextValidOrderId> {
+        let fields = Fields::decode(IncomingMessage::StartApi, server_version, parse)?;
+        let version = fields.string("version");
+        let client_id = fields.parse("clientId")?;
 
         Ok(NextValidOrderId { version, client_id })
     }
 
-    /// Apply the `Get` command to the specified `Db` instance.
+    /// Apply the `NextValidOrderId` command to the specified `Db` instance.
+    ///
+    /// The client id is reserved in `db` for as long as `dst` is open. If it
+    /// is already taken, the client gets error 326 and the session is closed.
     ///
     /// The response is written to `dst`. This is called by the server in order
-    /// to execute a received command.
-    #[instrument(skip(self, dst))]
-    pub(crate) async fn apply(self, dst: &mut Connection) -> crate::Result<()> {
-        // Here I need to call a function that can remove "v" from the key and then split
-        // the rest using ".." as a separator. If api version in the connector is between
-        // min and max valuesm then return <version>\0<date time>0\  ex: "176\x0020240209 22:23:12 EST\x00"
-        // Get the value from the shared database state
-
-        let mut response = Frame::array();
-        let order_id = self.get_next_valid_order_id(&self.client_id);
-        let account_id = self.get_user_account_id(&self.client_id);
-        
-        response.push_bulk(order_id); // for each tag we need to send a separate message
-        response.push_bulk(account_id); // we need to send one more message with account summary end  like b"64\01\09001\0"
- 
-
-        debug!(?response);
-
-        // Write the response back to the client
-        dst.write_frame(&response).await?;
+    /// to execute a received command. From then on, the changes of the
+    /// client's orders are streamed by a task registered in `subscriptions`.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+
+        let client = match db.register_client(self.client_id) {
+            Some(client) => client,
+            None => {
+                warn!(client_id = self.client_id, "client id is already in use");
+
+                let response = errors::CLIENT_ID_IN_USE.to_frame(-1, server_version);
+                debug!(?response);
+                dst.write_frame(&response).await?;
+
+                return Ok(());
+            }
+        };
+
+        dst.set_client(client);
+
+        // Subscribe before answering, so no change of an order placed after
+        // the client learns its next valid id is missed.
+        let events = db.order_events();
+        let client_id = self.client_id;
+        subscriptions.spawn(Topic::Orders, 0, move |sink| {
+            orders::forward(events, client_id, sink, server_version)
+        });
+
+        // The client expects the next valid order id followed by the list of
+        // managed accounts, each as a separate message.
+        let order_id = self.get_next_valid_order_id(db, self.client_id, server_version);
+        let account_id = self.get_user_account_id(db, self.client_id, server_version);
+
+        for response in [order_id, account_id] {
+            debug!(?response);
+
+            // Write the response back to the client
+            dst.write_frame(&response).await?;
+        }
 
         Ok(())
     }
 
-    /// New function that returns the next valid order id
-    pub(crate) fn get_next_valid_order_id(&self, client_id: &str) -> Bytes {
-        info!("Inside get_next_valid_order_id");
-
-        info!("client_id is: {}", client_id);
-
-        let order_id = 1;
-
-        let value = format!("9\01\0{}\0", order_id);
-        Bytes::copy_from_slice(&value.into_bytes())
+    /// Returns the `nextValidId` message for `client_id`.
+    ///
+    /// The id is the lowest one `client_id` has not used yet, including in
+    /// earlier sessions.
+    pub(crate) fn get_next_valid_order_id(&self, db: &Db, client_id: i32, server_version: u16) -> Frame {
+        let order_id = db.next_order_id(client_id);
+        debug!(client_id, order_id, "next valid order id");
+
+        Encoder::new(OutgoingMessage::NextValidId, server_version)
+            .put("orderId", order_id)
+            .into_frame()
     }
 
-    /// New function that returns the next valid order id
-    pub(crate) fn get_user_account_id(&self, client_id: &str) -> Bytes {
-        info!("Inside get_user_account_id");
-
-        info!("client_id is: {}", client_id);
-
-        let account_id = "U12345678"; // one or coma separated list
+    /// Returns the `managedAccounts` message, listing one account or several
+    /// separated by commas.
+    pub(crate) fn get_user_account_id(&self, db: &Db, client_id: i32, server_version: u16) -> Frame {
+        let account_id = db.managed_accounts();
+        debug!(client_id, accounts = %account_id, "managed accounts");
 
-        let value = format!("15\01\0{}\0", account_id);
-        Bytes::copy_from_slice(&value.into_bytes())
+        Encoder::new(OutgoingMessage::ManagedAccts, server_version)
+            .put("accountsList", account_id)
+            .into_frame()
     }
 }
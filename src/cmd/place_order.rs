@@ -0,0 +1,992 @@
+use crate::messages::errors;
+use crate::messages::versions::*;
+use crate::messages::{Field, Fields};
+use crate::orders::{self, Contract, Order, OrderCondition, Status, TagValue, TrackedOrder};
+use crate::{Connection, Db, Frame, Parse};
+
+use tracing::{debug, instrument, warn};
+
+/// Place or modify an order (`placeOrder`, message 3).
+///
+/// Order ids are scoped to the client and may only grow: an id lower than the
+/// client's next valid order id is refused with error 103, unless it is the
+/// id of a working order, which is then modified. Orders the broker layer
+/// cannot carry out are refused with error 201. Accepted orders are reported
+/// with `openOrder` and `orderStatus` as they progress.
+#[derive(Debug)]
+pub struct PlaceOrder {
+    order_id: i32,
+    contract: Contract,
+    order: Order,
+}
+
+// `placeOrder` has repeated and optional groups, whose presence depends on
+// earlier fields. The fixed parts in between are read with these layouts.
+
+const HEADER: &[Field] = &[
+    Field::new("version").until(MIN_SERVER_VER_ORDER_CONTAINER),
+    Field::new("orderId"),
+];
+
+const CONTRACT: &[Field] = &[
+    Field::new("conId"),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("lastTradeDateOrContractMonth"),
+    Field::new("strike"),
+    Field::new("right"),
+    Field::new("multiplier"),
+    Field::new("exchange"),
+    Field::new("primaryExchange"),
+    Field::new("currency"),
+    Field::new("localSymbol"),
+    Field::new("tradingClass").since(MIN_SERVER_VER_TRADING_CLASS),
+    Field::new("secIdType"),
+    Field::new("secId"),
+];
+
+const MAIN: &[Field] = &[
+    Field::new("action"),
+    Field::new("totalQuantity"),
+    Field::new("orderType"),
+    Field::new("lmtPrice"),
+    Field::new("auxPrice"),
+    Field::new("tif"),
+    Field::new("ocaGroup"),
+    Field::new("account"),
+    Field::new("openClose"),
+    Field::new("origin"),
+    Field::new("orderRef"),
+    Field::new("transmit"),
+    Field::new("parentId"),
+    Field::new("blockOrder"),
+    Field::new("sweepToFill"),
+    Field::new("displaySize"),
+    Field::new("triggerMethod"),
+    Field::new("outsideRth"),
+    Field::new("hidden"),
+];
+
+const EXTENDED: &[Field] = &[
+    Field::new("sharesAllocation"),
+    Field::new("discretionaryAmt"),
+    Field::new("goodAfterTime"),
+    Field::new("goodTillDate"),
+    Field::new("faGroup"),
+    Field::new("faMethod"),
+    Field::new("faPercentage"),
+    Field::new("faProfile"),
+    Field::new("modelCode").since(MIN_SERVER_VER_MODELS_SUPPORT),
+    Field::new("shortSaleSlot"),
+    Field::new("designatedLocation"),
+    Field::new("exemptCode"),
+    Field::new("ocaType"),
+    Field::new("rule80A"),
+    Field::new("settlingFirm"),
+    Field::new("allOrNone"),
+    Field::new("minQty"),
+    Field::new("percentOffset"),
+    Field::new("eTradeOnly"),
+    Field::new("firmQuoteOnly"),
+    Field::new("nbboPriceCap"),
+    Field::new("auctionStrategy"),
+    Field::new("startingPrice"),
+    Field::new("stockRefPrice"),
+    Field::new("delta"),
+    Field::new("stockRangeLower"),
+    Field::new("stockRangeUpper"),
+    Field::new("overridePercentageConstraints"),
+    Field::new("volatility"),
+    Field::new("volatilityType"),
+    Field::new("deltaNeutralOrderType"),
+    Field::new("deltaNeutralAuxPrice"),
+];
+
+/// Only sent when `deltaNeutralOrderType` is set.
+const DELTA_NEUTRAL: &[Field] = &[
+    Field::new("deltaNeutralConId"),
+    Field::new("deltaNeutralSettlingFirm"),
+    Field::new("deltaNeutralClearingAccount"),
+    Field::new("deltaNeutralClearingIntent"),
+    Field::new("deltaNeutralOpenClose"),
+    Field::new("deltaNeutralShortSale"),
+    Field::new("deltaNeutralShortSaleSlot"),
+    Field::new("deltaNeutralDesignatedLocation"),
+];
+
+const SCALE: &[Field] = &[
+    Field::new("continuousUpdate"),
+    Field::new("referencePriceType"),
+    Field::new("trailStopPrice"),
+    Field::new("trailingPercent"),
+    Field::new("scaleInitLevelSize"),
+    Field::new("scaleSubsLevelSize"),
+    Field::new("scalePriceIncrement"),
+];
+
+/// Only sent when `scalePriceIncrement` is positive.
+const SCALE_ORDER: &[Field] = &[
+    Field::new("scalePriceAdjustValue"),
+    Field::new("scalePriceAdjustInterval"),
+    Field::new("scaleProfitOffset"),
+    Field::new("scaleAutoReset"),
+    Field::new("scaleInitPosition"),
+    Field::new("scaleInitFillQty"),
+    Field::new("scaleRandomPercent"),
+];
+
+const HEDGE: &[Field] = &[
+    Field::new("scaleTable"),
+    Field::new("activeStartTime"),
+    Field::new("activeStopTime"),
+    Field::new("hedgeType"),
+];
+
+const CLEARING: &[Field] = &[
+    Field::new("optOutSmartRouting"),
+    Field::new("clearingAccount"),
+    Field::new("clearingIntent"),
+    Field::new("notHeld"),
+    Field::new("deltaNeutralContractPresent"),
+];
+
+/// Only sent when `deltaNeutralContractPresent` is set.
+const DELTA_NEUTRAL_CONTRACT: &[Field] = &[
+    Field::new("deltaNeutralContractConId"),
+    Field::new("deltaNeutralContractDelta"),
+    Field::new("deltaNeutralContractPrice"),
+];
+
+const ALGO: &[Field] = &[Field::new("algoStrategy")];
+
+const MISC: &[Field] = &[
+    Field::new("algoId"),
+    Field::new("whatIf"),
+    Field::new("orderMiscOptions"),
+    Field::new("solicited"),
+    Field::new("randomizeSize"),
+    Field::new("randomizePrice"),
+];
+
+/// Only sent for `PEG BENCH` orders.
+const PEGGED_TO_BENCHMARK: &[Field] = &[
+    Field::new("referenceContractId"),
+    Field::new("isPeggedChangeAmountDecrease"),
+    Field::new("peggedChangeAmount"),
+    Field::new("referenceChangeAmount"),
+    Field::new("referenceExchangeId"),
+];
+
+/// Only sent when there is at least one condition.
+const CONDITIONS_OPTIONS: &[Field] = &[
+    Field::new("conditionsIgnoreRth"),
+    Field::new("conditionsCancelOrder"),
+];
+
+const ADJUSTED: &[Field] = &[
+    Field::new("adjustedOrderType"),
+    Field::new("triggerPrice"),
+    Field::new("lmtPriceOffset"),
+    Field::new("adjustedStopPrice"),
+    Field::new("adjustedStopLimitPrice"),
+    Field::new("adjustedTrailingAmount"),
+    Field::new("adjustableTrailingUnit"),
+];
+
+const TRAILER: &[Field] = &[
+    Field::new("extOperator").since(MIN_SERVER_VER_EXT_OPERATOR),
+    Field::new("softDollarTierName").since(MIN_SERVER_VER_SOFT_DOLLAR_TIER),
+    Field::new("softDollarTierValue").since(MIN_SERVER_VER_SOFT_DOLLAR_TIER),
+    Field::new("cashQty").since(MIN_SERVER_VER_CASH_QTY),
+    Field::new("mifid2DecisionMaker").since(MIN_SERVER_VER_DECISION_MAKER),
+    Field::new("mifid2DecisionAlgo").since(MIN_SERVER_VER_DECISION_MAKER),
+    Field::new("mifid2ExecutionTrader").since(MIN_SERVER_VER_MIFID_EXECUTION),
+    Field::new("mifid2ExecutionAlgo").since(MIN_SERVER_VER_MIFID_EXECUTION),
+    Field::new("dontUseAutoPriceForHedge").since(MIN_SERVER_VER_AUTO_PRICE_FOR_HEDGE),
+    Field::new("isOmsContainer").since(MIN_SERVER_VER_ORDER_CONTAINER),
+    Field::new("discretionaryUpToLimitPrice").since(MIN_SERVER_VER_D_PEG_ORDERS),
+    Field::new("usePriceMgmtAlgo").since(MIN_SERVER_VER_PRICE_MGMT_ALGO),
+];
+
+// Fields of each kind of condition, after its type.
+
+const PRICE_CONDITION: &[Field] = &[
+    Field::new("conjunction"),
+    Field::new("isMore"),
+    Field::new("price"),
+    Field::new("conId"),
+    Field::new("exchange"),
+    Field::new("triggerMethod"),
+];
+
+const TIME_CONDITION: &[Field] = &[
+    Field::new("conjunction"),
+    Field::new("isMore"),
+    Field::new("time"),
+];
+
+const MARGIN_CONDITION: &[Field] = &[
+    Field::new("conjunction"),
+    Field::new("isMore"),
+    Field::new("percent"),
+];
+
+const EXECUTION_CONDITION: &[Field] = &[
+    Field::new("conjunction"),
+    Field::new("secType"),
+    Field::new("exchange"),
+    Field::new("symbol"),
+];
+
+const VOLUME_CONDITION: &[Field] = &[
+    Field::new("conjunction"),
+    Field::new("isMore"),
+    Field::new("volume"),
+    Field::new("conId"),
+    Field::new("exchange"),
+];
+
+const PERCENT_CHANGE_CONDITION: &[Field] = &[
+    Field::new("conjunction"),
+    Field::new("isMore"),
+    Field::new("changePercent"),
+    Field::new("conId"),
+    Field::new("exchange"),
+];
+
+impl PlaceOrder {
+    pub fn order_id(&self) -> i32 {
+        self.order_id
+    }
+
+    /// Parse a `PlaceOrder` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 3 [version] orderId contract... order...
+    /// ```
+    ///
+    /// The fields follow the official client's `placeOrder`. The version
+    /// field was dropped in `MIN_SERVER_VER_ORDER_CONTAINER`.
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<PlaceOrder> {
+        let mut fields = Fields::default();
+
+        fields.read(HEADER, server_version, parse)?;
+        fields.read(CONTRACT, server_version, parse)?;
+        fields.read(MAIN, server_version, parse)?;
+
+        // Combo legs, their prices and the routing options of combos. They
+        // are not supported and skipped.
+        if fields.string("secType") == "BAG" {
+            for _ in 0..count(parse)? {
+                skip(parse, 8)?;
+            }
+            for _ in 0..count(parse)? {
+                skip(parse, 1)?;
+            }
+            tag_values(parse)?;
+        }
+
+        fields.read(EXTENDED, server_version, parse)?;
+
+        if !fields.string("deltaNeutralOrderType").is_empty() {
+            fields.read(DELTA_NEUTRAL, server_version, parse)?;
+        }
+
+        fields.read(SCALE, server_version, parse)?;
+
+        if fields
+            .optional::<f64>("scalePriceIncrement")?
+            .is_some_and(|increment| increment > 0.0)
+        {
+            fields.read(SCALE_ORDER, server_version, parse)?;
+        }
+
+        fields.read(HEDGE, server_version, parse)?;
+
+        if !fields.string("hedgeType").is_empty() {
+            skip(parse, 1)?;
+        }
+
+        fields.read(CLEARING, server_version, parse)?;
+
+        if fields.flag("deltaNeutralContractPresent")? {
+            fields.read(DELTA_NEUTRAL_CONTRACT, server_version, parse)?;
+        }
+
+        fields.read(ALGO, server_version, parse)?;
+
+        let algo_params = if fields.string("algoStrategy").is_empty() {
+            Vec::new()
+        } else {
+            tag_values(parse)?
+        };
+
+        fields.read(MISC, server_version, parse)?;
+
+        let mut conditions = Vec::new();
+
+        if server_version >= MIN_SERVER_VER_PEGGED_TO_BENCHMARK {
+            if fields.string("orderType") == "PEG BENCH" {
+                fields.read(PEGGED_TO_BENCHMARK, server_version, parse)?;
+            }
+
+            for _ in 0..count(parse)? {
+                conditions.push(condition(parse, server_version)?);
+            }
+
+            if !conditions.is_empty() {
+                fields.read(CONDITIONS_OPTIONS, server_version, parse)?;
+            }
+
+            fields.read(ADJUSTED, server_version, parse)?;
+        }
+
+        fields.read(TRAILER, server_version, parse)?;
+
+        let order_id = fields.parse("orderId")?;
+
+        let contract = Contract {
+            con_id: fields.optional("conId")?.unwrap_or_default(),
+            symbol: fields.string("symbol"),
+            sec_type: fields.string("secType"),
+            last_trade_date_or_contract_month: fields.string("lastTradeDateOrContractMonth"),
+            strike: fields.optional("strike")?.filter(|strike| *strike != 0.0),
+            right: fields.string("right"),
+            multiplier: fields.string("multiplier"),
+            exchange: fields.string("exchange"),
+            primary_exchange: fields.string("primaryExchange"),
+            currency: fields.string("currency"),
+            local_symbol: fields.string("localSymbol"),
+            trading_class: fields.string("tradingClass"),
+        };
+
+        let order = Order {
+            action: fields.string("action"),
+            total_quantity: fields.parse("totalQuantity")?,
+            order_type: fields.string("orderType"),
+            lmt_price: fields.optional("lmtPrice")?,
+            aux_price: fields.optional("auxPrice")?,
+            tif: fields.string("tif"),
+            oca_group: fields.string("ocaGroup"),
+            oca_type: fields.optional("ocaType")?.unwrap_or_default(),
+            account: fields.string("account"),
+            order_ref: fields.string("orderRef"),
+            transmit: fields.flag("transmit")?,
+            parent_id: fields.optional("parentId")?.unwrap_or_default(),
+            outside_rth: fields.flag("outsideRth")?,
+            hidden: fields.flag("hidden")?,
+            display_size: fields.optional("displaySize")?.unwrap_or_default(),
+            good_after_time: fields.string("goodAfterTime"),
+            good_till_date: fields.string("goodTillDate"),
+            all_or_none: fields.flag("allOrNone")?,
+            min_qty: fields.optional("minQty")?,
+            trail_stop_price: fields.optional("trailStopPrice")?,
+            trailing_percent: fields.optional("trailingPercent")?,
+            algo_strategy: fields.string("algoStrategy"),
+            algo_params,
+            what_if: fields.flag("whatIf")?,
+            conditions,
+            conditions_ignore_rth: fields.flag("conditionsIgnoreRth")?,
+            conditions_cancel_order: fields.flag("conditionsCancelOrder")?,
+            model_code: fields.string("modelCode"),
+            cash_qty: fields.optional("cashQty")?,
+        };
+
+        Ok(PlaceOrder {
+            order_id,
+            contract,
+            order,
+        })
+    }
+
+    /// Apply the `PlaceOrder` command to the specified `Db` instance.
+    ///
+    /// Refusals are written to `dst`. Accepted orders are reported through
+    /// the order events of `db`, which the connection forwards to the client.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let server_version = dst.server_version();
+        let client_id = dst.client_id().ok_or("placeOrder before startApi")?;
+
+        // Sending an order again with the same id modifies it.
+        if let Some(current) = db.order(client_id, self.order_id) {
+            return self.modify(current, db, dst).await;
+        }
+
+        if !db.claim_order_id(client_id, self.order_id)? {
+            warn!(client_id, order_id = self.order_id, "order id already used or out of range");
+
+            let response = errors::DUPLICATE_ORDER_ID.to_frame(self.order_id.into(), server_version);
+            debug!(?response);
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        // Orders without an account go to the first managed one.
+        let account = match self.order.account.as_str() {
+            "" => db
+                .config()
+                .accounts
+                .first()
+                .map(|account| account.id.clone())
+                .unwrap_or_default(),
+            account => account.to_string(),
+        };
+
+        let parent_id = self.order.parent_id;
+
+        let rejection = if db.broker(&account).is_none() {
+            Some(format!("Unknown account {}", account))
+        } else if parent_id != 0 && db.order(client_id, parent_id).is_none() {
+            Some(format!("Parent order {} not found", parent_id))
+        } else {
+            self.order.to_request(&self.contract, "").err()
+        };
+
+        if let Some(reason) = rejection {
+            warn!(client_id, order_id = self.order_id, %reason, "order rejected");
+
+            let response = rejected(self.order_id, &reason, server_version);
+            debug!(?response);
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        let mut order = self.order;
+        order.account = account.clone();
+
+        let order = db.insert_order(TrackedOrder::new(
+            client_id,
+            self.order_id,
+            account,
+            self.contract,
+            order,
+        ));
+
+        if order.transmitted {
+            orders::transmit(db, client_id, order.order_id).await;
+        }
+
+        Ok(())
+    }
+
+    /// Modify `current`, the order the client placed earlier with the same
+    /// id.
+    ///
+    /// The instrument, side, type, account and parent of an order cannot
+    /// change, such requests are refused with error 105. Filled orders are
+    /// refused with error 104, and orders that are no longer working with
+    /// error 103, as their id is used up.
+    async fn modify(self, current: TrackedOrder, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let server_version = dst.server_version();
+        let id = self.order_id.into();
+
+        let (contract, order) = (&self.contract, &self.order);
+
+        let response = if current.status == Status::Filled {
+            Some(errors::CANNOT_MODIFY_FILLED.to_frame(id, server_version))
+        } else if current.status.is_done() {
+            Some(errors::DUPLICATE_ORDER_ID.to_frame(id, server_version))
+        } else if contract.symbol != current.contract.symbol
+            || contract.sec_type != current.contract.sec_type
+            || order.action != current.order.action
+            || order.order_type != current.order.order_type
+            || order.parent_id != current.order.parent_id
+            || !(order.account.is_empty() || order.account == current.account)
+        {
+            Some(errors::MODIFY_MISMATCH.to_frame(id, server_version))
+        } else if order.total_quantity <= current.filled {
+            Some(rejected(
+                self.order_id,
+                "Order quantity must exceed the filled quantity",
+                server_version,
+            ))
+        } else {
+            order
+                .to_request(contract, "")
+                .err()
+                .map(|reason| rejected(self.order_id, &reason, server_version))
+        };
+
+        if let Some(response) = response {
+            warn!(
+                client_id = current.client_id,
+                order_id = self.order_id,
+                "order modification refused"
+            );
+            debug!(?response);
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        orders::modify(db, current, self.contract, self.order).await;
+        Ok(())
+    }
+}
+
+/// Error 201, for an order the broker layer cannot carry out.
+fn rejected(order_id: i32, reason: &str, server_version: u16) -> Frame {
+    let message = format!("{}{}", errors::ORDER_REJECTED.message, reason);
+    errors::ORDER_REJECTED.to_frame_with(order_id.into(), server_version, &message)
+}
+
+/// Read the number of items of a repeated group.
+pub(super) fn count(parse: &mut Parse) -> crate::Result<usize> {
+    let count = parse.next_string()?;
+
+    match count.as_str() {
+        "" => Ok(0),
+        _ => count
+            .parse()
+            .map_err(|_| format!("protocol error; invalid count `{}`", count).into()),
+    }
+}
+
+/// Skip `n` fields the connector has no use for.
+pub(super) fn skip(parse: &mut Parse, n: usize) -> crate::Result<()> {
+    for _ in 0..n {
+        parse.next_string()?;
+    }
+
+    Ok(())
+}
+
+/// Read a count followed by as many tag-value pairs.
+fn tag_values(parse: &mut Parse) -> crate::Result<Vec<TagValue>> {
+    (0..count(parse)?)
+        .map(|_| {
+            Ok(TagValue {
+                tag: parse.next_string()?,
+                value: parse.next_string()?,
+            })
+        })
+        .collect()
+}
+
+/// Read one order condition, starting with its type.
+fn condition(parse: &mut Parse, server_version: u16) -> crate::Result<OrderCondition> {
+    let kind = parse.next_string()?;
+    let mut fields = Fields::default();
+
+    let layout = match kind.as_str() {
+        "1" => PRICE_CONDITION,
+        "3" => TIME_CONDITION,
+        "4" => MARGIN_CONDITION,
+        "5" => EXECUTION_CONDITION,
+        "6" => VOLUME_CONDITION,
+        "7" => PERCENT_CHANGE_CONDITION,
+        _ => return Err(format!("protocol error; invalid condition type `{}`", kind).into()),
+    };
+
+    fields.read(layout, server_version, parse)?;
+
+    let and = fields.string("conjunction") == "a";
+
+    let condition = match kind.as_str() {
+        "1" => OrderCondition::Price {
+            and,
+            is_more: fields.flag("isMore")?,
+            price: fields.parse("price")?,
+            con_id: fields.parse("conId")?,
+            exchange: fields.string("exchange"),
+            trigger_method: fields.parse("triggerMethod")?,
+        },
+        "3" => OrderCondition::Time {
+            and,
+            is_more: fields.flag("isMore")?,
+            time: fields.string("time"),
+        },
+        "4" => OrderCondition::Margin {
+            and,
+            is_more: fields.flag("isMore")?,
+            percent: fields.parse("percent")?,
+        },
+        "5" => OrderCondition::Execution {
+            and,
+            sec_type: fields.string("secType"),
+            exchange: fields.string("exchange"),
+            symbol: fields.string("symbol"),
+        },
+        "6" => OrderCondition::Volume {
+            and,
+            is_more: fields.flag("isMore")?,
+            volume: fields.parse("volume")?,
+            con_id: fields.parse("conId")?,
+            exchange: fields.string("exchange"),
+        },
+        _ => OrderCondition::PercentChange {
+            and,
+            is_more: fields.flag("isMore")?,
+            change_percent: fields.parse("changePercent")?,
+            con_id: fields.parse("conId")?,
+            exchange: fields.string("exchange"),
+        },
+    };
+
+    Ok(condition)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{OrderType, Side, TimeInForce, Trail};
+    use crate::orders::STOCK;
+    use crate::parse::ParseError;
+
+    use bytes::Bytes;
+
+    /// `placeOrder` of a market order as the official client sends it at
+    /// version 100, after the message id.
+    const MARKET_V100: &[&str] = &[
+        // version orderId
+        "45", "1",
+        // contract
+        "0", "AAPL", "STK", "", "0.0", "", "", "SMART", "ISLAND", "USD", "", "", "", "",
+        // action ... hidden
+        "BUY", "100", "MKT", "", "", "DAY", "", "DU123456", "", "0", "ref-1", "1", "0", "0", "0", "0", "0", "0",
+        "0",
+        // sharesAllocation ... deltaNeutralAuxPrice, no modelCode before 103
+        "", "0", "", "", "", "", "", "", "0", "", "-1", "0", "", "", "0", "", "", "0", "0", "", "0", "", "", "",
+        "", "", "0", "", "", "", "",
+        // scale
+        "0", "", "", "", "", "", "",
+        // scaleTable ... hedgeType
+        "", "", "", "",
+        // clearing
+        "0", "", "", "0", "0",
+        // algoStrategy
+        "",
+        // algoId ... randomizePrice, nothing after before 102
+        "", "0", "", "0", "0", "0",
+    ];
+
+    /// A limit order with an algo and two conditions at version 145, which
+    /// dropped the version field.
+    const LIMIT_V145: &[&str] = &[
+        // orderId
+        "2",
+        // contract
+        "265598", "AAPL", "STK", "", "0.0", "", "", "SMART", "", "USD", "", "NMS", "", "",
+        // action ... hidden
+        "SELL", "50", "LMT", "150.25", "", "GTC", "", "", "", "0", "", "1", "0", "0", "0", "0", "0", "1", "0",
+        // sharesAllocation ... deltaNeutralAuxPrice
+        "", "0", "", "", "", "", "", "", "", "0", "", "-1", "0", "", "", "0", "", "", "0", "0", "", "0", "", "",
+        "", "", "", "0", "", "", "", "",
+        // scale
+        "0", "", "", "", "", "", "",
+        // scaleTable ... hedgeType
+        "", "", "", "",
+        // clearing
+        "0", "", "", "0", "0",
+        // algoStrategy and its parameters
+        "Adaptive", "1", "adaptivePriority", "Normal",
+        // algoId ... randomizePrice
+        "", "0", "", "0", "0", "0",
+        // a price and a time condition
+        "2",
+        "1", "a", "1", "155.0", "265598", "SMART", "2",
+        "3", "o", "0", "20240112 15:30:00 US/Eastern",
+        // conditionsIgnoreRth conditionsCancelOrder
+        "0", "1",
+        // adjusted
+        "", "", "", "", "", "", "0",
+        // extOperator ... isOmsContainer
+        "", "", "", "", "", "", "", "", "0", "0",
+    ];
+
+    /// The layout of a stop limit or trailing stop order at version 151,
+    /// with `order` in place of action ... hidden.
+    fn order_v151(order: &[&str], trail_stop_price: &str, trailing_percent: &str) -> Vec<String> {
+        let fields: Vec<&str> = [
+            // orderId
+            &["3"][..],
+            // contract
+            &["0", "MSFT", "STK", "", "", "", "", "SMART", "", "USD", "", "", "", ""],
+            order,
+            // sharesAllocation ... deltaNeutralAuxPrice
+            &[
+                "", "0", "", "", "", "", "", "", "", "0", "", "-1", "0", "", "", "0", "", "", "0", "0", "", "0", "",
+                "", "", "", "", "0", "", "", "", "",
+            ],
+            // scale
+            &["0", "", trail_stop_price, trailing_percent, "", "", ""],
+            // scaleTable ... hedgeType
+            &["", "", "", ""],
+            // clearing
+            &["0", "", "", "0", "0"],
+            // algoStrategy
+            &[""],
+            // algoId ... randomizePrice
+            &["", "0", "", "0", "0", "0"],
+            // no conditions
+            &["0"],
+            // adjusted
+            &["", "", "", "", "", "", "0"],
+            // extOperator ... usePriceMgmtAlgo
+            &["", "", "", "", "", "", "", "", "0", "0", "0", "1"],
+        ]
+        .concat();
+
+        fields.into_iter().map(String::from).collect()
+    }
+
+    /// Decode `fields` at `server_version` and check nothing is left over.
+    fn decode<S: AsRef<str>>(fields: &[S], server_version: u16) -> PlaceOrder {
+        let frame = Frame::Message(
+            fields
+                .iter()
+                .map(|field| Bytes::copy_from_slice(field.as_ref().as_bytes()))
+                .collect(),
+        );
+        let mut parse = Parse::new(frame).unwrap();
+
+        let place_order = PlaceOrder::parse_frames(&mut parse, server_version).unwrap();
+        assert!(matches!(parse.next_string(), Err(ParseError::EndOfStream)));
+        place_order
+    }
+
+    #[test]
+    fn decodes_a_market_order_at_version_100() {
+        let PlaceOrder {
+            order_id,
+            contract,
+            order,
+        } = decode(MARKET_V100, 100);
+
+        assert_eq!(order_id, 1);
+        assert_eq!(contract.con_id, 0);
+        assert_eq!((contract.symbol.as_str(), contract.sec_type.as_str()), ("AAPL", "STK"));
+        assert_eq!((contract.exchange.as_str(), contract.primary_exchange.as_str()), ("SMART", "ISLAND"));
+        assert_eq!(contract.strike, None);
+
+        assert_eq!((order.action.as_str(), order.total_quantity, order.order_type.as_str()), ("BUY", 100.0, "MKT"));
+        assert_eq!((order.lmt_price, order.aux_price), (None, None));
+        assert_eq!((order.account.as_str(), order.order_ref.as_str()), ("DU123456", "ref-1"));
+        assert!(order.transmit && !order.outside_rth);
+        assert_eq!(order.min_qty, None);
+        assert!(order.conditions.is_empty());
+
+        let request = order.to_request(&contract, "1-1").unwrap();
+        assert_eq!((request.side, request.order_type), (Side::Buy, OrderType::Market));
+        assert_eq!((request.quantity, request.time_in_force), (100.0, TimeInForce::Day));
+    }
+
+    #[test]
+    fn decodes_algo_params_and_conditions_at_version_145() {
+        let PlaceOrder {
+            order_id,
+            contract,
+            order,
+        } = decode(LIMIT_V145, 145);
+
+        assert_eq!((order_id, contract.con_id), (2, 265598));
+        assert_eq!(contract.trading_class, "NMS");
+        assert_eq!((order.order_type.as_str(), order.lmt_price), ("LMT", Some(150.25)));
+        assert_eq!(order.tif, "GTC");
+        assert!(order.outside_rth);
+
+        assert_eq!(order.algo_strategy, "Adaptive");
+        assert_eq!(
+            order.algo_params,
+            vec![TagValue {
+                tag: "adaptivePriority".to_string(),
+                value: "Normal".to_string(),
+            }]
+        );
+
+        assert_eq!(
+            order.conditions,
+            vec![
+                OrderCondition::Price {
+                    and: true,
+                    is_more: true,
+                    price: 155.0,
+                    con_id: 265598,
+                    exchange: "SMART".to_string(),
+                    trigger_method: 2,
+                },
+                OrderCondition::Time {
+                    and: false,
+                    is_more: false,
+                    time: "20240112 15:30:00 US/Eastern".to_string(),
+                },
+            ]
+        );
+        assert!(!order.conditions_ignore_rth && order.conditions_cancel_order);
+    }
+
+    #[test]
+    fn decodes_a_stop_limit_order_at_version_151() {
+        let action = [
+            "BUY", "10", "STP LMT", "99.5", "100.0", "DAY", "", "", "", "0", "", "1", "0", "0", "0", "0", "0", "0", "0",
+        ];
+        let PlaceOrder {
+            order_id,
+            contract,
+            order,
+        } = decode(&order_v151(&action, "", ""), 151);
+
+        assert_eq!(order_id, 3);
+        assert_eq!((order.lmt_price, order.aux_price), (Some(99.5), Some(100.0)));
+
+        let request = order.to_request(&contract, "").unwrap();
+        assert_eq!(request.order_type, OrderType::StopLimit);
+        assert_eq!((request.limit_price, request.stop_price), (Some(99.5), Some(100.0)));
+    }
+
+    #[test]
+    fn decodes_a_trailing_stop_at_version_151() {
+        let action = [
+            "SELL", "10", "TRAIL", "", "", "GTC", "", "", "", "0", "", "1", "0", "0", "0", "0", "0", "0", "0",
+        ];
+        let PlaceOrder { contract, order, .. } = decode(&order_v151(&action, "180.0", "1.5"), 151);
+
+        assert_eq!((order.trail_stop_price, order.trailing_percent), (Some(180.0), Some(1.5)));
+
+        let request = order.to_request(&contract, "").unwrap();
+        assert_eq!((request.side, request.order_type), (Side::Sell, OrderType::TrailingStop));
+        assert_eq!(request.trail, Some(Trail::Percent(1.5)));
+        assert_eq!(request.time_in_force, TimeInForce::GoodTillCancel);
+
+        // Without a percentage, the trailing amount is the aux price.
+        let amount = Order {
+            trailing_percent: None,
+            aux_price: Some(2.5),
+            ..order
+        };
+        assert_eq!(amount.to_request(&contract, "").unwrap().trail, Some(Trail::Price(2.5)));
+    }
+
+    #[test]
+    fn orders_the_broker_cannot_carry_out_are_refused_with_201() {
+        let contract = Contract {
+            symbol: "MSFT".to_string(),
+            sec_type: STOCK.to_string(),
+            ..Contract::default()
+        };
+        let order = Order {
+            action: "BUY".to_string(),
+            total_quantity: 10.0,
+            order_type: "MKT".to_string(),
+            ..Order::default()
+        };
+
+        let cases = [
+            (
+                Contract {
+                    sec_type: "OPT".to_string(),
+                    ..contract.clone()
+                },
+                order.clone(),
+                "Security type OPT is not supported",
+            ),
+            (
+                Contract {
+                    symbol: String::new(),
+                    ..contract.clone()
+                },
+                order.clone(),
+                "Missing symbol",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    total_quantity: 0.0,
+                    ..order.clone()
+                },
+                "Order quantity must be positive",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    what_if: true,
+                    ..order.clone()
+                },
+                "What-if orders are not supported",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    algo_strategy: "Vwap".to_string(),
+                    ..order.clone()
+                },
+                "Algo Vwap is not supported",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    conditions: vec![OrderCondition::Margin {
+                        and: true,
+                        is_more: false,
+                        percent: 20,
+                    }],
+                    ..order.clone()
+                },
+                "Conditional orders are not supported",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    action: "HOLD".to_string(),
+                    ..order.clone()
+                },
+                "Invalid action HOLD",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    tif: "GTD".to_string(),
+                    ..order.clone()
+                },
+                "Time in force GTD is not supported",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    order_type: "LMT".to_string(),
+                    ..order.clone()
+                },
+                "Missing limit price",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    order_type: "STP".to_string(),
+                    ..order.clone()
+                },
+                "Missing stop price",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    order_type: "TRAIL".to_string(),
+                    ..order.clone()
+                },
+                "Missing trailing amount",
+            ),
+            (
+                contract.clone(),
+                Order {
+                    order_type: "PEG MID".to_string(),
+                    ..order.clone()
+                },
+                "Order type PEG MID is not supported",
+            ),
+        ];
+
+        for (contract, order, reason) in cases {
+            assert_eq!(order.to_request(&contract, "").unwrap_err(), reason);
+
+            let Frame::Message(fields) = rejected(7, reason, 151) else {
+                panic!("errMsg is not a message");
+            };
+            let fields: Vec<_> = fields.iter().map(|field| String::from_utf8_lossy(field)).collect();
+
+            assert_eq!(fields[fields.len() - 3..], ["7", "201", &format!("Order rejected - reason:{}", reason)]);
+        }
+    }
+}
@@ -1,32 +1,134 @@
-// b"62\01\01\0All\0AccountType,NetLiquidation,TotalCashValue,SettledCash,AccruedCash,BuyingPower,EquityWithLoanValue,PreviousEquityWithLoanValue,GrossPositionValue,ReqTEquity,ReqTMargin,SMA,InitMarginReq,MaintMarginReq,AvailableFunds,ExcessLiquidity,Cushion,FullInitMarginReq,FullMaintMarginReq,FullAvailableFunds,FullExcessLiquidity,LookAheadNextChange,LookAheadInitMarginReq,LookAheadMaintMarginReq,LookAheadAvailableFunds,LookAheadExcessLiquidity,HighestSeverity,DayTradesRemaining,Leverage\0"
-// b"63\01\09001\0U12345678\0NetLiquidation\0196.39\0USD\0
-use crate::{Connection, Frame, Parse, API_VERSION};
+use crate::broker::{Account, Broker, Position};
+use crate::messages::{errors, Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::subscriptions::{Sink, Topic};
+use crate::{Connection, Db, Frame, Parse, Subscriptions};
 
-use bytes::Bytes;
-use tracing::{debug, info, instrument};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::{self, Duration};
+use tracing::{debug, instrument, warn};
 
-/// Get the value of key.
+/// How often the summary is recomputed. TWS sends changed values every three
+/// minutes.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(180);
+
+/// Maximum number of account summaries a client may stream at once, as in
+/// TWS.
+const MAX_SUBSCRIPTIONS: usize = 2;
+
+/// Tags that can be requested, besides the `$LEDGER` ones.
+pub(super) const SUMMARY_TAGS: &[&str] = &[
+    "AccountType",
+    "NetLiquidation",
+    "TotalCashValue",
+    "SettledCash",
+    "AccruedCash",
+    "BuyingPower",
+    "EquityWithLoanValue",
+    "PreviousEquityWithLoanValue",
+    "GrossPositionValue",
+    "ReqTEquity",
+    "ReqTMargin",
+    "SMA",
+    "InitMarginReq",
+    "MaintMarginReq",
+    "AvailableFunds",
+    "ExcessLiquidity",
+    "Cushion",
+    "FullInitMarginReq",
+    "FullMaintMarginReq",
+    "FullAvailableFunds",
+    "FullExcessLiquidity",
+    "LookAheadNextChange",
+    "LookAheadInitMarginReq",
+    "LookAheadMaintMarginReq",
+    "LookAheadAvailableFunds",
+    "LookAheadExcessLiquidity",
+    "HighestSeverity",
+    "DayTradesRemaining",
+    "Leverage",
+];
+
+/// Tags sent for every currency selected by a `$LEDGER` tag.
+pub(super) const LEDGER_TAGS: &[&str] = &[
+    "CashBalance",
+    "TotalCashBalance",
+    "AccruedCash",
+    "StockMarketValue",
+    "OptionMarketValue",
+    "FutureOptionValue",
+    "FuturesPNL",
+    "NetLiquidationByCurrency",
+    "UnrealizedPnL",
+    "RealizedPnL",
+    "ExchangeRate",
+    "FundValue",
+    "NetDividend",
+    "MutualFundValue",
+    "MoneyMarketFundValue",
+    "CorporateBondValue",
+    "TBondValue",
+    "TBillValue",
+    "WarrantValue",
+    "FxCashBalance",
+    "RealCurrency",
+    "IssuerOptionValue",
+];
+
+/// Currency code of ledger values expressed in the account's base currency.
+pub(super) const BASE: &str = "BASE";
+
+/// Request a summary of the accounts in a group (`reqAccountSummary`,
+/// message 62).
 ///
-/// If the key does not exist the special value nil is returned. An error is
-/// returned if the value stored at key is not a string, because GET only
-/// handles string values.
+/// The requested tags are sent for every account of the group, followed by
+/// `accountSummaryEnd`. From then on the summary is recomputed periodically
+/// and values that changed are sent again, until the client cancels it with
+/// `cancelAccountSummary` (63).
 #[derive(Debug)]
 pub struct ReqAccountSummary {
-    /// Name of the key to get
+    /// Message version
     version: String,
-    req_id: String,
+    req_id: i64,
+
+    /// `All` or the name of a group from the configuration.
     group: String,
-    tags: Vec<String>, // parse value and split using "," as a delimiter
+
+    /// Requested tags, as sent by the client.
+    tags: Vec<String>,
+}
+
+/// Which ledger currencies a `$LEDGER` tag selects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Ledger {
+    /// `$LEDGER`: values in the base currency only.
+    Base,
+
+    /// `$LEDGER:ALL`: the base currency and every currency held.
+    All,
+
+    /// `$LEDGER:<CCY>`: a single currency.
+    Currency(String),
+}
+
+/// One value of the summary.
+#[derive(Debug)]
+struct Entry {
+    account: String,
+    tag: &'static str,
+    value: String,
+    currency: String,
 }
 
-impl  ReqAccountSummary {
-    /// Create a new `Get` command which fetches `version` and `id`.
-    pub fn new(version: impl ToString, req_id: impl ToString, group: impl ToString, tags: impl ToString) -> ReqAccountSummary {
+impl ReqAccountSummary {
+    /// Create a new `ReqAccountSummary` command for the comma separated
+    /// `tags` of the accounts in `group`.
+    pub fn new(version: impl ToString, req_id: i64, group: impl ToString, tags: &str) -> ReqAccountSummary {
         ReqAccountSummary {
-            version: version.to_string(), // originally int
-            req_id: req_id.to_string(), // originally int
+            version: version.to_string(),
+            req_id,
             group: group.to_string(),
-            tags: tags.to_string().split(",").map(|t: &str| t.to_owned()).collect(),
+            tags: split_tags(tags),
         }
     }
 
@@ -35,8 +137,8 @@ impl  ReqAccountSummary {
         &self.version
     }
 
-    pub fn req_id(&self) -> &str {
-        &self.req_id
+    pub fn req_id(&self) -> i64 {
+        self.req_id
     }
 
     pub fn group(&self) -> &str {
@@ -47,120 +149,296 @@ impl  ReqAccountSummary {
         &self.tags
     }
 
-    /// Parse a `Get` instance from a received frame.
-    ///
-    /// The `Parse` argument provides a cursor-like API to read fields from the
-    /// `Frame`. At this point, the entire frame has already been received from
-    /// the socket.
-    ///
-    /// The `GET` string has already been consumed.
+    /// Parse a `ReqAccountSummary` instance from a received frame.
     ///
-    /// # Returns
-    ///
-    /// Returns the `Get` value on success. If the frame is malformed, `Err` is
-    /// returned.
+    /// The message id has already been consumed.
     ///
     /// # Format
     ///
-    /// Expects an array frame containing two entries.
-    ///
     /// ```text
-    /// GET key
+    /// 62 version reqId group tags
     /// ```
-    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ReqAccountSummary> {
-        // The `GET` string has already been consumed. The next value is the
-        // name of the key to get. If the next value is not a string or the
-        // input is fully consumed, then an error is returned.
-        let version = parse.next_string()?;
-        let req_id = parse.next_string()?;
-        let group = parse.next_string()?;
-        let tags = parse.next_string()?.split(",").map(|t: &str| t.to_owned()).collect();
+    ///
+    /// `tags` is a comma separated list.
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqAccountSummary> {
+        let fields = Fields::decode(IncomingMessage::ReqAccountSummary, server_version, parse)?;
+        let version = fields.string("version");
+        let req_id = fields.parse("reqId")?;
+        let group = fields.string("group");
+        let tags = split_tags(&fields.string("tags"));
 
         Ok(ReqAccountSummary { version, req_id, group, tags })
     }
 
-    /// Apply the `Get` command to the specified `Db` instance.
+    /// Apply the `ReqAccountSummary` command to the specified `Db` instance.
     ///
-    /// The response is written to `dst`. This is called by the server in order
-    /// to execute a received command.
-    #[instrument(skip(self, dst))]
-    pub(crate) async fn apply(self, dst: &mut Connection) -> crate::Result<()> {
-        // Here I need to call a function that can remove "v" from the key and then split
-        // the rest using ".." as a separator. If api version in the connector is between
-        // min and max valuesm then return <version>\0<date time>0\  ex: "176\x0020240209 22:23:12 EST\x00"
-        // Get the value from the shared database state
-        let mut response = Frame::array();
-        let account_type = self.get_tag_value(&self.req_id, "AccountType", "INDIVIDUAL", "");
-        let net_liquidation = self.get_tag_value(&self.req_id, "NetLiquidation", "196.35", "USD");
-        let total_cash_value = self.get_tag_value(&self.req_id, "TotalCashValue", "51.39", "USD");
-        let accrued_cache = self.get_tag_value(&self.req_id, "AccruedCash", "0.00", "USD");
-        let buying_power = self.get_tag_value(&self.req_id, "BuyingPower", "51.39", "USD");
-        let equity_with_loan_value = self.get_tag_value(&self.req_id, "EquityWithLoanValue", "196.35", "USD");
-        let gross_position_value = self.get_tag_value(&self.req_id, "GrossPositionValue", "144.96", "USD");
-        let sma = self.get_tag_value(&self.req_id, "SMA", "125.84", "USD");
-        let init_margin_req = self.get_tag_value(&self.req_id, "InitMarginReq", "145.01", "USD");
-        let maint_margin_req = self.get_tag_value(&self.req_id, "MaintMarginReq", "50.74", "USD");
-        let available_funds = self.get_tag_value(&self.req_id, "AvailableFunds", "51.39", "USD");
-        let excess_liquidity = self.get_tag_value(&self.req_id, "ExcessLiquidity", "145.61", "USD");
-        let cushion = self.get_tag_value(&self.req_id, "Cushion", "0.741604", "");
-        let full_init_margin_req = self.get_tag_value(&self.req_id, "FullInitMarginReq", "145.01", "USD");
-        let full_maint_margin_req = self.get_tag_value(&self.req_id, "FullMaintMarginReq", "50.75", "USD");
-        let full_available_funds = self.get_tag_value(&self.req_id, "FullAvailableFunds", "51.39", "USD");
-        let full_excess_liquidity = self.get_tag_value(&self.req_id, "FullExcessLiquidity", "145.61", "USD");
-        let look_ahead_next_change = self.get_tag_value(&self.req_id, "LookAheadNextChange", "1724702400", "");
-        let look_ahead_init_margin_req = self.get_tag_value(&self.req_id, "LookAheadInitMarginReq", "144.96", "USD");
-        let look_ahead_maint_margin_req = self.get_tag_value(&self.req_id, "LookAheadMaintMarginReq", "50.74", "USD");
-        let look_ahead_available_funds = self.get_tag_value(&self.req_id, "LookAheadAvailableFunds", "51.39", "USD");
-        let look_ahead_excess_liquidity = self.get_tag_value(&self.req_id, "LookAheadExcessLiquidity", "145.61", "USD");
-        let day_trades_remaining = self.get_tag_value(&self.req_id, "DayTradesRemaining", "3", "");
-        let leverage = self.get_tag_value(&self.req_id, "Leverage", "0.74", "");
-        let end_summary: Bytes = Bytes::copy_from_slice(&format!("64\01\0{}\0", &self.req_id).into_bytes());
-
-        response.push_bulk(account_type); // for each tag we need to send a separate message
-        response.push_bulk(net_liquidation);
-        response.push_bulk(total_cash_value);
-        response.push_bulk(accrued_cache);
-        response.push_bulk(buying_power);
-        response.push_bulk(equity_with_loan_value);
-        response.push_bulk(gross_position_value);
-        response.push_bulk(sma);
-        response.push_bulk(init_margin_req);
-        response.push_bulk(maint_margin_req);
-        response.push_bulk(available_funds);
-        response.push_bulk(excess_liquidity);
-        response.push_bulk(cushion);
-        response.push_bulk(full_init_margin_req);
-        response.push_bulk(full_maint_margin_req);
-        response.push_bulk(full_available_funds);
-        response.push_bulk(full_excess_liquidity);
-        response.push_bulk(look_ahead_next_change);
-        response.push_bulk(look_ahead_init_margin_req);
-        response.push_bulk(look_ahead_maint_margin_req);
-        response.push_bulk(look_ahead_available_funds);
-        response.push_bulk(look_ahead_excess_liquidity);
-        response.push_bulk(day_trades_remaining);
-        response.push_bulk(leverage);
-        response.push_bulk(end_summary); // we need to send one more message with account summary end  like b"64\01\09001\0"
-
-        debug!(?response);
-
-        // Write the response back to the client
-        dst.write_frame(&response).await?;
+    /// The summary is streamed by a task registered in `subscriptions`. Errors
+    /// with the request itself are written to `dst` right away.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+
+        let rejection = if subscriptions.contains(Topic::AccountSummary, self.req_id) {
+            Some(errors::PROCESSING_FAILED.to_frame_with(
+                self.req_id,
+                server_version,
+                "Error processing request: Duplicate ticker id.",
+            ))
+        } else if subscriptions.count(Topic::AccountSummary) >= MAX_SUBSCRIPTIONS {
+            Some(errors::PROCESSING_FAILED.to_frame_with(
+                self.req_id,
+                server_version,
+                "Error processing request: Maximum number of account summary requests exceeded.",
+            ))
+        } else if db.config().group(&self.group).is_none() {
+            Some(errors::VALIDATION_FAILED.to_frame_with(
+                self.req_id,
+                server_version,
+                &format!("Error validating request: Unknown group {}.", self.group),
+            ))
+        } else {
+            None
+        };
+
+        if let Some(response) = rejection {
+            warn!(req_id = self.req_id, group = %self.group, "account summary rejected");
+            debug!(?response);
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        let accounts: Vec<_> = db
+            .config()
+            .group(&self.group)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|account| Some((account.id.clone(), db.broker(&account.id)?)))
+            .collect();
+
+        subscriptions.spawn(Topic::AccountSummary, self.req_id, move |sink| {
+            self.stream(accounts, sink, server_version)
+        });
 
         Ok(())
     }
 
-    /// New function that returns the account summary with all requested tags and values
-    pub(crate) fn get_tag_value(&self, req_id: &str, tag: &str, value: &str, currency: &str) -> Bytes {
-        info!("Inside get_account_summary");
+    /// Send the summary of `accounts` to `sink`, then keep sending the values
+    /// that change.
+    async fn stream(self, accounts: Vec<(String, Arc<dyn Broker>)>, sink: Sink, server_version: u16) {
+        let (tags, ledger) = self.selection();
+
+        // The last value sent for each account, tag and currency.
+        let mut sent = HashMap::new();
+        let mut interval = time::interval(UPDATE_INTERVAL);
+        let mut first = true;
+
+        loop {
+            interval.tick().await;
+
+            for (account_id, broker) in &accounts {
+                let entries = match summarize(account_id, broker.as_ref(), &tags, ledger.as_ref()).await {
+                    Ok(entries) => entries,
+                    Err(err) => {
+                        warn!(account = %account_id, cause = %err, "failed to get account state");
+                        continue;
+                    }
+                };
+
+                for entry in entries {
+                    let key = (entry.account.clone(), entry.tag, entry.currency.clone());
+
+                    if sent.get(&key) == Some(&entry.value) {
+                        continue;
+                    }
+
+                    let response = self.get_tag_value(&entry, server_version);
+                    sent.insert(key, entry.value);
+
+                    debug!(?response);
+
+                    if sink.send(response).await.is_err() {
+                        // The connection is closed.
+                        return;
+                    }
+                }
+            }
+
+            if first {
+                first = false;
+
+                // Each tag is sent as a separate accountSummary message,
+                // followed by one accountSummaryEnd message like b"64\01\09001\0"
+                let end_summary = Encoder::new(OutgoingMessage::AccountSummaryEnd, server_version)
+                    .put("reqId", self.req_id)
+                    .into_frame();
+
+                debug!(?end_summary);
+
+                if sink.send(end_summary).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Split the requested tags into the summary tags, in request order, and
+    /// the ledger currencies. Unknown tags are ignored, as by TWS.
+    fn selection(&self) -> (Vec<&'static str>, Option<Ledger>) {
+        let mut tags = Vec::new();
+        let mut ledger = None;
+
+        for tag in &self.tags {
+            if tag == "$LEDGER" {
+                ledger = Some(Ledger::Base);
+            } else if tag == "$LEDGER:ALL" {
+                ledger = Some(Ledger::All);
+            } else if let Some(currency) = tag.strip_prefix("$LEDGER:") {
+                ledger = Some(Ledger::Currency(currency.to_uppercase()));
+            } else if let Some(tag) = SUMMARY_TAGS.iter().find(|known| *known == tag) {
+                if !tags.contains(tag) {
+                    tags.push(*tag);
+                }
+            } else {
+                debug!(%tag, "ignoring unknown account summary tag");
+            }
+        }
+
+        (tags, ledger)
+    }
+
+    /// New function that returns the account summary message for one tag
+    fn get_tag_value(&self, entry: &Entry, server_version: u16) -> Frame {
+        // b"63\01\09001\0U12345678\0NetLiquidation\0196.39\0USD\0
+        Encoder::new(OutgoingMessage::AccountSummary, server_version)
+            .put("reqId", self.req_id)
+            .put("account", &entry.account)
+            .put("tag", entry.tag)
+            .put("value", &entry.value)
+            .put("currency", &entry.currency)
+            .into_frame()
+    }
+}
+
+/// Split a comma separated tag list, dropping blanks.
+fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Compute the selected values of one account from its broker's state.
+async fn summarize(
+    account_id: &str,
+    broker: &dyn Broker,
+    tags: &[&'static str],
+    ledger: Option<&Ledger>,
+) -> crate::Result<Vec<Entry>> {
+    let account = broker.account().await?;
+    let positions = if ledger.is_some() {
+        broker.positions().await?
+    } else {
+        Vec::new()
+    };
+
+    let mut entries = Vec::new();
 
-        info!("req_id is: {}", req_id);
-        //info!("tags are: {:?}", &self.tags);
+    for tag in tags {
+        let (value, monetary) = summary_value(tag, &account);
 
+        entries.push(Entry {
+            account: account_id.to_string(),
+            tag,
+            value,
+            currency: if monetary { account.currency.clone() } else { String::new() },
+        });
+    }
 
-        let account_id = "U12345678";
+    // The connector's brokers hold a single currency, so the ledger of the
+    // base currency and of the account currency are the same.
+    let currencies: Vec<&str> = match ledger {
+        None => vec![],
+        Some(Ledger::Base) => vec![BASE],
+        Some(Ledger::All) => vec![BASE, &account.currency],
+        Some(Ledger::Currency(currency)) if currency == BASE => vec![BASE],
+        Some(Ledger::Currency(currency)) if *currency == account.currency => vec![&account.currency],
+        Some(Ledger::Currency(_)) => vec![],
+    };
 
-        let value = format!("63\01\0{}\0{}\0{}\0{}\0{}\0", req_id, account_id, tag, value, currency); // b"63\01\09001\0U12345678\0NetLiquidation\0196.39\0USD\0
-        Bytes::copy_from_slice(&value.into_bytes())
+    for currency in currencies {
+        for tag in LEDGER_TAGS {
+            entries.push(Entry {
+                account: account_id.to_string(),
+                tag,
+                value: ledger_value(tag, &account, &positions),
+                currency: currency.to_string(),
+            });
+        }
     }
+
+    Ok(entries)
+}
+
+/// Returns the value of a summary tag and whether it is an amount in the
+/// base currency.
+pub(super) fn summary_value(tag: &str, account: &Account) -> (String, bool) {
+    let gross_position_value = account.long_market_value - account.short_market_value;
+    let available_funds = account.equity - account.initial_margin;
+    let excess_liquidity = account.equity - account.maintenance_margin;
+
+    let ratio = |value: f64| {
+        if account.equity > 0.0 {
+            value / account.equity
+        } else {
+            0.0
+        }
+    };
+
+    let amount = match tag {
+        "AccountType" => return ("INDIVIDUAL".to_string(), false),
+        "Cushion" => return (format!("{:.6}", ratio(excess_liquidity)), false),
+        "Leverage" => return (format!("{:.2}", ratio(gross_position_value)), false),
+        "LookAheadNextChange" | "HighestSeverity" => return ("0".to_string(), false),
+        "DayTradesRemaining" => {
+            let remaining = account.day_trades_remaining.map_or(-1, i64::from);
+            return (remaining.to_string(), false);
+        }
+        "NetLiquidation" | "EquityWithLoanValue" | "ReqTEquity" => account.equity,
+        "TotalCashValue" | "SettledCash" => account.cash,
+        "AccruedCash" => 0.0,
+        "BuyingPower" => account.buying_power,
+        "PreviousEquityWithLoanValue" => account.last_equity,
+        "GrossPositionValue" => gross_position_value,
+        "SMA" => account.sma,
+        "ReqTMargin" | "InitMarginReq" | "FullInitMarginReq" | "LookAheadInitMarginReq" => {
+            account.initial_margin
+        }
+        "MaintMarginReq" | "FullMaintMarginReq" | "LookAheadMaintMarginReq" => account.maintenance_margin,
+        "AvailableFunds" | "FullAvailableFunds" | "LookAheadAvailableFunds" => available_funds,
+        "ExcessLiquidity" | "FullExcessLiquidity" | "LookAheadExcessLiquidity" => excess_liquidity,
+        _ => unreachable!("unknown summary tag {}", tag),
+    };
+
+    (format!("{:.2}", amount), true)
+}
+
+/// Returns the value of a ledger tag.
+pub(super) fn ledger_value(tag: &str, account: &Account, positions: &[Position]) -> String {
+    let amount = match tag {
+        "RealCurrency" => return account.currency.clone(),
+        "CashBalance" | "TotalCashBalance" => account.cash,
+        "StockMarketValue" => account.long_market_value + account.short_market_value,
+        "NetLiquidationByCurrency" => account.equity,
+        "UnrealizedPnL" => positions.iter().map(|position| position.unrealized_pnl).sum(),
+        "ExchangeRate" => 1.0,
+        _ => 0.0,
+    };
+
+    format!("{:.2}", amount)
 }
@@ -0,0 +1,391 @@
+use crate::broker::{Account, Broker, Position};
+use crate::cmd::req_account_summary::{ledger_value, summary_value, BASE, LEDGER_TAGS, SUMMARY_TAGS};
+use crate::market_data::PriceWatch;
+use crate::messages::{errors, Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::orders::OrderEvent;
+use crate::pnl::day_start;
+use crate::positions::Holding;
+use crate::subscriptions::{Sink, Topic};
+use crate::{Connection, Db, Frame, Parse, Subscriptions};
+
+use chrono::{Local, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{self, Duration};
+use tracing::{debug, instrument, warn};
+
+/// How often the account values are recomputed. TWS sends changed values
+/// every three minutes.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(180);
+
+/// How often portfolio entries whose market price moved are sent again.
+const PORTFOLIO_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Subscribe to the values and portfolio of an account (`reqAccountUpdates`,
+/// message 6).
+///
+/// The account values are sent as `updateAccountValue`, each position as
+/// `updatePortfolio`, then `updateAccountTime` and `accountDownloadEnd`. From
+/// then on, changed values are sent every three minutes and positions as
+/// fills change them or the market moves. Market prices come from the market
+/// data feed, or from the broker without one.
+///
+/// A client follows one account at a time: subscribing again replaces the
+/// stream, `subscribe` unset stops it.
+#[derive(Debug)]
+pub struct ReqAccountUpdates {
+    /// Message version
+    version: String,
+    subscribe: bool,
+
+    /// Account code, may be empty when a single account is configured.
+    account: String,
+}
+
+/// A position valued at the market price.
+struct Valuation {
+    price: f64,
+    market_value: f64,
+    unrealized_pnl: f64,
+}
+
+impl ReqAccountUpdates {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn subscribe(&self) -> bool {
+        self.subscribe
+    }
+
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    /// Parse a `ReqAccountUpdates` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 6 version subscribe acctCode
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqAccountUpdates> {
+        let fields = Fields::decode(IncomingMessage::ReqAccountUpdates, server_version, parse)?;
+        let version = fields.string("version");
+        let subscribe = fields.flag("subscribe")?;
+        let account = fields.string("acctCode");
+
+        Ok(ReqAccountUpdates {
+            version,
+            subscribe,
+            account,
+        })
+    }
+
+    /// Apply the `ReqAccountUpdates` command to the specified `Db` instance.
+    ///
+    /// The account is streamed by a task registered in `subscriptions`. An
+    /// unknown account is answered with error 321 right away.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+
+        if !self.subscribe {
+            if !subscriptions.cancel(Topic::AccountUpdates, 0) {
+                debug!("no account updates to cancel");
+            }
+            return Ok(());
+        }
+
+        let accounts = &db.config().accounts;
+        let account = match (self.account.trim(), accounts.as_slice()) {
+            ("", [only]) => only.id.clone(),
+            (account, _) => account.to_string(),
+        };
+
+        let Some(broker) = db.broker(&account) else {
+            let response = errors::VALIDATION_FAILED.to_frame_with(
+                -1,
+                server_version,
+                &format!("Error validating request: Unknown account {}.", account),
+            );
+            warn!(%account, "account updates rejected");
+            debug!(?response);
+
+            dst.write_frame(&response).await?;
+            return Ok(());
+        };
+
+        // Subscribe before the positions are read, so no fill in between is
+        // missed.
+        let events = db.order_events();
+        let db = db.clone();
+
+        subscriptions.spawn(Topic::AccountUpdates, 0, move |sink| {
+            stream(db, account, broker, events, sink, server_version)
+        });
+
+        Ok(())
+    }
+}
+
+/// Send the values and portfolio of `account` to `sink`, then keep sending
+/// the ones that change.
+async fn stream(
+    db: Db,
+    account: String,
+    broker: Arc<dyn Broker>,
+    mut events: broadcast::Receiver<OrderEvent>,
+    sink: Sink,
+    server_version: u16,
+) {
+    let mut prices = PriceWatch::new(db.market_data());
+    let mut portfolio = BTreeMap::new();
+
+    match db.positions(&account).await {
+        Ok(holdings) => {
+            for holding in holdings {
+                prices.watch(&holding.contract.symbol).await;
+                portfolio.insert(holding.contract.symbol.clone(), holding);
+            }
+        }
+        Err(err) => warn!(%account, cause = %err, "failed to get positions"),
+    }
+
+    // The last value sent for each key and currency.
+    let mut sent = HashMap::new();
+
+    // Positions whose market price changed since they were last sent.
+    let mut moved = BTreeSet::new();
+
+    let mut update = time::interval(UPDATE_INTERVAL);
+    let mut flush = time::interval(PORTFOLIO_INTERVAL);
+    let mut downloaded = false;
+
+    loop {
+        let mut responses = Vec::new();
+
+        tokio::select! {
+            biased;
+
+            _ = update.tick() => {
+                match state(broker.as_ref()).await {
+                    Ok((state, positions)) => {
+                        // Without a feed, the broker's prices are the best
+                        // there is.
+                        for position in &positions {
+                            prices.seed(&position.symbol, position.market_price);
+                        }
+
+                        let realized_pnl = realized_pnl(&db, &account, None);
+                        let values = account_values(&account, &state, &portfolio, &prices, realized_pnl);
+
+                        for (key, value, currency) in values {
+                            if sent.get(&(key, currency.clone())) == Some(&value) {
+                                continue;
+                            }
+
+                            responses.push(account_value_frame(key, &value, &currency, &account, server_version));
+                            sent.insert((key, currency), value);
+                        }
+                    }
+                    Err(err) => warn!(%account, cause = %err, "failed to get account state"),
+                }
+
+                if !downloaded {
+                    moved.clear();
+                    responses.extend(
+                        portfolio
+                            .values()
+                            .map(|holding| portfolio_frame(&db, holding, &prices, server_version)),
+                    );
+                }
+
+                responses.push(
+                    Encoder::new(OutgoingMessage::AcctUpdateTime, server_version)
+                        .put("timeStamp", Local::now().format("%H:%M"))
+                        .into_frame(),
+                );
+
+                if !downloaded {
+                    downloaded = true;
+                    responses.push(
+                        Encoder::new(OutgoingMessage::AcctDownloadEnd, server_version)
+                            .put("account", &account)
+                            .into_frame(),
+                    );
+                }
+            }
+            event = events.recv() => match event {
+                Ok(OrderEvent::Position(holding)) if holding.account == account => {
+                    let symbol = holding.contract.symbol.clone();
+
+                    if holding.quantity == 0.0 {
+                        portfolio.remove(&symbol);
+                        moved.remove(&symbol);
+                        responses.push(portfolio_frame(&db, &holding, &prices, server_version));
+                        prices.unwatch(&symbol);
+                    } else {
+                        prices.watch(&symbol).await;
+                        responses.push(portfolio_frame(&db, &holding, &prices, server_version));
+                        portfolio.insert(symbol, *holding);
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => warn!(missed, "position changes were dropped"),
+                Err(RecvError::Closed) => return,
+            },
+            _ = flush.tick() => {
+                for symbol in std::mem::take(&mut moved) {
+                    if let Some(holding) = portfolio.get(&symbol) {
+                        responses.push(portfolio_frame(&db, holding, &prices, server_version));
+                    }
+                }
+            }
+            symbol = prices.changed() => {
+                if portfolio.contains_key(&symbol) {
+                    moved.insert(symbol);
+                }
+            }
+        }
+
+        for response in responses {
+            debug!(?response);
+
+            if sink.send(response).await.is_err() {
+                // The connection is closed.
+                return;
+            }
+        }
+    }
+}
+
+/// Returns the state of the account and its positions as the broker reports
+/// them.
+async fn state(broker: &dyn Broker) -> crate::Result<(Account, Vec<Position>)> {
+    let account = broker.account().await?;
+    let positions = broker.positions().await?;
+    Ok((account, positions))
+}
+
+/// Returns the key, value and currency of every account value, with the
+/// positions valued at the market prices in `prices` and `realized_pnl`
+/// realized during the trading day.
+fn account_values(
+    account_id: &str,
+    account: &Account,
+    portfolio: &BTreeMap<String, Holding>,
+    prices: &PriceWatch,
+    realized_pnl: f64,
+) -> Vec<(&'static str, String, String)> {
+    let currency = &account.currency;
+
+    let mut values = vec![
+        ("AccountCode", account_id.to_string(), String::new()),
+        ("AccountReady", "true".to_string(), String::new()),
+        ("Currency", currency.clone(), currency.clone()),
+    ];
+
+    for tag in SUMMARY_TAGS {
+        let (value, monetary) = summary_value(tag, account);
+        values.push((tag, value, if monetary { currency.clone() } else { String::new() }));
+    }
+
+    let valuations: Vec<_> = portfolio.values().map(|holding| valuation(holding, prices)).collect();
+    let market_value: f64 = valuations.iter().map(|valuation| valuation.market_value).sum();
+    let unrealized_pnl: f64 = valuations.iter().map(|valuation| valuation.unrealized_pnl).sum();
+
+    // The connector's brokers hold a single currency, so the ledger of the
+    // base currency and of the account currency are the same.
+    for ledger in [BASE, currency.as_str()] {
+        for tag in LEDGER_TAGS {
+            let value = match *tag {
+                "StockMarketValue" => format!("{:.2}", market_value),
+                "UnrealizedPnL" => format!("{:.2}", unrealized_pnl),
+                "RealizedPnL" => format!("{:.2}", realized_pnl),
+                _ => ledger_value(tag, account, &[]),
+            };
+            values.push((tag, value, ledger.to_string()));
+        }
+    }
+
+    values
+}
+
+/// Value `holding` at its last known market price, or at its cost if there
+/// is none.
+fn valuation(holding: &Holding, prices: &PriceWatch) -> Valuation {
+    let price = prices.price(&holding.contract.symbol).unwrap_or(holding.avg_cost);
+
+    Valuation {
+        price,
+        market_value: holding.quantity * price,
+        unrealized_pnl: (price - holding.avg_cost) * holding.quantity,
+    }
+}
+
+/// Returns the P&L the fills of `account` realized since the start of the
+/// trading day, in `symbol` only if one is given.
+fn realized_pnl(db: &Db, account: &str, symbol: Option<&str>) -> f64 {
+    let start = day_start(db.config().time_zone(), Utc::now());
+
+    db.executions(|execution| {
+        execution.account == account
+            && execution.time >= start
+            && symbol.is_none_or(|symbol| execution.symbol == symbol)
+    })
+    .iter()
+    .filter_map(|execution| execution.realized_pnl)
+    .fold(0.0, |total, pnl| total + pnl)
+}
+
+/// Encode the `updateAccountValue` message of one value.
+fn account_value_frame(key: &str, value: &str, currency: &str, account: &str, server_version: u16) -> Frame {
+    Encoder::new(OutgoingMessage::AcctValue, server_version)
+        .put("key", key)
+        .put("value", value)
+        .put("currency", currency)
+        .put("accountName", account)
+        .into_frame()
+}
+
+/// Encode the `updatePortfolio` message of `holding`, with the P&L its fills
+/// realized during the trading day.
+fn portfolio_frame(db: &Db, holding: &Holding, prices: &PriceWatch, server_version: u16) -> Frame {
+    let contract = &holding.contract;
+    let valuation = valuation(holding, prices);
+    let realized_pnl = realized_pnl(db, &holding.account, Some(&contract.symbol));
+
+    Encoder::new(OutgoingMessage::PortfolioValue, server_version)
+        .put("conId", contract.con_id)
+        .put("symbol", &contract.symbol)
+        .put("secType", &contract.sec_type)
+        .put(
+            "lastTradeDateOrContractMonth",
+            &contract.last_trade_date_or_contract_month,
+        )
+        .put("strike", contract.strike.unwrap_or_default())
+        .put("right", &contract.right)
+        .put("multiplier", &contract.multiplier)
+        .put("primaryExchange", &contract.exchange)
+        .put("currency", &contract.currency)
+        .put("localSymbol", &contract.local_symbol)
+        .put("tradingClass", &contract.trading_class)
+        .put("position", holding.quantity)
+        .put("marketPrice", valuation.price)
+        .put("marketValue", valuation.market_value)
+        .put("averageCost", holding.avg_cost)
+        .put("unrealizedPNL", valuation.unrealized_pnl)
+        .put("realizedPNL", realized_pnl)
+        .put("accountName", &holding.account)
+        .into_frame()
+}
@@ -0,0 +1,50 @@
+use crate::messages::{Fields, IncomingMessage};
+use crate::orders;
+use crate::{Connection, Db, Parse};
+
+use super::req_open_orders::write_open_orders;
+
+use tracing::instrument;
+
+/// Request the open orders of all clients (`reqAllOpenOrders`, message 16).
+///
+/// This includes the orders placed at the brokers without going through the
+/// connector, with order id 0.
+#[derive(Debug)]
+pub struct ReqAllOpenOrders {
+    /// Message version
+    version: String,
+}
+
+impl ReqAllOpenOrders {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Parse a `ReqAllOpenOrders` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 16 version
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqAllOpenOrders> {
+        let fields = Fields::decode(IncomingMessage::ReqAllOpenOrders, server_version, parse)?;
+        let version = fields.string("version");
+
+        Ok(ReqAllOpenOrders { version })
+    }
+
+    /// Apply the `ReqAllOpenOrders` command to the specified `Db` instance.
+    ///
+    /// The response is written to `dst`. This is called by the server in order
+    /// to execute a received command.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let orders = orders::working(db, |_| true, true).await;
+        write_open_orders(dst, &orders).await
+    }
+}
@@ -0,0 +1,91 @@
+use crate::messages::{errors, Fields, IncomingMessage};
+use crate::orders;
+use crate::subscriptions::Topic;
+use crate::{Connection, Db, Parse, Subscriptions};
+
+use super::req_open_orders::write_open_orders;
+
+use tracing::{debug, instrument};
+
+/// Follow the orders placed without going through the connector
+/// (`reqAutoOpenOrders`, message 15).
+///
+/// Only client 0 may send it. With `autoBind` set, the changes of those
+/// orders are streamed to the client, with order id 0, until a request with
+/// `autoBind` unset. Either way the client gets its open orders, as with
+/// `reqOpenOrders`.
+#[derive(Debug)]
+pub struct ReqAutoOpenOrders {
+    /// Message version
+    version: String,
+    auto_bind: bool,
+}
+
+impl ReqAutoOpenOrders {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn auto_bind(&self) -> bool {
+        self.auto_bind
+    }
+
+    /// Parse a `ReqAutoOpenOrders` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 15 version autoBind
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqAutoOpenOrders> {
+        let fields = Fields::decode(IncomingMessage::ReqAutoOpenOrders, server_version, parse)?;
+        let version = fields.string("version");
+        let auto_bind = fields.flag("autoBind")?;
+
+        Ok(ReqAutoOpenOrders { version, auto_bind })
+    }
+
+    /// Apply the `ReqAutoOpenOrders` command to the specified `Db` instance.
+    ///
+    /// The response is written to `dst`. This is called by the server in order
+    /// to execute a received command. The stream of changes is registered in
+    /// `subscriptions`.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+        let client_id = dst.client_id().ok_or("reqAutoOpenOrders before startApi")?;
+
+        if client_id != 0 {
+            let response = errors::VALIDATION_FAILED.to_frame_with(
+                -1,
+                server_version,
+                "Error validating request: reqAutoOpenOrders is only allowed for client 0.",
+            );
+            debug!(?response);
+
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        if self.auto_bind {
+            // Subscribe before listing, so no change is missed in between.
+            let events = db.order_events();
+            subscriptions.spawn(Topic::AutoOpenOrders, 0, move |sink| {
+                orders::forward_foreign(events, sink, server_version)
+            });
+        } else {
+            subscriptions.cancel(Topic::AutoOpenOrders, 0);
+        }
+
+        let orders = orders::working(db, |order| order.client_id == client_id, true).await;
+        write_open_orders(dst, &orders).await
+    }
+}
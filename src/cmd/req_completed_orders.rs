@@ -0,0 +1,59 @@
+use crate::messages::{Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::{Connection, Db, Parse};
+
+use tracing::{debug, instrument};
+
+/// Request the orders that filled or were cancelled today
+/// (`reqCompletedOrders`, message 99).
+///
+/// Each order is answered with `completedOrder`, followed by
+/// `completedOrdersEnd`. With `apiOnly` set, the orders placed at the brokers
+/// without going through the connector are left out.
+#[derive(Debug)]
+pub struct ReqCompletedOrders {
+    api_only: bool,
+}
+
+impl ReqCompletedOrders {
+    pub fn api_only(&self) -> bool {
+        self.api_only
+    }
+
+    /// Parse a `ReqCompletedOrders` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 99 apiOnly
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqCompletedOrders> {
+        let fields = Fields::decode(IncomingMessage::ReqCompletedOrders, server_version, parse)?;
+        let api_only = fields.flag("apiOnly")?;
+
+        Ok(ReqCompletedOrders { api_only })
+    }
+
+    /// Apply the `ReqCompletedOrders` command to the specified `Db` instance.
+    ///
+    /// The response is written to `dst`. This is called by the server in order
+    /// to execute a received command.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let server_version = dst.server_version();
+
+        for order in db.completed_orders(self.api_only) {
+            let response = order.completed_order_frame(server_version);
+            debug!(?response);
+
+            dst.write_frame(&response).await?;
+        }
+
+        let response = Encoder::new(OutgoingMessage::CompletedOrdersEnd, server_version).into_frame();
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
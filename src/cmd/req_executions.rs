@@ -0,0 +1,178 @@
+use crate::executions::Execution;
+use crate::messages::{errors, Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::{Connection, Db, Parse};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use tracing::{debug, instrument};
+
+/// Request the executions matching a filter (`reqExecutions`, message 7).
+///
+/// Each execution is answered with `execDetails` and `commissionReport`,
+/// followed by `execDetailsEnd`. The executions of the last seven days are
+/// kept, including across restarts.
+#[derive(Debug)]
+pub struct ReqExecutions {
+    /// Message version
+    version: String,
+    req_id: i64,
+    filter: ExecutionFilter,
+}
+
+/// The `ExecutionFilter` of the request. Empty fields match everything.
+#[derive(Debug)]
+struct ExecutionFilter {
+    /// Client that placed the order, 0 for any.
+    client_id: i32,
+    account: String,
+
+    /// Only executions from this time on, as `yyyymmdd-hh:mm:ss` in UTC or
+    /// `yyyymmdd hh:mm:ss` with an optional time zone, local time otherwise.
+    time: String,
+    symbol: String,
+    sec_type: String,
+    exchange: String,
+
+    /// `BUY` or `SELL`.
+    side: String,
+}
+
+impl ReqExecutions {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    /// Parse a `ReqExecutions` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 7 version reqId clientId acctCode time symbol secType exchange side
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqExecutions> {
+        let fields = Fields::decode(IncomingMessage::ReqExecutions, server_version, parse)?;
+        let version = fields.string("version");
+        let req_id = fields.parse("reqId")?;
+
+        let filter = ExecutionFilter {
+            client_id: fields.parse("clientId").unwrap_or_default(),
+            account: fields.string("acctCode"),
+            time: fields.string("time"),
+            symbol: fields.string("symbol"),
+            sec_type: fields.string("secType"),
+            exchange: fields.string("exchange"),
+            side: fields.string("side"),
+        };
+
+        Ok(ReqExecutions {
+            version,
+            req_id,
+            filter,
+        })
+    }
+
+    /// Apply the `ReqExecutions` command to the specified `Db` instance.
+    ///
+    /// The response is written to `dst`. This is called by the server in order
+    /// to execute a received command. A time the filter cannot be read with is
+    /// answered with error 321.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let server_version = dst.server_version();
+
+        let Some(since) = self.filter.since() else {
+            let response = errors::VALIDATION_FAILED.to_frame_with(
+                self.req_id,
+                server_version,
+                &format!(
+                    "Error validating request: Invalid time {} in filter.",
+                    self.filter.time
+                ),
+            );
+            debug!(?response);
+
+            dst.write_frame(&response).await?;
+            return Ok(());
+        };
+
+        let executions = db.executions(|execution| self.filter.matches(execution, since));
+
+        for execution in &executions {
+            for response in [
+                execution.exec_details_frame(self.req_id, server_version),
+                execution.commission_report_frame(server_version),
+            ] {
+                debug!(?response);
+                dst.write_frame(&response).await?;
+            }
+        }
+
+        let response = Encoder::new(OutgoingMessage::ExecutionDataEnd, server_version)
+            .put("reqId", self.req_id)
+            .into_frame();
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
+
+impl ExecutionFilter {
+    /// Returns the time executions must not be older than, the epoch if the
+    /// filter has none, or `None` if the time is invalid.
+    fn since(&self) -> Option<DateTime<Utc>> {
+        let time = self.time.trim();
+
+        if time.is_empty() {
+            return Some(DateTime::<Utc>::default());
+        }
+
+        if let Ok(time) = NaiveDateTime::parse_from_str(time, "%Y%m%d-%H:%M:%S") {
+            return Some(time.and_utc());
+        }
+
+        // Older clients leave two spaces between the date and the time.
+        let mut parts = time.split_whitespace();
+        let date_time = format!("{} {}", parts.next()?, parts.next()?);
+        let date_time = NaiveDateTime::parse_from_str(&date_time, "%Y%m%d %H:%M:%S").ok()?;
+
+        let local = match parts.next() {
+            Some(zone) => zone
+                .parse::<Tz>()
+                .ok()?
+                .from_local_datetime(&date_time)
+                .earliest()?
+                .with_timezone(&Utc),
+            None => Local
+                .from_local_datetime(&date_time)
+                .earliest()?
+                .with_timezone(&Utc),
+        };
+
+        Some(local)
+    }
+
+    /// Returns `true` if `execution` passes the filter, whose time was read
+    /// as `since`.
+    fn matches(&self, execution: &Execution, since: DateTime<Utc>) -> bool {
+        let side = match execution.side.as_str() {
+            "BOT" => "BUY",
+            _ => "SELL",
+        };
+
+        (self.client_id == 0 || execution.client_id == self.client_id)
+            && (self.account.is_empty() || execution.account == self.account)
+            && execution.time >= since
+            && (self.symbol.is_empty() || execution.symbol.eq_ignore_ascii_case(&self.symbol))
+            && (self.sec_type.is_empty() || execution.sec_type.eq_ignore_ascii_case(&self.sec_type))
+            && (self.exchange.is_empty() || execution.exchange.eq_ignore_ascii_case(&self.exchange))
+            && (self.side.is_empty() || side.eq_ignore_ascii_case(&self.side))
+    }
+}
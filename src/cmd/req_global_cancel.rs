@@ -0,0 +1,85 @@
+use crate::broker::OrderFilter;
+use crate::messages::{Fields, IncomingMessage};
+use crate::orders;
+use crate::{Db, Parse};
+
+use std::collections::HashSet;
+use tracing::{debug, instrument, warn};
+
+/// Cancel all open orders (`reqGlobalCancel`, message 58).
+///
+/// As with TWS, this covers the orders of every client, as well as the orders
+/// placed at the brokers without going through the connector.
+#[derive(Debug)]
+pub struct ReqGlobalCancel {
+    /// Message version
+    version: String,
+}
+
+impl ReqGlobalCancel {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Parse a `ReqGlobalCancel` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 58 version
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqGlobalCancel> {
+        let fields = Fields::decode(IncomingMessage::ReqGlobalCancel, server_version, parse)?;
+        let version = fields.string("version");
+
+        Ok(ReqGlobalCancel { version })
+    }
+
+    /// Apply the `ReqGlobalCancel` command to the specified `Db` instance.
+    ///
+    /// There is no direct response. The clients see their orders cancelled
+    /// through `orderStatus`, failures are only logged.
+    #[instrument(skip(self, db))]
+    pub(crate) async fn apply(self, db: &Db) {
+        let tracked = db.orders(|_| true);
+
+        for order in tracked.iter().filter(|order| !order.status.is_done()) {
+            if let Err(err) = orders::cancel(db, order).await {
+                warn!(client_id = order.client_id, order_id = order.order_id, cause = %err, "failed to cancel order");
+            }
+        }
+
+        let known: HashSet<_> = tracked
+            .iter()
+            .map(|order| order.client_order_id.as_str())
+            .collect();
+
+        for account in &db.config().accounts {
+            let Some(broker) = db.broker(&account.id) else {
+                continue;
+            };
+
+            let open = match broker.orders(OrderFilter::Open).await {
+                Ok(open) => open,
+                Err(err) => {
+                    warn!(account = %account.id, cause = %err, "failed to list open orders");
+                    continue;
+                }
+            };
+
+            for order in open
+                .iter()
+                .filter(|order| !known.contains(order.client_order_id.as_str()))
+            {
+                debug!(account = %account.id, id = %order.id, "cancelling order placed outside the connector");
+
+                if let Err(err) = broker.cancel_order(&order.id).await {
+                    warn!(account = %account.id, id = %order.id, cause = %err, "failed to cancel order");
+                }
+            }
+        }
+    }
+}
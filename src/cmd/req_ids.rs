@@ -0,0 +1,64 @@
+use crate::messages::{Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::{Connection, Db, Parse};
+
+use tracing::{debug, instrument};
+
+/// Request the next valid order id (`reqIds`, message 8).
+///
+/// TWS ignores the number of ids asked for and always answers with a single
+/// `nextValidId`, the lowest id the client has not used yet.
+#[derive(Debug)]
+pub struct ReqIds {
+    /// Message version
+    version: String,
+
+    /// Number of ids requested. Ignored, as by TWS.
+    num_ids: String,
+}
+
+impl ReqIds {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn num_ids(&self) -> &str {
+        &self.num_ids
+    }
+
+    /// Parse a `ReqIds` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 8 version numIds
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqIds> {
+        let fields = Fields::decode(IncomingMessage::ReqIds, server_version, parse)?;
+        let version = fields.string("version");
+        let num_ids = fields.string("numIds");
+
+        Ok(ReqIds { version, num_ids })
+    }
+
+    /// Apply the `ReqIds` command to the specified `Db` instance.
+    ///
+    /// The response is written to `dst`. This is called by the server in order
+    /// to execute a received command.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        // Requests are only applied once `startApi` reserved a client id.
+        let client_id = dst.client_id().ok_or("reqIds before startApi")?;
+
+        let response = Encoder::new(OutgoingMessage::NextValidId, dst.server_version())
+            .put("orderId", db.next_order_id(client_id))
+            .into_frame();
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use crate::messages::{Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::{Connection, Db, Parse};
+
+use tracing::{debug, instrument};
+
+/// Request the list of managed accounts (`reqManagedAccts`, message 17).
+///
+/// TWS already sends `managedAccounts` after `startApi`, this request asks for
+/// it again.
+#[derive(Debug)]
+pub struct ReqManagedAccts {
+    /// Message version
+    version: String,
+}
+
+impl ReqManagedAccts {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Parse a `ReqManagedAccts` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 17 version
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqManagedAccts> {
+        let fields = Fields::decode(IncomingMessage::ReqManagedAccts, server_version, parse)?;
+        let version = fields.string("version");
+
+        Ok(ReqManagedAccts { version })
+    }
+
+    /// Apply the `ReqManagedAccts` command to the specified `Db` instance.
+    ///
+    /// The response is written to `dst`. This is called by the server in order
+    /// to execute a received command.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let response = Encoder::new(OutgoingMessage::ManagedAccts, dst.server_version())
+            .put("accountsList", db.managed_accounts())
+            .into_frame();
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use crate::messages::{errors, Fields, IncomingMessage};
+use crate::ticker::MarketDataType;
+use crate::{Connection, Parse, Subscriptions};
+
+use tracing::{debug, instrument, warn};
+
+/// Switch the kind of market data of the connection (`reqMarketDataType`,
+/// message 59).
+///
+/// The types are live (1), frozen (2), delayed (3) and delayed-frozen (4).
+/// Frozen data is the last values, sent once, while the market is closed.
+/// Running `reqMktData` streams switch right away and announce it with
+/// `marketDataType`, and so do the ones requested later.
+#[derive(Debug)]
+pub struct ReqMarketDataType {
+    /// Message version
+    version: String,
+
+    market_data_type: i32,
+}
+
+impl ReqMarketDataType {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn market_data_type(&self) -> i32 {
+        self.market_data_type
+    }
+
+    /// Parse a `ReqMarketDataType` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 59 version marketDataType
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqMarketDataType> {
+        let fields = Fields::decode(IncomingMessage::ReqMarketDataType, server_version, parse)?;
+        let version = fields.string("version");
+        let market_data_type = fields.parse("marketDataType")?;
+
+        Ok(ReqMarketDataType {
+            version,
+            market_data_type,
+        })
+    }
+
+    /// Apply the `ReqMarketDataType` command.
+    ///
+    /// The switch itself is not answered, an unknown type is answered with
+    /// error 321.
+    #[instrument(skip(self, dst, subscriptions))]
+    pub(crate) async fn apply(self, dst: &mut Connection, subscriptions: &mut Subscriptions) -> crate::Result<()> {
+        match MarketDataType::from_id(self.market_data_type) {
+            Some(data_type) => {
+                debug!(?data_type, "switching market data type");
+                subscriptions.set_market_data_type(data_type);
+            }
+            None => {
+                let response = errors::VALIDATION_FAILED.to_frame_with(
+                    -1,
+                    dst.server_version(),
+                    &format!(
+                        "Error validating request: Invalid market data type {}.",
+                        self.market_data_type
+                    ),
+                );
+                warn!(market_data_type = self.market_data_type, "market data type rejected");
+                debug!(?response);
+
+                dst.write_frame(&response).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+use crate::cmd::place_order::{count, skip};
+use crate::market_data::{MarketEvent, Subscription};
+use crate::messages::versions::*;
+use crate::messages::{errors, Encoder, Field, Fields, OutgoingMessage};
+use crate::orders::{Contract, STOCK};
+use crate::subscriptions::{Sink, Topic};
+use crate::ticker::{describe, market_open, trading_day, GenericTick, GenericTicks, MarketDataType, Tick, Ticker};
+use crate::{Connection, Db, Frame, Parse, Subscriptions};
+
+use chrono::Utc;
+use std::future;
+use tokio::sync::watch;
+use tokio::time::{self, Duration};
+use tracing::{debug, instrument, warn};
+
+/// How often streams check whether the market opened or closed, which
+/// switches frozen data to streaming and back, and whether a new trading day
+/// started.
+const MARKET_HOURS_CHECK: Duration = Duration::from_secs(60);
+
+/// Subscribe to the market data of a contract (`reqMktData`, message 1).
+///
+/// The current bid, ask, last trade and day values are sent as `tickPrice`,
+/// `tickSize` and `tickString`, then every change the feed reports, until
+/// `cancelMktData`. With `snapshot` or `regulatorySnapshot` set, the values
+/// are sent once, followed by `tickSnapshotEnd`. Only stocks are supported.
+/// Clients following the same symbol share the feed's subscription to it.
+///
+/// The data is live, frozen or delayed as set with `reqMarketDataType`, which
+/// is announced with `marketDataType` before the first ticks and again at
+/// every switch. Delayed data uses the delayed tick types.
+///
+/// Of the generic ticks, misc stats (165), RTVolume (233), shortable (236),
+/// trade count (293), trade rate (294) and volume rate (295) are supported.
+/// The others TWS accepts are answered with error 10090, the ticks that are
+/// available still stream. Ids TWS does not accept fail the request with
+/// error 321.
+#[derive(Debug)]
+pub struct ReqMktData {
+    req_id: i64,
+    contract: Contract,
+
+    /// Comma separated ids of the generic ticks to send on top of the default
+    /// ones.
+    generic_tick_list: String,
+    snapshot: bool,
+    regulatory_snapshot: bool,
+}
+
+// `reqMktData` carries the legs of combo contracts and an optional delta
+// neutral contract, whose presence depends on earlier fields. The fixed parts
+// in between are read with these layouts.
+
+const CONTRACT: &[Field] = &[
+    Field::new("version"),
+    Field::new("reqId"),
+    Field::new("conId"),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("lastTradeDateOrContractMonth"),
+    Field::new("strike"),
+    Field::new("right"),
+    Field::new("multiplier"),
+    Field::new("exchange"),
+    Field::new("primaryExchange"),
+    Field::new("currency"),
+    Field::new("localSymbol"),
+    Field::new("tradingClass").since(MIN_SERVER_VER_TRADING_CLASS),
+];
+
+const DELTA_NEUTRAL: &[Field] = &[Field::new("deltaNeutralContractPresent")];
+
+/// Only sent when `deltaNeutralContractPresent` is set.
+const DELTA_NEUTRAL_CONTRACT: &[Field] = &[
+    Field::new("deltaNeutralContractConId"),
+    Field::new("deltaNeutralContractDelta"),
+    Field::new("deltaNeutralContractPrice"),
+];
+
+const OPTIONS: &[Field] = &[
+    Field::new("genericTickList"),
+    Field::new("snapshot"),
+    Field::new("regulatorySnapshot").since(MIN_SERVER_VER_REQ_SMART_COMPONENTS),
+    Field::new("mktDataOptions").since(MIN_SERVER_VER_LINKING),
+];
+
+impl ReqMktData {
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.contract.symbol
+    }
+
+    pub fn generic_tick_list(&self) -> &str {
+        &self.generic_tick_list
+    }
+
+    pub fn snapshot(&self) -> bool {
+        self.snapshot
+    }
+
+    pub fn regulatory_snapshot(&self) -> bool {
+        self.regulatory_snapshot
+    }
+
+    /// Parse a `ReqMktData` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 1 version reqId contract... [comboLegs...] deltaNeutralContractPresent
+    ///   [deltaNeutralContract...] genericTickList snapshot [regulatorySnapshot]
+    ///   [mktDataOptions]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqMktData> {
+        let mut fields = Fields::default();
+
+        fields.read(CONTRACT, server_version, parse)?;
+
+        // The legs of combos, which are not supported and skipped.
+        if fields.string("secType") == "BAG" {
+            for _ in 0..count(parse)? {
+                skip(parse, 4)?;
+            }
+        }
+
+        fields.read(DELTA_NEUTRAL, server_version, parse)?;
+
+        if fields.flag("deltaNeutralContractPresent")? {
+            fields.read(DELTA_NEUTRAL_CONTRACT, server_version, parse)?;
+        }
+
+        fields.read(OPTIONS, server_version, parse)?;
+
+        let contract = Contract {
+            con_id: fields.optional("conId")?.unwrap_or_default(),
+            symbol: fields.string("symbol"),
+            sec_type: fields.string("secType"),
+            last_trade_date_or_contract_month: fields.string("lastTradeDateOrContractMonth"),
+            strike: fields.optional("strike")?.filter(|strike| *strike != 0.0),
+            right: fields.string("right"),
+            multiplier: fields.string("multiplier"),
+            exchange: fields.string("exchange"),
+            primary_exchange: fields.string("primaryExchange"),
+            currency: fields.string("currency"),
+            local_symbol: fields.string("localSymbol"),
+            trading_class: fields.string("tradingClass"),
+        };
+
+        Ok(ReqMktData {
+            req_id: fields.parse("reqId")?,
+            contract,
+            generic_tick_list: fields.string("genericTickList"),
+            snapshot: fields.flag("snapshot")?,
+            regulatory_snapshot: fields.flag("regulatorySnapshot")?,
+        })
+    }
+
+    /// Apply the `ReqMktData` command to the specified `Db` instance.
+    ///
+    /// The ticks are sent by a task registered in `subscriptions`, which ends
+    /// after a snapshot. Errors with the request itself are written to `dst`
+    /// right away.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+        let req_id = self.req_id;
+        let symbol = self.contract.symbol.trim().to_uppercase();
+
+        let generic = GenericTicks::parse(&self.generic_tick_list);
+
+        let response = match (db.market_data(), generic) {
+            _ if subscriptions.contains(Topic::MktData, req_id) => {
+                errors::DUPLICATE_TICKER_ID.to_frame(req_id, server_version)
+            }
+            _ if symbol.is_empty() || !matches!(self.contract.sec_type.as_str(), "" | STOCK) => {
+                errors::NO_SECURITY_DEFINITION.to_frame(req_id, server_version)
+            }
+            (_, Err(reason)) => errors::VALIDATION_FAILED.to_frame_with(
+                req_id,
+                server_version,
+                &format!("Error validating request: {}", reason),
+            ),
+            _ if self.snapshot && !self.generic_tick_list.trim().is_empty() => {
+                errors::VALIDATION_FAILED.to_frame_with(
+                    req_id,
+                    server_version,
+                    "Error validating request: Snapshot market data subscription is not applicable to generic ticks",
+                )
+            }
+            (None, _) => errors::MARKET_DATA_NOT_SUBSCRIBED.to_frame_with(
+                req_id,
+                server_version,
+                "Requested market data is not subscribed. No market data feed is configured.",
+            ),
+            (Some(_), Ok(generic)) => {
+                let db = db.clone();
+
+                let data_type = subscriptions.market_data_type();
+
+                if self.snapshot || self.regulatory_snapshot {
+                    let data_type = *data_type.borrow();
+                    subscriptions.spawn(Topic::MktData, req_id, move |sink| {
+                        snapshot(db, symbol, req_id, data_type, sink, server_version)
+                    });
+                } else {
+                    subscriptions.spawn(Topic::MktData, req_id, move |sink| {
+                        stream(db, symbol, generic, req_id, data_type, sink, server_version)
+                    });
+                }
+
+                return Ok(());
+            }
+        };
+
+        warn!(req_id, %symbol, "market data rejected");
+        debug!(?response);
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+/// Send the values of `symbol` and the `generic` ticks to `sink` as the kind
+/// of data `data_type` asks for, then the ticks of every update from the feed.
+///
+/// Every switch of kind, whether asked for or because the market opened or
+/// closed, is announced with `marketDataType` and starts over from the current
+/// values, and so does a new trading day.
+async fn stream(
+    db: Db,
+    symbol: String,
+    generic: GenericTicks,
+    req_id: i64,
+    mut data_type: watch::Receiver<MarketDataType>,
+    sink: Sink,
+    server_version: u16,
+) {
+    let mut reported = false;
+
+    loop {
+        let requested = *data_type.borrow_and_update();
+        let effective = requested.effective(market_open(Utc::now()));
+
+        let Some((ticker, events)) = open(&db, &symbol, &generic.supported, effective).await else {
+            let response = errors::NO_SECURITY_DEFINITION.to_frame(req_id, server_version);
+            debug!(?response);
+            let _ = sink.send(response).await;
+            return;
+        };
+
+        let mut responses = vec![data_type_frame(req_id, effective, server_version)];
+
+        // Tell about the generic ticks that will not come before the ones
+        // that will, once per request.
+        let mut missing = generic.unsupported.clone();
+        missing.extend(ticker.missing().iter().map(|tick| *tick as u16));
+
+        if !missing.is_empty() && !reported {
+            responses.push(errors::PART_OF_MARKET_DATA_NOT_SUBSCRIBED.to_frame_with(
+                req_id,
+                server_version,
+                &format!(
+                    "{} Not available: {}",
+                    errors::PART_OF_MARKET_DATA_NOT_SUBSCRIBED.message,
+                    describe(&missing)
+                ),
+            ));
+            reported = true;
+        }
+
+        responses.extend(tick_frames(req_id, ticker.ticks(), effective, server_version));
+
+        if !send(&sink, responses).await {
+            return;
+        }
+
+        if !follow(ticker, events, effective, req_id, &mut data_type, &sink, server_version).await {
+            return;
+        }
+    }
+}
+
+/// Send the ticks of every update of `events` to `sink`, until the kind of
+/// data to send or the trading day changes, which starts the day values
+/// over.
+///
+/// Frozen data has no `events` and only waits for the market to open.
+/// Returns `false` once the connection or the feed is closed.
+async fn follow(
+    mut ticker: Ticker,
+    mut events: Option<Subscription>,
+    effective: MarketDataType,
+    req_id: i64,
+    data_type: &mut watch::Receiver<MarketDataType>,
+    sink: &Sink,
+    server_version: u16,
+) -> bool {
+    let mut hours = time::interval(MARKET_HOURS_CHECK);
+    let day = trading_day(Utc::now());
+
+    loop {
+        let ticks = tokio::select! {
+            changed = data_type.changed() => return changed.is_ok(),
+            _ = hours.tick() => {
+                let now = Utc::now();
+                if data_type.borrow().effective(market_open(now)) != effective || trading_day(now) != day {
+                    return true;
+                }
+                continue;
+            }
+            event = next_event(&mut events) => match event {
+                Some(event) => ticker.apply(&event),
+                None => {
+                    warn!(req_id, "market data stream ended");
+                    return false;
+                }
+            },
+        };
+
+        if !send(sink, tick_frames(req_id, ticks, effective, server_version)).await {
+            return false;
+        }
+    }
+}
+
+/// Send the current values of `symbol` to `sink` once, as the kind of data
+/// `data_type` asks for, followed by `tickSnapshotEnd`.
+///
+/// The values are read from the feed's REST API, nothing is subscribed.
+async fn snapshot(
+    db: Db,
+    symbol: String,
+    req_id: i64,
+    data_type: MarketDataType,
+    sink: Sink,
+    server_version: u16,
+) {
+    let effective = data_type.effective(market_open(Utc::now()));
+
+    let responses = match Ticker::load(&db, &symbol, &[], effective.is_delayed()).await {
+        Some(ticker) => {
+            let mut responses = vec![data_type_frame(req_id, effective, server_version)];
+            responses.extend(tick_frames(req_id, ticker.ticks(), effective, server_version));
+            responses.push(
+                Encoder::new(OutgoingMessage::TickSnapshotEnd, server_version)
+                    .put("reqId", req_id)
+                    .into_frame(),
+            );
+            responses
+        }
+        None => vec![errors::NO_SECURITY_DEFINITION.to_frame(req_id, server_version)],
+    };
+
+    send(&sink, responses).await;
+}
+
+/// Load the current values of `symbol` and the data of the `generic` ticks,
+/// as the `effective` kind of data, and subscribe to its updates unless the
+/// data is frozen. Delayed data shares the delayed updates of the symbol.
+///
+/// Returns `None` if the feed does not know the symbol.
+async fn open(
+    db: &Db,
+    symbol: &str,
+    generic: &[GenericTick],
+    effective: MarketDataType,
+) -> Option<(Ticker, Option<Subscription>)> {
+    let provider = db.market_data()?;
+
+    // Subscribe first, so no update is missed while the values load.
+    let events = if effective.is_frozen() {
+        None
+    } else {
+        let subscribed = match db.delayed_market_data() {
+            Some(delayed) if effective.is_delayed() => delayed.subscribe(symbol).await,
+            _ => provider.subscribe(symbol).await,
+        };
+
+        match subscribed {
+            Ok(events) => Some(events),
+            Err(err) => {
+                warn!(%symbol, cause = %err, "failed to subscribe to market data");
+                return None;
+            }
+        }
+    };
+
+    let ticker = Ticker::load(db, symbol, generic, effective.is_delayed()).await?;
+    Some((ticker, events))
+}
+
+/// Returns the next update of `events`, never if there is no subscription.
+async fn next_event(events: &mut Option<Subscription>) -> Option<MarketEvent> {
+    match events {
+        Some(events) => events.recv().await,
+        None => future::pending().await,
+    }
+}
+
+/// Send `responses` to `sink` in order.
+///
+/// Returns `false` if the connection is closed.
+async fn send(sink: &Sink, responses: Vec<Frame>) -> bool {
+    for response in responses {
+        debug!(?response);
+
+        if sink.send(response).await.is_err() {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Encode the `marketDataType` message announcing the kind of data sent for
+/// `req_id`.
+fn data_type_frame(req_id: i64, data_type: MarketDataType, server_version: u16) -> Frame {
+    Encoder::new(OutgoingMessage::MarketDataType, server_version)
+        .put("reqId", req_id)
+        .put("marketDataType", data_type as i32)
+        .into_frame()
+}
+
+/// Encode `ticks`, with the delayed tick types if the `effective` data is
+/// delayed.
+fn tick_frames(req_id: i64, ticks: Vec<Tick>, effective: MarketDataType, server_version: u16) -> Vec<Frame> {
+    ticks
+        .into_iter()
+        .map(|tick| if effective.is_delayed() { tick.delayed() } else { tick })
+        .map(|tick| tick_frame(req_id, tick, server_version))
+        .collect()
+}
+
+/// Encode `tick` as the message TWS sends it with.
+fn tick_frame(req_id: i64, tick: Tick, server_version: u16) -> Frame {
+    match tick {
+        Tick::Price(tick_type, price, size) => Encoder::new(OutgoingMessage::TickPrice, server_version)
+            .put("tickerId", req_id)
+            .put("tickType", tick_type as i32)
+            .put("price", price)
+            .put("size", size)
+            .put("attrMask", 0)
+            .into_frame(),
+        Tick::Size(tick_type, size) => Encoder::new(OutgoingMessage::TickSize, server_version)
+            .put("tickerId", req_id)
+            .put("tickType", tick_type as i32)
+            .put("size", size)
+            .into_frame(),
+        Tick::String(tick_type, value) => Encoder::new(OutgoingMessage::TickString, server_version)
+            .put("tickerId", req_id)
+            .put("tickType", tick_type as i32)
+            .put("value", value)
+            .into_frame(),
+        Tick::Generic(tick_type, value) => Encoder::new(OutgoingMessage::TickGeneric, server_version)
+            .put("tickerId", req_id)
+            .put("tickType", tick_type as i32)
+            .put("value", value)
+            .into_frame(),
+    }
+}
@@ -0,0 +1,235 @@
+use crate::market_data::{MarketEvent, Side};
+use crate::messages::{errors, Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::order_book::{Change, OrderBook, View};
+use crate::orders::{Contract, STOCK};
+use crate::subscriptions::{Sink, Topic};
+use crate::{Connection, Db, Frame, Parse, Subscriptions};
+
+use tracing::{debug, instrument, warn};
+
+/// Subscribe to the order book of a contract (`reqMktDepth`, message 10).
+///
+/// The book is built from the quotes of the venues the market data feed
+/// reports and sent as the inserts, updates and deletes of its top
+/// `numRows` rows per side, until `cancelMktDepth`. Smart depth has one row
+/// per venue, sent as `updateMktDepthL2` with the venue as market maker.
+/// Otherwise the rows are sent as `updateMktDepth`: one per price for
+/// `SMART`, or the row of the one venue asked for. Venues the feed does not
+/// report are refused with error 10092. Only stocks are supported.
+#[derive(Debug)]
+pub struct ReqMktDepth {
+    req_id: i64,
+    contract: Contract,
+
+    /// Rows of each side of the book to send.
+    num_rows: i32,
+    is_smart_depth: bool,
+}
+
+impl ReqMktDepth {
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.contract.symbol
+    }
+
+    pub fn exchange(&self) -> &str {
+        &self.contract.exchange
+    }
+
+    pub fn num_rows(&self) -> i32 {
+        self.num_rows
+    }
+
+    pub fn is_smart_depth(&self) -> bool {
+        self.is_smart_depth
+    }
+
+    /// Parse a `ReqMktDepth` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 10 version reqId [conId] symbol secType lastTradeDateOrContractMonth
+    ///    strike right multiplier exchange [primaryExchange] currency
+    ///    localSymbol [tradingClass] numRows [isSmartDepth] [mktDepthOptions]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqMktDepth> {
+        let fields = Fields::decode(IncomingMessage::ReqMktDepth, server_version, parse)?;
+
+        let contract = Contract {
+            con_id: fields.optional("conId")?.unwrap_or_default(),
+            symbol: fields.string("symbol"),
+            sec_type: fields.string("secType"),
+            last_trade_date_or_contract_month: fields.string("lastTradeDateOrContractMonth"),
+            strike: fields.optional("strike")?.filter(|strike| *strike != 0.0),
+            right: fields.string("right"),
+            multiplier: fields.string("multiplier"),
+            exchange: fields.string("exchange"),
+            primary_exchange: fields.string("primaryExchange"),
+            currency: fields.string("currency"),
+            local_symbol: fields.string("localSymbol"),
+            trading_class: fields.string("tradingClass"),
+        };
+
+        Ok(ReqMktDepth {
+            req_id: fields.parse("reqId")?,
+            contract,
+            num_rows: fields.parse("numRows")?,
+            is_smart_depth: fields.flag("isSmartDepth")?,
+        })
+    }
+
+    /// Apply the `ReqMktDepth` command to the specified `Db` instance.
+    ///
+    /// The book is streamed by a task registered in `subscriptions`. Errors
+    /// with the request itself are written to `dst` right away.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+        let req_id = self.req_id;
+        let symbol = self.contract.symbol.trim().to_uppercase();
+        let exchange = self.contract.exchange.trim().to_uppercase();
+
+        let response = match db.market_data() {
+            _ if subscriptions.contains(Topic::MktDepth, req_id) => {
+                errors::DUPLICATE_TICKER_ID.to_frame(req_id, server_version)
+            }
+            _ if symbol.is_empty() || !matches!(self.contract.sec_type.as_str(), "" | STOCK) => {
+                errors::NO_SECURITY_DEFINITION.to_frame(req_id, server_version)
+            }
+            _ if self.num_rows < 1 => errors::VALIDATION_FAILED.to_frame_with(
+                req_id,
+                server_version,
+                &format!("Error validating request: Invalid number of rows {}.", self.num_rows),
+            ),
+            None => errors::MARKET_DATA_NOT_SUBSCRIBED.to_frame_with(
+                req_id,
+                server_version,
+                "Requested market data is not subscribed. No market data feed is configured.",
+            ),
+            Some(provider) => {
+                let view = if self.is_smart_depth {
+                    Some(View::Venues)
+                } else if matches!(exchange.as_str(), "" | "SMART") {
+                    Some(View::Prices)
+                } else if provider.venues().contains(&exchange) {
+                    Some(View::Venue(exchange))
+                } else {
+                    None
+                };
+
+                match view {
+                    Some(view) => {
+                        let book = OrderBook::new(view, self.num_rows as usize);
+                        let db = db.clone();
+
+                        subscriptions.spawn(Topic::MktDepth, req_id, move |sink| {
+                            stream(db, symbol, book, req_id, sink, server_version)
+                        });
+
+                        return Ok(());
+                    }
+                    None => errors::DEPTH_NOT_SUPPORTED.to_frame(req_id, server_version),
+                }
+            }
+        };
+
+        warn!(req_id, %symbol, "market depth rejected");
+        debug!(?response);
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+}
+
+/// Fill `book` with the venues quoting the best bid and offer of `symbol`
+/// and send its rows to `sink`, then the changes of every quote from the
+/// feed.
+async fn stream(db: Db, symbol: String, mut book: OrderBook, req_id: i64, sink: Sink, server_version: u16) {
+    let Some(provider) = db.market_data() else {
+        return;
+    };
+
+    // Subscribe first, so no quote is missed while the book loads.
+    let loaded = match provider.subscribe(&symbol).await {
+        Ok(events) => provider.last_depth(&symbol).await.map(|depth| (events, depth)),
+        Err(err) => Err(err),
+    };
+
+    let (mut events, mut depth) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            warn!(%symbol, cause = %err, "failed to load market depth");
+
+            let response = errors::NO_SECURITY_DEFINITION.to_frame(req_id, server_version);
+            debug!(?response);
+            let _ = sink.send(response).await;
+            return;
+        }
+    };
+
+    // Rows of several venues have no market maker to report.
+    let l2 = book.view() == &View::Venues;
+
+    loop {
+        for change in depth.iter().flat_map(|depth| book.apply(depth)) {
+            let response = change_frame(req_id, change, l2, server_version);
+            debug!(?response);
+
+            if sink.send(response).await.is_err() {
+                // The connection is closed.
+                return;
+            }
+        }
+
+        depth = match events.recv().await {
+            Some(MarketEvent::Depth(update)) => vec![update],
+            Some(_) => Vec::new(),
+            None => {
+                warn!(req_id, %symbol, "market data stream ended");
+                return;
+            }
+        };
+    }
+}
+
+/// Encode `change` as `updateMktDepthL2` if `l2` is set, as `updateMktDepth`
+/// otherwise.
+fn change_frame(req_id: i64, change: Change, l2: bool, server_version: u16) -> Frame {
+    let side = match change.side {
+        Side::Ask => 0,
+        Side::Bid => 1,
+    };
+
+    if l2 {
+        Encoder::new(OutgoingMessage::MarketDepthL2, server_version)
+            .put("id", req_id)
+            .put("position", change.position)
+            .put("marketMaker", change.row.market_maker)
+            .put("operation", change.operation as i32)
+            .put("side", side)
+            .put("price", change.row.price)
+            .put("size", change.row.size)
+            .put("isSmartDepth", 1)
+            .into_frame()
+    } else {
+        Encoder::new(OutgoingMessage::MarketDepth, server_version)
+            .put("id", req_id)
+            .put("position", change.position)
+            .put("operation", change.operation as i32)
+            .put("side", side)
+            .put("price", change.row.price)
+            .put("size", change.row.size)
+            .into_frame()
+    }
+}
@@ -0,0 +1,65 @@
+use crate::messages::versions::*;
+use crate::messages::{Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::orders::STOCK;
+use crate::{Connection, Db, Parse};
+
+use tracing::{debug, instrument};
+
+/// Request the venues market depth is available from
+/// (`reqMktDepthExchanges`, message 82).
+///
+/// Answered with `mktDepthExchanges`, listing the venues of the market data
+/// feed for stocks, none without a feed. Each venue reports its best bid and
+/// offer only, so they are listed as `Deep` rather than `Deep2`.
+#[derive(Debug)]
+pub struct ReqMktDepthExchanges;
+
+impl ReqMktDepthExchanges {
+    /// Parse a `ReqMktDepthExchanges` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 82
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqMktDepthExchanges> {
+        Fields::decode(IncomingMessage::ReqMktDepthExchanges, server_version, parse)?;
+
+        Ok(ReqMktDepthExchanges)
+    }
+
+    /// Apply the `ReqMktDepthExchanges` command to the specified `Db`
+    /// instance.
+    ///
+    /// The response is written to `dst`.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let server_version = dst.server_version();
+        let venues = db.market_data().map(|provider| provider.venues()).unwrap_or_default();
+
+        let mut descriptions = Vec::new();
+        for venue in &venues {
+            descriptions.push(venue.clone());
+            descriptions.push(STOCK.to_string());
+
+            if server_version >= MIN_SERVER_VER_SERVICE_DATA_TYPE {
+                // No listing exchange and no aggregation group.
+                descriptions.extend(["", "Deep", ""].map(String::from));
+            } else {
+                descriptions.push("0".to_string());
+            }
+        }
+
+        let response = Encoder::new(OutgoingMessage::MktDepthExchanges, server_version)
+            .put("nDepthMktDataDescriptions", venues.len())
+            .put_group("depthMktDataDescriptions", descriptions)
+            .into_frame();
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::messages::{Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::orders::{self, TrackedOrder};
+use crate::{Connection, Db, Parse};
+
+use tracing::{debug, instrument};
+
+/// Request the client's open orders (`reqOpenOrders`, message 5).
+///
+/// Client 0 also gets the orders placed at the brokers without going through
+/// the connector, with order id 0.
+#[derive(Debug)]
+pub struct ReqOpenOrders {
+    /// Message version
+    version: String,
+}
+
+impl ReqOpenOrders {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Parse a `ReqOpenOrders` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 5 version
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqOpenOrders> {
+        let fields = Fields::decode(IncomingMessage::ReqOpenOrders, server_version, parse)?;
+        let version = fields.string("version");
+
+        Ok(ReqOpenOrders { version })
+    }
+
+    /// Apply the `ReqOpenOrders` command to the specified `Db` instance.
+    ///
+    /// The response is written to `dst`. This is called by the server in order
+    /// to execute a received command.
+    #[instrument(skip(self, db, dst))]
+    pub(crate) async fn apply(self, db: &Db, dst: &mut Connection) -> crate::Result<()> {
+        let client_id = dst.client_id().ok_or("reqOpenOrders before startApi")?;
+
+        let orders = orders::working(db, |order| order.client_id == client_id, client_id == 0).await;
+        write_open_orders(dst, &orders).await
+    }
+}
+
+/// Write `orders` to `dst`, each as `openOrder` and `orderStatus`, followed
+/// by `openOrderEnd`.
+pub(crate) async fn write_open_orders(dst: &mut Connection, orders: &[TrackedOrder]) -> crate::Result<()> {
+    let server_version = dst.server_version();
+
+    for order in orders {
+        for response in [
+            order.open_order_frame(server_version),
+            order.order_status_frame(server_version),
+        ] {
+            debug!(?response);
+            dst.write_frame(&response).await?;
+        }
+    }
+
+    let response = Encoder::new(OutgoingMessage::OpenOrderEnd, server_version).into_frame();
+    debug!(?response);
+
+    dst.write_frame(&response).await?;
+    Ok(())
+}
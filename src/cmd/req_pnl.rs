@@ -0,0 +1,161 @@
+use crate::messages::{errors, Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::pnl::{Pnl, PnlWatch};
+use crate::subscriptions::{Sink, Topic};
+use crate::{Connection, Db, Frame, Parse, Subscriptions};
+
+use tracing::{debug, instrument, warn};
+
+/// Subscribe to the P&L of an account (`reqPnL`, message 92).
+///
+/// The daily, unrealized and realized P&L of the account's positions is sent
+/// as `pnl`, and again every time it changes, until `cancelPnL`.
+#[derive(Debug)]
+pub struct ReqPnL {
+    req_id: i64,
+    account: String,
+
+    /// Model portfolio, the connector has none.
+    model_code: String,
+}
+
+impl ReqPnL {
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    pub fn model_code(&self) -> &str {
+        &self.model_code
+    }
+
+    /// Parse a `ReqPnL` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 92 reqId account modelCode
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqPnL> {
+        let fields = Fields::decode(IncomingMessage::ReqPnL, server_version, parse)?;
+        let req_id = fields.parse("reqId")?;
+        let account = fields.string("account");
+        let model_code = fields.string("modelCode");
+
+        Ok(ReqPnL {
+            req_id,
+            account,
+            model_code,
+        })
+    }
+
+    /// Apply the `ReqPnL` command to the specified `Db` instance.
+    ///
+    /// The P&L is streamed by a task registered in `subscriptions`. Errors
+    /// with the request itself are written to `dst` right away.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+
+        if let Some(response) = rejection(
+            db,
+            subscriptions,
+            Topic::Pnl,
+            self.req_id,
+            &self.account,
+            server_version,
+        ) {
+            warn!(req_id = self.req_id, account = %self.account, "P&L rejected");
+            debug!(?response);
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        let watch = PnlWatch::new(db.clone(), self.account);
+        let req_id = self.req_id;
+
+        subscriptions.spawn(Topic::Pnl, req_id, move |sink| {
+            stream(watch, req_id, sink, server_version)
+        });
+
+        Ok(())
+    }
+}
+
+/// Returns the error answering a P&L request that cannot be served, if any.
+pub(super) fn rejection(
+    db: &Db,
+    subscriptions: &Subscriptions,
+    topic: Topic,
+    req_id: i64,
+    account: &str,
+    server_version: u16,
+) -> Option<Frame> {
+    if subscriptions.contains(topic, req_id) {
+        Some(errors::PROCESSING_FAILED.to_frame_with(
+            req_id,
+            server_version,
+            "Error processing request: Duplicate ticker id.",
+        ))
+    } else if db.broker(account).is_none() {
+        Some(errors::VALIDATION_FAILED.to_frame_with(
+            req_id,
+            server_version,
+            &format!("Error validating request: Unknown account {}.", account),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Send the P&L of the account `watch` follows to `sink` whenever it changes.
+async fn stream(mut watch: PnlWatch, req_id: i64, sink: Sink, server_version: u16) {
+    let mut sent = None;
+
+    loop {
+        watch.tick().await;
+
+        let positions = match watch.positions().await {
+            Ok(positions) => positions,
+            Err(err) => {
+                warn!(req_id, cause = %err, "failed to get positions");
+                continue;
+            }
+        };
+
+        let mut total = Pnl::default();
+        for position in positions.values() {
+            total.daily += position.pnl.daily;
+            total.unrealized += position.pnl.unrealized;
+            total.realized += position.pnl.realized;
+        }
+
+        if sent == Some(total) {
+            continue;
+        }
+
+        let response = Encoder::new(OutgoingMessage::Pnl, server_version)
+            .put("reqId", req_id)
+            .put("dailyPnL", total.daily)
+            .put("unrealizedPnL", total.unrealized)
+            .put("realizedPnL", total.realized)
+            .into_frame();
+        debug!(?response);
+
+        if sink.send(response).await.is_err() {
+            // The connection is closed.
+            return;
+        }
+
+        sent = Some(total);
+    }
+}
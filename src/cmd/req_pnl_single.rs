@@ -0,0 +1,147 @@
+use crate::cmd::req_pnl::rejection;
+use crate::messages::{Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::pnl::PnlWatch;
+use crate::subscriptions::{Sink, Topic};
+use crate::{Connection, Db, Parse, Subscriptions};
+
+use tracing::{debug, instrument, warn};
+
+/// Subscribe to the P&L of one position (`reqPnLSingle`, message 94).
+///
+/// The position, its daily, unrealized and realized P&L and its market value
+/// are sent as `pnlSingle`, and again every time they change, until
+/// `cancelPnLSingle`. A position that is not held is reported with zeros.
+#[derive(Debug)]
+pub struct ReqPnLSingle {
+    req_id: i64,
+    account: String,
+
+    /// Model portfolio, the connector has none.
+    model_code: String,
+    con_id: i32,
+}
+
+impl ReqPnLSingle {
+    pub fn req_id(&self) -> i64 {
+        self.req_id
+    }
+
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    pub fn model_code(&self) -> &str {
+        &self.model_code
+    }
+
+    pub fn con_id(&self) -> i32 {
+        self.con_id
+    }
+
+    /// Parse a `ReqPnLSingle` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 94 reqId account modelCode conId
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqPnLSingle> {
+        let fields = Fields::decode(IncomingMessage::ReqPnLSingle, server_version, parse)?;
+        let req_id = fields.parse("reqId")?;
+        let account = fields.string("account");
+        let model_code = fields.string("modelCode");
+        let con_id = fields.parse("conId")?;
+
+        Ok(ReqPnLSingle {
+            req_id,
+            account,
+            model_code,
+            con_id,
+        })
+    }
+
+    /// Apply the `ReqPnLSingle` command to the specified `Db` instance.
+    ///
+    /// The P&L is streamed by a task registered in `subscriptions`. Errors
+    /// with the request itself are written to `dst` right away.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+
+        if let Some(response) = rejection(
+            db,
+            subscriptions,
+            Topic::PnlSingle,
+            self.req_id,
+            &self.account,
+            server_version,
+        ) {
+            warn!(req_id = self.req_id, account = %self.account, "P&L rejected");
+            debug!(?response);
+            dst.write_frame(&response).await?;
+            return Ok(());
+        }
+
+        let watch = PnlWatch::new(db.clone(), self.account);
+        let (req_id, con_id) = (self.req_id, self.con_id);
+
+        subscriptions.spawn(Topic::PnlSingle, req_id, move |sink| {
+            stream(watch, req_id, con_id, sink, server_version)
+        });
+
+        Ok(())
+    }
+}
+
+/// Send the P&L of position `con_id` of the account `watch` follows to
+/// `sink` whenever it changes.
+async fn stream(mut watch: PnlWatch, req_id: i64, con_id: i32, sink: Sink, server_version: u16) {
+    let mut sent = None;
+
+    loop {
+        watch.tick().await;
+
+        let positions = match watch.positions().await {
+            Ok(positions) => positions,
+            Err(err) => {
+                warn!(req_id, cause = %err, "failed to get positions");
+                continue;
+            }
+        };
+
+        let position = positions
+            .into_values()
+            .find(|position| position.con_id == con_id)
+            .map(|position| (position.quantity, position.pnl, position.value))
+            .unwrap_or_default();
+
+        if sent == Some(position) {
+            continue;
+        }
+
+        let (quantity, pnl, value) = position;
+        let response = Encoder::new(OutgoingMessage::PnlSingle, server_version)
+            .put("reqId", req_id)
+            .put("pos", quantity)
+            .put("dailyPnL", pnl.daily)
+            .put("unrealizedPnL", pnl.unrealized)
+            .put("realizedPnL", pnl.realized)
+            .put("value", value)
+            .into_frame();
+        debug!(?response);
+
+        if sink.send(response).await.is_err() {
+            // The connection is closed.
+            return;
+        }
+
+        sent = Some(position);
+    }
+}
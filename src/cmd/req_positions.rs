@@ -0,0 +1,104 @@
+use crate::messages::{Encoder, Fields, IncomingMessage, OutgoingMessage};
+use crate::orders::OrderEvent;
+use crate::subscriptions::{Sink, Topic};
+use crate::{Connection, Db, Parse, Subscriptions};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, instrument, warn};
+
+/// Subscribe to the positions of all accounts (`reqPositions`, message 61).
+///
+/// Every open position is sent as `position`, followed by `positionEnd`.
+/// From then on, each position a fill changes is sent again, until
+/// `cancelPositions`.
+#[derive(Debug)]
+pub struct ReqPositions {
+    /// Message version
+    version: String,
+}
+
+impl ReqPositions {
+    /// Get the version
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Parse a `ReqPositions` instance from a received frame.
+    ///
+    /// The message id has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// 61 version
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse, server_version: u16) -> crate::Result<ReqPositions> {
+        let fields = Fields::decode(IncomingMessage::ReqPositions, server_version, parse)?;
+        let version = fields.string("version");
+
+        Ok(ReqPositions { version })
+    }
+
+    /// Apply the `ReqPositions` command to the specified `Db` instance.
+    ///
+    /// The positions are streamed by a task registered in `subscriptions`, a
+    /// running stream is replaced.
+    #[instrument(skip(self, db, dst, subscriptions))]
+    pub(crate) async fn apply(
+        self,
+        db: &Db,
+        dst: &mut Connection,
+        subscriptions: &mut Subscriptions,
+    ) -> crate::Result<()> {
+        let server_version = dst.server_version();
+
+        // Subscribe before the positions are read, so no fill in between is
+        // missed.
+        let events = db.order_events();
+        let db = db.clone();
+
+        subscriptions.spawn(Topic::Positions, 0, move |sink| {
+            stream(db, events, sink, server_version)
+        });
+
+        Ok(())
+    }
+}
+
+/// Send the positions of all accounts to `sink`, then the ones that change.
+async fn stream(db: Db, mut events: broadcast::Receiver<OrderEvent>, sink: Sink, server_version: u16) {
+    let mut responses = Vec::new();
+
+    for account in &db.config().accounts {
+        match db.positions(&account.id).await {
+            Ok(holdings) => {
+                responses.extend(
+                    holdings
+                        .iter()
+                        .map(|holding| holding.position_frame(server_version)),
+                );
+            }
+            Err(err) => warn!(account = %account.id, cause = %err, "failed to get positions"),
+        }
+    }
+
+    responses.push(Encoder::new(OutgoingMessage::PositionEnd, server_version).into_frame());
+
+    loop {
+        for response in responses.drain(..) {
+            debug!(?response);
+
+            if sink.send(response).await.is_err() {
+                // The connection is closed.
+                return;
+            }
+        }
+
+        match events.recv().await {
+            Ok(OrderEvent::Position(holding)) => responses.push(holding.position_frame(server_version)),
+            Ok(_) => {}
+            Err(RecvError::Lagged(missed)) => warn!(missed, "position changes were dropped"),
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
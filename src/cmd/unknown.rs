@@ -1,8 +1,9 @@
-use crate::{Connection, Frame};
+use crate::messages::errors;
+use crate::Connection;
 
 use tracing::{debug, instrument};
 
-/// Represents an "unknown" command. This is not a real `Redis` command.
+/// Represents an "unknown" command. This is not a real TWS API request.
 #[derive(Debug)]
 pub struct Unknown {
     command_name: String,
@@ -17,17 +18,17 @@ impl Unknown {
         }
     }
 
-    /// Returns the command name
-    pub(crate) fn get_name(&self) -> &str {
-        &self.command_name
-    }
-
     /// Responds to the client, indicating the command is not recognized.
     ///
-    /// This usually means the command is not yet implemented by `mini-redis`.
+    /// This usually means the request is not yet implemented by the connector.
     #[instrument(skip(self, dst))]
     pub(crate) async fn apply(self, dst: &mut Connection) -> crate::Result<()> {
-        let response = Frame::Error(format!("ERR unknown command '{}'", self.command_name));
+        // TWS answers an unknown message id with error 505 and no request id.
+        let response = errors::UNKNOWN_ID.to_frame_with(
+            -1,
+            dst.server_version(),
+            &format!("Fatal Error: Unknown message id {}.", self.command_name),
+        );
 
         debug!(?response);
 
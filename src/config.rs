@@ -0,0 +1,266 @@
+//! Connector configuration.
+//!
+//! The configuration is read from a TOML file passed with `--config`. Each
+//! `[[accounts]]` entry is one account tiger.trade sees as managed by the
+//! connector, together with the broker adapter that holds it:
+//!
+//! ```toml
+//! data_dir = "/var/lib/tiger_trade_connector"
+//! time_zone = "America/New_York"
+//!
+//! [[accounts]]
+//! id = "DU1000001"
+//! currency = "USD"
+//!
+//! [accounts.broker]
+//! kind = "paper"
+//! cash = 100000.0
+//!
+//! [[groups]]
+//! name = "Growth"
+//! accounts = ["DU1000001"]
+//!
+//! [market_data]
+//! kind = "polygon"
+//! api_key = "..."
+//! ```
+//!
+//! Groups play the part of TWS financial advisor groups, requests that take a
+//! group name act on all of its accounts. The group `All` always exists.
+
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the group made of every configured account.
+const ALL_GROUP: &str = "All";
+
+/// Settings of a connector instance.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Directory for state kept across restarts, such as used order ids.
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+
+    /// Time zone of the trading day, e.g. `America/New_York`. The daily P&L
+    /// starts over at midnight there.
+    #[serde(default = "default_time_zone")]
+    pub time_zone: String,
+
+    /// Accounts reported in `managedAccounts`, in that order.
+    pub accounts: Vec<AccountConfig>,
+
+    /// Named sets of accounts.
+    #[serde(default)]
+    pub groups: Vec<GroupConfig>,
+
+    /// Market data feed. Without one, no market data is available.
+    #[serde(default)]
+    pub market_data: Option<MarketDataConfig>,
+}
+
+/// One account routed through the connector.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountConfig {
+    /// Account code shown to clients, e.g. `U1234567`.
+    pub id: String,
+
+    /// Base currency of the account.
+    #[serde(default = "default_currency")]
+    pub currency: String,
+
+    /// The broker adapter that holds the account.
+    pub broker: BrokerConfig,
+}
+
+/// A named set of accounts.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupConfig {
+    pub name: String,
+
+    /// Ids of the accounts in the group.
+    pub accounts: Vec<String>,
+}
+
+/// Broker adapter behind an account and its settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum BrokerConfig {
+    /// A simulated account kept inside the connector.
+    Paper {
+        /// Starting cash balance, in the account's base currency.
+        #[serde(default = "default_cash")]
+        cash: f64,
+    },
+
+    /// alpaca.markets, see <https://docs.alpaca.markets/reference>.
+    Alpaca {
+        key_id: String,
+        secret_key: String,
+
+        /// Base URL of the trading REST API. Defaults to the paper trading
+        /// environment.
+        #[serde(default = "default_alpaca_base_url")]
+        base_url: String,
+
+        /// URL of the `trade_updates` stream.
+        #[serde(default = "default_alpaca_stream_url")]
+        stream_url: String,
+    },
+}
+
+/// Market data adapter and its settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum MarketDataConfig {
+    /// polygon.io, see <https://polygon.io/docs/stocks>.
+    Polygon {
+        api_key: String,
+
+        /// Base URL of the REST API.
+        #[serde(default = "default_polygon_rest_url")]
+        rest_url: String,
+
+        /// URL of the WebSocket cluster.
+        #[serde(default = "default_polygon_ws_url")]
+        ws_url: String,
+    },
+}
+
+impl Config {
+    /// Read and validate the configuration file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> crate::Result<Config> {
+        let path = path.as_ref();
+
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        let config: Config = toml::from_str(&contents)
+            .map_err(|err| format!("invalid configuration in {}: {}", path.display(), err))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Returns the time zone of the trading day.
+    pub fn time_zone(&self) -> Tz {
+        // Checked by `validate`, the default only applies to a configuration
+        // built in code.
+        self.time_zone.parse().unwrap_or(chrono_tz::America::New_York)
+    }
+
+    /// Returns the account with the code `id`.
+    pub fn account(&self, id: &str) -> Option<&AccountConfig> {
+        self.accounts.iter().find(|account| account.id == id)
+    }
+
+    /// Returns the accounts in the group `name`, or `None` if there is no
+    /// such group. `All` is every account.
+    pub fn group(&self, name: &str) -> Option<Vec<&AccountConfig>> {
+        if name.eq_ignore_ascii_case(ALL_GROUP) {
+            return Some(self.accounts.iter().collect());
+        }
+
+        let group = self.groups.iter().find(|group| group.name == name)?;
+        Some(group.accounts.iter().filter_map(|id| self.account(id)).collect())
+    }
+
+    /// Check the settings that the TOML schema cannot express.
+    fn validate(&self) -> crate::Result<()> {
+        if self.accounts.is_empty() {
+            return Err("at least one account must be configured".into());
+        }
+
+        if self.time_zone.parse::<Tz>().is_err() {
+            return Err(format!("unknown time zone `{}`", self.time_zone).into());
+        }
+
+        let mut ids = HashSet::new();
+
+        for account in &self.accounts {
+            // The accounts are sent as a comma separated list.
+            if account.id.is_empty() || account.id.contains([',', '\0']) {
+                return Err(format!("invalid account id `{}`", account.id).into());
+            }
+
+            if !ids.insert(&account.id) {
+                return Err(format!("account `{}` is configured twice", account.id).into());
+            }
+
+            if account.currency.len() != 3 || !account.currency.chars().all(|c| c.is_ascii_uppercase()) {
+                return Err(format!(
+                    "invalid currency `{}` for account `{}`",
+                    account.currency, account.id
+                )
+                .into());
+            }
+        }
+
+        for group in &self.groups {
+            if group.name.eq_ignore_ascii_case(ALL_GROUP) {
+                return Err(format!("group name `{}` is reserved", group.name).into());
+            }
+
+            if let Some(id) = group.accounts.iter().find(|id| !ids.contains(id)) {
+                return Err(format!("group `{}` refers to unknown account `{}`", group.name, id).into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    /// A single paper account, used when no configuration file is given.
+    fn default() -> Config {
+        Config {
+            data_dir: default_data_dir(),
+            time_zone: default_time_zone(),
+            accounts: vec![AccountConfig {
+                id: "DU0000001".to_string(),
+                currency: default_currency(),
+                broker: BrokerConfig::Paper {
+                    cash: default_cash(),
+                },
+            }],
+            groups: Vec::new(),
+            market_data: None,
+        }
+    }
+}
+
+fn default_data_dir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+fn default_time_zone() -> String {
+    "America/New_York".to_string()
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+fn default_cash() -> f64 {
+    100_000.0
+}
+
+fn default_alpaca_base_url() -> String {
+    "https://paper-api.alpaca.markets".to_string()
+}
+
+fn default_alpaca_stream_url() -> String {
+    "wss://paper-api.alpaca.markets/stream".to_string()
+}
+
+fn default_polygon_rest_url() -> String {
+    "https://api.polygon.io".to_string()
+}
+
+fn default_polygon_ws_url() -> String {
+    "wss://socket.polygon.io/stocks".to_string()
+}
@@ -1,7 +1,7 @@
+use crate::db::ClientGuard;
 use crate::frame::{self, Frame};
 
 use bytes::{Buf, BytesMut};
-use tracing::info;
 use std::io::{self, Cursor};
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
 use tokio::net::TcpStream;
@@ -27,6 +27,18 @@ pub struct Connection {
 
     // The buffer for reading frames.
     buffer: BytesMut,
+
+    // The server version agreed on during the handshake. `None` until the
+    // handshake completes.
+    server_version: Option<u16>,
+
+    // Set when the client asked TWS to pace its requests with the
+    // `+PACEAPI` connection option instead of rejecting bursts.
+    pace_api: bool,
+
+    // The client id reserved by `startApi`. Dropping the connection releases
+    // it for other sessions.
+    client: Option<ClientGuard>,
 }
 
 impl Connection {
@@ -35,14 +47,54 @@ impl Connection {
     pub fn new(socket: TcpStream) -> Connection {
         Connection {
             stream: BufWriter::new(socket),
-            // Default to a 4KB read buffer. For the use case of mini redis,
+            // Default to a 4KB read buffer. For the use case of the connector,
             // this is fine. However, real applications will want to tune this
             // value to their specific use case. There is a high likelihood that
             // a larger read buffer will work better.
             buffer: BytesMut::with_capacity(4 * 1024),
+            server_version: None,
+            pace_api: false,
+            client: None,
         }
     }
 
+    /// The server version negotiated for this connection.
+    ///
+    /// Messages are encoded and decoded for this version. Before the
+    /// handshake completes, this is the oldest version the connector speaks.
+    pub fn server_version(&self) -> u16 {
+        self.server_version.unwrap_or(crate::MIN_API_VERSION)
+    }
+
+    /// Returns `true` once a server version has been negotiated.
+    pub fn is_negotiated(&self) -> bool {
+        self.server_version.is_some()
+    }
+
+    /// Record the server version agreed on during the handshake.
+    pub(crate) fn set_server_version(&mut self, version: u16) {
+        self.server_version = Some(version);
+    }
+
+    /// Returns `true` if the client connected with `+PACEAPI`.
+    pub fn pace_api(&self) -> bool {
+        self.pace_api
+    }
+
+    pub(crate) fn set_pace_api(&mut self, pace_api: bool) {
+        self.pace_api = pace_api;
+    }
+
+    /// The client id the session started with, once `startApi` succeeded.
+    pub fn client_id(&self) -> Option<i32> {
+        self.client.as_ref().map(ClientGuard::client_id)
+    }
+
+    /// Attach the client id reserved for this session.
+    pub(crate) fn set_client(&mut self, client: ClientGuard) {
+        self.client = Some(client);
+    }
+
     /// Read a single `Frame` value from the underlying stream.
     ///
     /// The function waits until it has retrieved enough data to parse a frame.
@@ -107,8 +159,6 @@ impl Connection {
                 // frame by checking the cursor position.
                 let len = buf.position() as usize;
 
-                info!("len in connection::parse_frame is: {}", len);
-
                 // Reset the position to zero before passing the cursor to
                 // `Frame::parse`.
                 buf.set_position(0);
@@ -150,6 +200,10 @@ impl Connection {
 
     /// Write a single `Frame` value to the underlying stream.
     ///
+    /// Every frame is written as a 4-byte big-endian size followed by the
+    /// encoded body. A `Handshake` frame is additionally preceded by the
+    /// `API\0` prelude.
+    ///
     /// The `Frame` value is written to the socket using the various `write_*`
     /// functions provided by `AsyncWrite`. Calling these functions directly on
     /// a `TcpStream` is **not** advised, as this will result in a large number of
@@ -157,111 +211,25 @@ impl Connection {
     /// write stream. The data will be written to the buffer. Once the buffer is
     /// full, it is flushed to the underlying socket.
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        // Arrays are encoded by encoding each entry. All other frame types are
-        // considered literals. For now, mini-redis is not able to encode
-        // recursive frame structures. See below for more details.
-        match frame {
-            Frame::Array(val) => {
-                // Iterate and encode each entry in the array.
-                for entry in &**val {
-                    self.write_value(entry).await?;
-                }
-            }
-            // The frame type is a literal. Encode the value directly.
-            _ => self.write_value(frame).await?,
+        if let Frame::Handshake(_) = frame {
+            self.stream.write_all(frame::API_PREFIX).await?;
         }
 
+        let body = frame.body();
+        let len = u32::try_from(body.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+
+        self.write_size(len).await?;
+        self.stream.write_all(&body).await?;
+
         // Ensure the encoded frame is written to the socket. The calls above
         // are to the buffered stream and writes. Calling `flush` writes the
         // remaining contents of the buffer to the socket.
         self.stream.flush().await
     }
 
-    /// Write a frame literal to the stream
-    async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
-        match frame {
-            Frame::Simple(val) => {
-                self.stream.write_u8(b'+').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            Frame::Error(val) => {
-                self.stream.write_u8(b'-').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            Frame::Integer(val) => {
-                self.stream.write_u8(b':').await?;
-                self.write_decimal(*val).await?;
-            }
-            Frame::Null => {
-                self.stream.write_all(b"$-1\r\n").await?;
-            }
-            Frame::Bulk(val) => {
-                let len = val.len();
-
-                self.write_size(len as u32).await?;
-                self.stream.write_all(val).await?;
-            }
-            // Frame::Bulk(val) => {
-            //     let len = val.len();
-
-            //     self.stream.write_u8(b'$').await?;
-            //     self.write_decimal(len as u64).await?;
-            //     self.stream.write_all(val).await?;
-            //     self.stream.write_all(b"\r\n").await?;
-            // }
-            Frame::Api(val) => {
-                let len = val.len();
-
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
-                self.stream.write_all(val).await?;
-                self.stream.write_all(b"\r\n").await?;
-            }
-            // Encoding an `Array` from within a value cannot be done using a
-            // recursive strategy. In general, async fns do not support
-            // recursion. Mini-redis has not needed to encode nested arrays yet,
-            // so for now it is skipped.
-            Frame::Array(_val) => unreachable!(),
-        }
-
-        Ok(())
-    }
-
-    /// Write a decimal frame to the stream
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
-        use std::io::Write;
-
-        // Convert the value to a string
-        let mut buf = [0u8; 20];
-        let mut buf = Cursor::new(&mut buf[..]);
-        write!(&mut buf, "{}", val)?;
-
-        let pos = buf.position() as usize;
-        self.stream.write_all(&buf.get_ref()[..pos]).await?;
-        self.stream.write_all(b"\r\n").await?;
-
-        Ok(())
-    }
-
-    /// Write a decimal frame to the stream
+    /// Write the 4-byte big-endian size prefix to the stream
     async fn write_size(&mut self, val: u32) -> io::Result<()> {
-        let size: [u8; 4] = val.to_be_bytes();
-        self.stream.write_all(&size).await?;
-
-        Ok(())
-    }
-
-
-    /// u32_to_vec_u8 converts u32 to a vector of four bytes since we need
-    /// the same prefix size for all messages
-    pub async fn u32_to_vec_u8(value: u32) -> Vec<u8> {
-        let byte1 = ((value >> 24) & 0xFF) as u8;
-        let byte2 = ((value >> 16) & 0xFF) as u8;
-        let byte3 = ((value >> 8) & 0xFF) as u8;
-        let byte4 = (value & 0xFF) as u8;
-
-        vec![byte1, byte2, byte3, byte4]
+        self.stream.write_all(&val.to_be_bytes()).await
     }
 }
@@ -1,369 +1,506 @@
-use tokio::sync::{broadcast, Notify};
-use tokio::time::{self, Duration, Instant};
-
-use bytes::Bytes;
-use std::collections::{BTreeSet, HashMap};
+use crate::broker::{self, Broker};
+use crate::executions::{Execution, Executions};
+use crate::market_data::{self, MarketDataProvider};
+use crate::order_ids::OrderIds;
+use crate::positions::{Holding, Positions};
+use crate::ticker::DelayedFeed;
+use crate::orders::{self, CompletedOrder, OrderBook, OrderEvent, OrderHistory, TrackedOrder};
+use crate::Config;
+
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
-use tracing::debug;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
 
-/// A wrapper around a `Db` instance. This exists to allow orderly cleanup
-/// of the `Db` by signalling the background purge task to shut down when
-/// this struct is dropped.
-#[derive(Debug)]
-pub(crate) struct DbDropGuard {
-    /// The `Db` instance that will be shut down when this `DbHolder` struct
-    /// is dropped.
-    db: Db,
-}
+/// Number of order events a slow connection may lag behind before it misses
+/// some.
+const ORDER_EVENTS_CAPACITY: usize = 1024;
 
 /// Server state shared across all connections.
 ///
-/// `Db` contains a `HashMap` storing the key/value data and all
-/// `broadcast::Sender` values for active pub/sub channels.
-///
 /// A `Db` instance is a handle to shared state. Cloning `Db` is shallow and
 /// only incurs an atomic ref count increment.
-///
-/// When a `Db` value is created, a background task is spawned. This task is
-/// used to expire values after the requested duration has elapsed. The task
-/// runs until all instances of `Db` are dropped, at which point the task
-/// terminates.
 #[derive(Debug, Clone)]
 pub(crate) struct Db {
-    /// Handle to shared state. The background task will also have an
-    /// `Arc<Shared>`.
+    /// Handle to shared state.
     shared: Arc<Shared>,
 }
 
 #[derive(Debug)]
 struct Shared {
+    /// Settings the connector was started with. They never change while it
+    /// runs, so they live outside of the mutex.
+    config: Config,
+
+    /// The broker adapter of each account, by account id.
+    brokers: HashMap<String, Arc<dyn Broker>>,
+
+    /// The market data feed, if one is configured.
+    market_data: Option<Arc<dyn MarketDataProvider>>,
+
+    /// The updates of the market data feed, `DELAY` late, if one is
+    /// configured.
+    delayed: Option<DelayedFeed>,
+
+    /// Every change of a tracked order is published here. The connections
+    /// forward the changes of their client's orders.
+    order_events: broadcast::Sender<OrderEvent>,
+
     /// The shared state is guarded by a mutex. This is a `std::sync::Mutex` and
     /// not a Tokio mutex. This is because there are no asynchronous operations
-    /// being performed while holding the mutex. Additionally, the critical
-    /// sections are very small.
-    ///
-    /// A Tokio mutex is mostly intended to be used when locks need to be held
-    /// across `.await` yield points. All other cases are **usually** best
-    /// served by a std mutex. If the critical section does not include any
-    /// async operations but is long (CPU intensive or performing blocking
-    /// operations), then the entire operation, including waiting for the mutex,
-    /// is considered a "blocking" operation and `tokio::task::spawn_blocking`
-    /// should be used.
+    /// being performed while holding the mutex. Claiming an order id and
+    /// recording an execution or a completed order do write to their file
+    /// with the mutex held, so the files change in the same order as the
+    /// state. These are small, blocking writes.
     state: Mutex<State>,
-
-    /// Notifies the background task handling entry expiration. The background
-    /// task waits on this to be notified, then checks for expired values or the
-    /// shutdown signal.
-    background_task: Notify,
 }
 
 #[derive(Debug)]
 struct State {
-    /// The key-value data. We are not trying to do anything fancy so a
-    /// `std::collections::HashMap` works fine.
-    entries: HashMap<String, Entry>,
+    /// Client ids of the sessions that are currently connected.
+    client_ids: HashSet<i32>,
 
-    /// The pub/sub key-space. Redis uses a **separate** key space for key-value
-    /// and pub/sub. `mini-redis` handles this by using a separate `HashMap`.
-    pub_sub: HashMap<String, broadcast::Sender<Bytes>>,
+    /// Next valid order id of every client, persisted across restarts.
+    order_ids: OrderIds,
 
-    /// Tracks key TTLs.
-    ///
-    /// A `BTreeSet` is used to maintain expirations sorted by when they expire.
-    /// This allows the background task to iterate this map to find the value
-    /// expiring next.
-    ///
-    /// While highly unlikely, it is possible for more than one expiration to be
-    /// created for the same instant. Because of this, the `Instant` is
-    /// insufficient for the key. A unique key (`String`) is used to
-    /// break these ties.
-    expirations: BTreeSet<(Instant, String)>,
-
-    /// True when the Db instance is shutting down. This happens when all `Db`
-    /// values drop. Setting this to `true` signals to the background task to
-    /// exit.
-    shutdown: bool,
+    /// The orders placed by all clients.
+    orders: OrderBook,
+
+    /// Fills of the last days, persisted across restarts.
+    executions: Executions,
+
+    /// Orders that completed today, persisted across restarts.
+    history: OrderHistory,
+
+    /// Positions of the accounts, loaded from the brokers when first needed.
+    positions: Positions,
 }
 
-/// Entry in the key-value store
+/// A client id reserved for one connection.
+///
+/// The id stays in use for as long as the guard is alive and is released
+/// when it is dropped, which happens when the connection closes.
 #[derive(Debug)]
-struct Entry {
-    /// Stored data
-    data: Bytes,
-
-    /// Instant at which the entry expires and should be removed from the
-    /// database.
-    expires_at: Option<Instant>,
+pub(crate) struct ClientGuard {
+    db: Db,
+    client_id: i32,
 }
 
-impl DbDropGuard {
-    /// Create a new `DbHolder`, wrapping a `Db` instance. When this is dropped
-    /// the `Db`'s purge task will be shut down.
-    pub(crate) fn new() -> DbDropGuard {
-        DbDropGuard { db: Db::new() }
-    }
+impl Db {
+    /// Create a new `Db` instance for `config`, loading the persisted state
+    /// from its data directory.
+    ///
+    /// Must be called from within a Tokio runtime, the updates of every
+    /// broker are followed by a background task.
+    pub(crate) fn open(config: Config) -> crate::Result<Db> {
+        let mut brokers = HashMap::new();
+        for account in &config.accounts {
+            brokers.insert(account.id.clone(), broker::connect(account)?);
+        }
 
-    /// Get the shared database. Internally, this is an
-    /// `Arc`, so a clone only increments the ref count.
-    pub(crate) fn db(&self) -> Db {
-        self.db.clone()
+        Db::with_brokers(config, brokers)
     }
-}
 
-impl Drop for DbDropGuard {
-    fn drop(&mut self) {
-        // Signal the 'Db' instance to shut down the task that purges expired keys
-        self.db.shutdown_purge_task();
-    }
-}
+    /// Create a new `Db` instance for `config` whose accounts are held by
+    /// `brokers`, by account id, instead of the configured adapters.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub(crate) fn with_brokers(config: Config, brokers: HashMap<String, Arc<dyn Broker>>) -> crate::Result<Db> {
+        let order_ids = OrderIds::open(&config.data_dir)?;
+        let executions = Executions::open(&config.data_dir)?;
+        let history = OrderHistory::open(&config.data_dir)?;
+
+        // Clients key their records by permanent id, which must not be
+        // handed out again after a restart.
+        let last_perm_id = executions.last_perm_id().max(history.last_perm_id());
+
+        let market_data = config.market_data.as_ref().map(market_data::connect).transpose()?;
+        let delayed = market_data.clone().map(DelayedFeed::new);
+
+        let (order_events, _) = broadcast::channel(ORDER_EVENTS_CAPACITY);
 
-impl Db {
-    /// Create a new, empty, `Db` instance. Allocates shared state and spawns a
-    /// background task to manage key expiration.
-    pub(crate) fn new() -> Db {
         let shared = Arc::new(Shared {
+            config,
+            brokers,
+            market_data,
+            delayed,
+            order_events,
             state: Mutex::new(State {
-                entries: HashMap::new(),
-                pub_sub: HashMap::new(),
-                expirations: BTreeSet::new(),
-                shutdown: false,
+                client_ids: HashSet::new(),
+                order_ids,
+                orders: OrderBook::new(last_perm_id),
+                executions,
+                history,
+                positions: Positions::new(),
             }),
-            background_task: Notify::new(),
         });
 
-        // Start the background task.
-        tokio::spawn(purge_expired_tasks(shared.clone()));
+        let db = Db { shared };
+
+        for (account, broker) in &db.shared.brokers {
+            tokio::spawn(orders::track(db.clone(), account.clone(), broker.updates()));
+        }
+
+        Ok(db)
+    }
+
+    /// Returns the settings the connector was started with.
+    pub(crate) fn config(&self) -> &Config {
+        &self.shared.config
+    }
+
+    /// Returns the broker adapter of `account`.
+    pub(crate) fn broker(&self, account: &str) -> Option<Arc<dyn Broker>> {
+        self.shared.brokers.get(account).cloned()
+    }
+
+    /// Returns the market data feed, or `None` if none is configured.
+    pub(crate) fn market_data(&self) -> Option<Arc<dyn MarketDataProvider>> {
+        self.shared.market_data.clone()
+    }
+
+    /// Returns the updates of the market data feed, `DELAY` late, or `None`
+    /// if no feed is configured.
+    pub(crate) fn delayed_market_data(&self) -> Option<&DelayedFeed> {
+        self.shared.delayed.as_ref()
+    }
 
-        Db { shared }
+    /// Returns the codes of the configured accounts as sent in
+    /// `managedAccounts`, separated by commas.
+    pub(crate) fn managed_accounts(&self) -> String {
+        let ids: Vec<_> = self.config().accounts.iter().map(|account| account.id.as_str()).collect();
+        ids.join(",")
     }
 
-    /// Get the value associated with a key.
+    /// Reserve `client_id` for a new session.
     ///
-    /// Returns `None` if there is no value associated with the key. This may be
-    /// due to never having assigned a value to the key or a previously assigned
-    /// value expired.
-    pub(crate) fn get(&self, key: &str) -> Option<Bytes> {
-        // Acquire the lock, get the entry and clone the value.
-        //
-        // Because data is stored using `Bytes`, a clone here is a shallow
-        // clone. Data is not copied.
+    /// Returns `None` if another connected session already uses the id.
+    pub(crate) fn register_client(&self, client_id: i32) -> Option<ClientGuard> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if !state.client_ids.insert(client_id) {
+            return None;
+        }
+
+        Some(ClientGuard {
+            db: self.clone(),
+            client_id,
+        })
+    }
+
+    /// Returns the next valid order id of `client_id`.
+    pub(crate) fn next_order_id(&self, client_id: i32) -> i32 {
         let state = self.shared.state.lock().unwrap();
-        state.entries.get(key).map(|entry| entry.data.clone())
+        state.order_ids.next_id(client_id)
     }
 
-    /// Set the value associated with a key along with an optional expiration
-    /// Duration.
+    /// Record that `client_id` used `order_id` for a new order.
     ///
-    /// If a value is already associated with the key, it is removed.
-    pub(crate) fn set(&self, key: String, value: Bytes, expire: Option<Duration>) {
+    /// Returns `Ok(false)` if the id is lower than the client's next valid
+    /// order id, in which case the order must be refused.
+    pub(crate) fn claim_order_id(&self, client_id: i32, order_id: i32) -> crate::Result<bool> {
         let mut state = self.shared.state.lock().unwrap();
+        state.order_ids.claim(client_id, order_id)
+    }
 
-        // If this `set` becomes the key that expires **next**, the background
-        // task needs to be notified so it can update its state.
-        //
-        // Whether or not the task needs to be notified is computed during the
-        // `set` routine.
-        let mut notify = false;
-
-        let expires_at = expire.map(|duration| {
-            // `Instant` at which the key expires.
-            let when = Instant::now() + duration;
-
-            // Only notify the worker task if the newly inserted expiration is the
-            // **next** key to evict. In this case, the worker needs to be woken up
-            // to update its state.
-            notify = state
-                .next_expiration()
-                .map(|expiration| expiration > when)
-                .unwrap_or(true);
-
-            when
-        });
+    /// Subscribe to the changes of all tracked orders.
+    pub(crate) fn order_events(&self) -> broadcast::Receiver<OrderEvent> {
+        self.shared.order_events.subscribe()
+    }
 
-        // Insert the entry into the `HashMap`.
-        let prev = state.entries.insert(
-            key.clone(),
-            Entry {
-                data: value,
-                expires_at,
-            },
-        );
-
-        // If there was a value previously associated with the key **and** it
-        // had an expiration time. The associated entry in the `expirations` map
-        // must also be removed. This avoids leaking data.
-        if let Some(prev) = prev {
-            if let Some(when) = prev.expires_at {
-                // clear expiration
-                state.expirations.remove(&(when, key.clone()));
-            }
+    /// Returns order `order_id` of `client_id`.
+    pub(crate) fn order(&self, client_id: i32, order_id: i32) -> Option<TrackedOrder> {
+        let state = self.shared.state.lock().unwrap();
+        state.orders.get(client_id, order_id).cloned()
+    }
+
+    /// Returns the tracked orders matching `filter`.
+    pub(crate) fn orders(&self, filter: impl Fn(&TrackedOrder) -> bool) -> Vec<TrackedOrder> {
+        let state = self.shared.state.lock().unwrap();
+        state.orders.select(filter)
+    }
+
+    /// Start tracking a newly placed order and announce it.
+    pub(crate) fn insert_order(&self, order: TrackedOrder) -> TrackedOrder {
+        let mut state = self.shared.state.lock().unwrap();
+        let order = state.orders.insert(order).clone();
+
+        self.publish(OrderEvent::Changed(Box::new(order.clone())));
+        order
+    }
+
+    /// Change order `order_id` of `client_id` with `f` and announce the
+    /// change. `f` returns `false` if it left the order as it was.
+    ///
+    /// Returns the order after the change, or `None` if there is no such
+    /// order or it did not change.
+    pub(crate) fn update_order(
+        &self,
+        client_id: i32,
+        order_id: i32,
+        f: impl FnOnce(&mut TrackedOrder) -> bool,
+    ) -> Option<TrackedOrder> {
+        let mut state = self.shared.state.lock().unwrap();
+        let order = state.orders.get_mut(client_id, order_id)?;
+
+        let was_done = order.status.is_done();
+        if !f(order) {
+            return None;
+        }
+
+        let order = order.clone();
+        if !was_done && order.status.is_done() {
+            self.complete(&mut state, &order, true);
+        }
+
+        self.publish(OrderEvent::Changed(Box::new(order.clone())));
+        Some(order)
+    }
+
+    /// Give order `order_id` of `client_id` a new identifier at the broker,
+    /// for the order that replaces it there, returning the order as it was.
+    pub(crate) fn revise_order(&self, client_id: i32, order_id: i32) -> Option<TrackedOrder> {
+        let mut state = self.shared.state.lock().unwrap();
+        state.orders.revise(client_id, order_id)
+    }
+
+    /// Apply a change reported by the broker to the order it belongs to, and
+    /// announce it.
+    ///
+    /// Returns the order before and after the change, or `None` if the order
+    /// was not placed through the connector or the update is stale.
+    pub(crate) fn apply_order_update(
+        &self,
+        update: &broker::OrderUpdate,
+    ) -> Option<(TrackedOrder, TrackedOrder)> {
+        let mut state = self.shared.state.lock().unwrap();
+        let order = state.orders.find_mut(&update.order.client_order_id)?;
+
+        let before = order.clone();
+        if !order.apply(update) {
+            return None;
         }
 
-        // Track the expiration. If we insert before remove that will cause bug
-        // when current `(when, key)` equals prev `(when, key)`. Remove then insert
-        // can avoid this.
-        if let Some(when) = expires_at {
-            state.expirations.insert((when, key));
+        let order = order.clone();
+        if !before.status.is_done() && order.status.is_done() {
+            self.complete(&mut state, &order, true);
         }
 
-        // Release the mutex before notifying the background task. This helps
-        // reduce contention by avoiding the background task waking up only to
-        // be unable to acquire the mutex due to this function still holding it.
-        drop(state);
+        self.publish(OrderEvent::Changed(Box::new(order.clone())));
+        Some((before, order))
+    }
+
+    /// Describe `order`, which the broker of `account` reports, if it was
+    /// placed without going through the connector.
+    ///
+    /// Returns `None` if the order is tracked.
+    pub(crate) fn foreign_order(&self, account: &str, order: &broker::Order) -> Option<TrackedOrder> {
+        let mut state = self.shared.state.lock().unwrap();
 
-        if notify {
-            // Finally, only notify the background task if it needs to update
-            // its state to reflect a new expiration.
-            self.shared.background_task.notify_one();
+        if state.orders.is_tracked(&order.client_order_id) {
+            return None;
         }
+
+        let currency = self
+            .config()
+            .account(account)
+            .map(|account| account.currency.as_str())
+            .unwrap_or_default();
+        let perm_id = state.orders.foreign_perm_id(&order.id);
+
+        Some(TrackedOrder::foreign(account, currency, order, perm_id))
     }
 
-    /// Returns a `Receiver` for the requested channel.
+    /// Record the fill the broker of `account` reports in `update`, if any.
     ///
-    /// The returned `Receiver` is used to receive values broadcast by `PUBLISH`
-    /// commands.
-    pub(crate) fn subscribe(&self, key: String) -> broadcast::Receiver<Bytes> {
-        use std::collections::hash_map::Entry;
+    /// Returns the new execution, or `None` if the update has no fill or the
+    /// fill was recorded before.
+    pub(crate) fn record_fill(
+        &self,
+        account: &str,
+        update: &broker::OrderUpdate,
+    ) -> crate::Result<Option<Execution>> {
+        let Some(fill) = &update.fill else {
+            return Ok(None);
+        };
+
+        let tracked = {
+            let state = self.shared.state.lock().unwrap();
+            state.orders.find(&update.order.client_order_id).cloned()
+        };
+
+        let Some(order) = tracked.or_else(|| self.foreign_order(account, &update.order)) else {
+            return Ok(None);
+        };
+
+        let mut execution = Execution::new(&order, update, fill);
 
-        // Acquire the mutex
         let mut state = self.shared.state.lock().unwrap();
+        execution.realized_pnl = state.positions.realized_pnl(&execution);
 
-        // If there is no entry for the requested channel, then create a new
-        // broadcast channel and associate it with the key. If one already
-        // exists, return an associated receiver.
-        match state.pub_sub.entry(key) {
-            Entry::Occupied(e) => e.get().subscribe(),
-            Entry::Vacant(e) => {
-                // No broadcast channel exists yet, so create one.
-                //
-                // The channel is created with a capacity of `1024` messages. A
-                // message is stored in the channel until **all** subscribers
-                // have seen it. This means that a slow subscriber could result
-                // in messages being held indefinitely.
-                //
-                // When the channel's capacity fills up, publishing will result
-                // in old messages being dropped. This prevents slow consumers
-                // from blocking the entire system.
-                let (tx, rx) = broadcast::channel(1024);
-                e.insert(tx);
-                rx
-            }
+        if !state.executions.record(execution.clone())? {
+            return Ok(None);
         }
+
+        Ok(Some(execution))
     }
 
-    /// Publish a message to the channel. Returns the number of subscribers
-    /// listening on the channel.
-    pub(crate) fn publish(&self, key: &str, value: Bytes) -> usize {
+    /// Returns the executions matching `filter`, oldest first.
+    pub(crate) fn executions(&self, filter: impl Fn(&Execution) -> bool) -> Vec<Execution> {
         let state = self.shared.state.lock().unwrap();
+        state.executions.select(filter)
+    }
 
-        state
-            .pub_sub
-            .get(key)
-            // On a successful message send on the broadcast channel, the number
-            // of subscribers is returned. An error indicates there are no
-            // receivers, in which case, `0` should be returned.
-            .map(|tx| tx.send(value).unwrap_or(0))
-            // If there is no entry for the channel key, then there are no
-            // subscribers. In this case, return `0`.
-            .unwrap_or(0)
+    /// Returns the open positions of `account`, loading them from its broker
+    /// unless they were loaded already, which happens as the broker's updates
+    /// start being followed.
+    pub(crate) async fn positions(&self, account: &str) -> Result<Vec<Holding>, broker::Error> {
+        if let Some(holdings) = self.shared.state.lock().unwrap().positions.get(account) {
+            return Ok(holdings);
+        }
+
+        let broker = self
+            .broker(account)
+            .ok_or_else(|| format!("no broker for account {}", account))?;
+        let positions = broker.positions().await?;
+
+        let currency = self
+            .config()
+            .account(account)
+            .map(|account| account.currency.as_str())
+            .unwrap_or_default();
+
+        let mut state = self.shared.state.lock().unwrap();
+        state.positions.load(account, currency, &positions);
+        Ok(state.positions.get(account).unwrap_or_default())
     }
 
-    /// Signals the purge background task to shut down. This is called by the
-    /// `DbShutdown`s `Drop` implementation.
-    fn shutdown_purge_task(&self) {
-        // The background task must be signaled to shut down. This is done by
-        // setting `State::shutdown` to `true` and signalling the task.
+    /// Update the position `execution` changes and announce it.
+    pub(crate) fn apply_execution(&self, execution: &Execution) {
         let mut state = self.shared.state.lock().unwrap();
-        state.shutdown = true;
 
-        // Drop the lock before signalling the background task. This helps
-        // reduce lock contention by ensuring the background task doesn't
-        // wake up only to be unable to acquire the mutex.
-        drop(state);
-        self.shared.background_task.notify_one();
+        if let Some(holding) = state.positions.apply(execution) {
+            self.publish(OrderEvent::Position(Box::new(holding)));
+        }
     }
-}
 
-impl Shared {
-    /// Purge all expired keys and return the `Instant` at which the **next**
-    /// key will expire. The background task will sleep until this instant.
-    fn purge_expired_keys(&self) -> Option<Instant> {
-        let mut state = self.state.lock().unwrap();
+    /// Record that `order`, which was placed without going through the
+    /// connector, is done.
+    pub(crate) fn complete_foreign_order(&self, order: &TrackedOrder) {
+        let mut state = self.shared.state.lock().unwrap();
+        self.complete(&mut state, order, false);
+    }
 
-        if state.shutdown {
-            // The database is shutting down. All handles to the shared state
-            // have dropped. The background task should exit.
-            return None;
-        }
+    /// Returns the orders completed today, only those placed through the
+    /// connector if `api_only` is set.
+    pub(crate) fn completed_orders(&self, api_only: bool) -> Vec<CompletedOrder> {
+        let state = self.shared.state.lock().unwrap();
+        state.history.today(api_only)
+    }
 
-        // This is needed to make the borrow checker happy. In short, `lock()`
-        // returns a `MutexGuard` and not a `&mut State`. The borrow checker is
-        // not able to see "through" the mutex guard and determine that it is
-        // safe to access both `state.expirations` and `state.entries` mutably,
-        // so we get a "real" mutable reference to `State` outside of the loop.
-        let state = &mut *state;
-
-        // Find all keys scheduled to expire **before** now.
-        let now = Instant::now();
-
-        while let Some(&(when, ref key)) = state.expirations.iter().next() {
-            if when > now {
-                // Done purging, `when` is the instant at which the next key
-                // expires. The worker task will wait until this instant.
-                return Some(when);
-            }
-
-            // The key expired, remove it
-            state.entries.remove(key);
-            state.expirations.remove(&(when, key.clone()));
+    /// Add `order`, which just became done, to the history. A failure to
+    /// store it is only logged, the order is done either way.
+    fn complete(&self, state: &mut State, order: &TrackedOrder, api: bool) {
+        let parent_perm_id = match order.order.parent_id {
+            0 => 0,
+            parent_id => state
+                .orders
+                .get(order.client_id, parent_id)
+                .map(|parent| parent.perm_id)
+                .unwrap_or_default(),
+        };
+
+        if let Err(err) = state.history.record(CompletedOrder::new(order, parent_perm_id, api)) {
+            warn!(client_id = order.client_id, order_id = order.order_id, cause = %err, "failed to record completed order");
         }
+    }
 
-        None
+    /// Announce an order event to the connections.
+    pub(crate) fn publish(&self, event: OrderEvent) {
+        // There is no receiver when no client is connected, which is fine.
+        let _ = self.shared.order_events.send(event);
     }
+}
 
-    /// Returns `true` if the database is shutting down
-    ///
-    /// The `shutdown` flag is set when all `Db` values have dropped, indicating
-    /// that the shared state can no longer be accessed.
-    fn is_shutdown(&self) -> bool {
-        self.state.lock().unwrap().shutdown
+impl ClientGuard {
+    /// Returns the reserved client id.
+    pub(crate) fn client_id(&self) -> i32 {
+        self.client_id
     }
 }
 
-impl State {
-    fn next_expiration(&self) -> Option<Instant> {
-        self.expirations
-            .iter()
-            .next()
-            .map(|expiration| expiration.0)
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        debug!(client_id = self.client_id, "releasing client id");
+
+        let mut state = self.db.shared.state.lock().unwrap();
+        state.client_ids.remove(&self.client_id);
     }
 }
 
-/// Routine executed by the background task.
-///
-/// Wait to be notified. On notification, purge any expired keys from the shared
-/// state handle. If `shutdown` is set, terminate the task.
-async fn purge_expired_tasks(shared: Arc<Shared>) {
-    // If the shutdown flag is set, then the task should exit.
-    while !shared.is_shutdown() {
-        // Purge all keys that are expired. The function returns the instant at
-        // which the **next** key will expire. The worker should wait until the
-        // instant has passed then purge again.
-        if let Some(when) = shared.purge_expired_keys() {
-            // Wait until the next key expires **or** until the background task
-            // is notified. If the task is notified, then it must reload its
-            // state as new keys have been set to expire early. This is done by
-            // looping.
-            tokio::select! {
-                _ = time::sleep_until(when) => {}
-                _ = shared.background_task.notified() => {}
-            }
-        } else {
-            // There are no keys expiring in the future. Wait until the task is
-            // notified.
-            shared.background_task.notified().await;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::NextValidOrderId;
+    use crate::messages::errors;
+    use crate::orders::{Contract, Order};
+    use crate::{Connection, Subscriptions};
+
+    use std::fs;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[tokio::test]
+    async fn perm_ids_follow_the_stored_ones() {
+        let data_dir = std::env::temp_dir().join(format!("db_perm_ids_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let mut done = TrackedOrder::new(1, 1, "DU1".to_string(), Contract::default(), Order::default());
+        done.perm_id = 41;
+        let mut history = OrderHistory::open(&data_dir).unwrap();
+        history.record(CompletedOrder::new(&done, 0, true)).unwrap();
+
+        let config = Config {
+            data_dir,
+            ..Config::default()
+        };
+        let db = Db::with_brokers(config, HashMap::new()).unwrap();
+
+        let order = TrackedOrder::new(1, 2, "DU1".to_string(), Contract::default(), Order::default());
+        assert_eq!(db.insert_order(order).perm_id, 42);
     }
 
-    debug!("Purge background task shut down")
+    #[tokio::test]
+    async fn client_id_is_held_until_its_guard_drops() {
+        let data_dir = std::env::temp_dir().join(format!("db_client_ids_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let config = Config {
+            data_dir,
+            ..Config::default()
+        };
+        let db = Db::with_brokers(config, HashMap::new()).unwrap();
+
+        let guard = db.register_client(7).unwrap();
+        assert!(db.register_client(7).is_none());
+
+        // A second session with the id gets error 326.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = Connection::new(TcpStream::connect(listener.local_addr().unwrap()).await.unwrap());
+        let mut server = Connection::new(listener.accept().await.unwrap().0);
+        let (mut subscriptions, _) = Subscriptions::new();
+
+        NextValidOrderId::new(2, 7)
+            .apply(&db, &mut server, &mut subscriptions)
+            .await
+            .unwrap();
+        assert_eq!(server.client_id(), None);
+
+        let response = client.read_frame().await.unwrap().unwrap();
+        assert_eq!(response, errors::CLIENT_ID_IN_USE.to_frame(-1, server.server_version()));
+
+        drop(guard);
+        assert_eq!(db.register_client(7).map(|guard| guard.client_id()), Some(7));
+    }
 }
@@ -0,0 +1,211 @@
+use crate::broker::{Fill, OrderUpdate, Side};
+use crate::journal::{Journal, Record};
+use crate::messages::{Encoder, OutgoingMessage};
+use crate::orders::{con_id, TrackedOrder};
+use crate::Frame;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Name of the file the executions are stored in, inside the data directory.
+const FILE_NAME: &str = "executions.jsonl";
+
+/// Number of days an execution is kept. TWS reports at most a week of
+/// executions as well.
+const KEEP_DAYS: i64 = 7;
+
+/// Sent by TWS for amounts it does not know, such as the realized P&L of an
+/// execution that opens a position.
+const UNSET_DOUBLE: &str = "1.7976931348623157E308";
+
+/// A fill of an order, as reported in `execDetails`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Execution {
+    /// Identifier the broker assigned to the fill. It is the same every time
+    /// the execution is reported.
+    pub(crate) exec_id: String,
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) account: String,
+
+    /// Order that filled, 0 for orders placed without going through the
+    /// connector.
+    pub(crate) client_id: i32,
+    pub(crate) order_id: i32,
+    pub(crate) perm_id: i32,
+
+    pub(crate) symbol: String,
+    pub(crate) sec_type: String,
+    pub(crate) exchange: String,
+    pub(crate) currency: String,
+    pub(crate) local_symbol: String,
+
+    /// `BOT` or `SLD`.
+    pub(crate) side: String,
+    pub(crate) shares: f64,
+    pub(crate) price: f64,
+
+    /// Quantity filled and average price of the order, this fill included.
+    pub(crate) cum_qty: f64,
+    pub(crate) avg_price: f64,
+
+    pub(crate) order_ref: String,
+    pub(crate) model_code: String,
+    pub(crate) commission: f64,
+
+    /// Profit or loss realized by reducing a position, `None` if the
+    /// execution opened one or the position was not known.
+    #[serde(default)]
+    pub(crate) realized_pnl: Option<f64>,
+}
+
+/// The executions of the last `KEEP_DAYS` days.
+///
+/// Clients keep trade journals keyed by execution id, so executions are
+/// reported the same way after a restart. They are kept in a `Journal`.
+#[derive(Debug)]
+pub(crate) struct Executions {
+    journal: Journal<Execution>,
+}
+
+impl Record for Execution {
+    fn id(&self) -> &str {
+        &self.exec_id
+    }
+}
+
+impl Execution {
+    /// Describe `fill`, which the broker reported for `order` in `update`.
+    pub(crate) fn new(order: &TrackedOrder, update: &OrderUpdate, fill: &Fill) -> Execution {
+        let (cum_qty, avg_price) = order.totals(&update.order);
+
+        Execution {
+            exec_id: fill.execution_id.clone(),
+            time: fill.timestamp,
+            account: order.account.clone(),
+            client_id: order.client_id,
+            order_id: order.order_id,
+            perm_id: order.perm_id,
+            symbol: order.contract.symbol.clone(),
+            sec_type: order.contract.sec_type.clone(),
+            exchange: order.contract.exchange.clone(),
+            currency: order.contract.currency.clone(),
+            local_symbol: order.contract.local_symbol.clone(),
+            side: match update.order.side {
+                Side::Buy => "BOT",
+                Side::Sell => "SLD",
+            }
+            .to_string(),
+            shares: fill.quantity,
+            price: fill.price,
+            cum_qty,
+            avg_price,
+            order_ref: order.order.order_ref.clone(),
+            model_code: order.order.model_code.clone(),
+            commission: 0.0,
+            realized_pnl: None,
+        }
+    }
+
+    /// Returns the number of shares, negative for a sale.
+    pub(crate) fn signed_shares(&self) -> f64 {
+        match self.side.as_str() {
+            "BOT" => self.shares,
+            _ => -self.shares,
+        }
+    }
+
+    /// Encode the `execDetails` message answering request `req_id`, -1 for
+    /// executions reported as they happen.
+    pub(crate) fn exec_details_frame(&self, req_id: i64, server_version: u16) -> Frame {
+        Encoder::new(OutgoingMessage::ExecutionData, server_version)
+            .put("reqId", req_id)
+            .put("orderId", self.order_id)
+            .put("conId", con_id(&self.symbol))
+            .put("symbol", &self.symbol)
+            .put("secType", &self.sec_type)
+            .put("lastTradeDateOrContractMonth", "")
+            .put("strike", 0)
+            .put("right", "")
+            .put("multiplier", "")
+            .put("exchange", &self.exchange)
+            .put("currency", &self.currency)
+            .put("localSymbol", &self.local_symbol)
+            .put("tradingClass", "")
+            .put("execId", &self.exec_id)
+            .put("time", self.time.format("%Y%m%d-%H:%M:%S"))
+            .put("acctNumber", &self.account)
+            .put("execExchange", &self.exchange)
+            .put("side", &self.side)
+            .put("shares", self.shares)
+            .put("price", self.price)
+            .put("permId", self.perm_id)
+            .put("clientId", self.client_id)
+            .put("liquidation", 0)
+            .put("cumQty", self.cum_qty)
+            .put("avgPrice", self.avg_price)
+            .put("orderRef", &self.order_ref)
+            .put("evRule", "")
+            .put("evMultiplier", 0)
+            .put("modelCode", &self.model_code)
+            .put("lastLiquidity", 0)
+            .into_frame()
+    }
+
+    /// Encode the `commissionReport` message of the execution.
+    pub(crate) fn commission_report_frame(&self, server_version: u16) -> Frame {
+        Encoder::new(OutgoingMessage::CommissionReport, server_version)
+            .put("execId", &self.exec_id)
+            .put("commission", self.commission)
+            .put("currency", &self.currency)
+            .put(
+                "realizedPNL",
+                self.realized_pnl.map_or(UNSET_DOUBLE.to_string(), |pnl| pnl.to_string()),
+            )
+            .put("yield", UNSET_DOUBLE)
+            .put("yieldRedemptionDate", 0)
+            .into_frame()
+    }
+}
+
+impl Executions {
+    /// Load the executions stored in `data_dir`, dropping the ones older than
+    /// `KEEP_DAYS`. A missing file means there was no execution yet.
+    pub(crate) fn open(data_dir: impl Into<PathBuf>) -> crate::Result<Executions> {
+        let oldest = Utc::now() - Duration::days(KEEP_DAYS);
+        let journal = Journal::open(data_dir.into().join(FILE_NAME), |execution: &Execution| {
+            execution.time >= oldest
+        })?;
+
+        Ok(Executions { journal })
+    }
+
+    /// Add `execution` and store it.
+    ///
+    /// Returns `Ok(false)` without changing anything if it was recorded
+    /// before, brokers may report a fill again after reconnecting.
+    pub(crate) fn record(&mut self, execution: Execution) -> crate::Result<bool> {
+        self.journal.record(execution)
+    }
+
+    /// Returns the executions matching `filter`, oldest first.
+    pub(crate) fn select(&self, filter: impl Fn(&Execution) -> bool) -> Vec<Execution> {
+        self.journal
+            .records()
+            .iter()
+            .filter(|execution| filter(execution))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the highest permanent id of the executions, 0 if there is
+    /// none.
+    pub(crate) fn last_perm_id(&self) -> i32 {
+        self.journal
+            .records()
+            .iter()
+            .map(|execution| execution.perm_id)
+            .max()
+            .unwrap_or_default()
+    }
+}
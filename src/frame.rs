@@ -1,23 +1,33 @@
-//! Provides a type representing a Redis protocol frame as well as utilities for
+//! Provides a type representing a TWS protocol frame as well as utilities for
 //! parsing frames from a byte array.
 
 use bytes::{Buf, Bytes};
-use std::convert::TryInto;
 use std::fmt;
-use std::io::{BufRead, Cursor, Read};
+use std::io::Cursor;
 use std::num::TryFromIntError;
 use std::string::FromUtf8Error;
-use tracing::info;
-/// A frame in the Redis protocol.
-#[derive(Clone, Debug)]
+
+/// The prelude a client sends before its first length-prefixed message.
+pub(crate) const API_PREFIX: &[u8] = b"API\0";
+
+/// The largest message body TWS will accept. A larger size prefix means the
+/// stream is out of sync and cannot be recovered.
+const MAX_MESSAGE_LEN: usize = 0x00FF_FFFF;
+
+/// A frame in the TWS protocol.
+///
+/// Apart from the initial handshake, every frame on the wire is a 4-byte
+/// big-endian size followed by that many bytes of body. The body is a list of
+/// fields, each terminated by a NUL byte.
+#[derive(Clone, Debug, PartialEq)]
 pub enum Frame {
-    Api(Bytes),
-    Simple(String),
-    Error(String),
-    Integer(u64),
-    Bulk(Bytes),
-    Null,
-    Array(Vec<Frame>),
+    /// The `API\0` prelude followed by a length-prefixed version string, e.g.
+    /// `v100..176`.
+    Handshake(Bytes),
+
+    /// A length-prefixed message. Each entry is one field without its NUL
+    /// terminator.
+    Message(Vec<Bytes>),
 }
 
 #[derive(Debug)]
@@ -30,282 +40,108 @@ pub enum Error {
 }
 
 impl Frame {
-    /// Returns an empty array
-    pub(crate) fn array() -> Frame {
-        Frame::Array(vec![])
+    /// Returns an empty message
+    pub(crate) fn message() -> Frame {
+        Frame::Message(vec![])
     }
 
-    /// Push a "bulk" frame into the array. `self` must be an Array frame.
+    /// Push a raw field into the message. `self` must be a Message frame.
     ///
     /// # Panics
     ///
-    /// panics if `self` is not an array
-    pub(crate) fn push_bulk(&mut self, bytes: Bytes) {
+    /// panics if `self` is not a message
+    pub(crate) fn push_bytes(&mut self, bytes: Bytes) {
         match self {
-            Frame::Array(vec) => {
-                vec.push(Frame::Bulk(bytes));
+            Frame::Message(fields) => {
+                fields.push(bytes);
             }
-            _ => panic!("not an array frame"),
+            _ => panic!("not a message frame"),
         }
     }
 
-    /// Push an "integer" frame into the array. `self` must be an Array frame.
+    /// Push a string field into the message. `self` must be a Message frame.
     ///
     /// # Panics
     ///
-    /// panics if `self` is not an array
-    pub(crate) fn push_int(&mut self, value: u64) {
-        match self {
-            Frame::Array(vec) => {
-                vec.push(Frame::Integer(value));
-            }
-            _ => panic!("not an array frame"),
-        }
+    /// panics if `self` is not a message
+    pub(crate) fn push_str(&mut self, value: &str) {
+        self.push_bytes(Bytes::copy_from_slice(value.as_bytes()));
     }
 
-    /// Checks if an entire message can be decoded from `src`
-    /// I need to make some kind of condition or separate function
-    /// that checks if first four bytes are b"API\0", or can be read as u32 size
-    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
-        match get_four_u8(src)? {
-            b"API\0" => {
-                info!("Message::check() matched b'API\0'");
-                // get_size from get_four_u8(src) to check if size is equal to src.len() - 4,
-                // advance if needed, or skip
-
-                // need to offset b"API\0" in order to read size bytes correctly
-                skip(src, 4)?;
-
-                let size = get_size(src)? as usize;
-                let length = src.get_ref().len() - 8;
-
-                info!("Size is: {}", size);
-                info!("Length is: {}", length);
-
-                // we need to skip the rest of the frame as well since Connection::parse_frame
-                // calculates the buffer size based on the cursor position
-                skip(src, length + 4)?;
+    /// Push an integer field into the message. `self` must be a Message frame.
+    ///
+    /// # Panics
+    ///
+    /// panics if `self` is not a message
+    pub(crate) fn push_int(&mut self, value: i64) {
+        self.push_bytes(Bytes::from(value.to_string()));
+    }
 
-                if size == length {
-                    Ok(())
-                } else {
-                    Err(format!("protocol error; unable to decode an API frame").into())
-                }
-            }
-            actual => {
-                // if get_four_u8(src) is equal to src.len() - 4, return Ok(()),
-                // else return error below
-                let size = get_size(src)? as usize;
-                if size == src.get_ref().len() - 4 {
-                    skip(src, size + 4)?;
-                    return Ok(());
+    /// Returns the encoded body of the frame, without the size prefix.
+    ///
+    /// For a `Message` this is every field followed by a NUL byte. For a
+    /// `Handshake` it is the version string as received.
+    pub(crate) fn body(&self) -> Vec<u8> {
+        match self {
+            Frame::Handshake(version) => version.to_vec(),
+            Frame::Message(fields) => {
+                let len = fields.iter().map(|field| field.len() + 1).sum();
+                let mut body = Vec::with_capacity(len);
+
+                for field in fields {
+                    body.extend_from_slice(field);
+                    body.push(b'\0');
                 }
-                Err(format!("protocol error; invalid frame type byte `{:?}`", actual).into())
+
+                body
             }
         }
     }
 
-    // I need to create a function that reads from the cursor first 4 bytes,
-    // if there is not enough bytes, throw incomplete error,
-    // if enough bytes then check if those bytes contain b'API0\',
-    // if so, then read next 4 bytes, if there is not enough bytes,
-    // throw incomplete error, else read message size from those bytes,
-    // then read message content (length from the size), if there is
-    // not enough bytes, then throw incomplete error, else read those bytes
-    // and return a message with type "HANDSHAKE"
-    // if first 4 bytes contain something else, we read those 4 bytes
-    // and get message size from it, then we return a message with type "REGULAR",
-    // else we return incomplete
-
-
-    // So this check function (instead of get_u8) checks first four bytes content,
-    // if they can be decoded as 'API\0' or a valid array of utf-8
-    // it returns Ok(), else it returns error. We put it as a match check.
-
-    //Instead of get_line(src) we need to create a function that reads the message
-    // content and return it or an error, if message is incomplete
-
-    /// Checks if an entire message can be decoded from `src`
-    // pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
-    //     match get_u8(src)? {
-    //         // b'A' => {
-    //         //     info!("Message::check() matched b'A'");
-    //         //     get_line_api(src)?;
-    //         //     Ok(())
-    //         // }
-    //         b'+' => {
-    //             get_line(src)?;
-    //             Ok(())
-    //         }
-    //         b'-' => {
-    //             get_line(src)?;
-    //             Ok(())
-    //         }
-    //         b':' => {
-    //             let _ = get_decimal(src)?;
-    //             Ok(())
-    //         }
-    //         b'$' => {
-    //             if b'-' == peek_u8(src)? {
-    //                 // Skip '-1\r\n'
-    //                 skip(src, 4)
-    //             } else {
-    //                 // Read the bulk string
-    //                 let len: usize = get_decimal(src)?.try_into()?;
-
-    //                 // skip that number of bytes + 2 (\r\n).
-    //                 skip(src, len + 2)
-    //             }
-    //         }
-    //         b'A' => {
-    //             info!("Frame::check() matched b'A'");
-    //             if b'-' == peek_u8(src)? {
-    //                 info!("Frame::check() matched b'A' and then '-'");
-    //                 // Skip '-1\r\n'
-    //                 skip(src, 4)
-    //             } else {
-    //                 info!("Frame::check() matched b'A' and then didn't match '-'");
-    //                 // Read the bulk string
-    //                 info!("src after check is: {:?}", src);
-    //                 // let len: usize = get_decimal_api(src)?.try_into()?;
-    //                 // info!("len is: {}", len);
-
-    //                 // skip that number of bytes + 2 (\r\n).
-    //                 skip(src, len + 2)
-    //             }
-    //         }
-    //         b'*' => {
-    //             let len = get_decimal(src)?;
-
-    //             for _ in 0..len {
-    //                 Frame::check(src)?;
-    //             }
-
-    //             Ok(())
-    //         }
-    //         actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
-    //     }
-    // }
+    /// Checks if an entire message can be decoded from `src`, starting at the
+    /// cursor's current position.
+    ///
+    /// On success the cursor is left at the end of the frame, so the caller
+    /// can tell how many bytes the frame occupies. Any bytes after it belong
+    /// to the next frame and are not looked at. If the buffer holds fewer
+    /// bytes than the frame declares, `Incomplete` is returned.
+    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+        if peek_four_u8(src)? == API_PREFIX {
+            skip(src, API_PREFIX.len())?;
+        }
+
+        let size = get_size(src)?;
+        skip(src, size)
+    }
 
     /// The message has already been validated with `check`.
+    ///
+    /// Reads one frame starting at the cursor's current position and leaves
+    /// the cursor at the end of it.
     pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
-        match get_four_u8(src)? {
-            b"API\0" => {
-                info!("Message::parse() matched b'API\0'");
-                // we need to cut the "API" prefix
-                // src.advance(4);
-                skip(src, 4)?;
-
-                let size = get_size(src)? as usize;
-
-                if src.remaining() < size {
-                    return Err(Error::Incomplete);
-                }
+        let handshake = peek_four_u8(src)? == API_PREFIX;
 
-                let data = Bytes::copy_from_slice(&src.chunk()[4..4 + size]);
-
-                info!("data is: {:?}", data);
-
-                Ok(Frame::Api(data))
-            }
-            _ => {
-                info!("Message::parse() didn't match b'API\0'");
-                let size = get_size(src)? as usize;
+        if handshake {
+            skip(src, API_PREFIX.len())?;
+        }
 
-                if src.remaining() < size {
-                    return Err(Error::Incomplete);
-                }
+        let size = get_size(src)?;
 
-                let data = Bytes::copy_from_slice(&src.chunk()[4..4 + size]);
+        if src.remaining() < size {
+            return Err(Error::Incomplete);
+        }
 
-                info!("data is: {:?}", data);
+        let body = &src.chunk()[..size];
 
-                // check why they use skip here
-                // skip(src, size)?;
-                // unimplemented!()
-                Ok(Frame::Bulk(data))
-            },
-        }
-    }
+        let frame = if handshake {
+            Frame::Handshake(Bytes::copy_from_slice(body))
+        } else {
+            Frame::Message(split_fields(body))
+        };
 
-    /// The message has already been validated with `check`.
-    // pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
-    //     match get_u8(src)? {
-    //         b'+' => {
-    //             // Read the line and convert it to `Vec<u8>`
-    //             let line = get_line(src)?.to_vec();
-
-    //             // Convert the line to a String
-    //             let string = String::from_utf8(line)?;
-
-    //             Ok(Frame::Simple(string))
-    //         }
-    //         b'-' => {
-    //             // Read the line and convert it to `Vec<u8>`
-    //             let line = get_line(src)?.to_vec();
-
-    //             // Convert the line to a String
-    //             let string = String::from_utf8(line)?;
-
-    //             Ok(Frame::Error(string))
-    //         }
-    //         b':' => {
-    //             let len = get_decimal(src)?;
-    //             Ok(Frame::Integer(len))
-    //         }
-    //         b'$' => {
-    //             if b'-' == peek_u8(src)? {
-    //                 let line = get_line(src)?;
-
-    //                 if line != b"-1" {
-    //                     return Err("protocol error; invalid frame format".into());
-    //                 }
-
-    //                 Ok(Frame::Null)
-    //             } else {
-    //                 // Read the bulk string
-    //                 let len = get_decimal(src)?.try_into()?;
-    //                 let n = len + 2;
-
-    //                 if src.remaining() < n {
-    //                     return Err(Error::Incomplete);
-    //                 }
-
-    //                 let data = Bytes::copy_from_slice(&src.chunk()[..len]);
-
-    //                 // skip that number of bytes + 2 (\r\n).
-    //                 skip(src, n)?;
-
-    //                 Ok(Frame::Bulk(data))
-    //             }
-    //         }
-    //         b'*' => {
-    //             let len = get_decimal(src)?.try_into()?;
-    //             let mut out = Vec::with_capacity(len);
-
-    //             for _ in 0..len {
-    //                 out.push(Frame::parse(src)?);
-    //             }
-
-    //             Ok(Frame::Array(out))
-    //         }
-    //         _ => unimplemented!(),
-    //     }
-    // }
-
-    /// Converts the frame to an "unexpected frame" error
-    pub(crate) fn to_error(&self) -> crate::Error {
-        format!("unexpected frame: {}", self).into()
-    }
-}
+        skip(src, size)?;
 
-impl PartialEq<&str> for Frame {
-    fn eq(&self, other: &&str) -> bool {
-        match self {
-            Frame::Simple(s) => s.eq(other),
-            Frame::Bulk(s) => s.eq(other),
-            _ => false,
-        }
+        Ok(frame)
     }
 }
 
@@ -314,26 +150,21 @@ impl fmt::Display for Frame {
         use std::str;
 
         match self {
-            Frame::Simple(response) => response.fmt(fmt),
-            Frame::Error(msg) => write!(fmt, "error: {}", msg),
-            Frame::Integer(num) => num.fmt(fmt),
-            Frame::Bulk(msg) => match str::from_utf8(msg) {
-                Ok(string) => string.fmt(fmt),
-                Err(_) => write!(fmt, "{:?}", msg),
-            },
-            Frame::Api(msg) => match str::from_utf8(msg) {
-                Ok(string) => string.fmt(fmt),
-                Err(_) => write!(fmt, "{:?}", msg),
+            Frame::Handshake(version) => match str::from_utf8(version) {
+                Ok(string) => write!(fmt, "API {}", string),
+                Err(_) => write!(fmt, "API {:?}", version),
             },
-            Frame::Null => "(nil)".fmt(fmt),
-            Frame::Array(parts) => {
-                for (i, part) in parts.iter().enumerate() {
+            Frame::Message(fields) => {
+                for (i, field) in fields.iter().enumerate() {
                     if i > 0 {
-                        // use space as the array element display separator
+                        // use space as the field display separator
                         write!(fmt, " ")?;
                     }
 
-                    part.fmt(fmt)?;
+                    match str::from_utf8(field) {
+                        Ok(string) => string.fmt(fmt)?,
+                        Err(_) => write!(fmt, "{:?}", field)?,
+                    }
                 }
 
                 Ok(())
@@ -342,43 +173,14 @@ impl fmt::Display for Frame {
     }
 }
 
-fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
-    if !src.has_remaining() {
-        return Err(Error::Incomplete);
-    }
-
-    Ok(src.chunk()[0])
-}
-
-fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
-    if !src.has_remaining() {
-        return Err(Error::Incomplete);
-    }
-
-    Ok(src.get_u8())
-}
-
-// fn get_four_u8(src: &mut Cursor<&[u8]>) -> Result<[u8; 4], Error> {
-//     if src.remaining() < 4 {
-//         return Err(Error::Incomplete);
-//     }
-
-//     let mut buffer = [0; 4];
-//     src.read_exact(&mut buffer).unwrap();
-
-//     // Ok(src.get_u8())
-//     Ok(buffer)
-// }
-
-fn get_four_u8<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a[u8], Error> {
+/// Look at the next four bytes without advancing the cursor
+fn peek_four_u8<'a>(src: &Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     if src.remaining() < 4 {
         return Err(Error::Incomplete);
     }
 
-    // let mut buffer = [0; 4];
-    // src.read_exact(&mut buffer).unwrap();
-
-    Ok(&src.get_ref()[0..4])
+    let start = src.position() as usize;
+    Ok(&src.get_ref()[start..start + 4])
 }
 
 fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
@@ -390,115 +192,37 @@ fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
     Ok(())
 }
 
-/// Read a new-line terminated decimal
-fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
-    use atoi::atoi;
+/// Read the 4-byte big-endian size prefix of a frame
+fn get_size(src: &mut Cursor<&[u8]>) -> Result<usize, Error> {
+    if src.remaining() < 4 {
+        return Err(Error::Incomplete);
+    }
 
-    let line = get_line(src)?;
+    let size = src.get_u32() as usize;
 
-    atoi::<u64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
-}
+    if size > MAX_MESSAGE_LEN {
+        return Err(format!("protocol error; message size {} exceeds the maximum", size).into());
+    }
 
-/// Frame size from the first 4 bytes
-fn get_size(src: &mut Cursor<&[u8]>) -> Result<u32, Error> {
-    info!("Inside get_size");
-    info!("cursor length is {}", src.get_ref().len());
-    info!("cursor position is {}", src.position());
-    let start = src.position() as usize;
-    //this ignores advance and reads from the beginning of the cursor
-    let buf = &src.get_ref()[start..start + 4];
-    info!("buf is {:?}", buf);
-    let size = u32::from_be_bytes(buf.try_into().unwrap());
-    info!("After size");
     Ok(size)
 }
 
+/// Split a message body into its NUL-terminated fields.
+///
+/// The terminator of the last field does not start a new one, so a body of
+/// `b"71\x002\x000\x00"` yields three fields.
+fn split_fields(body: &[u8]) -> Vec<Bytes> {
+    let body = body.strip_suffix(b"\0").unwrap_or(body);
 
-
-/// Read a new-line terminated decimal
-// fn get_decimal_api(src: &mut Cursor<&[u8]>) -> Result<u32, Error> {
-//     info!("Frame::get_decimal_api()");
-
-//     let mut line = get_line_api(src)?;
-//     info!("line is: {:?}", line); // [80, 73] which is second and third byte
-//                                   // let decimal = line.get_u32();
-
-//     let decimal = line.get_u32();
-//     info!("decimal is: {:?}", decimal);
-//     Ok(decimal)
-// }
-
-/// Find a line
-fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
-    info!("Inside get_line");
-    info!("cursor length is {}", src.get_ref().len());
-    info!("cursor position is {}", src.position());
-    let size = get_size(src).unwrap();
-    info!("size is {}", size);
-    // Scan the bytes directly
-    let start = src.position() as usize;
-    // Scan to the second to last byte
-    let end = src.get_ref().len();
-
-    if start + size as usize + 4 == src.get_ref().len() {
-        let line = &src.get_ref()[start + 4..end];
-        let message = String::from_utf8(line.try_into().unwrap()).unwrap();
-        info!("line is {:?}", line);
-        info!("message is: {}", message);
-        return Ok(line);
+    if body.is_empty() {
+        return vec![];
     }
 
-    Err(Error::Incomplete)
+    body.split(|&b| b == b'\0')
+        .map(Bytes::copy_from_slice)
+        .collect()
 }
 
-
-/// Find a line
-// fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
-//     // Scan the bytes directly
-//     let start = src.position() as usize;
-//     // Scan to the second to last byte
-//     let end = src.get_ref().len() - 1;
-
-//     for i in start..end {
-//         if src.get_ref()[i] == b'\r' && src.get_ref()[i + 1] == b'\n' {
-//             // We found a line, update the position to be *after* the \n
-//             src.set_position((i + 2) as u64);
-
-//             // Return the line
-//             return Ok(&src.get_ref()[start..i]);
-//         }
-//     }
-
-//     Err(Error::Incomplete)
-// }
-
-/// Find a line from the API message
-// fn get_line_api<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
-//     // Scan the bytes directly
-//     let start = src.position() as usize;
-//     // Scan to the second to last byte
-//     let end = src.get_ref().len() - 1;
-
-//     info!(
-//         "Iside the get_line_api, start is: {}, end is: {}",
-//         start, end
-//     );
-
-//     //b"API\x00\x00\x00\x00\tv100..176"; // length
-
-//     for i in start..end {
-//         if src.get_ref()[i] == b'\0' {
-//             // We found a line, update the position to be *after* the \n
-//             src.set_position((i + 1) as u64);
-
-//             // Return the line
-//             return Ok(&src.get_ref()[start..i]);
-//         }
-//     }
-
-//     Err(Error::Incomplete)
-// }
-
 impl From<String> for Error {
     fn from(src: String) -> Error {
         Error::Other(src.into())
@@ -533,3 +257,69 @@ impl fmt::Display for Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `body` with its size prefix.
+    fn sized(body: &[u8]) -> Vec<u8> {
+        let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn message(fields: &[&str]) -> Frame {
+        Frame::Message(fields.iter().map(|field| Bytes::copy_from_slice(field.as_bytes())).collect())
+    }
+
+    /// Check then parse the frame at the cursor's position, as the connection
+    /// does, and check that both end at the same place.
+    fn next(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+        let start = src.position();
+        Frame::check(src)?;
+        let end = src.position();
+
+        src.set_position(start);
+        let frame = Frame::parse(src)?;
+        assert_eq!(src.position(), end);
+
+        Ok(frame)
+    }
+
+    #[test]
+    fn pipelined_messages_parse_one_at_a_time() {
+        let mut buf = sized(b"71\x002\x000\x00");
+        buf.extend(sized(b"49\x001\x00"));
+        let mut src = Cursor::new(&buf[..]);
+
+        assert_eq!(next(&mut src).unwrap(), message(&["71", "2", "0"]));
+        assert_eq!(next(&mut src).unwrap(), message(&["49", "1"]));
+        assert_eq!(src.remaining(), 0);
+        assert!(matches!(next(&mut src), Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn size_larger_than_the_data_is_incomplete() {
+        let mut buf = sized(b"71\x002\x000\x00");
+        buf.truncate(buf.len() - 1);
+
+        assert!(matches!(Frame::check(&mut Cursor::new(&buf[..])), Err(Error::Incomplete)));
+        assert!(matches!(Frame::parse(&mut Cursor::new(&buf[..])), Err(Error::Incomplete)));
+
+        // Not even the size prefix.
+        assert!(matches!(Frame::check(&mut Cursor::new(&buf[..3])), Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn handshake_followed_by_a_message() {
+        let mut buf = API_PREFIX.to_vec();
+        buf.extend(sized(b"v100..176"));
+        buf.extend(sized(b"71\x002\x000\x00\x00"));
+        let mut src = Cursor::new(&buf[..]);
+
+        assert_eq!(next(&mut src).unwrap(), Frame::Handshake(Bytes::from_static(b"v100..176")));
+        assert_eq!(next(&mut src).unwrap(), message(&["71", "2", "0", ""]));
+        assert_eq!(src.remaining(), 0);
+    }
+}
@@ -0,0 +1,196 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+/// A record kept in a `Journal`.
+pub(crate) trait Record: Serialize + DeserializeOwned {
+    /// Identifier of the record, each one is recorded once.
+    fn id(&self) -> &str;
+}
+
+/// Records kept in a file with one JSON object per line.
+///
+/// A line is appended for every new record. The records that expired are
+/// dropped when the file is opened, which rewrites it, and so is a last line
+/// cut short by a crash while it was written.
+#[derive(Debug)]
+pub(crate) struct Journal<T> {
+    /// Location of the backing file.
+    path: PathBuf,
+
+    /// Records in the order they were recorded.
+    records: Vec<T>,
+
+    /// Ids of all the records, to record each one once.
+    ids: HashSet<String>,
+}
+
+impl<T: Record> Journal<T> {
+    /// Load the records stored in `path`, keeping the ones `keep` accepts. A
+    /// missing file means nothing was recorded yet. A last line that cannot
+    /// be read is dropped, any other one is an error.
+    pub(crate) fn open(path: PathBuf, keep: impl Fn(&T) -> bool) -> crate::Result<Journal<T>> {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut records = Vec::new();
+        let mut expired = 0;
+        let mut torn = false;
+
+        let mut lines = contents.lines().filter(|line| !line.trim().is_empty()).peekable();
+
+        while let Some(line) = lines.next() {
+            let record: T = match serde_json::from_str(line) {
+                Ok(record) => record,
+                Err(err) if lines.peek().is_none() => {
+                    warn!(path = %path.display(), %line, cause = %err, "dropping the torn last line");
+                    torn = true;
+                    continue;
+                }
+                Err(err) => return Err(format!("invalid line `{}` in {}: {}", line, path.display(), err).into()),
+            };
+
+            if keep(&record) {
+                records.push(record);
+            } else {
+                expired += 1;
+            }
+        }
+
+        debug!(path = %path.display(), records = records.len(), expired, "loaded journal");
+
+        let ids = records.iter().map(|record| record.id().to_string()).collect();
+        let journal = Journal { path, records, ids };
+
+        if expired > 0 || torn {
+            journal.save()?;
+        }
+
+        Ok(journal)
+    }
+
+    /// Add `record` and store it.
+    ///
+    /// Returns `Ok(false)` without changing anything if it was recorded
+    /// before.
+    pub(crate) fn record(&mut self, record: T) -> crate::Result<bool> {
+        if self.ids.contains(record.id()) {
+            return Ok(false);
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+
+        self.ids.insert(record.id().to_string());
+        self.records.push(record);
+
+        Ok(true)
+    }
+
+    /// Returns the records, oldest first.
+    pub(crate) fn records(&self) -> &[T] {
+        &self.records
+    }
+
+    /// Write all records to the backing file.
+    ///
+    /// The contents go to a temporary file first, which is then renamed over
+    /// the old one.
+    fn save(&self) -> crate::Result<()> {
+        let mut contents = String::new();
+        for record in &self.records {
+            contents.push_str(&serde_json::to_string(record)?);
+            contents.push('\n');
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Entry {
+        id: String,
+        age: u32,
+    }
+
+    impl Record for Entry {
+        fn id(&self) -> &str {
+            &self.id
+        }
+    }
+
+    fn entry(id: &str, age: u32) -> Entry {
+        Entry {
+            id: id.to_string(),
+            age,
+        }
+    }
+
+    fn path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("journal_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("entries.jsonl")
+    }
+
+    #[test]
+    fn records_each_id_once() {
+        let path = path("record");
+        let mut journal = Journal::open(path.clone(), |_: &Entry| true).unwrap();
+
+        assert!(journal.record(entry("a", 1)).unwrap());
+        assert!(!journal.record(entry("a", 2)).unwrap());
+        assert!(journal.record(entry("b", 3)).unwrap());
+
+        let reopened = Journal::open(path, |_: &Entry| true).unwrap();
+        assert_eq!(reopened.records(), &[entry("a", 1), entry("b", 3)]);
+    }
+
+    #[test]
+    fn open_drops_the_expired_records_from_the_file() {
+        let path = path("expired");
+        let mut journal = Journal::open(path.clone(), |_: &Entry| true).unwrap();
+        for (id, age) in [("old", 10), ("new", 1)] {
+            journal.record(entry(id, age)).unwrap();
+        }
+
+        let young = |entry: &Entry| entry.age < 5;
+        assert_eq!(Journal::open(path.clone(), young).unwrap().records(), &[entry("new", 1)]);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn open_drops_a_torn_last_line() {
+        let path = path("torn");
+        fs::write(&path, "{\"id\":\"a\",\"age\":1}\n{\"id\":\"b\",\"ag").unwrap();
+
+        let mut journal = Journal::open(path.clone(), |_: &Entry| true).unwrap();
+        assert_eq!(journal.records(), &[entry("a", 1)]);
+
+        // What is recorded next starts on a line of its own.
+        journal.record(entry("b", 2)).unwrap();
+        let reopened = Journal::open(path.clone(), |_: &Entry| true).unwrap();
+        assert_eq!(reopened.records(), &[entry("a", 1), entry("b", 2)]);
+
+        // Only the last line may be torn.
+        fs::write(&path, "{\"id\":\"a\",\"ag\n{\"id\":\"b\",\"age\":2}\n").unwrap();
+        assert!(Journal::open(path, |_: &Entry| true).is_err());
+    }
+}
@@ -1,60 +1,115 @@
-//! A minimal (i.e. very incomplete) implementation of a Redis server and
-//! client.
-//!
-//! The purpose of this project is to provide a larger example of an
-//! asynchronous Rust project built with Tokio. Do not attempt to run this in
-//! production... seriously.
+//! A middleware that speaks the Trader Workstation (TWS) API to the
+//! tiger.trade terminal.
 //!
 //! # Layout
 //!
-//! The library is structured such that it can be used with guides. There are
-//! modules that are public that probably would not be public in a "real" redis
-//! client library.
-//!
 //! The major components are:
 //!
-//! * `server`: Redis server implementation. Includes a single `run` function
-//!   that takes a `TcpListener` and starts accepting redis client connections.
+//! * `server`: TWS API server implementation. Includes a single `run` function
+//!   that takes a `TcpListener` and starts accepting client connections.
+//!
+//! * `broker`: the `Broker` trait and its adapters, which hold the accounts.
+//!
+//! * `cmd`: implementations of the supported TWS API requests.
+//!
+//! * `config`: the accounts routed through the connector and their brokers.
+//!
+//! * `market_data`: the `MarketDataProvider` trait and its adapters, which
+//!   supply quotes, trades and bars.
+//!
+//! * `orders`: the orders placed by clients, from `placeOrder` to the
+//!   broker and back as `openOrder` and `orderStatus`.
 //!
-//! * `clients/client`: an asynchronous Redis client implementation. Demonstrates how to
-//!   build clients with Tokio.
+//! * `executions`: the fills of the orders, kept across restarts and
+//!   reported as `execDetails`.
 //!
-//! * `cmd`: implementations of the supported Redis commands.
+//! * `positions`: the positions of the accounts, kept up to date from the
+//!   fills.
 //!
-//! * `frame`: represents a single Redis protocol frame. A frame is used as an
+//! * `pnl`: the daily, unrealized and realized P&L of the positions.
+//!
+//! * `ticker`: the market data of a symbol as TWS ticks.
+//!
+//! * `order_book`: the market depth of a symbol, built from the quotes of
+//!   the venues.
+//!
+//! * `mock`: local stand-ins for the broker and market data APIs, behind the
+//!   `mock` feature.
+//!
+//! * `messages`: the TWS message ids and the field layout of each message.
+//!
+//! * `frame`: represents a single TWS protocol frame. A frame is used as an
 //!   intermediate representation between a "command" and the byte
 //!   representation.
 
-// pub mod clients;
-// pub use clients::{BlockingClient, BufferedClient, Client};
+pub mod broker;
+
 pub mod cmd;
 pub use cmd::Command;
 
+pub mod config;
+pub use config::Config;
+
 mod connection;
 pub use connection::Connection;
 
 pub mod frame;
 pub use frame::Frame;
 
+pub mod market_data;
+
+pub mod messages;
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
 mod db;
 use db::Db;
-use db::DbDropGuard;
+
+mod executions;
+
+mod journal;
+
+mod order_ids;
+
+mod orders;
+
+mod pnl;
+mod positions;
 
 mod parse;
-use parse::{Parse, ParseError};
+use parse::Parse;
 
 pub mod server;
 
+mod session;
+use session::{Admission, SessionState};
+
 mod shutdown;
 use shutdown::Shutdown;
 
-/// Default port that a redis server listens on.
+mod subscriptions;
+use subscriptions::Subscriptions;
+
+mod ticker;
+
+mod order_book;
+
+/// Default port that TWS listens on.
 ///
 /// Used if no port is specified.
 pub const DEFAULT_PORT: u16 = 7496;
 
+/// Highest server version the connector speaks.
+///
+/// During the handshake the connector picks the highest version supported by
+/// both sides, so a newer client is answered with this version.
 pub const API_VERSION: u16 = 151;
 
+/// Oldest server version the connector speaks. Clients that cannot go up to
+/// at least this version are rejected during the handshake.
+pub const MIN_API_VERSION: u16 = 100;
+
 /// Error returned by most functions.
 ///
 /// When writing a real application, one might want to consider a specialized
@@ -68,7 +123,7 @@ pub const API_VERSION: u16 = 151;
 /// it to be converted to `Box<dyn std::error::Error>`.
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 
-/// A specialized `Result` type for mini-redis operations.
+/// A specialized `Result` type for connector operations.
 ///
 /// This is defined as a convenience.
 pub type Result<T> = std::result::Result<T, Error>;
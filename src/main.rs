@@ -1,14 +1,15 @@
-//! mini-redis server.
+//! tiger_trade_connector server.
 //!
 //! This file is the entry point for the server implemented in the library. It
 //! performs command line parsing and passes the arguments on to
-//! `mini_redis::server`.
+//! `tiger_trade_connector::server`.
 //!
 //! The `clap` crate is used for parsing arguments.
 
-use tiger_trade_connector::{server, DEFAULT_PORT};
+use tiger_trade_connector::{server, Config, DEFAULT_PORT};
 
 use clap::Parser;
+use std::path::PathBuf;
 use tokio::net::TcpListener;
 use tokio::signal;
 
@@ -35,19 +36,41 @@ pub async fn main() -> tiger_trade_connector::Result<()> {
     let cli = Cli::parse();
     let port = cli.port.unwrap_or(DEFAULT_PORT);
 
+    let mut config = match cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    if let Some(data_dir) = cli.data_dir {
+        config.data_dir = data_dir;
+    }
+
     // Bind a TCP listener
     let listener = TcpListener::bind(&format!("127.0.0.1:{}", port)).await?;
 
-    server::run(listener, signal::ctrl_c()).await;
-
-    Ok(())
+    server::run(listener, config, signal::ctrl_c()).await
 }
 
 #[derive(Parser, Debug)]
-#[clap(name = "mini-redis-server", version, author, about = "A Redis server")]
+#[clap(
+    name = "tiger-trade-connector",
+    version,
+    author,
+    about = "A TWS API server for the tiger.trade terminal"
+)]
 struct Cli {
     #[clap(long)]
     port: Option<u16>,
+
+    /// TOML file listing the accounts to serve. Without it, a single paper
+    /// account is served.
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Directory for state kept across restarts. Overrides the one in the
+    /// configuration file.
+    #[clap(long)]
+    data_dir: Option<PathBuf>,
 }
 
 #[cfg(not(feature = "otel"))]
@@ -0,0 +1,244 @@
+//! Market data feeds.
+//!
+//! A `MarketDataProvider` answers one-off requests for the latest quote,
+//! trade and bars of a symbol and streams its updates. The connector turns
+//! these into TWS ticks, the adapters translate from the feed's own API.
+
+mod polygon;
+pub use polygon::Polygon;
+mod watch;
+pub(crate) use watch::PriceWatch;
+
+use crate::config::MarketDataConfig;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// Best bid and offer of a symbol.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quote {
+    pub bid_price: f64,
+    pub bid_size: f64,
+    pub ask_price: f64,
+    pub ask_size: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A single trade.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trade {
+    pub price: f64,
+    pub size: f64,
+
+    /// Feed specific id of the exchange the trade was reported on.
+    pub exchange: i32,
+
+    /// Feed specific trade condition codes.
+    pub conditions: Vec<i32>,
+
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Side of the book.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// The price and size one venue quotes on one side of the book.
+///
+/// The feed reports it when the venue sets the best bid or offer, so no
+/// venue quotes a better price at that time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Depth {
+    /// Name of the venue, the way TWS names exchanges, e.g. `ISLAND`.
+    pub venue: String,
+    pub side: Side,
+    pub price: f64,
+    pub size: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Aggregated trades over a period of time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bar {
+    /// Start of the period.
+    pub start: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+
+    /// Volume weighted average price, if the feed provides it.
+    pub vwap: Option<f64>,
+
+    /// Number of trades, if the feed provides it.
+    pub trades: Option<u64>,
+}
+
+/// Length of the period covered by a `Bar`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timespan {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+/// Reference data of a symbol.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickerDetails {
+    pub symbol: String,
+    pub name: String,
+
+    /// Market identifier code of the listing exchange, e.g. `XNAS`.
+    pub primary_exchange: String,
+
+    /// ISO currency code, e.g. `USD`.
+    pub currency: String,
+
+    /// Feed specific security type, e.g. `CS` for common stock.
+    pub security_type: String,
+
+    pub round_lot: Option<u32>,
+    pub shares_outstanding: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub list_date: Option<NaiveDate>,
+}
+
+/// An update of a streamed symbol.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarketEvent {
+    Quote(Quote),
+    Trade(Trade),
+
+    /// A one minute bar, sent when the minute ends.
+    Bar(Bar),
+
+    /// The quote of one venue, sent along with the `Quote` it is part of.
+    Depth(Depth),
+}
+
+/// Operations every market data adapter provides.
+#[async_trait]
+pub trait MarketDataProvider: Debug + Send + Sync {
+    /// Returns the current best bid and offer of `symbol`.
+    async fn last_quote(&self, symbol: &str) -> crate::Result<Quote>;
+
+    /// Returns the venues quoting the current best bid and offer of
+    /// `symbol`, with their prices and sizes.
+    async fn last_depth(&self, symbol: &str) -> crate::Result<Vec<Depth>>;
+
+    /// Returns the names of the venues depth is reported for.
+    fn venues(&self) -> Vec<String>;
+
+    /// Returns the most recent trade of `symbol`.
+    async fn last_trade(&self, symbol: &str) -> crate::Result<Trade>;
+
+    /// Returns the bar of the previous trading day of `symbol`.
+    async fn previous_close(&self, symbol: &str) -> crate::Result<Bar>;
+
+    /// Returns the reference data of `symbol`.
+    async fn ticker_details(&self, symbol: &str) -> crate::Result<TickerDetails>;
+
+    /// Returns the `timespan` bars of `symbol` between `from` and `to`, both
+    /// included, oldest first.
+    async fn bars(
+        &self,
+        symbol: &str,
+        timespan: Timespan,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> crate::Result<Vec<Bar>>;
+
+    /// Start streaming the quotes, depth, trades and minute bars of `symbol`.
+    ///
+    /// Subscribers of the same symbol share a single upstream subscription,
+    /// which ends when the last `Subscription` is dropped.
+    async fn subscribe(&self, symbol: &str) -> crate::Result<Subscription>;
+}
+
+/// A stream of updates of one symbol.
+///
+/// Dropping the value ends the subscription.
+pub struct Subscription {
+    symbol: String,
+    events: broadcast::Receiver<MarketEvent>,
+
+    /// Called on drop to let the adapter release the upstream subscription.
+    release: Option<Box<dyn FnOnce() + Send + Sync>>,
+}
+
+impl Subscription {
+    /// Create a subscription to `symbol` that receives `events` and calls
+    /// `release` when dropped.
+    pub fn new(
+        symbol: impl ToString,
+        events: broadcast::Receiver<MarketEvent>,
+        release: impl FnOnce() + Send + Sync + 'static,
+    ) -> Subscription {
+        Subscription {
+            symbol: symbol.to_string(),
+            events,
+            release: Some(Box::new(release)),
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Receive the next update.
+    ///
+    /// Returns `None` once the adapter stopped streaming. Updates missed
+    /// because the subscriber fell behind are skipped, the next one is
+    /// returned instead.
+    pub async fn recv(&mut self) -> Option<MarketEvent> {
+        loop {
+            match self.events.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(symbol = %self.symbol, skipped, "market data subscriber is lagging");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription").field("symbol", &self.symbol).finish()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+/// Create the market data adapter described by `config`.
+pub fn connect(config: &MarketDataConfig) -> crate::Result<Arc<dyn MarketDataProvider>> {
+    let provider: Arc<dyn MarketDataProvider> = match config {
+        MarketDataConfig::Polygon {
+            api_key,
+            rest_url,
+            ws_url,
+        } => Arc::new(Polygon::new(api_key, rest_url, ws_url)?),
+    };
+
+    Ok(provider)
+}
@@ -0,0 +1,667 @@
+//! polygon.io adapter.
+//!
+//! One-off requests use the REST API, streaming uses a single WebSocket
+//! connection shared by all subscriptions. See <https://polygon.io/docs/stocks>.
+
+use crate::market_data::{
+    Bar, Depth, MarketDataProvider, MarketEvent, Quote, Side, Subscription, Timespan, TickerDetails, Trade,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{self, Duration};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+/// Number of updates a subscriber may fall behind before it misses some.
+const EVENT_CAPACITY: usize = 1024;
+
+/// Longest wait between two attempts to reconnect the stream, in seconds.
+const MAX_BACKOFF: u64 = 64;
+
+/// The exchanges polygon reports quotes from, by polygon id, with the names
+/// TWS gives them. Ids that are not venues, such as the SIPs, are left out.
+const VENUES: &[(i32, &str)] = &[
+    (1, "AMEX"),
+    (2, "BEX"),
+    (3, "NYSENAT"),
+    (4, "ADF"),
+    (7, "EDGEA"),
+    (8, "EDGX"),
+    (9, "CHX"),
+    (10, "NYSE"),
+    (11, "ARCA"),
+    (12, "ISLAND"),
+    (14, "LTSE"),
+    (15, "IEX"),
+    (17, "PSX"),
+    (18, "BYX"),
+    (19, "BATS"),
+    (20, "PEARL"),
+    (21, "MEMX"),
+];
+
+/// polygon.io market data feed.
+///
+/// Requests are authenticated with the account's API key. The base URLs can
+/// be pointed at the bundled stand-in, see `mock::polygon`.
+#[derive(Debug)]
+pub struct Polygon {
+    http: reqwest::Client,
+    rest_url: String,
+    api_key: String,
+
+    /// Symbols being streamed, shared with the stream task.
+    stream: Arc<Stream>,
+
+    /// Tells the stream task about new and released symbols.
+    control: mpsc::UnboundedSender<Control>,
+}
+
+/// State shared between the adapter and its stream task.
+#[derive(Debug)]
+struct Stream {
+    ws_url: String,
+    api_key: String,
+
+    /// Subscribed symbols and their subscribers.
+    channels: Mutex<HashMap<String, Channel>>,
+}
+
+/// Fan-out of one upstream symbol subscription.
+#[derive(Debug)]
+struct Channel {
+    sender: broadcast::Sender<MarketEvent>,
+
+    /// Number of live `Subscription` values for the symbol.
+    subscribers: usize,
+}
+
+/// Change of the streamed symbols.
+#[derive(Debug)]
+enum Control {
+    Subscribe(String),
+    Unsubscribe(String),
+}
+
+impl Polygon {
+    /// Create an adapter using `api_key` against the REST API at `rest_url`
+    /// and the WebSocket cluster at `ws_url`.
+    ///
+    /// Must be called from within a Tokio runtime, the stream task is spawned
+    /// right away. It only connects once a symbol is subscribed.
+    pub fn new(api_key: &str, rest_url: &str, ws_url: &str) -> crate::Result<Polygon> {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
+
+        let stream = Arc::new(Stream {
+            ws_url: ws_url.to_string(),
+            api_key: api_key.to_string(),
+            channels: Mutex::new(HashMap::new()),
+        });
+
+        let (control, commands) = mpsc::unbounded_channel();
+        tokio::spawn(run_stream(stream.clone(), commands));
+
+        Ok(Polygon {
+            http,
+            rest_url: rest_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            stream,
+            control,
+        })
+    }
+
+    /// Send a GET request for `path` and decode the JSON response.
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> crate::Result<T> {
+        self.get_url(&format!("{}{}", self.rest_url, path), query).await
+    }
+
+    async fn get_url<T: DeserializeOwned>(&self, url: &str, query: &[(&str, &str)]) -> crate::Result<T> {
+        debug!(%url, "polygon request");
+
+        let response = self
+            .http
+            .get(url)
+            .bearer_auth(&self.api_key)
+            .query(query)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("polygon request {} failed with {}: {}", url, status, body).into());
+        }
+
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for Polygon {
+    async fn last_quote(&self, symbol: &str) -> crate::Result<Quote> {
+        let response: Response<RestQuote> = self.get(&format!("/v2/last/nbbo/{}", symbol), &[]).await?;
+        let quote = response.results.ok_or_else(|| no_data("quote", symbol))?;
+
+        Ok(Quote {
+            bid_price: quote.bid_price,
+            bid_size: quote.bid_size,
+            ask_price: quote.ask_price,
+            ask_size: quote.ask_size,
+            timestamp: DateTime::from_timestamp_nanos(quote.timestamp),
+        })
+    }
+
+    async fn last_depth(&self, symbol: &str) -> crate::Result<Vec<Depth>> {
+        let response: Response<RestQuote> = self.get(&format!("/v2/last/nbbo/{}", symbol), &[]).await?;
+        let quote = response.results.ok_or_else(|| no_data("quote", symbol))?;
+        let timestamp = DateTime::from_timestamp_nanos(quote.timestamp);
+
+        Ok([
+            depth(quote.bid_exchange, Side::Bid, quote.bid_price, quote.bid_size, timestamp),
+            depth(quote.ask_exchange, Side::Ask, quote.ask_price, quote.ask_size, timestamp),
+        ]
+        .into_iter()
+        .flatten()
+        .collect())
+    }
+
+    fn venues(&self) -> Vec<String> {
+        VENUES.iter().map(|(_, name)| name.to_string()).collect()
+    }
+
+    async fn last_trade(&self, symbol: &str) -> crate::Result<Trade> {
+        let response: Response<RestTrade> = self.get(&format!("/v2/last/trade/{}", symbol), &[]).await?;
+        let trade = response.results.ok_or_else(|| no_data("trade", symbol))?;
+
+        Ok(Trade {
+            price: trade.price,
+            size: trade.size,
+            exchange: trade.exchange,
+            conditions: trade.conditions.unwrap_or_default(),
+            timestamp: DateTime::from_timestamp_nanos(trade.timestamp),
+        })
+    }
+
+    async fn previous_close(&self, symbol: &str) -> crate::Result<Bar> {
+        let response: Response<Vec<RestBar>> = self
+            .get(&format!("/v2/aggs/ticker/{}/prev", symbol), &[("adjusted", "true")])
+            .await?;
+
+        response
+            .results
+            .and_then(|bars| bars.into_iter().next())
+            .map(RestBar::into_bar)
+            .ok_or_else(|| no_data("previous close", symbol))
+    }
+
+    async fn ticker_details(&self, symbol: &str) -> crate::Result<TickerDetails> {
+        let response: Response<RestTickerDetails> =
+            self.get(&format!("/v3/reference/tickers/{}", symbol), &[]).await?;
+        let details = response.results.ok_or_else(|| no_data("reference data", symbol))?;
+
+        Ok(TickerDetails {
+            symbol: details.ticker,
+            name: details.name,
+            primary_exchange: details.primary_exchange.unwrap_or_default(),
+            currency: details.currency_name.unwrap_or_default().to_uppercase(),
+            security_type: details.security_type.unwrap_or_default(),
+            round_lot: details.round_lot,
+            shares_outstanding: details.share_class_shares_outstanding,
+            market_cap: details.market_cap,
+            list_date: details
+                .list_date
+                .and_then(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+        })
+    }
+
+    async fn bars(
+        &self,
+        symbol: &str,
+        timespan: Timespan,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> crate::Result<Vec<Bar>> {
+        let path = format!(
+            "/v2/aggs/ticker/{}/range/1/{}/{}/{}",
+            symbol,
+            timespan_name(timespan),
+            from.timestamp_millis(),
+            to.timestamp_millis()
+        );
+
+        let query = [("adjusted", "true"), ("sort", "asc"), ("limit", "50000")];
+        let mut response: Response<Vec<RestBar>> = self.get(&path, &query).await?;
+        let mut bars = Vec::new();
+
+        // Long ranges are split into pages, each pointing to the next one.
+        loop {
+            bars.extend(response.results.unwrap_or_default().into_iter().map(RestBar::into_bar));
+
+            match response.next_url {
+                Some(url) => response = self.get_url(&url, &[]).await?,
+                None => return Ok(bars),
+            }
+        }
+    }
+
+    async fn subscribe(&self, symbol: &str) -> crate::Result<Subscription> {
+        let events = {
+            let mut channels = self.stream.channels.lock().unwrap();
+
+            let channel = channels.entry(symbol.to_string()).or_insert_with(|| {
+                // First subscriber of the symbol, the stream task subscribes
+                // upstream.
+                let _ = self.control.send(Control::Subscribe(symbol.to_string()));
+
+                Channel {
+                    sender: broadcast::channel(EVENT_CAPACITY).0,
+                    subscribers: 0,
+                }
+            });
+
+            channel.subscribers += 1;
+            channel.sender.subscribe()
+        };
+
+        let stream = self.stream.clone();
+        let control = self.control.clone();
+        let released = symbol.to_string();
+
+        Ok(Subscription::new(symbol, events, move || {
+            let mut channels = stream.channels.lock().unwrap();
+
+            if let Some(channel) = channels.get_mut(&released) {
+                channel.subscribers -= 1;
+
+                if channel.subscribers == 0 {
+                    channels.remove(&released);
+                    let _ = control.send(Control::Unsubscribe(released));
+                }
+            }
+        }))
+    }
+}
+
+/// Keep the WebSocket connected while symbols are subscribed, reconnecting
+/// with an exponential backoff when it drops.
+///
+/// Returns once the adapter and all its subscriptions are dropped.
+async fn run_stream(stream: Arc<Stream>, mut commands: mpsc::UnboundedReceiver<Control>) {
+    let mut backoff = 1;
+
+    loop {
+        // Nothing to stream, wait for the first subscription.
+        if stream.symbols().is_empty() {
+            match commands.recv().await {
+                Some(_) => continue,
+                None => return,
+            }
+        }
+
+        match stream.session(&mut commands, &mut backoff).await {
+            Ok(()) => return,
+            Err(err) => {
+                warn!(cause = %err, backoff, "polygon stream disconnected");
+                time::sleep(Duration::from_secs(backoff)).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+impl Stream {
+    /// Returns the symbols that have subscribers.
+    fn symbols(&self) -> Vec<String> {
+        self.channels.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Connect, authenticate and stream until the connection fails or the
+    /// adapter is dropped.
+    async fn session(
+        &self,
+        commands: &mut mpsc::UnboundedReceiver<Control>,
+        backoff: &mut u64,
+    ) -> crate::Result<()> {
+        let (socket, _) = tokio_tungstenite::connect_async(self.ws_url.as_str()).await?;
+        let (mut sink, mut source) = socket.split();
+
+        let auth = json!({ "action": "auth", "params": self.api_key });
+        sink.send(Message::text(auth.to_string())).await?;
+
+        // Wait for the outcome of the authentication.
+        loop {
+            let message = source.next().await.ok_or("connection closed during authentication")??;
+
+            for event in parse_events(&message)? {
+                if let Event::Status { status, message } = event {
+                    match status.as_str() {
+                        "auth_success" => {}
+                        "auth_failed" => return Err(format!("polygon authentication failed: {}", message).into()),
+                        _ => continue,
+                    }
+
+                    info!(url = %self.ws_url, "polygon stream authenticated");
+                    *backoff = 1;
+
+                    // Subscriptions made while disconnected, or before a
+                    // reconnect, are all sent at once.
+                    let symbols = self.symbols();
+                    if !symbols.is_empty() {
+                        sink.send(subscription_message("subscribe", &symbols)).await?;
+                    }
+
+                    return self.dispatch(&mut sink, &mut source, commands).await;
+                }
+            }
+        }
+    }
+
+    /// Forward stream updates to the subscribers and subscription changes to
+    /// the stream.
+    async fn dispatch<S, R>(
+        &self,
+        sink: &mut S,
+        source: &mut R,
+        commands: &mut mpsc::UnboundedReceiver<Control>,
+    ) -> crate::Result<()>
+    where
+        S: futures_util::Sink<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin,
+        R: futures_util::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+    {
+        loop {
+            tokio::select! {
+                message = source.next() => {
+                    let message = message.ok_or("connection closed by polygon")??;
+
+                    for event in parse_events(&message)? {
+                        self.publish(event);
+                    }
+                }
+                command = commands.recv() => {
+                    let message = match command {
+                        Some(Control::Subscribe(symbol)) => subscription_message("subscribe", &[symbol]),
+                        Some(Control::Unsubscribe(symbol)) => subscription_message("unsubscribe", &[symbol]),
+                        None => return Ok(()),
+                    };
+
+                    sink.send(message).await?;
+                }
+            }
+        }
+    }
+
+    /// Send `event` to the subscribers of its symbol.
+    ///
+    /// A quote is sent as a `Quote` followed by the `Depth` of the venues
+    /// quoting it.
+    fn publish(&self, event: Event) {
+        let (symbol, events) = match event {
+            Event::Quote(quote) => (quote.sym.clone(), quote.into_events()),
+            Event::Trade(trade) => (trade.sym.clone(), vec![MarketEvent::Trade(trade.into_trade())]),
+            Event::MinuteBar(bar) => (bar.sym.clone(), vec![MarketEvent::Bar(bar.into_bar())]),
+            Event::Status { status, message } => {
+                debug!(%status, %message, "polygon status");
+                return;
+            }
+            Event::Other => return,
+        };
+
+        let channels = self.channels.lock().unwrap();
+        if let Some(channel) = channels.get(&symbol) {
+            for event in events {
+                // Fails only when every subscriber is gone, which the release
+                // of the last one takes care of.
+                let _ = channel.sender.send(event);
+            }
+        }
+    }
+}
+
+/// The `subscribe` or `unsubscribe` message for the quotes, trades and minute
+/// bars of `symbols`.
+fn subscription_message(action: &str, symbols: &[String]) -> Message {
+    let params: Vec<_> = symbols
+        .iter()
+        .map(|symbol| format!("Q.{0},T.{0},AM.{0}", symbol))
+        .collect();
+
+    Message::text(json!({ "action": action, "params": params.join(",") }).to_string())
+}
+
+/// Decode the array of events in a stream message.
+fn parse_events(message: &Message) -> crate::Result<Vec<Event>> {
+    match message {
+        Message::Text(text) => Ok(serde_json::from_str(text)?),
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn timespan_name(timespan: Timespan) -> &'static str {
+    match timespan {
+        Timespan::Second => "second",
+        Timespan::Minute => "minute",
+        Timespan::Hour => "hour",
+        Timespan::Day => "day",
+        Timespan::Week => "week",
+        Timespan::Month => "month",
+        Timespan::Quarter => "quarter",
+        Timespan::Year => "year",
+    }
+}
+
+/// Returns the quote of the venue with polygon id `exchange`, or `None` if
+/// the id is not a venue.
+fn depth(exchange: Option<i32>, side: Side, price: f64, size: f64, timestamp: DateTime<Utc>) -> Option<Depth> {
+    let (_, venue) = VENUES.iter().find(|(id, _)| Some(*id) == exchange)?;
+
+    Some(Depth {
+        venue: venue.to_string(),
+        side,
+        price,
+        size,
+        timestamp,
+    })
+}
+
+fn no_data(what: &str, symbol: &str) -> crate::Error {
+    format!("polygon has no {} for {}", what, symbol).into()
+}
+
+fn from_millis(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+/// Envelope of the REST responses.
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    results: Option<T>,
+    next_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestQuote {
+    #[serde(rename = "p")]
+    bid_price: f64,
+    #[serde(rename = "s")]
+    bid_size: f64,
+    #[serde(rename = "P")]
+    ask_price: f64,
+    #[serde(rename = "S")]
+    ask_size: f64,
+    #[serde(rename = "x")]
+    bid_exchange: Option<i32>,
+    #[serde(rename = "X")]
+    ask_exchange: Option<i32>,
+    /// SIP timestamp in nanoseconds.
+    #[serde(rename = "t")]
+    timestamp: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestTrade {
+    #[serde(rename = "p")]
+    price: f64,
+    #[serde(rename = "s")]
+    size: f64,
+    #[serde(rename = "x")]
+    exchange: i32,
+    #[serde(rename = "c")]
+    conditions: Option<Vec<i32>>,
+    /// SIP timestamp in nanoseconds.
+    #[serde(rename = "t")]
+    timestamp: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestBar {
+    o: f64,
+    h: f64,
+    l: f64,
+    c: f64,
+    v: f64,
+    vw: Option<f64>,
+    n: Option<u64>,
+    /// Start of the bar in milliseconds.
+    t: i64,
+}
+
+impl RestBar {
+    fn into_bar(self) -> Bar {
+        Bar {
+            start: from_millis(self.t),
+            open: self.o,
+            high: self.h,
+            low: self.l,
+            close: self.c,
+            volume: self.v,
+            vwap: self.vw,
+            trades: self.n,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RestTickerDetails {
+    ticker: String,
+    name: String,
+    primary_exchange: Option<String>,
+    currency_name: Option<String>,
+    #[serde(rename = "type")]
+    security_type: Option<String>,
+    round_lot: Option<u32>,
+    share_class_shares_outstanding: Option<f64>,
+    market_cap: Option<f64>,
+    list_date: Option<String>,
+}
+
+/// A stream event, tagged by its `ev` field.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "ev")]
+enum Event {
+    #[serde(rename = "Q")]
+    Quote(StreamQuote),
+    #[serde(rename = "T")]
+    Trade(StreamTrade),
+    #[serde(rename = "AM")]
+    MinuteBar(StreamBar),
+    #[serde(rename = "status")]
+    Status { status: String, message: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamQuote {
+    sym: String,
+    bp: f64,
+    bs: f64,
+    ap: f64,
+    #[serde(rename = "as")]
+    ask_size: f64,
+    bx: Option<i32>,
+    ax: Option<i32>,
+    /// Timestamp in milliseconds.
+    t: i64,
+}
+
+impl StreamQuote {
+    /// Returns the quote followed by the depth of the venues quoting it.
+    fn into_events(self) -> Vec<MarketEvent> {
+        let timestamp = from_millis(self.t);
+        let bid = depth(self.bx, Side::Bid, self.bp, self.bs, timestamp);
+        let ask = depth(self.ax, Side::Ask, self.ap, self.ask_size, timestamp);
+
+        let quote = Quote {
+            bid_price: self.bp,
+            bid_size: self.bs,
+            ask_price: self.ap,
+            ask_size: self.ask_size,
+            timestamp,
+        };
+
+        let mut events = vec![MarketEvent::Quote(quote)];
+        events.extend([bid, ask].into_iter().flatten().map(MarketEvent::Depth));
+        events
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamTrade {
+    sym: String,
+    p: f64,
+    s: f64,
+    x: i32,
+    c: Option<Vec<i32>>,
+    /// Timestamp in milliseconds.
+    t: i64,
+}
+
+impl StreamTrade {
+    fn into_trade(self) -> Trade {
+        Trade {
+            price: self.p,
+            size: self.s,
+            exchange: self.x,
+            conditions: self.c.unwrap_or_default(),
+            timestamp: from_millis(self.t),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamBar {
+    sym: String,
+    o: f64,
+    h: f64,
+    l: f64,
+    c: f64,
+    v: f64,
+    vw: Option<f64>,
+    /// Start of the bar in milliseconds.
+    s: i64,
+}
+
+impl StreamBar {
+    fn into_bar(self) -> Bar {
+        Bar {
+            start: from_millis(self.s),
+            open: self.o,
+            high: self.h,
+            low: self.l,
+            close: self.c,
+            volume: self.v,
+            vwap: self.vw,
+            // Minute bars carry the average trade size, not the number of
+            // trades.
+            trades: None,
+        }
+    }
+}
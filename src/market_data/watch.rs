@@ -0,0 +1,110 @@
+use crate::market_data::{MarketDataProvider, MarketEvent};
+
+use futures_util::stream::BoxStream;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio_stream::{StreamExt, StreamMap};
+use tracing::warn;
+
+/// The last trade price of a changing set of symbols, kept up to date from
+/// the feed.
+///
+/// Each watched symbol holds a subscription to the feed, which ends when the
+/// symbol is unwatched or the `PriceWatch` is dropped. Without a feed, prices
+/// are only the ones given to `seed`.
+#[derive(Default)]
+pub(crate) struct PriceWatch {
+    provider: Option<Arc<dyn MarketDataProvider>>,
+
+    /// Trade prices of every watched symbol.
+    streams: StreamMap<String, BoxStream<'static, f64>>,
+
+    /// Last known price by symbol.
+    prices: HashMap<String, f64>,
+
+    /// Symbols whose price came from the feed, `seed` leaves them alone.
+    live: HashSet<String>,
+}
+
+impl PriceWatch {
+    pub(crate) fn new(provider: Option<Arc<dyn MarketDataProvider>>) -> PriceWatch {
+        PriceWatch {
+            provider,
+            ..PriceWatch::default()
+        }
+    }
+
+    /// Start following the price of `symbol`, starting with its last trade.
+    /// Failures are logged, the symbol then keeps the price it was seeded
+    /// with.
+    pub(crate) async fn watch(&mut self, symbol: &str) {
+        let Some(provider) = self.provider.clone() else {
+            return;
+        };
+
+        if self.streams.contains_key(symbol) {
+            return;
+        }
+
+        match provider.last_trade(symbol).await {
+            Ok(trade) => self.set(symbol, trade.price),
+            Err(err) => warn!(%symbol, cause = %err, "failed to get last trade"),
+        }
+
+        match provider.subscribe(symbol).await {
+            Ok(mut subscription) => {
+                let trades = async_stream::stream! {
+                    while let Some(event) = subscription.recv().await {
+                        if let MarketEvent::Trade(trade) = event {
+                            yield trade.price;
+                        }
+                    }
+                };
+                self.streams.insert(symbol.to_string(), Box::pin(trades));
+            }
+            Err(err) => warn!(%symbol, cause = %err, "failed to subscribe to trades"),
+        }
+    }
+
+    /// Stop following the price of `symbol` and forget it.
+    pub(crate) fn unwatch(&mut self, symbol: &str) {
+        self.streams.remove(symbol);
+        self.prices.remove(symbol);
+        self.live.remove(symbol);
+    }
+
+    /// Use `price` for `symbol` until the feed reports one.
+    pub(crate) fn seed(&mut self, symbol: &str, price: f64) {
+        if !self.live.contains(symbol) {
+            self.prices.insert(symbol.to_string(), price);
+        }
+    }
+
+    /// Returns the last known price of `symbol`.
+    pub(crate) fn price(&self, symbol: &str) -> Option<f64> {
+        self.prices.get(symbol).copied()
+    }
+
+    /// Wait for the next trade of a watched symbol and take in its price.
+    ///
+    /// Returns the symbol whose price changed. Never returns while no symbol
+    /// is watched.
+    pub(crate) async fn changed(&mut self) -> String {
+        loop {
+            match self.streams.next().await {
+                Some((symbol, price)) => {
+                    if self.price(&symbol) != Some(price) {
+                        self.set(&symbol, price);
+                        return symbol;
+                    }
+                }
+                None => std::future::pending().await,
+            }
+        }
+    }
+
+    fn set(&mut self, symbol: &str, price: f64) {
+        self.prices.insert(symbol.to_string(), price);
+        self.live.insert(symbol.to_string());
+    }
+}
@@ -0,0 +1,126 @@
+//! Error codes and texts TWS reports to clients through `errMsg` (4).
+//!
+//! Codes and messages match the ones real TWS sends, so clients that react to
+//! specific codes behave the same against the connector.
+
+use crate::messages::{Encoder, OutgoingMessage};
+use crate::Frame;
+
+/// An error TWS can report to a client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct TwsError {
+    pub(crate) code: i32,
+    pub(crate) message: &'static str,
+}
+
+impl TwsError {
+    /// Encode the error for the request `id`, or `-1` if it is not tied to a
+    /// request.
+    pub(crate) fn to_frame(self, id: i64, server_version: u16) -> Frame {
+        self.to_frame_with(id, server_version, self.message)
+    }
+
+    /// Encode the error with a more specific text than the default one.
+    pub(crate) fn to_frame_with(self, id: i64, server_version: u16, message: &str) -> Frame {
+        Encoder::new(OutgoingMessage::ErrMsg, server_version)
+            .put("id", id)
+            .put("errorCode", self.code)
+            .put("errorMsg", message)
+            .into_frame()
+    }
+}
+
+pub(crate) const DUPLICATE_TICKER_ID: TwsError = TwsError {
+    code: 102,
+    message: "Duplicate ticker ID.",
+};
+
+pub(crate) const DUPLICATE_ORDER_ID: TwsError = TwsError {
+    code: 103,
+    message: "Duplicate order id",
+};
+
+pub(crate) const CANNOT_MODIFY_FILLED: TwsError = TwsError {
+    code: 104,
+    message: "Can't modify a filled order.",
+};
+
+pub(crate) const MODIFY_MISMATCH: TwsError = TwsError {
+    code: 105,
+    message: "Order being modified does not match original order.",
+};
+
+pub(crate) const NOT_CANCELLABLE: TwsError = TwsError {
+    code: 161,
+    message: "Cancel attempted when order is not in a cancellable state. Order permId =",
+};
+
+pub(crate) const NO_SECURITY_DEFINITION: TwsError = TwsError {
+    code: 200,
+    message: "No security definition has been found for the request",
+};
+
+pub(crate) const ORDER_REJECTED: TwsError = TwsError {
+    code: 201,
+    message: "Order rejected - reason:",
+};
+
+pub(crate) const ORDER_CANCELLED: TwsError = TwsError {
+    code: 202,
+    message: "Order Canceled - reason:",
+};
+
+pub(crate) const VALIDATION_FAILED: TwsError = TwsError {
+    code: 321,
+    message: "Error validating request.",
+};
+
+pub(crate) const PROCESSING_FAILED: TwsError = TwsError {
+    code: 322,
+    message: "Error processing request.",
+};
+
+pub(crate) const CLIENT_ID_IN_USE: TwsError = TwsError {
+    code: 326,
+    message: "Unable to connect as the client id is already in use. Retry with a unique client id.",
+};
+
+pub(crate) const MARKET_DATA_NOT_SUBSCRIBED: TwsError = TwsError {
+    code: 354,
+    message: "Requested market data is not subscribed.",
+};
+
+pub(crate) const ALREADY_CONNECTED: TwsError = TwsError {
+    code: 501,
+    message: "Already connected.",
+};
+
+pub(crate) const UPDATE_TWS: TwsError = TwsError {
+    code: 503,
+    message: "The TWS is out of date and must be upgraded.",
+};
+
+pub(crate) const NOT_CONNECTED: TwsError = TwsError {
+    code: 504,
+    message: "Not connected",
+};
+
+pub(crate) const UNKNOWN_ID: TwsError = TwsError {
+    code: 505,
+    message: "Fatal Error: Unknown message id.",
+};
+
+pub(crate) const PART_OF_MARKET_DATA_NOT_SUBSCRIBED: TwsError = TwsError {
+    code: 10090,
+    message: "Part of requested market data is not subscribed. Subscription-independent ticks are still active.",
+};
+
+pub(crate) const DEPTH_NOT_SUPPORTED: TwsError = TwsError {
+    code: 10092,
+    message: "Deep market data is not supported for this combination of security type/exchange",
+};
+
+pub(crate) const ORDER_TO_CANCEL_NOT_FOUND: TwsError = TwsError {
+    code: 10147,
+    message: "OrderId that needs to be cancelled is not found.",
+};
@@ -0,0 +1,212 @@
+use crate::messages::versions::*;
+use crate::messages::{message_ids, Field};
+
+message_ids! {
+    /// Requests a client can send to the connector.
+    pub enum IncomingMessage {
+        ReqMktData = 1,
+        CancelMktData = 2,
+        PlaceOrder = 3,
+        CancelOrder = 4,
+        ReqOpenOrders = 5,
+        ReqAccountUpdates = 6,
+        ReqExecutions = 7,
+        ReqIds = 8,
+        ReqContractData = 9,
+        ReqMktDepth = 10,
+        CancelMktDepth = 11,
+        ReqNewsBulletins = 12,
+        CancelNewsBulletins = 13,
+        SetServerLogLevel = 14,
+        ReqAutoOpenOrders = 15,
+        ReqAllOpenOrders = 16,
+        ReqManagedAccts = 17,
+        ReqFa = 18,
+        ReplaceFa = 19,
+        ReqHistoricalData = 20,
+        ExerciseOptions = 21,
+        ReqScannerSubscription = 22,
+        CancelScannerSubscription = 23,
+        ReqScannerParameters = 24,
+        CancelHistoricalData = 25,
+        ReqCurrentTime = 49,
+        ReqRealTimeBars = 50,
+        CancelRealTimeBars = 51,
+        ReqFundamentalData = 52,
+        CancelFundamentalData = 53,
+        ReqCalcImpliedVolat = 54,
+        ReqCalcOptionPrice = 55,
+        CancelCalcImpliedVolat = 56,
+        CancelCalcOptionPrice = 57,
+        ReqGlobalCancel = 58,
+        ReqMarketDataType = 59,
+        ReqPositions = 61,
+        ReqAccountSummary = 62,
+        CancelAccountSummary = 63,
+        CancelPositions = 64,
+        VerifyRequest = 65,
+        VerifyMessage = 66,
+        QueryDisplayGroups = 67,
+        SubscribeToGroupEvents = 68,
+        UpdateDisplayGroup = 69,
+        UnsubscribeFromGroupEvents = 70,
+        StartApi = 71,
+        VerifyAndAuthRequest = 72,
+        VerifyAndAuthMessage = 73,
+        ReqPositionsMulti = 74,
+        CancelPositionsMulti = 75,
+        ReqAccountUpdatesMulti = 76,
+        CancelAccountUpdatesMulti = 77,
+        ReqSecDefOptParams = 78,
+        ReqSoftDollarTiers = 79,
+        ReqFamilyCodes = 80,
+        ReqMatchingSymbols = 81,
+        ReqMktDepthExchanges = 82,
+        ReqSmartComponents = 83,
+        ReqNewsArticle = 84,
+        ReqNewsProviders = 85,
+        ReqHistoricalNews = 86,
+        ReqHeadTimestamp = 87,
+        ReqHistogramData = 88,
+        CancelHistogramData = 89,
+        CancelHeadTimestamp = 90,
+        ReqMarketRule = 91,
+        ReqPnL = 92,
+        CancelPnL = 93,
+        ReqPnLSingle = 94,
+        CancelPnLSingle = 95,
+        ReqHistoricalTicks = 96,
+        ReqTickByTickData = 97,
+        CancelTickByTickData = 98,
+        ReqCompletedOrders = 99,
+        ReqWshMetaData = 100,
+        CancelWshMetaData = 101,
+        ReqWshEventData = 102,
+        CancelWshEventData = 103,
+        ReqUserInfo = 104,
+    }
+}
+
+impl IncomingMessage {
+    /// Returns the fields that follow the message id, in wire order.
+    ///
+    /// Requests whose layout depends on the content of earlier fields, such
+    /// as `placeOrder`, are decoded by their command and have no layout here.
+    pub(crate) fn layout(self) -> &'static [Field] {
+        use IncomingMessage::*;
+
+        match self {
+            CancelMktData => CANCEL_MKT_DATA,
+            CancelOrder => CANCEL_ORDER,
+            ReqOpenOrders | ReqAllOpenOrders | ReqManagedAccts | ReqCurrentTime
+            | ReqGlobalCancel | ReqPositions | CancelPositions => VERSION_ONLY,
+            ReqAccountUpdates => REQ_ACCOUNT_UPDATES,
+            ReqExecutions => REQ_EXECUTIONS,
+            ReqIds => REQ_IDS,
+            ReqMktDepth => REQ_MKT_DEPTH,
+            CancelMktDepth => CANCEL_MKT_DEPTH,
+            ReqAutoOpenOrders => REQ_AUTO_OPEN_ORDERS,
+            ReqMarketDataType => REQ_MARKET_DATA_TYPE,
+            ReqAccountSummary => REQ_ACCOUNT_SUMMARY,
+            CancelAccountSummary => CANCEL_ACCOUNT_SUMMARY,
+            StartApi => START_API,
+            ReqPnL => REQ_PNL,
+            CancelPnL | CancelPnLSingle => CANCEL_PNL,
+            ReqPnLSingle => REQ_PNL_SINGLE,
+            ReqCompletedOrders => REQ_COMPLETED_ORDERS,
+            _ => &[],
+        }
+    }
+}
+
+const VERSION_ONLY: &[Field] = &[Field::new("version")];
+
+const CANCEL_MKT_DATA: &[Field] = &[Field::new("version"), Field::new("reqId")];
+
+const CANCEL_ORDER: &[Field] = &[
+    Field::new("version"),
+    Field::new("orderId"),
+    Field::new("manualOrderCancelTime").since(MIN_SERVER_VER_MANUAL_ORDER_TIME),
+];
+
+const REQ_ACCOUNT_UPDATES: &[Field] = &[
+    Field::new("version"),
+    Field::new("subscribe"),
+    Field::new("acctCode"),
+];
+
+const REQ_EXECUTIONS: &[Field] = &[
+    Field::new("version"),
+    Field::new("reqId"),
+    Field::new("clientId"),
+    Field::new("acctCode"),
+    Field::new("time"),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("exchange"),
+    Field::new("side"),
+];
+
+const REQ_IDS: &[Field] = &[Field::new("version"), Field::new("numIds")];
+
+const REQ_MKT_DEPTH: &[Field] = &[
+    Field::new("version"),
+    Field::new("reqId"),
+    Field::new("conId").since(MIN_SERVER_VER_TRADING_CLASS),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("lastTradeDateOrContractMonth"),
+    Field::new("strike"),
+    Field::new("right"),
+    Field::new("multiplier"),
+    Field::new("exchange"),
+    Field::new("primaryExchange").since(MIN_SERVER_VER_MKT_DEPTH_PRIM_EXCHANGE),
+    Field::new("currency"),
+    Field::new("localSymbol"),
+    Field::new("tradingClass").since(MIN_SERVER_VER_TRADING_CLASS),
+    Field::new("numRows"),
+    Field::new("isSmartDepth").since(MIN_SERVER_VER_SMART_DEPTH),
+    Field::new("mktDepthOptions").since(MIN_SERVER_VER_LINKING),
+];
+
+const CANCEL_MKT_DEPTH: &[Field] = &[
+    Field::new("version"),
+    Field::new("reqId"),
+    Field::new("isSmartDepth").since(MIN_SERVER_VER_SMART_DEPTH),
+];
+
+const REQ_AUTO_OPEN_ORDERS: &[Field] = &[Field::new("version"), Field::new("autoBind")];
+
+const REQ_MARKET_DATA_TYPE: &[Field] = &[Field::new("version"), Field::new("marketDataType")];
+
+const REQ_ACCOUNT_SUMMARY: &[Field] = &[
+    Field::new("version"),
+    Field::new("reqId"),
+    Field::new("group"),
+    Field::new("tags"),
+];
+
+const CANCEL_ACCOUNT_SUMMARY: &[Field] = &[Field::new("version"), Field::new("reqId")];
+
+const START_API: &[Field] = &[
+    Field::new("version"),
+    Field::new("clientId"),
+    Field::new("optionalCapabilities").since(MIN_SERVER_VER_OPTIONAL_CAPABILITIES),
+];
+
+const REQ_PNL: &[Field] = &[
+    Field::new("reqId"),
+    Field::new("account"),
+    Field::new("modelCode"),
+];
+
+const CANCEL_PNL: &[Field] = &[Field::new("reqId")];
+
+const REQ_PNL_SINGLE: &[Field] = &[
+    Field::new("reqId"),
+    Field::new("account"),
+    Field::new("modelCode"),
+    Field::new("conId"),
+];
+
+const REQ_COMPLETED_ORDERS: &[Field] = &[Field::new("apiOnly")];
@@ -0,0 +1,246 @@
+//! Declarative field layouts for TWS messages.
+//!
+//! A layout lists the fields of a message in wire order together with the
+//! server versions in which each field is present. Messages are encoded and
+//! decoded by walking the layout for the negotiated version, so handlers refer
+//! to fields by name and never need to know their position.
+
+use crate::messages::{IncomingMessage, OutgoingMessage};
+use crate::{Frame, Parse};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A single field of a message layout.
+#[derive(Debug)]
+pub(crate) struct Field {
+    /// Name used by handlers to supply or read the value
+    name: &'static str,
+
+    /// Value that is always sent for this field, e.g. the message version
+    constant: Option<&'static str>,
+
+    /// First server version that carries the field
+    since: u16,
+
+    /// First server version that no longer carries the field
+    until: u16,
+
+    /// Stands for a variable number of wire fields, such as a repeated group
+    group: bool,
+}
+
+impl Field {
+    /// A field present in every server version.
+    pub(crate) const fn new(name: &'static str) -> Field {
+        Field {
+            name,
+            constant: None,
+            since: 0,
+            until: u16::MAX,
+            group: false,
+        }
+    }
+
+    /// A repeated or optional group of fields, whose length depends on the
+    /// content of the message. Nothing is sent unless a value is supplied
+    /// with `Encoder::put_group`.
+    pub(crate) const fn group(name: &'static str) -> Field {
+        Field {
+            group: true,
+            ..Field::new(name)
+        }
+    }
+
+    /// A field whose value is fixed by the protocol.
+    pub(crate) const fn constant(name: &'static str, value: &'static str) -> Field {
+        Field {
+            constant: Some(value),
+            ..Field::new(name)
+        }
+    }
+
+    /// Only present from server version `version` onwards.
+    pub(crate) const fn since(self, version: u16) -> Field {
+        Field {
+            since: version,
+            ..self
+        }
+    }
+
+    /// Only present before server version `version`.
+    pub(crate) const fn until(self, version: u16) -> Field {
+        Field {
+            until: version,
+            ..self
+        }
+    }
+
+    /// Returns `true` if the field is on the wire at `server_version`.
+    fn is_present(&self, server_version: u16) -> bool {
+        self.since <= server_version && server_version < self.until
+    }
+}
+
+/// Builds an outgoing message by field name.
+///
+/// Values are collected with `put` and laid out in wire order by `into_frame`.
+/// Fields that do not exist at the negotiated version are dropped, and fields
+/// that exist but were not supplied are sent empty.
+#[derive(Debug)]
+pub(crate) struct Encoder {
+    message: OutgoingMessage,
+    server_version: u16,
+    values: HashMap<&'static str, Vec<String>>,
+}
+
+impl Encoder {
+    pub(crate) fn new(message: OutgoingMessage, server_version: u16) -> Encoder {
+        Encoder {
+            message,
+            server_version,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Set the value of the field `name`.
+    ///
+    /// # Panics
+    ///
+    /// panics if the message layout has no field called `name`
+    pub(crate) fn put(self, name: &'static str, value: impl ToString) -> Encoder {
+        self.put_group(name, vec![value.to_string()])
+    }
+
+    /// Set the wire fields that make up the group `name`, in order.
+    ///
+    /// # Panics
+    ///
+    /// panics if the message layout has no field called `name`
+    pub(crate) fn put_group(mut self, name: &'static str, values: Vec<String>) -> Encoder {
+        assert!(
+            self.message.layout().iter().any(|field| field.name == name),
+            "{:?} has no field `{}`",
+            self.message,
+            name
+        );
+
+        self.values.insert(name, values);
+        self
+    }
+
+    /// Encode the message id followed by every field present at the
+    /// negotiated version.
+    pub(crate) fn into_frame(mut self) -> Frame {
+        let mut frame = Frame::message();
+        frame.push_int(self.message.id().into());
+
+        for field in self.message.layout() {
+            if !field.is_present(self.server_version) {
+                continue;
+            }
+
+            match (field.constant, self.values.remove(field.name)) {
+                (Some(value), _) => frame.push_str(value),
+                (None, Some(values)) => values.iter().for_each(|value| frame.push_str(value)),
+                (None, None) if field.group => {}
+                (None, None) => frame.push_str(""),
+            }
+        }
+
+        frame
+    }
+}
+
+/// The fields of a decoded incoming message, by name.
+#[derive(Debug, Default)]
+pub(crate) struct Fields {
+    values: HashMap<&'static str, String>,
+}
+
+impl Fields {
+    /// Read the fields of `message` from `parse`, following its layout at
+    /// `server_version`. The message id has already been consumed.
+    pub(crate) fn decode(
+        message: IncomingMessage,
+        server_version: u16,
+        parse: &mut Parse,
+    ) -> crate::Result<Fields> {
+        let mut fields = Fields::default();
+        fields.read(message.layout(), server_version, parse)?;
+        Ok(fields)
+    }
+
+    /// Read the next part of a message from `parse`, following `layout` at
+    /// `server_version`.
+    ///
+    /// Used for requests whose layout depends on their content: each fixed
+    /// part is read with its own layout, the repeated groups in between by
+    /// the command.
+    pub(crate) fn read(
+        &mut self,
+        layout: &[Field],
+        server_version: u16,
+        parse: &mut Parse,
+    ) -> crate::Result<()> {
+        for field in layout {
+            if field.is_present(server_version) {
+                self.values.insert(field.name, parse.next_string()?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the raw value of `name`, or an empty string if the field was
+    /// not present at the negotiated version.
+    pub(crate) fn string(&self, name: &str) -> String {
+        self.values.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Returns the value of `name` parsed as `T`.
+    pub(crate) fn parse<T: FromStr>(&self, name: &str) -> crate::Result<T> {
+        let value = self.string(name);
+        value.parse().map_err(|_| {
+            format!("protocol error; invalid value `{}` for field `{}`", value, name).into()
+        })
+    }
+
+    /// Returns the value of `name` parsed as `T`, or `None` if it is empty or
+    /// the largest value of its type, which clients send for unset numbers.
+    pub(crate) fn optional<T: FromStr + Bounded>(&self, name: &str) -> crate::Result<Option<T>> {
+        if self.string(name).is_empty() {
+            return Ok(None);
+        }
+
+        let value: T = self.parse(name)?;
+        Ok(if value.is_unset() { None } else { Some(value) })
+    }
+
+    /// Returns the value of the boolean field `name`, sent as `0` or `1`.
+    /// Empty means `false`.
+    pub(crate) fn flag(&self, name: &str) -> crate::Result<bool> {
+        match self.string(name).as_str() {
+            "" | "0" | "false" => Ok(false),
+            "1" | "true" => Ok(true),
+            value => Err(format!("protocol error; invalid value `{}` for field `{}`", value, name).into()),
+        }
+    }
+}
+
+/// Numbers clients may leave unset by sending their largest value.
+pub(crate) trait Bounded {
+    fn is_unset(&self) -> bool;
+}
+
+impl Bounded for f64 {
+    fn is_unset(&self) -> bool {
+        *self >= f64::MAX
+    }
+}
+
+impl Bounded for i32 {
+    fn is_unset(&self) -> bool {
+        *self == i32::MAX
+    }
+}
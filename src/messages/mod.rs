@@ -0,0 +1,53 @@
+//! TWS message ids and the field layout of each message.
+//!
+//! Names are from the connector's point of view: an `IncomingMessage` is a
+//! request sent by the client, an `OutgoingMessage` is a response or event
+//! sent back to it.
+
+mod incoming;
+pub use incoming::IncomingMessage;
+
+mod outgoing;
+pub use outgoing::OutgoingMessage;
+
+mod layout;
+pub(crate) use layout::{Encoder, Field, Fields};
+
+pub(crate) mod errors;
+
+pub(crate) mod versions;
+
+/// Declares a message id enum together with its id conversions.
+macro_rules! message_ids {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($variant:ident = $id:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl $name {
+            /// Returns the id sent as the first field of the message.
+            pub fn id(self) -> u16 {
+                match self {
+                    $($name::$variant => $id,)*
+                }
+            }
+
+            /// Returns the message with the given id, if there is one.
+            pub fn from_id(id: u16) -> Option<$name> {
+                match id {
+                    $($id => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+use message_ids;
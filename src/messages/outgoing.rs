@@ -0,0 +1,604 @@
+use crate::messages::versions::*;
+use crate::messages::{message_ids, Field};
+
+message_ids! {
+    /// Responses and events the connector can send to a client.
+    pub enum OutgoingMessage {
+        TickPrice = 1,
+        TickSize = 2,
+        OrderStatus = 3,
+        ErrMsg = 4,
+        OpenOrder = 5,
+        AcctValue = 6,
+        PortfolioValue = 7,
+        AcctUpdateTime = 8,
+        NextValidId = 9,
+        ContractData = 10,
+        ExecutionData = 11,
+        MarketDepth = 12,
+        MarketDepthL2 = 13,
+        NewsBulletins = 14,
+        ManagedAccts = 15,
+        ReceiveFa = 16,
+        HistoricalData = 17,
+        BondContractData = 18,
+        ScannerParameters = 19,
+        ScannerData = 20,
+        TickOptionComputation = 21,
+        TickGeneric = 45,
+        TickString = 46,
+        TickEfp = 47,
+        CurrentTime = 49,
+        RealTimeBars = 50,
+        FundamentalData = 51,
+        ContractDataEnd = 52,
+        OpenOrderEnd = 53,
+        AcctDownloadEnd = 54,
+        ExecutionDataEnd = 55,
+        DeltaNeutralValidation = 56,
+        TickSnapshotEnd = 57,
+        MarketDataType = 58,
+        CommissionReport = 59,
+        PositionData = 61,
+        PositionEnd = 62,
+        AccountSummary = 63,
+        AccountSummaryEnd = 64,
+        VerifyMessageApi = 65,
+        VerifyCompleted = 66,
+        DisplayGroupList = 67,
+        DisplayGroupUpdated = 68,
+        VerifyAndAuthMessageApi = 69,
+        VerifyAndAuthCompleted = 70,
+        PositionMulti = 71,
+        PositionMultiEnd = 72,
+        AccountUpdateMulti = 73,
+        AccountUpdateMultiEnd = 74,
+        SecurityDefinitionOptionParameter = 75,
+        SecurityDefinitionOptionParameterEnd = 76,
+        SoftDollarTiers = 77,
+        FamilyCodes = 78,
+        SymbolSamples = 79,
+        MktDepthExchanges = 80,
+        TickReqParams = 81,
+        SmartComponents = 82,
+        NewsArticle = 83,
+        TickNews = 84,
+        NewsProviders = 85,
+        HistoricalNews = 86,
+        HistoricalNewsEnd = 87,
+        HeadTimestamp = 88,
+        HistogramData = 89,
+        HistoricalDataUpdate = 90,
+        RerouteMktDataReq = 91,
+        RerouteMktDepthReq = 92,
+        MarketRule = 93,
+        Pnl = 94,
+        PnlSingle = 95,
+        HistoricalTicks = 96,
+        HistoricalTicksBidAsk = 97,
+        HistoricalTicksLast = 98,
+        TickByTick = 99,
+        OrderBound = 100,
+        CompletedOrder = 101,
+        CompletedOrdersEnd = 102,
+        ReplaceFaEnd = 103,
+        WshMetaData = 104,
+        WshEventData = 105,
+        HistoricalSchedule = 106,
+        UserInfo = 107,
+    }
+}
+
+impl OutgoingMessage {
+    /// Returns the fields that follow the message id, in wire order.
+    ///
+    /// Repeated and optional parts, such as the algo parameters of
+    /// `openOrder`, are single `Field::group` entries filled in by the
+    /// command.
+    pub(crate) fn layout(self) -> &'static [Field] {
+        use OutgoingMessage::*;
+
+        match self {
+            TickPrice => TICK_PRICE,
+            TickSize => TICK_SIZE,
+            OrderStatus => ORDER_STATUS,
+            ErrMsg => ERR_MSG,
+            OpenOrder => OPEN_ORDER,
+            AcctValue => ACCT_VALUE,
+            PortfolioValue => PORTFOLIO_VALUE,
+            AcctUpdateTime => ACCT_UPDATE_TIME,
+            NextValidId => NEXT_VALID_ID,
+            ExecutionData => EXECUTION_DATA,
+            MarketDepth => MARKET_DEPTH,
+            MarketDepthL2 => MARKET_DEPTH_L2,
+            ManagedAccts => MANAGED_ACCTS,
+            TickGeneric | TickString => TICK_VALUE,
+            CurrentTime => CURRENT_TIME,
+            OpenOrderEnd | PositionEnd => VERSION_ONLY,
+            AcctDownloadEnd => ACCT_DOWNLOAD_END,
+            ExecutionDataEnd | TickSnapshotEnd | AccountSummaryEnd => REQ_ID_END,
+            MarketDataType => MARKET_DATA_TYPE,
+            MktDepthExchanges => MKT_DEPTH_EXCHANGES,
+            CommissionReport => COMMISSION_REPORT,
+            PositionData => POSITION_DATA,
+            AccountSummary => ACCOUNT_SUMMARY,
+            Pnl => PNL,
+            PnlSingle => PNL_SINGLE,
+            CompletedOrder => COMPLETED_ORDER,
+            CompletedOrdersEnd => COMPLETED_ORDERS_END,
+            _ => &[],
+        }
+    }
+}
+
+const VERSION_ONLY: &[Field] = &[Field::constant("version", "1")];
+
+const TICK_PRICE: &[Field] = &[
+    Field::constant("version", "6"),
+    Field::new("tickerId"),
+    Field::new("tickType"),
+    Field::new("price"),
+    Field::new("size"),
+    Field::new("attrMask"),
+];
+
+const TICK_SIZE: &[Field] = &[
+    Field::constant("version", "6"),
+    Field::new("tickerId"),
+    Field::new("tickType"),
+    Field::new("size"),
+];
+
+const TICK_VALUE: &[Field] = &[
+    Field::constant("version", "6"),
+    Field::new("tickerId"),
+    Field::new("tickType"),
+    Field::new("value"),
+];
+
+const ORDER_STATUS: &[Field] = &[
+    Field::constant("version", "6").until(MIN_SERVER_VER_MARKET_CAP_PRICE),
+    Field::new("orderId"),
+    Field::new("status"),
+    Field::new("filled"),
+    Field::new("remaining"),
+    Field::new("avgFillPrice"),
+    Field::new("permId"),
+    Field::new("parentId"),
+    Field::new("lastFillPrice"),
+    Field::new("clientId"),
+    Field::new("whyHeld"),
+    Field::new("mktCapPrice").since(MIN_SERVER_VER_MARKET_CAP_PRICE),
+];
+
+const ERR_MSG: &[Field] = &[
+    Field::constant("version", "2"),
+    Field::new("id"),
+    Field::new("errorCode"),
+    Field::new("errorMsg"),
+    Field::new("advancedOrderRejectJson").since(MIN_SERVER_VER_ADVANCED_ORDER_REJECT),
+];
+
+/// Follows the official client's `OrderDecoder`, which reads the contract,
+/// the order and the order state in one message.
+const OPEN_ORDER: &[Field] = &[
+    Field::constant("version", "34").until(MIN_SERVER_VER_ORDER_CONTAINER),
+    Field::new("orderId"),
+    // Contract
+    Field::new("conId"),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("lastTradeDateOrContractMonth"),
+    Field::new("strike"),
+    Field::new("right"),
+    Field::new("multiplier"),
+    Field::new("exchange"),
+    Field::new("currency"),
+    Field::new("localSymbol"),
+    Field::new("tradingClass"),
+    // Order
+    Field::new("action"),
+    Field::new("totalQuantity"),
+    Field::new("orderType"),
+    Field::new("lmtPrice"),
+    Field::new("auxPrice"),
+    Field::new("tif"),
+    Field::new("ocaGroup"),
+    Field::new("account"),
+    Field::new("openClose"),
+    Field::new("origin"),
+    Field::new("orderRef"),
+    Field::new("clientId"),
+    Field::new("permId"),
+    Field::new("outsideRth"),
+    Field::new("hidden"),
+    Field::new("discretionaryAmt"),
+    Field::new("goodAfterTime"),
+    Field::new("sharesAllocation"),
+    Field::new("faGroup"),
+    Field::new("faMethod"),
+    Field::new("faPercentage"),
+    Field::new("faProfile"),
+    Field::new("modelCode").since(MIN_SERVER_VER_MODELS_SUPPORT),
+    Field::new("goodTillDate"),
+    Field::new("rule80A"),
+    Field::new("percentOffset"),
+    Field::new("settlingFirm"),
+    Field::new("shortSaleSlot"),
+    Field::new("designatedLocation"),
+    Field::new("exemptCode"),
+    Field::new("auctionStrategy"),
+    Field::new("startingPrice"),
+    Field::new("stockRefPrice"),
+    Field::new("delta"),
+    Field::new("stockRangeLower"),
+    Field::new("stockRangeUpper"),
+    Field::new("displaySize"),
+    Field::new("blockOrder"),
+    Field::new("sweepToFill"),
+    Field::new("allOrNone"),
+    Field::new("minQty"),
+    Field::new("ocaType"),
+    Field::new("eTradeOnly"),
+    Field::new("firmQuoteOnly"),
+    Field::new("nbboPriceCap"),
+    Field::new("parentId"),
+    Field::new("triggerMethod"),
+    Field::new("volatility"),
+    Field::new("volatilityType"),
+    Field::new("deltaNeutralOrderType"),
+    Field::new("deltaNeutralAuxPrice"),
+    // Only when deltaNeutralOrderType is set
+    Field::group("deltaNeutralOrder"),
+    Field::new("continuousUpdate"),
+    Field::new("referencePriceType"),
+    Field::new("trailStopPrice"),
+    Field::new("trailingPercent"),
+    Field::new("basisPoints"),
+    Field::new("basisPointsType"),
+    Field::new("comboLegsDescrip"),
+    // comboLegsCount, the legs, orderComboLegsCount and their prices
+    Field::group("comboLegs"),
+    // smartComboRoutingParamsCount and the tag-value pairs
+    Field::group("smartComboRoutingParams"),
+    Field::new("scaleInitLevelSize"),
+    Field::new("scaleSubsLevelSize"),
+    Field::new("scalePriceIncrement"),
+    // Only when scalePriceIncrement is positive
+    Field::group("scaleOrder"),
+    Field::new("hedgeType"),
+    // hedgeParam, only when hedgeType is set
+    Field::group("hedgeParam"),
+    Field::new("optOutSmartRouting"),
+    Field::new("clearingAccount"),
+    Field::new("clearingIntent"),
+    Field::new("notHeld"),
+    // A flag, followed by conId, delta and price when set
+    Field::group("deltaNeutralContract"),
+    Field::new("algoStrategy"),
+    // algoParamsCount and the tag-value pairs, only when algoStrategy is set
+    Field::group("algoParams"),
+    Field::new("solicited"),
+    Field::new("whatIf"),
+    // Order state
+    Field::new("status"),
+    Field::new("initMarginBefore").since(MIN_SERVER_VER_WHAT_IF_EXT_FIELDS),
+    Field::new("maintMarginBefore").since(MIN_SERVER_VER_WHAT_IF_EXT_FIELDS),
+    Field::new("equityWithLoanBefore").since(MIN_SERVER_VER_WHAT_IF_EXT_FIELDS),
+    Field::new("initMarginChange").since(MIN_SERVER_VER_WHAT_IF_EXT_FIELDS),
+    Field::new("maintMarginChange").since(MIN_SERVER_VER_WHAT_IF_EXT_FIELDS),
+    Field::new("equityWithLoanChange").since(MIN_SERVER_VER_WHAT_IF_EXT_FIELDS),
+    Field::new("initMarginAfter"),
+    Field::new("maintMarginAfter"),
+    Field::new("equityWithLoanAfter"),
+    Field::new("commission"),
+    Field::new("minCommission"),
+    Field::new("maxCommission"),
+    Field::new("commissionCurrency"),
+    Field::new("warningText"),
+    Field::new("randomizeSize"),
+    Field::new("randomizePrice"),
+    // Only for PEG BENCH orders
+    Field::group("peggedToBenchmark").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    // conditionsCount, the conditions, then conditionsIgnoreRth and
+    // conditionsCancelOrder if there are any
+    Field::group("conditions").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("adjustedOrderType").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("triggerPrice").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("adjustedTrailStopPrice").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("lmtPriceOffset").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("adjustedStopPrice").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("adjustedStopLimitPrice").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("adjustedTrailingAmount").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("adjustableTrailingUnit").since(MIN_SERVER_VER_PEGGED_TO_BENCHMARK),
+    Field::new("softDollarTierName").since(MIN_SERVER_VER_SOFT_DOLLAR_TIER),
+    Field::new("softDollarTierValue").since(MIN_SERVER_VER_SOFT_DOLLAR_TIER),
+    Field::new("softDollarTierDisplayName").since(MIN_SERVER_VER_SOFT_DOLLAR_TIER),
+    Field::new("cashQty").since(MIN_SERVER_VER_CASH_QTY),
+    Field::new("dontUseAutoPriceForHedge").since(MIN_SERVER_VER_AUTO_PRICE_FOR_HEDGE),
+    Field::new("isOmsContainer").since(MIN_SERVER_VER_ORDER_CONTAINER),
+    Field::new("discretionaryUpToLimitPrice").since(MIN_SERVER_VER_D_PEG_ORDERS),
+    Field::new("usePriceMgmtAlgo").since(MIN_SERVER_VER_PRICE_MGMT_ALGO),
+];
+
+/// Completed orders only exist from server version 150 on, so no field
+/// depends on the version.
+const COMPLETED_ORDER: &[Field] = &[
+    // Contract
+    Field::new("conId"),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("lastTradeDateOrContractMonth"),
+    Field::new("strike"),
+    Field::new("right"),
+    Field::new("multiplier"),
+    Field::new("exchange"),
+    Field::new("currency"),
+    Field::new("localSymbol"),
+    Field::new("tradingClass"),
+    // Order
+    Field::new("action"),
+    Field::new("totalQuantity"),
+    Field::new("orderType"),
+    Field::new("lmtPrice"),
+    Field::new("auxPrice"),
+    Field::new("tif"),
+    Field::new("ocaGroup"),
+    Field::new("account"),
+    Field::new("openClose"),
+    Field::new("origin"),
+    Field::new("orderRef"),
+    Field::new("permId"),
+    Field::new("outsideRth"),
+    Field::new("hidden"),
+    Field::new("discretionaryAmt"),
+    Field::new("goodAfterTime"),
+    Field::new("faGroup"),
+    Field::new("faMethod"),
+    Field::new("faPercentage"),
+    Field::new("faProfile"),
+    Field::new("modelCode"),
+    Field::new("goodTillDate"),
+    Field::new("rule80A"),
+    Field::new("percentOffset"),
+    Field::new("settlingFirm"),
+    Field::new("shortSaleSlot"),
+    Field::new("designatedLocation"),
+    Field::new("exemptCode"),
+    Field::new("startingPrice"),
+    Field::new("stockRefPrice"),
+    Field::new("delta"),
+    Field::new("stockRangeLower"),
+    Field::new("stockRangeUpper"),
+    Field::new("displaySize"),
+    Field::new("sweepToFill"),
+    Field::new("allOrNone"),
+    Field::new("minQty"),
+    Field::new("ocaType"),
+    Field::new("triggerMethod"),
+    Field::new("volatility"),
+    Field::new("volatilityType"),
+    Field::new("deltaNeutralOrderType"),
+    Field::new("deltaNeutralAuxPrice"),
+    // Only when deltaNeutralOrderType is set
+    Field::group("deltaNeutralOrder"),
+    Field::new("continuousUpdate"),
+    Field::new("referencePriceType"),
+    Field::new("trailStopPrice"),
+    Field::new("trailingPercent"),
+    Field::new("comboLegsDescrip"),
+    // comboLegsCount, the legs, orderComboLegsCount and their prices
+    Field::group("comboLegs"),
+    // smartComboRoutingParamsCount and the tag-value pairs
+    Field::group("smartComboRoutingParams"),
+    Field::new("scaleInitLevelSize"),
+    Field::new("scaleSubsLevelSize"),
+    Field::new("scalePriceIncrement"),
+    // Only when scalePriceIncrement is positive
+    Field::group("scaleOrder"),
+    Field::new("hedgeType"),
+    // hedgeParam, only when hedgeType is set
+    Field::group("hedgeParam"),
+    Field::new("clearingAccount"),
+    Field::new("clearingIntent"),
+    Field::new("notHeld"),
+    // A flag, followed by conId, delta and price when set
+    Field::group("deltaNeutralContract"),
+    Field::new("algoStrategy"),
+    // algoParamsCount and the tag-value pairs, only when algoStrategy is set
+    Field::group("algoParams"),
+    Field::new("solicited"),
+    // Order state
+    Field::new("status"),
+    Field::new("randomizeSize"),
+    Field::new("randomizePrice"),
+    // Only for PEG BENCH orders
+    Field::group("peggedToBenchmark"),
+    // conditionsCount, the conditions, then conditionsIgnoreRth and
+    // conditionsCancelOrder if there are any
+    Field::group("conditions"),
+    // Read into trailStopPrice again by the client
+    Field::new("stopPrice"),
+    Field::new("lmtPriceOffset"),
+    Field::new("cashQty"),
+    Field::new("dontUseAutoPriceForHedge"),
+    Field::new("isOmsContainer"),
+    Field::new("autoCancelDate"),
+    Field::new("filledQuantity"),
+    Field::new("refFuturesConId"),
+    Field::new("autoCancelParent"),
+    Field::new("shareholder"),
+    Field::new("imbalanceOnly"),
+    Field::new("routeMarketableToBbo"),
+    Field::new("parentPermId"),
+    Field::new("completedTime"),
+    Field::new("completedStatus"),
+];
+
+/// Unlike the other end messages, `completedOrdersEnd` has no version.
+const COMPLETED_ORDERS_END: &[Field] = &[];
+
+const ACCT_VALUE: &[Field] = &[
+    Field::constant("version", "2"),
+    Field::new("key"),
+    Field::new("value"),
+    Field::new("currency"),
+    Field::new("accountName"),
+];
+
+const PORTFOLIO_VALUE: &[Field] = &[
+    Field::constant("version", "8"),
+    Field::new("conId"),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("lastTradeDateOrContractMonth"),
+    Field::new("strike"),
+    Field::new("right"),
+    Field::new("multiplier"),
+    Field::new("primaryExchange"),
+    Field::new("currency"),
+    Field::new("localSymbol"),
+    Field::new("tradingClass"),
+    Field::new("position"),
+    Field::new("marketPrice"),
+    Field::new("marketValue"),
+    Field::new("averageCost"),
+    Field::new("unrealizedPNL"),
+    Field::new("realizedPNL"),
+    Field::new("accountName"),
+];
+
+const ACCT_UPDATE_TIME: &[Field] = &[Field::constant("version", "1"), Field::new("timeStamp")];
+
+const NEXT_VALID_ID: &[Field] = &[Field::constant("version", "1"), Field::new("orderId")];
+
+const EXECUTION_DATA: &[Field] = &[
+    Field::constant("version", "10").until(MIN_SERVER_VER_LAST_LIQUIDITY),
+    Field::new("reqId"),
+    Field::new("orderId"),
+    Field::new("conId"),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("lastTradeDateOrContractMonth"),
+    Field::new("strike"),
+    Field::new("right"),
+    Field::new("multiplier"),
+    Field::new("exchange"),
+    Field::new("currency"),
+    Field::new("localSymbol"),
+    Field::new("tradingClass"),
+    Field::new("execId"),
+    Field::new("time"),
+    Field::new("acctNumber"),
+    Field::new("execExchange"),
+    Field::new("side"),
+    Field::new("shares"),
+    Field::new("price"),
+    Field::new("permId"),
+    Field::new("clientId"),
+    Field::new("liquidation"),
+    Field::new("cumQty"),
+    Field::new("avgPrice"),
+    Field::new("orderRef"),
+    Field::new("evRule"),
+    Field::new("evMultiplier"),
+    Field::new("modelCode").since(MIN_SERVER_VER_MODELS_SUPPORT),
+    Field::new("lastLiquidity").since(MIN_SERVER_VER_LAST_LIQUIDITY),
+];
+
+const MARKET_DEPTH: &[Field] = &[
+    Field::constant("version", "1"),
+    Field::new("id"),
+    Field::new("position"),
+    Field::new("operation"),
+    Field::new("side"),
+    Field::new("price"),
+    Field::new("size"),
+];
+
+const MARKET_DEPTH_L2: &[Field] = &[
+    Field::constant("version", "1"),
+    Field::new("id"),
+    Field::new("position"),
+    Field::new("marketMaker"),
+    Field::new("operation"),
+    Field::new("side"),
+    Field::new("price"),
+    Field::new("size"),
+    Field::new("isSmartDepth").since(MIN_SERVER_VER_SMART_DEPTH),
+];
+
+const MANAGED_ACCTS: &[Field] = &[Field::constant("version", "1"), Field::new("accountsList")];
+
+const CURRENT_TIME: &[Field] = &[Field::constant("version", "1"), Field::new("time")];
+
+const ACCT_DOWNLOAD_END: &[Field] = &[Field::constant("version", "1"), Field::new("account")];
+
+const REQ_ID_END: &[Field] = &[Field::constant("version", "1"), Field::new("reqId")];
+
+const MARKET_DATA_TYPE: &[Field] = &[
+    Field::constant("version", "1"),
+    Field::new("reqId"),
+    Field::new("marketDataType"),
+];
+
+const COMMISSION_REPORT: &[Field] = &[
+    Field::constant("version", "1"),
+    Field::new("execId"),
+    Field::new("commission"),
+    Field::new("currency"),
+    Field::new("realizedPNL"),
+    Field::new("yield"),
+    Field::new("yieldRedemptionDate"),
+];
+
+const POSITION_DATA: &[Field] = &[
+    Field::constant("version", "3"),
+    Field::new("account"),
+    Field::new("conId"),
+    Field::new("symbol"),
+    Field::new("secType"),
+    Field::new("lastTradeDateOrContractMonth"),
+    Field::new("strike"),
+    Field::new("right"),
+    Field::new("multiplier"),
+    Field::new("exchange"),
+    Field::new("currency"),
+    Field::new("localSymbol"),
+    Field::new("tradingClass"),
+    Field::new("position"),
+    Field::new("avgCost"),
+];
+
+const ACCOUNT_SUMMARY: &[Field] = &[
+    Field::constant("version", "1"),
+    Field::new("reqId"),
+    Field::new("account"),
+    Field::new("tag"),
+    Field::new("value"),
+    Field::new("currency"),
+];
+
+/// Each description is exchange, secType, listingExch, serviceDataType and
+/// aggGroup, or exchange, secType and isL2 before
+/// `MIN_SERVER_VER_SERVICE_DATA_TYPE`.
+const MKT_DEPTH_EXCHANGES: &[Field] = &[
+    Field::new("nDepthMktDataDescriptions"),
+    Field::group("depthMktDataDescriptions"),
+];
+
+const PNL: &[Field] = &[
+    Field::new("reqId"),
+    Field::new("dailyPnL"),
+    Field::new("unrealizedPnL").since(MIN_SERVER_VER_UNREALIZED_PNL),
+    Field::new("realizedPnL").since(MIN_SERVER_VER_REALIZED_PNL),
+];
+
+const PNL_SINGLE: &[Field] = &[
+    Field::new("reqId"),
+    Field::new("pos"),
+    Field::new("dailyPnL"),
+    Field::new("unrealizedPnL").since(MIN_SERVER_VER_UNREALIZED_PNL),
+    Field::new("realizedPnL").since(MIN_SERVER_VER_REALIZED_PNL),
+    Field::new("value"),
+];
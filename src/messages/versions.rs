@@ -0,0 +1,31 @@
+//! Server versions at which TWS changed the layout of a message.
+//!
+//! The names and values follow the `MIN_SERVER_VER_*` constants of the
+//! official TWS API client, so a field gated on one of them here can be
+//! cross-checked against the client's decoder.
+
+pub(crate) const MIN_SERVER_VER_TRADING_CLASS: u16 = 68;
+pub(crate) const MIN_SERVER_VER_LINKING: u16 = 70;
+pub(crate) const MIN_SERVER_VER_OPTIONAL_CAPABILITIES: u16 = 72;
+pub(crate) const MIN_SERVER_VER_PEGGED_TO_BENCHMARK: u16 = 102;
+pub(crate) const MIN_SERVER_VER_MODELS_SUPPORT: u16 = 103;
+pub(crate) const MIN_SERVER_VER_EXT_OPERATOR: u16 = 105;
+pub(crate) const MIN_SERVER_VER_SOFT_DOLLAR_TIER: u16 = 106;
+pub(crate) const MIN_SERVER_VER_CASH_QTY: u16 = 111;
+pub(crate) const MIN_SERVER_VER_REQ_SMART_COMPONENTS: u16 = 114;
+pub(crate) const MIN_SERVER_VER_UNREALIZED_PNL: u16 = 129;
+pub(crate) const MIN_SERVER_VER_MARKET_CAP_PRICE: u16 = 131;
+pub(crate) const MIN_SERVER_VER_REALIZED_PNL: u16 = 135;
+pub(crate) const MIN_SERVER_VER_LAST_LIQUIDITY: u16 = 136;
+pub(crate) const MIN_SERVER_VER_DECISION_MAKER: u16 = 138;
+pub(crate) const MIN_SERVER_VER_MIFID_EXECUTION: u16 = 139;
+pub(crate) const MIN_SERVER_VER_AUTO_PRICE_FOR_HEDGE: u16 = 141;
+pub(crate) const MIN_SERVER_VER_WHAT_IF_EXT_FIELDS: u16 = 142;
+pub(crate) const MIN_SERVER_VER_SERVICE_DATA_TYPE: u16 = 143;
+pub(crate) const MIN_SERVER_VER_ORDER_CONTAINER: u16 = 145;
+pub(crate) const MIN_SERVER_VER_SMART_DEPTH: u16 = 146;
+pub(crate) const MIN_SERVER_VER_D_PEG_ORDERS: u16 = 148;
+pub(crate) const MIN_SERVER_VER_MKT_DEPTH_PRIM_EXCHANGE: u16 = 149;
+pub(crate) const MIN_SERVER_VER_PRICE_MGMT_ALGO: u16 = 151;
+pub(crate) const MIN_SERVER_VER_ADVANCED_ORDER_REJECT: u16 = 166;
+pub(crate) const MIN_SERVER_VER_MANUAL_ORDER_TIME: u16 = 169;
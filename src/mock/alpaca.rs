@@ -0,0 +1,860 @@
+//! Stand-in for the alpaca.markets trading REST API v2 and `trade_updates`
+//! stream.
+//!
+//! Unlike the market data stand-in it keeps state: a single account starting
+//! with `STARTING_CASH`, its positions and its orders. Orders fill against a
+//! price per symbol, `DEFAULT_PRICE` unless set with
+//! `PUT /mock/prices/{symbol}` and a body of `{"price": 123.45}`:
+//!
+//! * market orders fill right away,
+//! * limit orders fill once the price reaches the limit,
+//! * stop and stop limit orders trigger once the price reaches the stop,
+//! * trailing stop orders never trigger,
+//! * orders above `PARTIAL_FILL_QUANTITY` fill in two executions.
+//!
+//! Any non-empty key id and secret key are accepted. The open streams are
+//! cut with `POST /mock/disconnect`.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, watch};
+use tracing::debug;
+
+/// Cash balance of the account when the stand-in starts.
+const STARTING_CASH: f64 = 100_000.0;
+
+/// Price of a symbol until one is set.
+const DEFAULT_PRICE: f64 = 100.0;
+
+/// Orders for more shares than this fill in two executions.
+const PARTIAL_FILL_QUANTITY: f64 = 100.0;
+
+/// Buying power as a multiple of the equity left after initial margin.
+const LEVERAGE: f64 = 2.0;
+
+/// Number of trade updates a stream may fall behind before it misses some.
+const UPDATE_CAPACITY: usize = 1024;
+
+type Shared = Arc<Mutex<Exchange>>;
+
+/// Serve the stand-in on `listener` until the process exits.
+///
+/// The REST API is served at the root and the stream at `/stream`.
+pub async fn serve(listener: TcpListener) -> crate::Result<()> {
+    axum::serve(listener, router()).await?;
+    Ok(())
+}
+
+/// Routes of the stand-in, with a fresh account.
+pub fn router() -> Router {
+    let exchange = Arc::new(Mutex::new(Exchange {
+        cash: STARTING_CASH,
+        positions: HashMap::new(),
+        orders: Vec::new(),
+        prices: HashMap::new(),
+        next_id: 1,
+        updates: broadcast::channel(UPDATE_CAPACITY).0,
+        disconnect: watch::channel(()).0,
+    }));
+
+    Router::new()
+        .route("/v2/account", get(account))
+        .route("/v2/positions", get(positions))
+        .route("/v2/assets/{symbol}", get(asset))
+        .route("/v2/orders", get(list_orders).post(submit_order))
+        .route(
+            "/v2/orders/{id}",
+            get(get_order).patch(replace_order).delete(cancel_order),
+        )
+        .route("/mock/prices/{symbol}", put(set_price))
+        .route("/mock/disconnect", post(disconnect))
+        .route("/stream", get(stream))
+        .with_state(exchange)
+}
+
+#[derive(Debug)]
+struct Exchange {
+    cash: f64,
+
+    /// Open positions by symbol.
+    positions: HashMap<String, MockPosition>,
+
+    /// Every order, oldest first.
+    orders: Vec<MockOrder>,
+
+    /// Current price by symbol.
+    prices: HashMap<String, f64>,
+
+    /// Used to assign order and execution ids.
+    next_id: u64,
+
+    /// `trade_updates` messages, ready to send.
+    updates: broadcast::Sender<Value>,
+
+    /// Changed to close every open stream.
+    disconnect: watch::Sender<()>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MockPosition {
+    qty: f64,
+    avg_entry_price: f64,
+}
+
+#[derive(Debug, Clone)]
+struct MockOrder {
+    id: String,
+    client_order_id: String,
+    symbol: String,
+    side: String,
+    order_type: String,
+    time_in_force: String,
+    qty: f64,
+    filled_qty: f64,
+    filled_avg_price: Option<f64>,
+    limit_price: Option<f64>,
+    stop_price: Option<f64>,
+    trail_price: Option<f64>,
+    trail_percent: Option<f64>,
+    extended_hours: bool,
+    status: String,
+    replaces: Option<String>,
+    replaced_by: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl MockOrder {
+    fn is_open(&self) -> bool {
+        !matches!(
+            self.status.as_str(),
+            "filled" | "canceled" | "replaced" | "expired" | "rejected"
+        )
+    }
+
+    fn to_json(&self) -> Value {
+        let amount = |value: Option<f64>| value.map(|value| value.to_string());
+
+        json!({
+            "id": self.id,
+            "client_order_id": self.client_order_id,
+            "created_at": self.created_at.to_rfc3339(),
+            "updated_at": self.updated_at.to_rfc3339(),
+            "submitted_at": self.created_at.to_rfc3339(),
+            "symbol": self.symbol,
+            "asset_class": "us_equity",
+            "qty": self.qty.to_string(),
+            "filled_qty": self.filled_qty.to_string(),
+            "filled_avg_price": amount(self.filled_avg_price),
+            "order_class": "",
+            "type": self.order_type,
+            "order_type": self.order_type,
+            "side": self.side,
+            "time_in_force": self.time_in_force,
+            "limit_price": amount(self.limit_price),
+            "stop_price": amount(self.stop_price),
+            "trail_price": amount(self.trail_price),
+            "trail_percent": amount(self.trail_percent),
+            "status": self.status,
+            "extended_hours": self.extended_hours,
+            "replaces": self.replaces,
+            "replaced_by": self.replaced_by,
+        })
+    }
+}
+
+impl Exchange {
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn price(&self, symbol: &str) -> f64 {
+        self.prices.get(symbol).copied().unwrap_or(DEFAULT_PRICE)
+    }
+
+    fn order(&mut self, id: &str) -> Option<&mut MockOrder> {
+        self.orders.iter_mut().find(|order| order.id == id)
+    }
+
+    /// Send a `trade_updates` message about `order`.
+    fn publish(&self, event: &str, order: &MockOrder, execution: Option<Value>) {
+        let mut data = json!({
+            "event": event,
+            "timestamp": order.updated_at.to_rfc3339(),
+            "order": order.to_json(),
+        });
+
+        if let Some(Value::Object(execution)) = execution {
+            data.as_object_mut().unwrap().extend(execution);
+        }
+
+        // Fails only when no stream is connected.
+        let _ = self
+            .updates
+            .send(json!({ "stream": "trade_updates", "data": data }));
+    }
+
+    /// Fill the open orders of `symbol` that are marketable at its current
+    /// price.
+    fn match_orders(&mut self, symbol: &str) {
+        let price = self.price(symbol);
+
+        for index in 0..self.orders.len() {
+            let order = &self.orders[index];
+
+            if order.symbol != symbol || !order.is_open() || !marketable(order, price) {
+                continue;
+            }
+
+            let remaining = order.qty - order.filled_qty;
+
+            if order.filled_qty == 0.0 && order.qty > PARTIAL_FILL_QUANTITY {
+                self.execute(index, (remaining / 2.0).floor(), price);
+            }
+
+            let remaining = self.orders[index].qty - self.orders[index].filled_qty;
+            self.execute(index, remaining, price);
+        }
+    }
+
+    /// Execute `qty` shares of order `index` at `price`.
+    fn execute(&mut self, index: usize, qty: f64, price: f64) {
+        let execution_id = format!("mock-execution-{}", self.next_id());
+        let now = Utc::now();
+
+        let order = &mut self.orders[index];
+        let signed = if order.side == "buy" { qty } else { -qty };
+
+        let filled_value = order.filled_avg_price.unwrap_or_default() * order.filled_qty + price * qty;
+        order.filled_qty += qty;
+        order.filled_avg_price = Some(filled_value / order.filled_qty);
+        order.updated_at = now;
+        order.status = if order.filled_qty < order.qty {
+            "partially_filled".to_string()
+        } else {
+            "filled".to_string()
+        };
+
+        let order = order.clone();
+
+        self.cash -= signed * price;
+
+        let position = self.positions.entry(order.symbol.clone()).or_default();
+        let qty_after = position.qty + signed;
+
+        if qty_after == 0.0 {
+            self.positions.remove(&order.symbol);
+        } else if position.qty * qty_after < 0.0 || position.qty == 0.0 {
+            // Opened or flipped
+            position.avg_entry_price = price;
+        } else if qty_after.abs() > position.qty.abs() {
+            position.avg_entry_price = (position.avg_entry_price * position.qty + price * signed) / qty_after;
+        }
+
+        if let Some(position) = self.positions.get_mut(&order.symbol) {
+            position.qty = qty_after;
+        }
+
+        let event = if order.status == "filled" {
+            "fill"
+        } else {
+            "partial_fill"
+        };
+
+        self.publish(
+            event,
+            &order,
+            Some(json!({
+                "execution_id": execution_id,
+                "price": price.to_string(),
+                "qty": qty.to_string(),
+                "position_qty": qty_after.to_string(),
+            })),
+        );
+    }
+
+    /// Cancel `order` if it is an immediate or cancel order that could not
+    /// fill completely.
+    fn expire_unfilled(&mut self, id: &str) {
+        let Some(order) = self.order(id) else { return };
+
+        if !order.is_open() || !matches!(order.time_in_force.as_str(), "ioc" | "fok") {
+            return;
+        }
+
+        order.status = "canceled".to_string();
+        order.updated_at = Utc::now();
+
+        let order = order.clone();
+        self.publish("canceled", &order, None);
+    }
+
+    fn account_json(&self) -> Value {
+        let (mut long_market_value, mut short_market_value) = (0.0, 0.0);
+
+        for (symbol, position) in &self.positions {
+            let value = position.qty * self.price(symbol);
+
+            if value >= 0.0 {
+                long_market_value += value;
+            } else {
+                short_market_value += value;
+            }
+        }
+
+        let equity = self.cash + long_market_value + short_market_value;
+        let initial_margin = (long_market_value - short_market_value) * 0.5;
+        let maintenance_margin = (long_market_value - short_market_value) * 0.25;
+
+        json!({
+            "id": "mock-account",
+            "account_number": "PA0000000001",
+            "status": "ACTIVE",
+            "currency": "USD",
+            "cash": self.cash.to_string(),
+            "equity": equity.to_string(),
+            "last_equity": STARTING_CASH.to_string(),
+            "portfolio_value": equity.to_string(),
+            "long_market_value": long_market_value.to_string(),
+            "short_market_value": short_market_value.to_string(),
+            "initial_margin": initial_margin.to_string(),
+            "maintenance_margin": maintenance_margin.to_string(),
+            "buying_power": ((equity - initial_margin) * LEVERAGE).max(0.0).to_string(),
+            "sma": (equity - initial_margin).max(0.0).to_string(),
+            "multiplier": LEVERAGE.to_string(),
+            "daytrade_count": 0,
+            "pattern_day_trader": false,
+        })
+    }
+}
+
+/// Returns `true` if `order` would execute at `price`.
+fn marketable(order: &MockOrder, price: f64) -> bool {
+    let buy = order.side == "buy";
+    let reached = |level: Option<f64>, upwards: bool| match level {
+        Some(level) if upwards => price >= level,
+        Some(level) => price <= level,
+        None => false,
+    };
+
+    match order.order_type.as_str() {
+        "market" => true,
+        "limit" => reached(order.limit_price, !buy),
+        "stop" => reached(order.stop_price, buy),
+        "stop_limit" => reached(order.stop_price, buy) && reached(order.limit_price, !buy),
+        _ => false,
+    }
+}
+
+/// An error answer, `{"code": ..., "message": ...}` as Alpaca sends them.
+#[derive(Debug)]
+struct Rejection {
+    status: StatusCode,
+    code: u32,
+    message: String,
+}
+
+impl Rejection {
+    fn unprocessable(message: impl ToString) -> Rejection {
+        Rejection {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            code: 42210000,
+            message: message.to_string(),
+        }
+    }
+
+    fn not_found() -> Rejection {
+        Rejection {
+            status: StatusCode::NOT_FOUND,
+            code: 40410000,
+            message: "order not found".to_string(),
+        }
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let body = json!({ "code": self.code, "message": self.message });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+type Answer = Result<Response, Rejection>;
+
+/// Reject requests without credentials, as Alpaca does.
+fn authorize(headers: &HeaderMap) -> Result<(), Rejection> {
+    let present = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| !value.is_empty())
+    };
+
+    if present("APCA-API-KEY-ID") && present("APCA-API-SECRET-KEY") {
+        Ok(())
+    } else {
+        Err(Rejection {
+            status: StatusCode::UNAUTHORIZED,
+            code: 40110000,
+            message: "request is not authorized".to_string(),
+        })
+    }
+}
+
+/// Returns the amount `key` of `body`, sent as a string or a number.
+fn amount(body: &Value, key: &str) -> Result<Option<f64>, Rejection> {
+    match &body[key] {
+        Value::Null => Ok(None),
+        Value::Number(number) => Ok(number.as_f64()),
+        Value::String(text) => text
+            .parse()
+            .map(Some)
+            .map_err(|_| Rejection::unprocessable(format!("invalid {}", key))),
+        _ => Err(Rejection::unprocessable(format!("invalid {}", key))),
+    }
+}
+
+async fn account(State(exchange): State<Shared>, headers: HeaderMap) -> Answer {
+    authorize(&headers)?;
+
+    Ok(Json(exchange.lock().unwrap().account_json()).into_response())
+}
+
+async fn positions(State(exchange): State<Shared>, headers: HeaderMap) -> Answer {
+    authorize(&headers)?;
+
+    let exchange = exchange.lock().unwrap();
+
+    let mut positions: Vec<_> = exchange
+        .positions
+        .iter()
+        .map(|(symbol, position)| {
+            let price = exchange.price(symbol);
+            let market_value = position.qty * price;
+            let cost_basis = position.qty * position.avg_entry_price;
+
+            json!({
+                "symbol": symbol,
+                "exchange": "NASDAQ",
+                "asset_class": "us_equity",
+                "side": if position.qty > 0.0 { "long" } else { "short" },
+                "qty": position.qty.to_string(),
+                "avg_entry_price": position.avg_entry_price.to_string(),
+                "current_price": price.to_string(),
+                "market_value": market_value.to_string(),
+                "cost_basis": cost_basis.to_string(),
+                "unrealized_pl": (market_value - cost_basis).to_string(),
+            })
+        })
+        .collect();
+
+    positions.sort_by(|a, b| a["symbol"].as_str().cmp(&b["symbol"].as_str()));
+    Ok(Json(positions).into_response())
+}
+
+/// Every symbol is an active, shortable and easy to borrow stock.
+async fn asset(headers: HeaderMap, Path(symbol): Path<String>) -> Answer {
+    authorize(&headers)?;
+
+    let symbol = symbol.to_uppercase();
+
+    Ok(Json(json!({
+        "id": format!("asset-{}", symbol.to_lowercase()),
+        "class": "us_equity",
+        "exchange": "NASDAQ",
+        "symbol": symbol,
+        "status": "active",
+        "tradable": true,
+        "marginable": true,
+        "shortable": true,
+        "easy_to_borrow": true,
+        "fractionable": true,
+    }))
+    .into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    after: Option<DateTime<Utc>>,
+    #[serde(default)]
+    limit: Option<usize>,
+    #[serde(default)]
+    direction: Option<String>,
+}
+
+async fn list_orders(
+    State(exchange): State<Shared>,
+    headers: HeaderMap,
+    Query(query): Query<ListQuery>,
+) -> Answer {
+    authorize(&headers)?;
+
+    let exchange = exchange.lock().unwrap();
+    let status = query.status.as_deref().unwrap_or("open");
+
+    let mut orders: Vec<_> = exchange
+        .orders
+        .iter()
+        .filter(|order| match status {
+            "open" => order.is_open(),
+            "closed" => !order.is_open(),
+            _ => true,
+        })
+        .filter(|order| query.after.is_none_or(|after| order.created_at > after))
+        .collect();
+
+    if query.direction.as_deref() != Some("asc") {
+        orders.reverse();
+    }
+
+    let orders: Vec<_> = orders
+        .into_iter()
+        .take(query.limit.unwrap_or(50))
+        .map(MockOrder::to_json)
+        .collect();
+
+    Ok(Json(orders).into_response())
+}
+
+async fn get_order(State(exchange): State<Shared>, headers: HeaderMap, Path(id): Path<String>) -> Answer {
+    authorize(&headers)?;
+
+    match exchange.lock().unwrap().order(&id) {
+        Some(order) => Ok(Json(order.to_json()).into_response()),
+        None => Err(Rejection::not_found()),
+    }
+}
+
+async fn submit_order(State(exchange): State<Shared>, headers: HeaderMap, Json(body): Json<Value>) -> Answer {
+    authorize(&headers)?;
+
+    let mut exchange = exchange.lock().unwrap();
+
+    let text = |key: &str| body[key].as_str().unwrap_or_default().to_string();
+    let (symbol, side, order_type) = (text("symbol"), text("side"), text("type"));
+    let time_in_force = text("time_in_force");
+
+    let qty = amount(&body, "qty")?;
+    let limit_price = amount(&body, "limit_price")?;
+    let stop_price = amount(&body, "stop_price")?;
+    let trail_price = amount(&body, "trail_price")?;
+    let trail_percent = amount(&body, "trail_percent")?;
+
+    let qty = match qty {
+        Some(qty) if qty > 0.0 => qty,
+        _ => return Err(Rejection::unprocessable("qty must be > 0")),
+    };
+
+    if symbol.is_empty() {
+        return Err(Rejection::unprocessable("symbol is required"));
+    }
+    if !matches!(side.as_str(), "buy" | "sell") {
+        return Err(Rejection::unprocessable("invalid side"));
+    }
+    if !matches!(
+        time_in_force.as_str(),
+        "day" | "gtc" | "opg" | "cls" | "ioc" | "fok"
+    ) {
+        return Err(Rejection::unprocessable("invalid time_in_force"));
+    }
+
+    let missing = match order_type.as_str() {
+        "market" => None,
+        "limit" => limit_price.is_none().then_some("limit_price"),
+        "stop" => stop_price.is_none().then_some("stop_price"),
+        "stop_limit" if limit_price.is_none() => Some("limit_price"),
+        "stop_limit" => stop_price.is_none().then_some("stop_price"),
+        "trailing_stop" => (trail_price.is_none() && trail_percent.is_none()).then_some("trail_price"),
+        _ => return Err(Rejection::unprocessable("invalid order type")),
+    };
+
+    if let Some(field) = missing {
+        return Err(Rejection::unprocessable(format!("{} is required", field)));
+    }
+
+    let id = exchange.next_id();
+    let client_order_id = match text("client_order_id") {
+        client_order_id if client_order_id.is_empty() => format!("mock-client-{}", id),
+        client_order_id => client_order_id,
+    };
+
+    if exchange
+        .orders
+        .iter()
+        .any(|order| order.client_order_id == client_order_id)
+    {
+        return Err(Rejection::unprocessable("client_order_id must be unique"));
+    }
+
+    if side == "buy" {
+        let price = limit_price.unwrap_or_else(|| exchange.price(&symbol));
+        let buying_power = exchange.account_json()["buying_power"]
+            .as_str()
+            .and_then(|value| value.parse::<f64>().ok())
+            .unwrap_or_default();
+
+        if qty * price > buying_power {
+            return Err(Rejection {
+                status: StatusCode::FORBIDDEN,
+                code: 40310000,
+                message: "insufficient buying power".to_string(),
+            });
+        }
+    }
+
+    let now = Utc::now();
+
+    let order = MockOrder {
+        id: format!("mock-order-{}", id),
+        client_order_id,
+        symbol: symbol.clone(),
+        side,
+        order_type,
+        time_in_force,
+        qty,
+        filled_qty: 0.0,
+        filled_avg_price: None,
+        limit_price,
+        stop_price,
+        trail_price,
+        trail_percent,
+        extended_hours: body["extended_hours"].as_bool().unwrap_or(false),
+        status: "new".to_string(),
+        replaces: None,
+        replaced_by: None,
+        created_at: now,
+        updated_at: now,
+    };
+
+    debug!(id = %order.id, %symbol, "alpaca stand-in order");
+
+    exchange.publish("new", &order, None);
+    exchange.orders.push(order.clone());
+    exchange.match_orders(&symbol);
+    exchange.expire_unfilled(&order.id);
+
+    Ok(Json(order.to_json()).into_response())
+}
+
+async fn replace_order(
+    State(exchange): State<Shared>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(body): Json<Value>,
+) -> Answer {
+    authorize(&headers)?;
+
+    let mut exchange = exchange.lock().unwrap();
+
+    let qty = amount(&body, "qty")?;
+    let limit_price = amount(&body, "limit_price")?;
+    let stop_price = amount(&body, "stop_price")?;
+    let trail = amount(&body, "trail")?;
+
+    let new_id = format!("mock-order-{}", exchange.next_id());
+    let now = Utc::now();
+
+    let Some(replaced) = exchange.order(&id) else {
+        return Err(Rejection::not_found());
+    };
+
+    if !replaced.is_open() {
+        return Err(Rejection::unprocessable(format!(
+            "order is already in \"{}\" state",
+            replaced.status
+        )));
+    }
+
+    let mut order = replaced.clone();
+
+    replaced.status = "replaced".to_string();
+    replaced.replaced_by = Some(new_id.clone());
+    replaced.updated_at = now;
+    let replaced = replaced.clone();
+
+    // The replacement is a new order for what is left, unless told
+    // otherwise.
+    order.id = new_id;
+    order.status = "new".to_string();
+    order.replaces = Some(id);
+    order.created_at = now;
+    order.updated_at = now;
+    order.qty = qty.unwrap_or(order.qty - order.filled_qty);
+    order.filled_qty = 0.0;
+    order.filled_avg_price = None;
+    order.limit_price = limit_price.or(order.limit_price);
+    order.stop_price = stop_price.or(order.stop_price);
+
+    if let Some(time_in_force) = body["time_in_force"].as_str() {
+        order.time_in_force = time_in_force.to_string();
+    }
+
+    if let Some(client_order_id) = body["client_order_id"].as_str() {
+        order.client_order_id = client_order_id.to_string();
+    }
+
+    if let Some(trail) = trail {
+        if order.trail_percent.is_some() {
+            order.trail_percent = Some(trail);
+        } else {
+            order.trail_price = Some(trail);
+        }
+    }
+
+    if order.qty <= 0.0 {
+        return Err(Rejection::unprocessable("qty must be > 0"));
+    }
+
+    exchange.publish("replaced", &replaced, None);
+    exchange.publish("new", &order, None);
+
+    let symbol = order.symbol.clone();
+    exchange.orders.push(order.clone());
+    exchange.match_orders(&symbol);
+
+    Ok(Json(order.to_json()).into_response())
+}
+
+async fn cancel_order(State(exchange): State<Shared>, headers: HeaderMap, Path(id): Path<String>) -> Answer {
+    authorize(&headers)?;
+
+    let mut exchange = exchange.lock().unwrap();
+
+    let Some(order) = exchange.order(&id) else {
+        return Err(Rejection::not_found());
+    };
+
+    if !order.is_open() {
+        return Err(Rejection::unprocessable(format!(
+            "order is already in \"{}\" state",
+            order.status
+        )));
+    }
+
+    order.status = "canceled".to_string();
+    order.updated_at = Utc::now();
+
+    let order = order.clone();
+    exchange.publish("canceled", &order, None);
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct PriceBody {
+    price: f64,
+}
+
+/// Set the price of `symbol` and fill the orders it makes marketable.
+async fn set_price(
+    State(exchange): State<Shared>,
+    Path(symbol): Path<String>,
+    Json(body): Json<PriceBody>,
+) -> Response {
+    let mut exchange = exchange.lock().unwrap();
+
+    exchange.prices.insert(symbol.clone(), body.price);
+    exchange.match_orders(&symbol);
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Close every open stream.
+async fn disconnect(State(exchange): State<Shared>) -> Response {
+    exchange.lock().unwrap().disconnect.send_replace(());
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn stream(State(exchange): State<Shared>, upgrade: WebSocketUpgrade) -> Response {
+    let (updates, disconnect) = {
+        let exchange = exchange.lock().unwrap();
+        (exchange.updates.subscribe(), exchange.disconnect.subscribe())
+    };
+    upgrade.on_upgrade(move |socket| forward(socket, updates, disconnect))
+}
+
+/// A request sent on the stream.
+#[derive(Debug, Deserialize)]
+struct Action {
+    action: String,
+    #[serde(default)]
+    key: String,
+    #[serde(default)]
+    secret: String,
+}
+
+/// Speak the Alpaca stream protocol on `socket`, forwarding `trade_updates`
+/// once the client listens to them, until `disconnect` changes.
+///
+/// Messages are sent as binary frames, as on the paper trading endpoint.
+async fn forward(
+    mut socket: WebSocket,
+    mut updates: broadcast::Receiver<Value>,
+    mut disconnect: watch::Receiver<()>,
+) {
+    let mut authenticated = false;
+    let mut listening = false;
+
+    loop {
+        let response = tokio::select! {
+            _ = disconnect.changed() => return,
+            message = socket.recv() => {
+                let data = match message {
+                    Some(Ok(Message::Text(text))) => text.as_bytes().to_vec(),
+                    Some(Ok(Message::Binary(data))) => data.to_vec(),
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => continue,
+                };
+
+                match serde_json::from_slice::<Action>(&data) {
+                    Ok(action) if action.action == "auth" => {
+                        authenticated = !action.key.is_empty() && !action.secret.is_empty();
+                        let status = if authenticated { "authorized" } else { "unauthorized" };
+
+                        json!({
+                            "stream": "authorization",
+                            "data": { "action": "authenticate", "status": status },
+                        })
+                    }
+                    Ok(action) if action.action == "listen" && authenticated => {
+                        listening = true;
+                        json!({ "stream": "listening", "data": { "streams": ["trade_updates"] } })
+                    }
+                    _ => json!({
+                        "stream": "authorization",
+                        "data": { "action": "listen", "status": "unauthorized" },
+                    }),
+                }
+            }
+            update = updates.recv() => match update {
+                Ok(update) if listening => update,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        };
+
+        if socket
+            .send(Message::Binary(response.to_string().into()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! Local stand-ins for the external services the connector talks to.
+//!
+//! They answer like the real APIs, from canned responses or simulated state,
+//! so the adapters can be run end to end without network access or
+//! credentials. Each one is also available as a binary under `src/bin`.
+
+pub mod alpaca;
+pub mod polygon;
@@ -0,0 +1,354 @@
+//! Stand-in for the polygon.io REST and WebSocket APIs.
+//!
+//! Every symbol gets the same canned quotes, trades and bars, recorded for
+//! `AAPL`, with the symbol swapped in. Reference data is recorded per symbol,
+//! other symbols are not found. Bars come `BARS_PER_PAGE` at a time, linked
+//! by `next_url` like long ranges upstream. Any non-empty API key is
+//! accepted.
+//!
+//! The WebSocket sessions can be looked into with `GET /mock/streams`, which
+//! answers the number of connections made so far and the symbols the open
+//! ones are subscribed to, and cut with `POST /mock/disconnect`.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::time::{self, Duration};
+use tracing::debug;
+
+const LAST_QUOTE: &str = include_str!("../../fixtures/polygon/last_quote.json");
+const LAST_TRADE: &str = include_str!("../../fixtures/polygon/last_trade.json");
+const PREVIOUS_CLOSE: &str = include_str!("../../fixtures/polygon/prev.json");
+const BARS: &str = include_str!("../../fixtures/polygon/range.json");
+const TICKER_DETAILS: &str = include_str!("../../fixtures/polygon/ticker_details.json");
+const STREAM: &str = include_str!("../../fixtures/polygon/stream.json");
+
+/// Symbol the fixtures were recorded for.
+const FIXTURE_SYMBOL: &str = "AAPL";
+
+/// Time between two replayed stream events.
+const REPLAY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Most bars answered at once, the rest of a range is linked by `next_url`.
+const BARS_PER_PAGE: usize = 2;
+
+type Shared = Arc<Mutex<Sessions>>;
+
+/// The WebSocket sessions of the stand-in.
+#[derive(Debug)]
+struct Sessions {
+    /// Symbols each open session is subscribed to, by session id.
+    subscribed: HashMap<u64, BTreeSet<String>>,
+
+    /// Number of sessions opened so far, also used to assign their ids.
+    opened: u64,
+
+    /// Changed to close every open session.
+    disconnect: watch::Sender<()>,
+}
+
+/// Serve the stand-in on `listener` until the process exits.
+///
+/// The REST API is served at the root and the stocks WebSocket cluster at
+/// `/stocks`.
+pub async fn serve(listener: TcpListener) -> crate::Result<()> {
+    axum::serve(listener, router()).await?;
+    Ok(())
+}
+
+/// Routes of the stand-in, with no session open.
+pub fn router() -> Router {
+    let sessions = Arc::new(Mutex::new(Sessions {
+        subscribed: HashMap::new(),
+        opened: 0,
+        disconnect: watch::channel(()).0,
+    }));
+
+    Router::new()
+        .route("/v2/last/nbbo/{ticker}", get(|h, p, q| rest(h, p, q, LAST_QUOTE)))
+        .route("/v2/last/trade/{ticker}", get(|h, p, q| rest(h, p, q, LAST_TRADE)))
+        .route("/v2/aggs/ticker/{ticker}/prev", get(|h, p, q| rest(h, p, q, PREVIOUS_CLOSE)))
+        .route(
+            "/v2/aggs/ticker/{ticker}/range/{multiplier}/{timespan}/{from}/{to}",
+            get(bars),
+        )
+        .route("/v3/reference/tickers/{ticker}", get(ticker_details))
+        .route("/mock/streams", get(streams))
+        .route("/mock/disconnect", post(disconnect))
+        .route("/stocks", get(stream))
+        .with_state(sessions)
+}
+
+/// Returns `true` if an API key is sent, as a bearer token or in the
+/// `apiKey` parameter.
+fn authorized(headers: &HeaderMap, query: &HashMap<String, String>) -> bool {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let api_key = bearer.or(query.get("apiKey").map(String::as_str)).unwrap_or_default();
+
+    !api_key.is_empty()
+}
+
+fn unauthorized() -> Response {
+    let body = json!({
+        "status": "ERROR",
+        "request_id": "",
+        "error": "API Key was not provided",
+    });
+
+    (StatusCode::UNAUTHORIZED, Json(body)).into_response()
+}
+
+/// Returns `fixture` with `ticker` in place of `FIXTURE_SYMBOL`.
+fn swap_symbol(fixture: &str, ticker: &str) -> String {
+    fixture.replace(&format!("\"{}\"", FIXTURE_SYMBOL), &format!("\"{}\"", ticker))
+}
+
+/// Answer a REST request with `fixture`, recorded for `FIXTURE_SYMBOL`.
+async fn rest(
+    headers: HeaderMap,
+    Path(params): Path<HashMap<String, String>>,
+    Query(query): Query<HashMap<String, String>>,
+    fixture: &'static str,
+) -> Response {
+    if !authorized(&headers, &query) {
+        return unauthorized();
+    }
+
+    let ticker = params.get("ticker").map(String::as_str).unwrap_or(FIXTURE_SYMBOL);
+    debug!(%ticker, "polygon stand-in request");
+
+    ([(header::CONTENT_TYPE, "application/json")], swap_symbol(fixture, ticker)).into_response()
+}
+
+/// Answer the page of bars starting at the `cursor` parameter, the first one
+/// without it.
+async fn bars(
+    headers: HeaderMap,
+    uri: Uri,
+    Path(params): Path<HashMap<String, String>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    if !authorized(&headers, &query) {
+        return unauthorized();
+    }
+
+    let ticker = &params["ticker"];
+    debug!(%ticker, query = ?uri.query(), "polygon stand-in bars");
+
+    let mut body: Value = serde_json::from_str(&swap_symbol(BARS, ticker)).expect("invalid bars fixture");
+    let Value::Array(results) = body["results"].take() else {
+        panic!("invalid bars fixture");
+    };
+
+    let limit = query.get("limit").and_then(|limit| limit.parse().ok()).unwrap_or(5000);
+    let start = query.get("cursor").and_then(|cursor| cursor.parse().ok()).unwrap_or(0);
+    let end = results.len().min(start + BARS_PER_PAGE.min(limit));
+    let page = results.get(start..end).unwrap_or_default().to_vec();
+
+    body["resultsCount"] = json!(page.len());
+    body["results"] = Value::Array(page);
+
+    if end < results.len() {
+        let host = headers
+            .get(header::HOST)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("127.0.0.1");
+        body["next_url"] = json!(format!("http://{}{}?cursor={}", host, uri.path(), end));
+    }
+
+    Json(body).into_response()
+}
+
+/// Answer the reference data recorded for the ticker, 404 for the others.
+async fn ticker_details(
+    headers: HeaderMap,
+    Path(ticker): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    if !authorized(&headers, &query) {
+        return unauthorized();
+    }
+
+    let mut recorded: HashMap<String, Value> =
+        serde_json::from_str(TICKER_DETAILS).expect("invalid ticker details fixture");
+
+    match recorded.remove(&ticker) {
+        Some(details) => Json(details).into_response(),
+        None => {
+            let body = json!({
+                "status": "NOT_FOUND",
+                "request_id": "",
+                "message": "Ticker not found.",
+            });
+
+            (StatusCode::NOT_FOUND, Json(body)).into_response()
+        }
+    }
+}
+
+/// Answer the number of sessions opened so far and the symbols the open
+/// ones are subscribed to.
+async fn streams(State(sessions): State<Shared>) -> Response {
+    let sessions = sessions.lock().unwrap();
+    let symbols: BTreeSet<&String> = sessions.subscribed.values().flatten().collect();
+
+    Json(json!({ "connections": sessions.opened, "symbols": symbols })).into_response()
+}
+
+/// Close every open session.
+async fn disconnect(State(sessions): State<Shared>) -> Response {
+    sessions.lock().unwrap().disconnect.send_replace(());
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn stream(State(sessions): State<Shared>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| async move {
+        let (id, disconnect) = {
+            let mut shared = sessions.lock().unwrap();
+            shared.opened += 1;
+
+            let id = shared.opened;
+            shared.subscribed.insert(id, BTreeSet::new());
+            (id, shared.disconnect.subscribe())
+        };
+
+        replay(socket, &sessions, id, disconnect).await;
+        sessions.lock().unwrap().subscribed.remove(&id);
+    })
+}
+
+/// A request sent on the WebSocket.
+#[derive(Debug, Deserialize)]
+struct Action {
+    action: String,
+    #[serde(default)]
+    params: String,
+}
+
+/// Speak the polygon stream protocol on `socket`, replaying the recorded
+/// events for every subscribed symbol in a loop, until `disconnect` changes.
+///
+/// The symbols subscribed to are kept in `sessions`, under `id`.
+async fn replay(mut socket: WebSocket, sessions: &Shared, id: u64, mut disconnect: watch::Receiver<()>) {
+    let events: Vec<Value> = serde_json::from_str(STREAM).expect("invalid stream fixture");
+
+    let mut authenticated = false;
+    let mut symbols = BTreeSet::new();
+    let mut next = 0;
+    let mut ticker = time::interval(REPLAY_INTERVAL);
+
+    if send(&mut socket, status("connected", "Connected Successfully")).await.is_err() {
+        return;
+    }
+
+    loop {
+        let response = tokio::select! {
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => continue,
+                };
+
+                let action: Action = match serde_json::from_str(&text) {
+                    Ok(action) => action,
+                    Err(_) => Action {
+                        action: String::new(),
+                        params: String::new(),
+                    },
+                };
+
+                match action.action.as_str() {
+                    "" => status("error", "invalid message"),
+                    "auth" if action.params.is_empty() => status("auth_failed", "authentication failed"),
+                    "auth" => {
+                        authenticated = true;
+                        status("auth_success", "authenticated")
+                    }
+                    _ if !authenticated => status("error", "not authorized"),
+                    "subscribe" | "unsubscribe" => {
+                        let mut acks = Vec::new();
+
+                        for param in action.params.split(',').filter(|param| !param.is_empty()) {
+                            let symbol = param.split_once('.').map_or(param, |(_, symbol)| symbol);
+
+                            if action.action == "subscribe" {
+                                symbols.insert(symbol.to_string());
+                            } else {
+                                symbols.remove(symbol);
+                            }
+
+                            acks.push(json!({
+                                "ev": "status",
+                                "status": "success",
+                                "message": format!("{}d to: {}", action.action, param),
+                            }));
+                        }
+
+                        sessions.lock().unwrap().subscribed.insert(id, symbols.clone());
+                        Value::Array(acks)
+                    }
+                    _ => status("error", "unknown action"),
+                }
+            }
+            _ = disconnect.changed() => {
+                let _ = socket.send(Message::Close(None)).await;
+                return;
+            }
+            _ = ticker.tick(), if authenticated && !symbols.is_empty() => {
+                let event = &events[next % events.len()];
+                next += 1;
+
+                let batch = symbols.iter().map(|symbol| restamp(event, symbol)).collect();
+                Value::Array(batch)
+            }
+        };
+
+        if send(&mut socket, response).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Returns `event` for `symbol`, timestamped now.
+fn restamp(event: &Value, symbol: &str) -> Value {
+    let mut event = event.clone();
+    let now = Utc::now().timestamp_millis();
+
+    event["sym"] = json!(symbol);
+
+    if event.get("t").is_some() {
+        event["t"] = json!(now);
+    }
+
+    // Minute bars cover the minute that just ended.
+    if event["ev"] == "AM" {
+        let end = now - now % 60_000;
+        event["s"] = json!(end - 60_000);
+        event["e"] = json!(end);
+    }
+
+    event
+}
+
+fn status(status: &str, message: &str) -> Value {
+    json!([{ "ev": "status", "status": status, "message": message }])
+}
+
+async fn send(socket: &mut WebSocket, value: Value) -> Result<(), axum::Error> {
+    socket.send(Message::Text(value.to_string().into())).await
+}
@@ -0,0 +1,397 @@
+use crate::market_data::{Depth, Side};
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// How long the quote of a venue is kept without news from it.
+///
+/// The feed only reports the venues that set the best bid or offer, a venue
+/// that falls behind is not heard of until it leads again.
+const STALE_AFTER: Duration = Duration::minutes(1);
+
+/// What a change does to the row at its position, as TWS numbers it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Operation {
+    /// A new row, the ones below move down.
+    Insert = 0,
+    Update = 1,
+
+    /// The row goes away, the ones below move up.
+    Delete = 2,
+}
+
+/// The rows an `OrderBook` shows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum View {
+    /// One row per venue, with the venue as market maker.
+    Venues,
+
+    /// One row per price, summing the size of the venues quoting it.
+    Prices,
+
+    /// The row of a single venue.
+    Venue(String),
+}
+
+/// One row of a side of the book.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Row {
+    pub(crate) price: f64,
+    pub(crate) size: f64,
+
+    /// The venue quoting the row, empty for rows of several venues.
+    pub(crate) market_maker: String,
+}
+
+/// A change to the rows of the book, as sent in `updateMktDepth`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Change {
+    pub(crate) position: usize,
+    pub(crate) operation: Operation,
+    pub(crate) side: Side,
+    pub(crate) row: Row,
+}
+
+/// A limit order book of one symbol, built from the quotes of the venues.
+///
+/// The book keeps the last price and size every venue quoted on each side.
+/// A quote that sets the best price pushes out the venues quoting better
+/// ones, which must have left, and quotes not renewed for `STALE_AFTER`
+/// are dropped. The top `rows` rows of each side are shown, every update
+/// comes out as the inserts, updates and deletes that turn the rows shown
+/// before into the new ones.
+#[derive(Clone, Debug)]
+pub(crate) struct OrderBook {
+    view: View,
+    rows: usize,
+
+    /// Price, size and time of the last quote of every venue, by side.
+    quotes: HashMap<(Side, String), (f64, f64, DateTime<Utc>)>,
+
+    /// The rows shown, by side.
+    shown: HashMap<Side, Vec<Row>>,
+}
+
+impl OrderBook {
+    pub(crate) fn new(view: View, rows: usize) -> OrderBook {
+        OrderBook {
+            view,
+            rows,
+            quotes: HashMap::new(),
+            shown: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn view(&self) -> &View {
+        &self.view
+    }
+
+    /// Take in `depth` and return the changes to the rows.
+    pub(crate) fn apply(&mut self, depth: &Depth) -> Vec<Change> {
+        let better = |price: f64| match depth.side {
+            Side::Bid => price > depth.price,
+            Side::Ask => price < depth.price,
+        };
+
+        self.quotes.retain(|(side, venue), (price, _, time)| {
+            let outbid = *side == depth.side && *venue != depth.venue && better(*price);
+            !outbid && *time > depth.timestamp - STALE_AFTER
+        });
+
+        let key = (depth.side, depth.venue.clone());
+        if depth.size > 0.0 {
+            self.quotes.insert(key, (depth.price, depth.size, depth.timestamp));
+        } else {
+            self.quotes.remove(&key);
+        }
+
+        [Side::Bid, Side::Ask]
+            .into_iter()
+            .flat_map(|side| self.refresh(side))
+            .collect()
+    }
+
+    /// Returns the rows of `side`, best first, at most `rows` of them.
+    fn rows(&self, side: Side) -> Vec<Row> {
+        let quotes = self
+            .quotes
+            .iter()
+            .filter(|((quoted, _), _)| *quoted == side)
+            .filter(|((_, venue), _)| match &self.view {
+                View::Venue(only) => venue == only,
+                View::Venues | View::Prices => true,
+            })
+            .map(|((_, venue), (price, size, _))| Row {
+                price: *price,
+                size: *size,
+                market_maker: venue.clone(),
+            });
+
+        let mut rows: Vec<Row> = match self.view {
+            View::Prices => {
+                let mut levels: Vec<Row> = Vec::new();
+
+                for quote in quotes {
+                    match levels.iter_mut().find(|level| level.price == quote.price) {
+                        Some(level) => level.size += quote.size,
+                        None => levels.push(Row {
+                            market_maker: String::new(),
+                            ..quote
+                        }),
+                    }
+                }
+
+                levels
+            }
+            View::Venues | View::Venue(_) => quotes.collect(),
+        };
+
+        // Best price first, then the largest size, then by venue so the order
+        // is stable.
+        rows.sort_by(|a, b| {
+            let price = match side {
+                Side::Bid => b.price.total_cmp(&a.price),
+                Side::Ask => a.price.total_cmp(&b.price),
+            };
+
+            price
+                .then(b.size.total_cmp(&a.size))
+                .then(a.market_maker.cmp(&b.market_maker))
+        });
+        rows.truncate(self.rows);
+        rows
+    }
+
+    /// Returns `true` if `a` and `b` are the same row, possibly changed.
+    fn same(&self, a: &Row, b: &Row) -> bool {
+        match self.view {
+            View::Prices => a.price == b.price,
+            View::Venues | View::Venue(_) => a.market_maker == b.market_maker,
+        }
+    }
+
+    /// Bring the rows shown of `side` up to date and return the changes.
+    ///
+    /// Rows that are gone are deleted first, bottom up, then the new rows are
+    /// walked top down: rows in place are updated, rows that moved are
+    /// deleted and inserted again, and new rows are inserted.
+    fn refresh(&mut self, side: Side) -> Vec<Change> {
+        let rows = self.rows(side);
+        let mut shown = self.shown.remove(&side).unwrap_or_default();
+        let mut changes = Vec::new();
+
+        let change = |position, operation, row: &Row| Change {
+            position,
+            operation,
+            side,
+            row: row.clone(),
+        };
+
+        for position in (0..shown.len()).rev() {
+            if !rows.iter().any(|row| self.same(row, &shown[position])) {
+                let row = shown.remove(position);
+                changes.push(change(position, Operation::Delete, &row));
+            }
+        }
+
+        for (position, row) in rows.iter().enumerate() {
+            match shown.iter().position(|old| self.same(old, row)) {
+                Some(old) if old == position => {
+                    if shown[old] != *row {
+                        shown[old] = row.clone();
+                        changes.push(change(position, Operation::Update, row));
+                    }
+                }
+                Some(old) => {
+                    let moved = shown.remove(old);
+                    changes.push(change(old, Operation::Delete, &moved));
+                    shown.insert(position, row.clone());
+                    changes.push(change(position, Operation::Insert, row));
+                }
+                None => {
+                    shown.insert(position, row.clone());
+                    changes.push(change(position, Operation::Insert, row));
+                }
+            }
+        }
+
+        self.shown.insert(side, shown);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The quote of `venue` on `side`, `seconds` into the session.
+    fn depth(venue: &str, side: Side, price: f64, size: f64, seconds: i64) -> Depth {
+        Depth {
+            venue: venue.to_string(),
+            side,
+            price,
+            size,
+            timestamp: DateTime::UNIX_EPOCH + Duration::seconds(seconds),
+        }
+    }
+
+    /// A book and the rows a client sees of it, built from the changes only.
+    struct Client {
+        book: OrderBook,
+        seen: HashMap<Side, Vec<Row>>,
+    }
+
+    impl Client {
+        fn new(view: View, rows: usize) -> Client {
+            Client {
+                book: OrderBook::new(view, rows),
+                seen: HashMap::new(),
+            }
+        }
+
+        /// Take in `depth`, apply the changes to the rows seen and check they
+        /// are the rows of the book. Returns the changes.
+        fn apply(&mut self, depth: &Depth) -> Vec<Change> {
+            let changes = self.book.apply(depth);
+
+            for change in &changes {
+                let rows = self.seen.entry(change.side).or_default();
+
+                match change.operation {
+                    Operation::Insert => rows.insert(change.position, change.row.clone()),
+                    Operation::Update => rows[change.position] = change.row.clone(),
+                    Operation::Delete => assert_eq!(rows.remove(change.position), change.row),
+                }
+            }
+
+            for side in [Side::Bid, Side::Ask] {
+                let seen = self.seen.get(&side).cloned().unwrap_or_default();
+                assert_eq!(seen, self.book.rows(side), "{:?} rows of {:?}", side, self.book.view());
+            }
+
+            changes
+        }
+
+        fn rows(&self, side: Side) -> Vec<(f64, f64, &str)> {
+            self.seen[&side]
+                .iter()
+                .map(|row| (row.price, row.size, row.market_maker.as_str()))
+                .collect()
+        }
+    }
+
+    fn operations(changes: &[Change]) -> Vec<(Operation, usize)> {
+        changes.iter().map(|change| (change.operation, change.position)).collect()
+    }
+
+    #[test]
+    fn every_view_replays_to_its_rows() {
+        let quotes = [
+            depth("ISLAND", Side::Bid, 10.0, 100.0, 0),
+            depth("ARCA", Side::Bid, 10.0, 200.0, 1),
+            depth("ISLAND", Side::Ask, 10.2, 100.0, 2),
+            depth("BATS", Side::Bid, 10.0, 50.0, 3),
+            depth("ARCA", Side::Ask, 10.1, 300.0, 4),
+            depth("ISLAND", Side::Bid, 10.0, 500.0, 5),
+            depth("BATS", Side::Bid, 9.9, 80.0, 6),
+            depth("ARCA", Side::Ask, 10.1, 0.0, 7),
+            depth("ISLAND", Side::Ask, 10.3, 100.0, 200),
+        ];
+
+        for view in [View::Venues, View::Prices, View::Venue("ISLAND".to_string())] {
+            for rows in [1, 2, 5] {
+                let mut client = Client::new(view.clone(), rows);
+
+                for quote in &quotes {
+                    client.apply(quote);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prices_sum_the_venues() {
+        let mut client = Client::new(View::Prices, 5);
+
+        client.apply(&depth("ISLAND", Side::Bid, 10.0, 100.0, 0));
+        client.apply(&depth("ARCA", Side::Bid, 10.0, 200.0, 1));
+        assert_eq!(client.rows(Side::Bid), vec![(10.0, 300.0, "")]);
+
+        // Another venue at the same price only updates the level.
+        let changes = client.apply(&depth("BATS", Side::Bid, 10.0, 50.0, 2));
+        assert_eq!(operations(&changes), vec![(Operation::Update, 0)]);
+        assert_eq!(client.rows(Side::Bid), vec![(10.0, 350.0, "")]);
+    }
+
+    #[test]
+    fn reordered_row_moves_with_a_delete_and_an_insert() {
+        let mut client = Client::new(View::Venues, 5);
+
+        client.apply(&depth("ISLAND", Side::Bid, 10.0, 100.0, 0));
+        client.apply(&depth("ARCA", Side::Bid, 10.0, 200.0, 1));
+        assert_eq!(client.rows(Side::Bid), vec![(10.0, 200.0, "ARCA"), (10.0, 100.0, "ISLAND")]);
+
+        // The largest size comes first at the same price.
+        let changes = client.apply(&depth("ISLAND", Side::Bid, 10.0, 300.0, 2));
+        assert_eq!(operations(&changes), vec![(Operation::Delete, 1), (Operation::Insert, 0)]);
+        assert_eq!(client.rows(Side::Bid), vec![(10.0, 300.0, "ISLAND"), (10.0, 200.0, "ARCA")]);
+    }
+
+    #[test]
+    fn rows_past_the_limit_are_not_shown() {
+        let mut client = Client::new(View::Venues, 2);
+
+        client.apply(&depth("ISLAND", Side::Ask, 10.0, 300.0, 0));
+        client.apply(&depth("ARCA", Side::Ask, 10.0, 200.0, 1));
+        let changes = client.apply(&depth("BATS", Side::Ask, 10.0, 100.0, 2));
+        assert!(changes.is_empty());
+
+        // BATS takes the place of ARCA once it quotes more.
+        let changes = client.apply(&depth("BATS", Side::Ask, 10.0, 250.0, 3));
+        assert_eq!(operations(&changes), vec![(Operation::Delete, 1), (Operation::Insert, 1)]);
+        assert_eq!(client.rows(Side::Ask), vec![(10.0, 300.0, "ISLAND"), (10.0, 250.0, "BATS")]);
+
+        // And ARCA comes back when BATS leaves.
+        client.apply(&depth("BATS", Side::Ask, 10.0, 0.0, 4));
+        assert_eq!(client.rows(Side::Ask), vec![(10.0, 300.0, "ISLAND"), (10.0, 200.0, "ARCA")]);
+    }
+
+    #[test]
+    fn new_best_price_evicts_the_better_quotes() {
+        let mut client = Client::new(View::Venues, 5);
+
+        client.apply(&depth("ISLAND", Side::Bid, 10.1, 100.0, 0));
+        client.apply(&depth("ARCA", Side::Bid, 10.0, 100.0, 1));
+        client.apply(&depth("ISLAND", Side::Ask, 10.2, 100.0, 2));
+
+        // ISLAND no longer bids 10.1 if ARCA has the best bid at 10.0, its
+        // ask stays.
+        assert_eq!(client.rows(Side::Bid), vec![(10.0, 100.0, "ARCA")]);
+        assert_eq!(client.rows(Side::Ask), vec![(10.2, 100.0, "ISLAND")]);
+
+        // A better bid keeps the others.
+        client.apply(&depth("BATS", Side::Bid, 10.0, 50.0, 3));
+        client.apply(&depth("ISLAND", Side::Bid, 10.05, 100.0, 4));
+        assert_eq!(
+            client.rows(Side::Bid),
+            vec![(10.05, 100.0, "ISLAND"), (10.0, 100.0, "ARCA"), (10.0, 50.0, "BATS")]
+        );
+    }
+
+    #[test]
+    fn quotes_not_renewed_go_stale() {
+        let mut client = Client::new(View::Venues, 5);
+        let stale = STALE_AFTER.num_seconds();
+
+        client.apply(&depth("ISLAND", Side::Bid, 10.0, 100.0, 0));
+        client.apply(&depth("ARCA", Side::Bid, 10.0, 200.0, 10));
+        client.apply(&depth("ARCA", Side::Ask, 10.2, 100.0, stale - 1));
+        assert_eq!(client.seen[&Side::Bid].len(), 2);
+
+        // ISLAND was last heard of `STALE_AFTER` ago, ARCA not yet.
+        let changes = client.apply(&depth("BATS", Side::Ask, 10.2, 50.0, stale));
+        assert_eq!(changes[0].operation, Operation::Delete);
+        assert_eq!(client.rows(Side::Bid), vec![(10.0, 200.0, "ARCA")]);
+        assert_eq!(client.rows(Side::Ask), vec![(10.2, 100.0, "ARCA"), (10.2, 50.0, "BATS")]);
+    }
+}
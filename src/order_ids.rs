@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// Name of the file the next valid order ids are stored in, inside the data
+/// directory.
+const FILE_NAME: &str = "order_ids.txt";
+
+/// Next valid order id of every client.
+///
+/// TWS order ids are scoped to the client id that placed them and must never
+/// be reused, not even after a restart, because an old order may still be
+/// working at the broker. The ids are kept in a small text file with one
+/// `<client id> <next order id>` line per client, which is rewritten every
+/// time an id is used.
+#[derive(Debug)]
+pub(crate) struct OrderIds {
+    /// Location of the backing file.
+    path: PathBuf,
+
+    /// The lowest order id each client has not used yet.
+    next: HashMap<i32, i32>,
+}
+
+impl OrderIds {
+    /// Load the order ids stored in `data_dir`. A missing file means no client
+    /// has placed an order yet.
+    pub(crate) fn open(data_dir: impl Into<PathBuf>) -> crate::Result<OrderIds> {
+        let path = data_dir.into().join(FILE_NAME);
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut next = HashMap::new();
+
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let invalid = || format!("invalid line `{}` in {}", line, path.display());
+
+            let (client_id, order_id) = line.trim().split_once(' ').ok_or_else(invalid)?;
+            let client_id = client_id.parse().map_err(|_| invalid())?;
+            let order_id = order_id.trim().parse().map_err(|_| invalid())?;
+
+            next.insert(client_id, order_id);
+        }
+
+        debug!(path = %path.display(), clients = next.len(), "loaded order ids");
+
+        Ok(OrderIds { path, next })
+    }
+
+    /// Returns the next valid order id of `client_id`.
+    pub(crate) fn next_id(&self, client_id: i32) -> i32 {
+        self.next.get(&client_id).copied().unwrap_or(1)
+    }
+
+    /// Record that `client_id` used `order_id`.
+    ///
+    /// Returns `Ok(false)` without changing anything if the id is lower than
+    /// the client's next valid order id, i.e. the client already used it or a
+    /// higher one, or if it is the highest id there is, which would leave the
+    /// client no next one.
+    pub(crate) fn claim(&mut self, client_id: i32, order_id: i32) -> crate::Result<bool> {
+        let Some(next) = order_id.checked_add(1) else {
+            return Ok(false);
+        };
+
+        if order_id < self.next_id(client_id) {
+            return Ok(false);
+        }
+
+        self.next.insert(client_id, next);
+        self.save()?;
+
+        Ok(true)
+    }
+
+    /// Write all ids to the backing file, through a temporary file renamed
+    /// over it.
+    fn save(&self) -> io::Result<()> {
+        let mut clients: Vec<_> = self.next.iter().collect();
+        clients.sort();
+
+        let contents: String = clients
+            .into_iter()
+            .map(|(client_id, order_id)| format!("{} {}\n", client_id, order_id))
+            .collect();
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_empty(name: &str) -> OrderIds {
+        let dir = std::env::temp_dir().join(format!("order_ids_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        OrderIds::open(dir).unwrap()
+    }
+
+    #[test]
+    fn claim_moves_the_next_id_past_the_claimed_one() {
+        let mut ids = open_empty("claim");
+
+        assert!(ids.claim(1, 5).unwrap());
+        assert_eq!(ids.next_id(1), 6);
+        assert!(!ids.claim(1, 5).unwrap());
+        assert_eq!(ids.next_id(2), 1);
+    }
+
+    #[test]
+    fn claim_refuses_the_highest_id() {
+        let mut ids = open_empty("highest");
+
+        assert!(!ids.claim(1, i32::MAX).unwrap());
+        assert_eq!(ids.next_id(1), 1);
+    }
+}
@@ -0,0 +1,509 @@
+use crate::broker::{self, OrderStatus, OrderType, OrderUpdate, Side, TimeInForce, Trail};
+use crate::orders::{con_id, Contract, Order, Status, STOCK};
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// An order placed through the connector, with its progress.
+#[derive(Clone, Debug)]
+pub(crate) struct TrackedOrder {
+    /// Client that placed the order.
+    pub(crate) client_id: i32,
+    pub(crate) order_id: i32,
+
+    /// Identifier of the order across all clients, assigned by the book.
+    pub(crate) perm_id: i32,
+
+    /// Account the order was placed in.
+    pub(crate) account: String,
+
+    pub(crate) contract: Contract,
+    pub(crate) order: Order,
+    pub(crate) status: Status,
+
+    /// `true` once the client released the order. Orders placed with
+    /// `transmit` unset wait for an order of their family that has it set.
+    pub(crate) transmitted: bool,
+
+    /// Identifier the broker assigned, once the order was submitted.
+    pub(crate) broker_id: Option<String>,
+
+    /// Identifier the broker reports the order's updates with. Changes when
+    /// the order is modified. Of the orders it replaced, only the fills of
+    /// the last one are taken in.
+    pub(crate) client_order_id: String,
+
+    /// The broker order the last modification takes the place of.
+    superseded: Option<Superseded>,
+
+    /// Number of times the order was sent again to the broker after a
+    /// modification.
+    revision: u32,
+
+    /// Quantity filled, and its value, by the broker orders this one
+    /// replaced, when a modification replaced it at the broker or cancelled
+    /// and placed it anew.
+    pub(crate) prior_filled: f64,
+    prior_value: f64,
+
+    pub(crate) filled: f64,
+    pub(crate) avg_fill_price: f64,
+    pub(crate) last_fill_price: f64,
+
+    /// Time of the last broker update applied, to drop the ones that arrive
+    /// out of order.
+    updated_at: Option<DateTime<Utc>>,
+}
+
+/// A broker order a modification takes the place of, which may still fill
+/// until the broker has replaced or cancelled it.
+#[derive(Clone, Debug)]
+struct Superseded {
+    client_order_id: String,
+
+    /// Quantity the broker order filled, and its value, as last heard of.
+    filled: f64,
+    value: f64,
+
+    /// The last update of the broker order since the modification.
+    last: Option<broker::Order>,
+
+    /// Set once the order is cancelled to place it anew, which happens when
+    /// the broker reports the superseded order done.
+    restart: bool,
+}
+
+/// The orders placed through the connector, by client id and order id.
+#[derive(Debug, Default)]
+pub(crate) struct OrderBook {
+    orders: HashMap<(i32, i32), TrackedOrder>,
+
+    /// Key of every order by the identifier the broker reports it with.
+    by_client_order_id: HashMap<String, (i32, i32)>,
+
+    /// Permanent ids of the orders placed at the brokers without going
+    /// through the connector, by broker id.
+    foreign: HashMap<String, i32>,
+
+    /// Last permanent id handed out.
+    last_perm_id: i32,
+}
+
+impl TrackedOrder {
+    /// Create the record of an order `client_id` just placed. The order is
+    /// held by the connector until it is submitted.
+    pub(crate) fn new(
+        client_id: i32,
+        order_id: i32,
+        account: String,
+        contract: Contract,
+        order: Order,
+    ) -> TrackedOrder {
+        TrackedOrder {
+            client_id,
+            order_id,
+            perm_id: 0,
+            account,
+            contract,
+            transmitted: order.transmit,
+            order,
+            status: Status::PreSubmitted,
+            broker_id: None,
+            client_order_id: format!("tws-{}-{}", client_id, order_id),
+            superseded: None,
+            revision: 0,
+            prior_filled: 0.0,
+            prior_value: 0.0,
+            filled: 0.0,
+            avg_fill_price: 0.0,
+            last_fill_price: 0.0,
+            updated_at: None,
+        }
+    }
+
+    /// Describe an order that was placed at the broker without going through
+    /// the connector, e.g. in the broker's web interface.
+    ///
+    /// As TWS does for orders it did not receive through the API, the order
+    /// has order id 0 and client id 0, and only its `perm_id` identifies it.
+    pub(crate) fn foreign(account: &str, currency: &str, order: &broker::Order, perm_id: i32) -> TrackedOrder {
+        let order_type = match (order.order_type, order.time_in_force) {
+            (OrderType::Market, TimeInForce::AtTheClose) => "MOC",
+            (OrderType::Limit, TimeInForce::AtTheClose) => "LOC",
+            (OrderType::Market, _) => "MKT",
+            (OrderType::Limit, _) => "LMT",
+            (OrderType::Stop, _) => "STP",
+            (OrderType::StopLimit, _) => "STP LMT",
+            (OrderType::TrailingStop, _) => "TRAIL",
+        };
+
+        let tif = match order.time_in_force {
+            TimeInForce::Day | TimeInForce::AtTheClose => "DAY",
+            TimeInForce::GoodTillCancel => "GTC",
+            TimeInForce::AtTheOpening => "OPG",
+            TimeInForce::ImmediateOrCancel => "IOC",
+            TimeInForce::FillOrKill => "FOK",
+        };
+
+        let (aux_price, trailing_percent) = match order.trail {
+            Some(Trail::Price(amount)) => (Some(amount), None),
+            Some(Trail::Percent(percent)) => (None, Some(percent)),
+            None => (order.stop_price, None),
+        };
+
+        let contract = Contract {
+            con_id: con_id(&order.symbol),
+            symbol: order.symbol.clone(),
+            sec_type: STOCK.to_string(),
+            exchange: "SMART".to_string(),
+            currency: currency.to_string(),
+            local_symbol: order.symbol.clone(),
+            ..Contract::default()
+        };
+
+        let tws_order = Order {
+            action: match order.side {
+                Side::Buy => "BUY",
+                Side::Sell => "SELL",
+            }
+            .to_string(),
+            total_quantity: order.quantity,
+            order_type: order_type.to_string(),
+            lmt_price: order.limit_price,
+            aux_price,
+            tif: tif.to_string(),
+            account: account.to_string(),
+            transmit: true,
+            outside_rth: order.extended_hours,
+            trailing_percent,
+            ..Order::default()
+        };
+
+        let mut tracked = TrackedOrder::new(0, 0, account.to_string(), contract, tws_order);
+        tracked.perm_id = perm_id;
+        tracked.client_order_id = order.client_order_id.clone();
+        tracked.apply(&OrderUpdate {
+            order: order.clone(),
+            fill: None,
+        });
+        tracked
+    }
+
+    /// Returns the quantity left to fill.
+    pub(crate) fn remaining(&self) -> f64 {
+        if self.status.is_done() {
+            0.0
+        } else {
+            (self.order.total_quantity - self.filled).max(0.0)
+        }
+    }
+
+    /// Returns `true` if the order is held by the connector and was never
+    /// sent to the broker.
+    pub(crate) fn is_held(&self) -> bool {
+        self.broker_id.is_none() && self.status == Status::PreSubmitted
+    }
+
+    /// Take in a change reported by the broker.
+    ///
+    /// Returns `false` if the update changes nothing the client sees, is
+    /// older than the last one applied, or would bring a finished order back
+    /// to life. A pending cancellation stays pending until the order ends.
+    pub(crate) fn apply(&mut self, update: &OrderUpdate) -> bool {
+        let order = &update.order;
+
+        if order.client_order_id != self.client_order_id {
+            return self.apply_superseded(update);
+        }
+
+        if self
+            .updated_at
+            .is_some_and(|updated_at| order.updated_at < updated_at)
+        {
+            return false;
+        }
+
+        let status = match Status::from(order.status) {
+            // A cancellation is pending until the broker ends the order, an
+            // update it sent before is no answer.
+            status if self.status == Status::PendingCancel && !status.is_done() => Status::PendingCancel,
+            status => status,
+        };
+
+        if self.status.is_done() && !status.is_done() {
+            return false;
+        }
+
+        let before = (self.status, self.filled, self.last_fill_price);
+
+        self.updated_at = Some(order.updated_at);
+        self.broker_id = Some(order.id.clone());
+        self.status = status;
+        (self.filled, self.avg_fill_price) = self.totals(order);
+
+        if let Some(fill) = &update.fill {
+            self.last_fill_price = fill.price;
+        }
+
+        before != (self.status, self.filled, self.last_fill_price)
+    }
+
+    /// Take in a change of the broker order the last modification takes the
+    /// place of: its late fills count towards the order, and once it is done,
+    /// an order waiting for it is placed anew.
+    ///
+    /// Returns `false` if the update changes nothing the client sees or is
+    /// not about that order.
+    fn apply_superseded(&mut self, update: &OrderUpdate) -> bool {
+        let order = &update.order;
+
+        let Some(superseded) = self
+            .superseded
+            .as_mut()
+            .filter(|superseded| superseded.client_order_id == order.client_order_id)
+        else {
+            return false;
+        };
+
+        if superseded
+            .last
+            .as_ref()
+            .is_some_and(|last| order.updated_at < last.updated_at)
+        {
+            return false;
+        }
+
+        superseded.last = Some(order.clone());
+
+        let filled = order.filled_quantity;
+        let value = filled * order.filled_avg_price.unwrap_or_default();
+        let (more, more_value) = (filled - superseded.filled, value - superseded.value);
+        (superseded.filled, superseded.value) = (filled, value);
+        let restart = superseded.restart && order.status.is_terminal();
+
+        let before = (self.status, self.filled, self.last_fill_price);
+
+        if more > 0.0 {
+            let value = self.filled * self.avg_fill_price + more_value;
+            self.prior_filled += more;
+            self.prior_value += more_value;
+            self.filled += more;
+            self.avg_fill_price = value / self.filled;
+
+            if let Some(fill) = &update.fill {
+                self.last_fill_price = fill.price;
+            }
+        }
+
+        if restart {
+            self.place_anew();
+        }
+
+        before != (self.status, self.filled, self.last_fill_price)
+    }
+
+    /// Returns the quantity filled and the average fill price once the broker
+    /// order `order` is counted in, along with the fills of the broker orders
+    /// it replaced.
+    pub(crate) fn totals(&self, order: &broker::Order) -> (f64, f64) {
+        let filled = self.prior_filled + order.filled_quantity;
+        let value = self.prior_value + order.filled_quantity * order.filled_avg_price.unwrap_or_default();
+
+        if filled > 0.0 {
+            (filled, value / filled)
+        } else {
+            (filled, 0.0)
+        }
+    }
+
+    /// Keep the fills so far, before the broker order is replaced by one that
+    /// starts unfilled.
+    fn carry_fills(&mut self) {
+        self.prior_value = self.filled * self.avg_fill_price;
+        self.prior_filled = self.filled;
+    }
+
+    /// Keep the fills so far, before the order is placed anew at the broker.
+    /// The broker order only has to fill what is left.
+    fn restart(&mut self) {
+        self.carry_fills();
+        self.broker_id = None;
+        self.updated_at = None;
+        self.status = Status::PreSubmitted;
+        self.transmitted = true;
+    }
+
+    /// Place the order anew once the broker order the last modification
+    /// takes the place of is done, which the broker is asked to cancel.
+    ///
+    /// Returns `true` if that order is done already, which changed the order.
+    pub(crate) fn restart_when_superseded(&mut self) -> bool {
+        let Some(superseded) = &mut self.superseded else {
+            return false;
+        };
+
+        superseded.restart = true;
+
+        let done = superseded
+            .last
+            .as_ref()
+            .is_some_and(|last| last.status.is_terminal());
+
+        if done {
+            self.place_anew();
+        }
+        done
+    }
+
+    /// Leave the superseded broker order behind, which is done, and get ready
+    /// to place what is left of the order anew. An order it filled
+    /// completely is filled.
+    fn place_anew(&mut self) {
+        self.superseded = None;
+
+        if self.filled >= self.order.total_quantity {
+            self.status = Status::Filled;
+        } else {
+            self.restart();
+        }
+    }
+
+    /// Go back to the identifier and fills of `previous`, the order as it was
+    /// before the last modification, which the broker did not carry out.
+    /// What the broker reported of the superseded order meanwhile is taken
+    /// in.
+    ///
+    /// Returns `false` if the order was placed anew already, which leaves it
+    /// as it is.
+    pub(crate) fn restore(&mut self, previous: &TrackedOrder) -> bool {
+        let Some(superseded) = std::mem::replace(&mut self.superseded, previous.superseded.clone()) else {
+            return false;
+        };
+
+        self.client_order_id = previous.client_order_id.clone();
+        self.prior_filled = previous.prior_filled;
+        self.prior_value = previous.prior_value;
+
+        if let Some(last) = superseded.last {
+            self.apply(&OrderUpdate {
+                order: last,
+                fill: None,
+            });
+        }
+        true
+    }
+}
+
+impl OrderBook {
+    /// Create an empty book whose permanent ids follow `last_perm_id`, the
+    /// highest one handed out before a restart.
+    pub(crate) fn new(last_perm_id: i32) -> OrderBook {
+        OrderBook {
+            last_perm_id,
+            ..OrderBook::default()
+        }
+    }
+
+    /// Add a newly placed order, assigning its permanent id.
+    pub(crate) fn insert(&mut self, mut order: TrackedOrder) -> &TrackedOrder {
+        self.last_perm_id += 1;
+        order.perm_id = self.last_perm_id;
+
+        let key = (order.client_id, order.order_id);
+        self.by_client_order_id.insert(order.client_order_id.clone(), key);
+
+        self.orders.insert(key, order);
+        &self.orders[&key]
+    }
+
+    /// Returns order `order_id` of `client_id`.
+    pub(crate) fn get(&self, client_id: i32, order_id: i32) -> Option<&TrackedOrder> {
+        self.orders.get(&(client_id, order_id))
+    }
+
+    pub(crate) fn get_mut(&mut self, client_id: i32, order_id: i32) -> Option<&mut TrackedOrder> {
+        self.orders.get_mut(&(client_id, order_id))
+    }
+
+    /// Give order `order_id` of `client_id` a new identifier at the broker,
+    /// for the order that replaces it there, and carry its fills so far over
+    /// to the replacement.
+    ///
+    /// Returns the order as it was, to `restore` if the replacement fails.
+    pub(crate) fn revise(&mut self, client_id: i32, order_id: i32) -> Option<TrackedOrder> {
+        let order = self.orders.get_mut(&(client_id, order_id))?;
+        let previous = order.clone();
+
+        order.revision += 1;
+        let client_order_id = format!("tws-{}-{}.{}", client_id, order_id, order.revision);
+        order.superseded = Some(Superseded {
+            client_order_id: std::mem::replace(&mut order.client_order_id, client_order_id),
+            filled: order.filled - order.prior_filled,
+            value: order.filled * order.avg_fill_price - order.prior_value,
+            last: None,
+            restart: false,
+        });
+        order.carry_fills();
+
+        self.by_client_order_id
+            .insert(order.client_order_id.clone(), (client_id, order_id));
+
+        Some(previous)
+    }
+
+    /// Returns the permanent id of the order the broker knows as `broker_id`,
+    /// which was not placed through the connector. One is assigned the first
+    /// time the order is seen.
+    pub(crate) fn foreign_perm_id(&mut self, broker_id: &str) -> i32 {
+        if let Some(perm_id) = self.foreign.get(broker_id) {
+            return *perm_id;
+        }
+
+        self.last_perm_id += 1;
+        self.foreign.insert(broker_id.to_string(), self.last_perm_id);
+        self.last_perm_id
+    }
+
+    /// Returns `true` if the broker reports an order placed through the
+    /// connector as `client_order_id`.
+    pub(crate) fn is_tracked(&self, client_order_id: &str) -> bool {
+        self.by_client_order_id.contains_key(client_order_id)
+    }
+
+    /// Returns the order the broker reports as `client_order_id`.
+    pub(crate) fn find(&self, client_order_id: &str) -> Option<&TrackedOrder> {
+        let key = self.by_client_order_id.get(client_order_id)?;
+        self.orders.get(key)
+    }
+
+    pub(crate) fn find_mut(&mut self, client_order_id: &str) -> Option<&mut TrackedOrder> {
+        let key = self.by_client_order_id.get(client_order_id)?;
+        self.orders.get_mut(key)
+    }
+
+    /// Returns the orders matching `filter`.
+    pub(crate) fn select(&self, filter: impl Fn(&TrackedOrder) -> bool) -> Vec<TrackedOrder> {
+        self.orders
+            .values()
+            .filter(|order| filter(order))
+            .cloned()
+            .collect()
+    }
+}
+
+impl From<OrderStatus> for Status {
+    fn from(status: OrderStatus) -> Status {
+        match status {
+            OrderStatus::PendingNew => Status::PendingSubmit,
+            OrderStatus::New
+            | OrderStatus::PartiallyFilled
+            | OrderStatus::PendingReplace
+            | OrderStatus::Replaced => Status::Submitted,
+            OrderStatus::Filled => Status::Filled,
+            OrderStatus::PendingCancel => Status::PendingCancel,
+            OrderStatus::Canceled | OrderStatus::Expired => Status::Cancelled,
+            OrderStatus::Rejected => Status::Inactive,
+            OrderStatus::Held => Status::PreSubmitted,
+        }
+    }
+}
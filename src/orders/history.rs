@@ -0,0 +1,207 @@
+use crate::journal::{Journal, Record};
+use crate::messages::{Encoder, OutgoingMessage};
+use crate::orders::{flag, optional, Contract, Order, Status, TrackedOrder};
+use crate::Frame;
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Name of the file the completed orders are stored in, inside the data
+/// directory.
+const FILE_NAME: &str = "completed_orders.jsonl";
+
+/// An order that is done, as reported in `completedOrder`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct CompletedOrder {
+    /// Identifier of the last broker order, each order is recorded once.
+    pub(crate) client_order_id: String,
+
+    pub(crate) client_id: i32,
+    pub(crate) order_id: i32,
+    pub(crate) perm_id: i32,
+
+    /// Permanent id of the parent order, 0 if there is none.
+    pub(crate) parent_perm_id: i32,
+    pub(crate) account: String,
+    pub(crate) contract: Contract,
+    pub(crate) order: Order,
+    pub(crate) status: Status,
+    pub(crate) filled: f64,
+    pub(crate) completed_at: DateTime<Utc>,
+
+    /// `false` for orders placed without going through the connector.
+    pub(crate) api: bool,
+}
+
+/// The orders completed today, by the local clock.
+///
+/// TWS reports the orders that filled or were cancelled during the day, also
+/// after a restart. The orders are kept in a `Journal`.
+#[derive(Debug)]
+pub(crate) struct OrderHistory {
+    journal: Journal<CompletedOrder>,
+}
+
+impl Record for CompletedOrder {
+    fn id(&self) -> &str {
+        &self.client_order_id
+    }
+}
+
+impl CompletedOrder {
+    /// Describe `order`, which just became done. `api` is unset for orders
+    /// placed without going through the connector.
+    pub(crate) fn new(order: &TrackedOrder, parent_perm_id: i32, api: bool) -> CompletedOrder {
+        CompletedOrder {
+            client_order_id: order.client_order_id.clone(),
+            client_id: order.client_id,
+            order_id: order.order_id,
+            perm_id: order.perm_id,
+            parent_perm_id,
+            account: order.account.clone(),
+            contract: order.contract.clone(),
+            order: order.order.clone(),
+            status: order.status,
+            filled: order.filled,
+            completed_at: Utc::now(),
+            api,
+        }
+    }
+
+    /// Returns `true` if the order completed today, by the local clock.
+    fn is_today(&self) -> bool {
+        self.completed_at.with_timezone(&Local).date_naive() == Local::now().date_naive()
+    }
+
+    /// Returns the text sent as `completedStatus`, as TWS words it.
+    fn completed_status(&self) -> String {
+        match self.status {
+            Status::Filled => format!("Filled Size: {}", self.filled),
+            Status::Inactive => "Rejected".to_string(),
+            _ if self.filled > 0.0 => format!("Partially Filled Size: {}, Cancelled", self.filled),
+            _ => "Cancelled".to_string(),
+        }
+    }
+
+    /// Encode the `completedOrder` message describing the order.
+    pub(crate) fn completed_order_frame(&self, server_version: u16) -> Frame {
+        let contract = &self.contract;
+        let order = &self.order;
+
+        Encoder::new(OutgoingMessage::CompletedOrder, server_version)
+            .put("conId", contract.con_id)
+            .put("symbol", &contract.symbol)
+            .put("secType", &contract.sec_type)
+            .put(
+                "lastTradeDateOrContractMonth",
+                &contract.last_trade_date_or_contract_month,
+            )
+            .put("strike", contract.strike.unwrap_or_default())
+            .put("right", &contract.right)
+            .put("multiplier", &contract.multiplier)
+            .put("exchange", &contract.exchange)
+            .put("currency", &contract.currency)
+            .put("localSymbol", &contract.local_symbol)
+            .put("tradingClass", &contract.trading_class)
+            .put("action", &order.action)
+            .put("totalQuantity", order.total_quantity)
+            .put("orderType", &order.order_type)
+            .put("lmtPrice", optional(order.lmt_price))
+            .put("auxPrice", optional(order.aux_price))
+            .put("tif", &order.tif)
+            .put("ocaGroup", &order.oca_group)
+            .put("account", &self.account)
+            .put("openClose", "O")
+            .put("origin", 0)
+            .put("orderRef", &order.order_ref)
+            .put("permId", self.perm_id)
+            .put("outsideRth", flag(order.outside_rth))
+            .put("hidden", flag(order.hidden))
+            .put("discretionaryAmt", 0)
+            .put("goodAfterTime", &order.good_after_time)
+            .put("modelCode", &order.model_code)
+            .put("goodTillDate", &order.good_till_date)
+            .put("shortSaleSlot", 0)
+            .put("exemptCode", -1)
+            .put("displaySize", order.display_size)
+            .put("sweepToFill", flag(false))
+            .put("allOrNone", flag(order.all_or_none))
+            .put("minQty", optional(order.min_qty))
+            .put("ocaType", order.oca_type)
+            .put("triggerMethod", 0)
+            .put("volatilityType", 0)
+            .put("continuousUpdate", flag(false))
+            .put("referencePriceType", 0)
+            .put("trailStopPrice", optional(order.trail_stop_price))
+            .put("trailingPercent", optional(order.trailing_percent))
+            .put_group("comboLegs", vec!["0".to_string(), "0".to_string()])
+            .put_group("smartComboRoutingParams", vec!["0".to_string()])
+            .put("notHeld", flag(false))
+            .put_group("deltaNeutralContract", vec![flag(false)])
+            .put("algoStrategy", &order.algo_strategy)
+            .put_group("algoParams", order.algo_params_group())
+            .put("solicited", flag(false))
+            .put("status", self.status.as_str())
+            .put("randomizeSize", flag(false))
+            .put("randomizePrice", flag(false))
+            .put_group("conditions", order.conditions_group())
+            .put("stopPrice", optional(order.trail_stop_price))
+            .put("cashQty", optional(order.cash_qty))
+            .put("dontUseAutoPriceForHedge", flag(false))
+            .put("isOmsContainer", flag(false))
+            .put("filledQuantity", self.filled)
+            .put("refFuturesConId", 0)
+            .put("autoCancelParent", flag(false))
+            .put("imbalanceOnly", flag(false))
+            .put("routeMarketableToBbo", flag(false))
+            .put("parentPermId", self.parent_perm_id)
+            .put(
+                "completedTime",
+                self.completed_at
+                    .with_timezone(&Local)
+                    .format("%Y%m%d %H:%M:%S %Z"),
+            )
+            .put("completedStatus", self.completed_status())
+            .into_frame()
+    }
+}
+
+impl OrderHistory {
+    /// Load the orders stored in `data_dir`, dropping the ones completed
+    /// before today. A missing file means no order completed yet.
+    pub(crate) fn open(data_dir: impl Into<PathBuf>) -> crate::Result<OrderHistory> {
+        let journal = Journal::open(data_dir.into().join(FILE_NAME), CompletedOrder::is_today)?;
+        Ok(OrderHistory { journal })
+    }
+
+    /// Add `order` and store it.
+    ///
+    /// Returns `Ok(false)` without changing anything if it was recorded
+    /// before.
+    pub(crate) fn record(&mut self, order: CompletedOrder) -> crate::Result<bool> {
+        self.journal.record(order)
+    }
+
+    /// Returns the orders completed today, only those placed through the
+    /// connector if `api_only` is set.
+    pub(crate) fn today(&self, api_only: bool) -> Vec<CompletedOrder> {
+        self.journal
+            .records()
+            .iter()
+            .filter(|order| order.is_today() && (order.api || !api_only))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the highest permanent id of the orders, or of their parents, 0
+    /// if there is none.
+    pub(crate) fn last_perm_id(&self) -> i32 {
+        self.journal
+            .records()
+            .iter()
+            .map(|order| order.perm_id.max(order.parent_perm_id))
+            .max()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,575 @@
+//! Orders placed by clients, in TWS terms.
+//!
+//! A client describes an order with a TWS `Contract` and `Order`. The
+//! connector keeps both in the `OrderBook`, translates them into an
+//! `OrderRequest` for the broker of the order's account and follows the
+//! broker's updates, which clients see as `openOrder` and `orderStatus`
+//! messages. Orders that are done are kept in the `OrderHistory`.
+
+mod book;
+pub(crate) use book::{OrderBook, TrackedOrder};
+
+mod history;
+pub(crate) use history::{CompletedOrder, OrderHistory};
+
+mod route;
+pub(crate) use route::{cancel, forward, forward_foreign, modify, track, transmit, working};
+
+use crate::broker::{OrderRequest, OrderType, Side, TimeInForce, Trail};
+use crate::executions::Execution;
+use crate::messages::{Encoder, OutgoingMessage};
+use crate::positions::Holding;
+use crate::Frame;
+
+use serde::{Deserialize, Serialize};
+
+/// Security type of the instruments brokers can trade.
+pub(crate) const STOCK: &str = "STK";
+
+/// Returns the contract id of the stock `symbol`.
+///
+/// Brokers and feeds know stocks by their symbol only, so the id is derived
+/// from it and is the same every time, also across restarts.
+pub(crate) fn con_id(symbol: &str) -> i32 {
+    // 32 bit FNV-1a, kept positive and non-zero as TWS contract ids are.
+    let hash = symbol.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte.to_ascii_uppercase())).wrapping_mul(0x0100_0193)
+    });
+
+    (hash & 0x7fff_ffff).max(1) as i32
+}
+
+/// The instrument of an order.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Contract {
+    pub(crate) con_id: i32,
+    pub(crate) symbol: String,
+
+    /// `STK`, `OPT`, `FUT`, ...
+    pub(crate) sec_type: String,
+
+    pub(crate) last_trade_date_or_contract_month: String,
+    pub(crate) strike: Option<f64>,
+    pub(crate) right: String,
+    pub(crate) multiplier: String,
+
+    /// Destination exchange, usually `SMART`.
+    pub(crate) exchange: String,
+
+    pub(crate) primary_exchange: String,
+    pub(crate) currency: String,
+    pub(crate) local_symbol: String,
+    pub(crate) trading_class: String,
+}
+
+/// A tag and its value, as used by algo parameters and routing options.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TagValue {
+    pub(crate) tag: String,
+    pub(crate) value: String,
+}
+
+/// A condition that activates or cancels an order.
+///
+/// `and` tells how the condition combines with the previous one: with a
+/// logical and, or a logical or.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum OrderCondition {
+    /// The price of `con_id` on `exchange` crossed `price`.
+    Price {
+        and: bool,
+        is_more: bool,
+        price: f64,
+        con_id: i32,
+        exchange: String,
+        trigger_method: i32,
+    },
+
+    /// The time passed `time`, formatted as `yyyyMMdd HH:mm:ss`.
+    Time { and: bool, is_more: bool, time: String },
+
+    /// The margin cushion of the account crossed `percent`.
+    Margin { and: bool, is_more: bool, percent: i32 },
+
+    /// An execution of `symbol` happened on `exchange`.
+    Execution {
+        and: bool,
+        sec_type: String,
+        exchange: String,
+        symbol: String,
+    },
+
+    /// The traded volume of `con_id` crossed `volume`.
+    Volume {
+        and: bool,
+        is_more: bool,
+        volume: i32,
+        con_id: i32,
+        exchange: String,
+    },
+
+    /// The price of `con_id` changed by more than `change_percent` since the
+    /// previous close.
+    PercentChange {
+        and: bool,
+        is_more: bool,
+        change_percent: f64,
+        con_id: i32,
+        exchange: String,
+    },
+}
+
+/// An order as sent by a client in `placeOrder`.
+///
+/// Only the fields the connector acts on or echoes back in `openOrder` are
+/// kept. The others are read and dropped.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Order {
+    /// `BUY`, `SELL` or `SSHORT`.
+    pub(crate) action: String,
+    pub(crate) total_quantity: f64,
+
+    /// `MKT`, `LMT`, `STP`, `STP LMT`, `TRAIL`, ...
+    pub(crate) order_type: String,
+    pub(crate) lmt_price: Option<f64>,
+
+    /// Stop price of stop orders, trailing amount of trailing stops.
+    pub(crate) aux_price: Option<f64>,
+
+    /// `DAY`, `GTC`, `OPG`, `IOC`, ...
+    pub(crate) tif: String,
+
+    /// Orders of the same client that share a non-empty group cancel each
+    /// other when one of them fills.
+    pub(crate) oca_group: String,
+    pub(crate) oca_type: i32,
+
+    /// Account to place the order in. Empty means the first managed account.
+    pub(crate) account: String,
+
+    pub(crate) order_ref: String,
+
+    /// `false` keeps the order in the connector until a child order with
+    /// `transmit` set arrives.
+    pub(crate) transmit: bool,
+
+    /// Order id of the parent order. The order is only sent to the broker
+    /// once its parent has filled.
+    pub(crate) parent_id: i32,
+
+    /// Allow the order to fill outside regular trading hours.
+    pub(crate) outside_rth: bool,
+
+    pub(crate) hidden: bool,
+    pub(crate) display_size: i32,
+    pub(crate) good_after_time: String,
+    pub(crate) good_till_date: String,
+    pub(crate) all_or_none: bool,
+    pub(crate) min_qty: Option<i32>,
+    pub(crate) trail_stop_price: Option<f64>,
+    pub(crate) trailing_percent: Option<f64>,
+    pub(crate) algo_strategy: String,
+    pub(crate) algo_params: Vec<TagValue>,
+    pub(crate) what_if: bool,
+    pub(crate) conditions: Vec<OrderCondition>,
+    pub(crate) conditions_ignore_rth: bool,
+    pub(crate) conditions_cancel_order: bool,
+    pub(crate) model_code: String,
+    pub(crate) cash_qty: Option<f64>,
+}
+
+/// A change to the orders, announced to the connections.
+#[derive(Clone, Debug)]
+pub(crate) enum OrderEvent {
+    /// The order was placed or its state changed.
+    Changed(Box<TrackedOrder>),
+
+    /// An order placed without going through the connector changed. Only
+    /// client 0 sees these, after `reqAutoOpenOrders`.
+    Foreign(Box<TrackedOrder>),
+
+    /// An order filled, in part or in full. All clients see these.
+    Executed(Box<Execution>),
+
+    /// A fill changed a position. Clients see these after `reqPositions`.
+    Position(Box<Holding>),
+
+    /// The order was refused, for `reason`.
+    Rejected {
+        client_id: i32,
+        order_id: i32,
+        reason: String,
+    },
+}
+
+/// Status of an order as reported in `orderStatus`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum Status {
+    /// Sent to the broker, not acknowledged yet.
+    PendingSubmit,
+
+    /// A cancellation was requested and not confirmed yet.
+    PendingCancel,
+
+    /// Accepted but held, here by the connector until it is transmitted or
+    /// its parent fills.
+    PreSubmitted,
+
+    /// Working at the broker.
+    Submitted,
+
+    /// Cancelled by the client, the broker or an OCA group.
+    Cancelled,
+    Filled,
+
+    /// Rejected by the broker.
+    Inactive,
+}
+
+impl Status {
+    /// Returns the text sent in `orderStatus` and `openOrder`.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Status::PendingSubmit => "PendingSubmit",
+            Status::PendingCancel => "PendingCancel",
+            Status::PreSubmitted => "PreSubmitted",
+            Status::Submitted => "Submitted",
+            Status::Cancelled => "Cancelled",
+            Status::Filled => "Filled",
+            Status::Inactive => "Inactive",
+        }
+    }
+
+    /// Returns `true` if the order can no longer change.
+    pub(crate) fn is_done(self) -> bool {
+        matches!(self, Status::Cancelled | Status::Filled | Status::Inactive)
+    }
+}
+
+impl Order {
+    /// Translate the order for the broker.
+    ///
+    /// Returns the reason sent in error 201 if the broker layer cannot carry
+    /// out the order.
+    pub(crate) fn to_request(
+        &self,
+        contract: &Contract,
+        client_order_id: &str,
+    ) -> Result<OrderRequest, String> {
+        if !contract.sec_type.is_empty() && contract.sec_type != STOCK {
+            return Err(format!("Security type {} is not supported", contract.sec_type));
+        }
+
+        if contract.symbol.is_empty() {
+            return Err("Missing symbol".to_string());
+        }
+
+        if self.total_quantity <= 0.0 {
+            return Err("Order quantity must be positive".to_string());
+        }
+
+        if self.what_if {
+            return Err("What-if orders are not supported".to_string());
+        }
+
+        if !self.algo_strategy.is_empty() {
+            return Err(format!("Algo {} is not supported", self.algo_strategy));
+        }
+
+        if !self.conditions.is_empty() {
+            return Err("Conditional orders are not supported".to_string());
+        }
+
+        let side = match self.action.as_str() {
+            "BUY" => Side::Buy,
+            "SELL" | "SSHORT" => Side::Sell,
+            action => return Err(format!("Invalid action {}", action)),
+        };
+
+        let mut time_in_force = match self.tif.as_str() {
+            "" | "DAY" => TimeInForce::Day,
+            "GTC" => TimeInForce::GoodTillCancel,
+            "OPG" => TimeInForce::AtTheOpening,
+            "IOC" => TimeInForce::ImmediateOrCancel,
+            "FOK" => TimeInForce::FillOrKill,
+            tif => return Err(format!("Time in force {} is not supported", tif)),
+        };
+
+        let (order_type, limit_price, stop_price, trail) = match self.order_type.as_str() {
+            "MKT" => (OrderType::Market, None, None, None),
+            "LMT" => (OrderType::Limit, Some(self.limit()?), None, None),
+            "STP" => (OrderType::Stop, None, Some(self.aux()?), None),
+            "STP LMT" => (OrderType::StopLimit, Some(self.limit()?), Some(self.aux()?), None),
+            "TRAIL" => {
+                let trail = match (self.trailing_percent, self.aux_price) {
+                    (Some(percent), _) => Trail::Percent(percent),
+                    (None, Some(amount)) => Trail::Price(amount),
+                    (None, None) => return Err("Missing trailing amount".to_string()),
+                };
+
+                (OrderType::TrailingStop, None, None, Some(trail))
+            }
+            "MOC" => {
+                time_in_force = TimeInForce::AtTheClose;
+                (OrderType::Market, None, None, None)
+            }
+            "LOC" => {
+                time_in_force = TimeInForce::AtTheClose;
+                (OrderType::Limit, Some(self.limit()?), None, None)
+            }
+            "MOO" => {
+                time_in_force = TimeInForce::AtTheOpening;
+                (OrderType::Market, None, None, None)
+            }
+            "LOO" => {
+                time_in_force = TimeInForce::AtTheOpening;
+                (OrderType::Limit, Some(self.limit()?), None, None)
+            }
+            order_type => return Err(format!("Order type {} is not supported", order_type)),
+        };
+
+        Ok(OrderRequest {
+            client_order_id: client_order_id.to_string(),
+            symbol: contract.symbol.clone(),
+            side,
+            quantity: self.total_quantity,
+            order_type,
+            time_in_force,
+            limit_price,
+            stop_price,
+            trail,
+            extended_hours: self.outside_rth,
+        })
+    }
+
+    fn limit(&self) -> Result<f64, String> {
+        self.lmt_price.ok_or_else(|| "Missing limit price".to_string())
+    }
+
+    fn aux(&self) -> Result<f64, String> {
+        self.aux_price.ok_or_else(|| "Missing stop price".to_string())
+    }
+}
+
+impl OrderCondition {
+    /// Returns the condition type sent before the condition's fields.
+    pub(crate) fn kind(&self) -> i32 {
+        match self {
+            OrderCondition::Price { .. } => 1,
+            OrderCondition::Time { .. } => 3,
+            OrderCondition::Margin { .. } => 4,
+            OrderCondition::Execution { .. } => 5,
+            OrderCondition::Volume { .. } => 6,
+            OrderCondition::PercentChange { .. } => 7,
+        }
+    }
+
+    /// Returns the wire fields of the condition, type included.
+    fn fields(&self) -> Vec<String> {
+        let conjunction = |and: bool| if and { "a" } else { "o" }.to_string();
+        let mut fields = vec![self.kind().to_string()];
+
+        match self {
+            OrderCondition::Price {
+                and,
+                is_more,
+                price,
+                con_id,
+                exchange,
+                trigger_method,
+            } => fields.extend([
+                conjunction(*and),
+                flag(*is_more),
+                price.to_string(),
+                con_id.to_string(),
+                exchange.clone(),
+                trigger_method.to_string(),
+            ]),
+            OrderCondition::Time { and, is_more, time } => {
+                fields.extend([conjunction(*and), flag(*is_more), time.clone()])
+            }
+            OrderCondition::Margin {
+                and,
+                is_more,
+                percent,
+            } => fields.extend([conjunction(*and), flag(*is_more), percent.to_string()]),
+            OrderCondition::Execution {
+                and,
+                sec_type,
+                exchange,
+                symbol,
+            } => fields.extend([
+                conjunction(*and),
+                sec_type.clone(),
+                exchange.clone(),
+                symbol.clone(),
+            ]),
+            OrderCondition::Volume {
+                and,
+                is_more,
+                volume,
+                con_id,
+                exchange,
+            } => fields.extend([
+                conjunction(*and),
+                flag(*is_more),
+                volume.to_string(),
+                con_id.to_string(),
+                exchange.clone(),
+            ]),
+            OrderCondition::PercentChange {
+                and,
+                is_more,
+                change_percent,
+                con_id,
+                exchange,
+            } => fields.extend([
+                conjunction(*and),
+                flag(*is_more),
+                change_percent.to_string(),
+                con_id.to_string(),
+                exchange.clone(),
+            ]),
+        }
+
+        fields
+    }
+}
+
+impl Order {
+    /// Returns the wire fields of the `algoParams` group of `openOrder` and
+    /// `completedOrder`.
+    fn algo_params_group(&self) -> Vec<String> {
+        let mut fields = Vec::new();
+        if !self.algo_strategy.is_empty() {
+            fields.push(self.algo_params.len().to_string());
+            for param in &self.algo_params {
+                fields.extend([param.tag.clone(), param.value.clone()]);
+            }
+        }
+        fields
+    }
+
+    /// Returns the wire fields of the `conditions` group of `openOrder` and
+    /// `completedOrder`.
+    fn conditions_group(&self) -> Vec<String> {
+        let mut fields = vec![self.conditions.len().to_string()];
+        for condition in &self.conditions {
+            fields.extend(condition.fields());
+        }
+        if !self.conditions.is_empty() {
+            fields.extend([flag(self.conditions_ignore_rth), flag(self.conditions_cancel_order)]);
+        }
+        fields
+    }
+}
+
+impl TrackedOrder {
+    /// Encode the `openOrder` message describing the order.
+    pub(crate) fn open_order_frame(&self, server_version: u16) -> Frame {
+        let contract = &self.contract;
+        let order = &self.order;
+
+        Encoder::new(OutgoingMessage::OpenOrder, server_version)
+            .put("orderId", self.order_id)
+            .put("conId", contract.con_id)
+            .put("symbol", &contract.symbol)
+            .put("secType", &contract.sec_type)
+            .put(
+                "lastTradeDateOrContractMonth",
+                &contract.last_trade_date_or_contract_month,
+            )
+            .put("strike", contract.strike.unwrap_or_default())
+            .put("right", &contract.right)
+            .put("multiplier", &contract.multiplier)
+            .put("exchange", &contract.exchange)
+            .put("currency", &contract.currency)
+            .put("localSymbol", &contract.local_symbol)
+            .put("tradingClass", &contract.trading_class)
+            .put("action", &order.action)
+            .put("totalQuantity", order.total_quantity)
+            .put("orderType", &order.order_type)
+            .put("lmtPrice", optional(order.lmt_price))
+            .put("auxPrice", optional(order.aux_price))
+            .put("tif", &order.tif)
+            .put("ocaGroup", &order.oca_group)
+            .put("account", &self.account)
+            .put("openClose", "O")
+            .put("origin", 0)
+            .put("orderRef", &order.order_ref)
+            .put("clientId", self.client_id)
+            .put("permId", self.perm_id)
+            .put("outsideRth", flag(order.outside_rth))
+            .put("hidden", flag(order.hidden))
+            .put("discretionaryAmt", 0)
+            .put("goodAfterTime", &order.good_after_time)
+            .put("modelCode", &order.model_code)
+            .put("goodTillDate", &order.good_till_date)
+            .put("shortSaleSlot", 0)
+            .put("exemptCode", -1)
+            .put("auctionStrategy", 0)
+            .put("displaySize", order.display_size)
+            .put("blockOrder", flag(false))
+            .put("sweepToFill", flag(false))
+            .put("allOrNone", flag(order.all_or_none))
+            .put("minQty", optional(order.min_qty))
+            .put("ocaType", order.oca_type)
+            .put("eTradeOnly", flag(false))
+            .put("firmQuoteOnly", flag(false))
+            .put("parentId", order.parent_id)
+            .put("triggerMethod", 0)
+            .put("volatilityType", 0)
+            .put("continuousUpdate", flag(false))
+            .put("referencePriceType", 0)
+            .put("trailStopPrice", optional(order.trail_stop_price))
+            .put("trailingPercent", optional(order.trailing_percent))
+            .put_group("comboLegs", vec!["0".to_string(), "0".to_string()])
+            .put_group("smartComboRoutingParams", vec!["0".to_string()])
+            .put("optOutSmartRouting", flag(false))
+            .put("notHeld", flag(false))
+            .put_group("deltaNeutralContract", vec![flag(false)])
+            .put("algoStrategy", &order.algo_strategy)
+            .put_group("algoParams", order.algo_params_group())
+            .put("solicited", flag(false))
+            .put("whatIf", flag(order.what_if))
+            .put("status", self.status.as_str())
+            .put("randomizeSize", flag(false))
+            .put("randomizePrice", flag(false))
+            .put_group("conditions", order.conditions_group())
+            .put("adjustedTrailStopPrice", optional(order.trail_stop_price))
+            .put("adjustableTrailingUnit", 0)
+            .put("cashQty", optional(order.cash_qty))
+            .put("dontUseAutoPriceForHedge", flag(false))
+            .put("isOmsContainer", flag(false))
+            .put("discretionaryUpToLimitPrice", flag(false))
+            .put("usePriceMgmtAlgo", flag(false))
+            .into_frame()
+    }
+
+    /// Encode the `orderStatus` message with the order's progress.
+    pub(crate) fn order_status_frame(&self, server_version: u16) -> Frame {
+        Encoder::new(OutgoingMessage::OrderStatus, server_version)
+            .put("orderId", self.order_id)
+            .put("status", self.status.as_str())
+            .put("filled", self.filled)
+            .put("remaining", self.remaining())
+            .put("avgFillPrice", self.avg_fill_price)
+            .put("permId", self.perm_id)
+            .put("parentId", self.order.parent_id)
+            .put("lastFillPrice", self.last_fill_price)
+            .put("clientId", self.client_id)
+            .put("whyHeld", "")
+            .put("mktCapPrice", 0)
+            .into_frame()
+    }
+}
+
+/// Encode a boolean as TWS does.
+fn flag(value: bool) -> String {
+    if value { "1" } else { "0" }.to_string()
+}
+
+/// Encode an unset number as an empty field.
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
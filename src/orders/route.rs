@@ -0,0 +1,951 @@
+use crate::broker::{self, OrderChanges, OrderFilter, OrderUpdate};
+use crate::messages::errors;
+use crate::orders::{Contract, Order, OrderEvent, Status, TrackedOrder};
+use crate::subscriptions::Sink;
+use crate::Db;
+
+use futures_util::future::{BoxFuture, FutureExt};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{self, Duration};
+use tracing::{debug, warn};
+
+/// Longest wait, in seconds, between two attempts to load the positions of
+/// an account.
+const MAX_BACKOFF: u64 = 64;
+
+/// Release order `order_id` of `client_id`, which was placed with `transmit`
+/// set, together with the orders of its family the client held back.
+///
+/// A family is a parent order and its children. Released orders are sent to
+/// the broker, except children, which wait for their parent to fill.
+pub(crate) async fn transmit(db: &Db, client_id: i32, order_id: i32) {
+    let Some(order) = db.order(client_id, order_id) else {
+        return;
+    };
+
+    let root = if order.order.parent_id != 0 {
+        order.order.parent_id
+    } else {
+        order_id
+    };
+
+    let family = db.orders(|other| {
+        other.client_id == client_id
+            && other.is_held()
+            && (other.order_id == root || other.order.parent_id == root)
+    });
+
+    // Parents first, so they reach the broker before anything waits on them.
+    let mut family: Vec<_> = family
+        .into_iter()
+        .filter_map(|order| {
+            db.update_order(order.client_id, order.order_id, |order| {
+                let released = !order.transmitted;
+                order.transmitted = true;
+                released
+            });
+            db.order(order.client_id, order.order_id)
+        })
+        .collect();
+    family.sort_by_key(|order| (order.order.parent_id != 0, order.order_id));
+
+    for order in family {
+        if is_ready(db, &order) {
+            submit(db, order).await;
+        }
+    }
+}
+
+/// Change the working order `current` to `contract` and `order`, which the
+/// client sent in a `placeOrder` with the same order id.
+///
+/// Held orders are changed in place. Orders at the broker are replaced there,
+/// or, if the broker cannot replace them, cancelled and placed anew with the
+/// same TWS order id once the broker confirms the cancellation. A change that
+/// fails otherwise leaves the order as it was and is reported as error 201.
+/// The caller checked that the change is allowed.
+pub(crate) async fn modify(db: &Db, current: TrackedOrder, contract: Contract, order: Order) {
+    let (client_id, order_id) = (current.client_id, current.order_id);
+
+    let old = current
+        .order
+        .to_request(&current.contract, &current.client_order_id);
+    let new = order.to_request(&contract, &current.client_order_id);
+    let transmit = order.transmit;
+
+    // The fields the broker does not know about only change in the book.
+    let changed = db.update_order(client_id, order_id, |tracked| {
+        tracked.contract = contract;
+        tracked.order = order;
+        tracked.order.account = tracked.account.clone();
+        true
+    });
+
+    let Some(changed) = changed else {
+        return;
+    };
+
+    if changed.is_held() {
+        if transmit && !changed.transmitted {
+            self::transmit(db, client_id, order_id).await;
+        }
+        return;
+    }
+
+    let (Ok(old), Ok(new), Some(broker_id)) = (old, new, current.broker_id.clone()) else {
+        return;
+    };
+
+    let changes = OrderChanges {
+        client_order_id: None,
+        quantity: differs(old.quantity, new.quantity),
+        time_in_force: differs(old.time_in_force, new.time_in_force),
+        limit_price: differs(old.limit_price, new.limit_price).flatten(),
+        stop_price: differs(old.stop_price, new.stop_price).flatten(),
+        trail: differs(old.trail, new.trail).flatten(),
+    };
+
+    if changes == OrderChanges::default() {
+        return;
+    }
+
+    let Some(broker) = db.broker(&current.account) else {
+        return;
+    };
+
+    let Some(previous) = db.revise_order(client_id, order_id) else {
+        return;
+    };
+
+    let changes = OrderChanges {
+        client_order_id: db.order(client_id, order_id).map(|order| order.client_order_id),
+        // The replacement starts unfilled, it only has to fill what is left.
+        // The broker order has what was left at the last modification.
+        quantity: differs(old.quantity - previous.prior_filled, new.quantity - previous.filled),
+        ..changes
+    };
+
+    match broker.replace_order(&broker_id, &changes).await {
+        Ok(replacement) => {
+            let update = OrderUpdate {
+                order: replacement,
+                fill: None,
+            };
+
+            if let Some((before, after)) = db.apply_order_update(&update) {
+                follow(db, &before, &after).await;
+            }
+
+            return;
+        }
+        Err(broker::Error::NotSupported) => {}
+        Err(err) => {
+            warn!(client_id, order_id, cause = %err, "order modification failed");
+            return keep(db, &current, &previous, err.to_string()).await;
+        }
+    }
+
+    debug!(client_id, order_id, "the broker cannot replace the order, cancelling it to place it anew");
+
+    // What is left is placed once the broker reports the cancelled order done,
+    // which fixes what it filled.
+    let mut before = None;
+    let restarted = db.update_order(client_id, order_id, |tracked| {
+        before = Some(tracked.clone());
+        tracked.restart_when_superseded()
+    });
+
+    if let (Some(before), Some(after)) = (before, restarted) {
+        return follow(db, &before, &after).await;
+    }
+
+    if let Err(err) = broker.cancel_order(&broker_id).await {
+        warn!(client_id, order_id, cause = %err, "failed to modify order");
+        keep(db, &current, &previous, err.to_string()).await;
+    }
+}
+
+/// Undo a modification of `current` that the broker refused, for `reason`.
+///
+/// The order keeps working as it was at the broker, with the identifier and
+/// fills of `previous`, and the client gets error 201. An order placed anew
+/// in the meantime is left as it is.
+async fn keep(db: &Db, current: &TrackedOrder, previous: &TrackedOrder, reason: String) {
+    let (client_id, order_id) = (current.client_id, current.order_id);
+
+    let mut before = None;
+    let kept = db.update_order(client_id, order_id, |tracked| {
+        before = Some(tracked.clone());
+        let restored = tracked.restore(previous);
+        if restored {
+            tracked.contract = current.contract.clone();
+            tracked.order = current.order.clone();
+        }
+        restored
+    });
+
+    let (Some(before), Some(after)) = (before, kept) else {
+        return;
+    };
+
+    db.publish(OrderEvent::Rejected {
+        client_id,
+        order_id,
+        reason,
+    });
+
+    // The superseded order may have filled meanwhile.
+    follow(db, &before, &after).await;
+}
+
+/// Returns `Some(new)` if `new` differs from `old`.
+fn differs<T: PartialEq>(old: T, new: T) -> Option<T> {
+    if old != new {
+        Some(new)
+    } else {
+        None
+    }
+}
+
+/// Follow the updates of one broker's orders, reacting to fills and
+/// cancellations of the orders placed through the connector.
+///
+/// The positions of the account are loaded first, for the fills to change
+/// them. The updates wait in `updates` meanwhile.
+pub(crate) async fn track(db: Db, account: String, mut updates: broadcast::Receiver<OrderUpdate>) {
+    let mut backoff = 1;
+
+    while let Err(err) = db.positions(&account).await {
+        warn!(%account, cause = %err, backoff, "failed to load positions");
+
+        time::sleep(Duration::from_secs(backoff)).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    loop {
+        match updates.recv().await {
+            Ok(update) => {
+                debug!(%account, id = %update.order.id, status = ?update.order.status, "order update");
+
+                // The fill is reported even if the update is stale, it still
+                // happened.
+                match db.record_fill(&account, &update) {
+                    Ok(Some(execution)) => {
+                        db.apply_execution(&execution);
+                        db.publish(OrderEvent::Executed(Box::new(execution)));
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(%account, id = %update.order.id, cause = %err, "failed to record fill"),
+                }
+
+                if let Some((before, after)) = db.apply_order_update(&update) {
+                    follow(&db, &before, &after).await;
+                } else if let Some(order) = db.foreign_order(&account, &update.order) {
+                    if order.status.is_done() {
+                        db.complete_foreign_order(&order);
+                    }
+
+                    db.publish(OrderEvent::Foreign(Box::new(order)));
+                }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                warn!(%account, missed, "order updates were dropped");
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Send the order events of `client_id` to `sink` as `openOrder` and
+/// `orderStatus` messages, followed by error 202 when an order is cancelled,
+/// and rejections as error 201. The executions of all orders are sent as
+/// `execDetails` and `commissionReport`.
+pub(crate) async fn forward(
+    mut events: broadcast::Receiver<OrderEvent>,
+    client_id: i32,
+    sink: Sink,
+    server_version: u16,
+) {
+    loop {
+        let responses = match events.recv().await {
+            Ok(OrderEvent::Changed(order)) if order.client_id == client_id => {
+                let mut responses = vec![
+                    order.open_order_frame(server_version),
+                    order.order_status_frame(server_version),
+                ];
+
+                if order.status == Status::Cancelled {
+                    responses.push(errors::ORDER_CANCELLED.to_frame(order.order_id.into(), server_version));
+                }
+
+                responses
+            }
+            Ok(OrderEvent::Executed(execution)) => vec![
+                execution.exec_details_frame(-1, server_version),
+                execution.commission_report_frame(server_version),
+            ],
+            Ok(OrderEvent::Rejected {
+                client_id: owner,
+                order_id,
+                reason,
+            }) if owner == client_id => {
+                vec![errors::ORDER_REJECTED.to_frame_with(
+                    order_id.into(),
+                    server_version,
+                    &format!("{}{}", errors::ORDER_REJECTED.message, reason),
+                )]
+            }
+            Ok(_) => continue,
+            Err(RecvError::Lagged(missed)) => {
+                warn!(client_id, missed, "order events were dropped");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        for response in responses {
+            debug!(?response);
+
+            if sink.send(response).await.is_err() {
+                // The connection is closed.
+                return;
+            }
+        }
+    }
+}
+
+/// Send the changes of the orders placed without going through the
+/// connector to `sink`, as `openOrder` and `orderStatus` messages.
+pub(crate) async fn forward_foreign(
+    mut events: broadcast::Receiver<OrderEvent>,
+    sink: Sink,
+    server_version: u16,
+) {
+    loop {
+        let order = match events.recv().await {
+            Ok(OrderEvent::Foreign(order)) => order,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(missed)) => {
+                warn!(missed, "order events were dropped");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        for response in [
+            order.open_order_frame(server_version),
+            order.order_status_frame(server_version),
+        ] {
+            debug!(?response);
+
+            if sink.send(response).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Returns the working orders matching `filter`, followed, if `foreign` is
+/// set, by the open orders the brokers report that were placed without going
+/// through the connector.
+///
+/// A broker that fails to list its orders is skipped.
+pub(crate) async fn working(
+    db: &Db,
+    filter: impl Fn(&TrackedOrder) -> bool,
+    foreign: bool,
+) -> Vec<TrackedOrder> {
+    let mut orders = db.orders(|order| !order.status.is_done() && filter(order));
+    orders.sort_by_key(|order| (order.client_id, order.order_id));
+
+    if !foreign {
+        return orders;
+    }
+
+    for account in &db.config().accounts {
+        let Some(broker) = db.broker(&account.id) else {
+            continue;
+        };
+
+        match broker.orders(OrderFilter::Open).await {
+            Ok(open) => orders.extend(open.iter().filter_map(|order| db.foreign_order(&account.id, order))),
+            Err(err) => warn!(account = %account.id, cause = %err, "failed to list open orders"),
+        }
+    }
+
+    orders
+}
+
+/// Returns `true` if `order` may be sent to the broker: it was released by
+/// the client, and its parent, if any, has filled.
+fn is_ready(db: &Db, order: &TrackedOrder) -> bool {
+    if !order.transmitted || !order.is_held() {
+        return false;
+    }
+
+    order.order.parent_id == 0
+        || db
+            .order(order.client_id, order.order.parent_id)
+            .is_some_and(|parent| parent.status == Status::Filled)
+}
+
+/// Send a held order to its broker.
+///
+/// Boxed because submitting an order can fill it, which submits its
+/// children in turn.
+fn submit(db: &Db, order: TrackedOrder) -> BoxFuture<'_, ()> {
+    async move {
+        let (client_id, order_id) = (order.client_id, order.order_id);
+
+        let mut request = match order.order.to_request(&order.contract, &order.client_order_id) {
+            Ok(request) => request,
+            Err(reason) => return reject(db, &order, reason),
+        };
+
+        // What an earlier broker order filled before a modification replaced
+        // it.
+        request.quantity -= order.prior_filled;
+
+        let Some(broker) = db.broker(&order.account) else {
+            return reject(db, &order, format!("Unknown account {}", order.account));
+        };
+
+        // Only one caller gets to submit the order.
+        let claimed = db.update_order(client_id, order_id, |order| {
+            let held = order.is_held();
+            if held {
+                order.status = Status::PendingSubmit;
+            }
+            held
+        });
+
+        if claimed.is_none() {
+            return;
+        }
+
+        match broker.submit_order(&request).await {
+            Ok(submitted) => {
+                let update = OrderUpdate {
+                    order: submitted,
+                    fill: None,
+                };
+
+                if let Some((before, after)) = db.apply_order_update(&update) {
+                    follow(db, &before, &after).await;
+                }
+            }
+            Err(broker::Error::Rejected(reason)) => reject(db, &order, reason),
+            Err(err) => {
+                warn!(client_id, order_id, cause = %err, "failed to submit order");
+                reject(db, &order, err.to_string());
+            }
+        }
+    }
+    .boxed()
+}
+
+/// Report that `order` could not be placed and mark it inactive.
+fn reject(db: &Db, order: &TrackedOrder, reason: String) {
+    warn!(client_id = order.client_id, order_id = order.order_id, %reason, "order rejected");
+
+    db.publish(OrderEvent::Rejected {
+        client_id: order.client_id,
+        order_id: order.order_id,
+        reason,
+    });
+
+    db.update_order(order.client_id, order.order_id, |order| {
+        order.status = Status::Inactive;
+        true
+    });
+}
+
+/// Cancel `order`, at the broker if it was submitted, right away otherwise.
+///
+/// Orders at the broker are `PendingCancel` until the broker confirms. If it
+/// refuses, e.g. because the order filled in the meantime, the order gets its
+/// status back and the broker's error is returned.
+pub(crate) async fn cancel(db: &Db, order: &TrackedOrder) -> Result<(), broker::Error> {
+    let (client_id, order_id) = (order.client_id, order.order_id);
+
+    let Some(broker_id) = &order.broker_id else {
+        let cancelled = db.update_order(client_id, order_id, |order| {
+            let held = order.is_held();
+            if held {
+                order.status = Status::Cancelled;
+            }
+            held
+        });
+
+        if let Some(after) = cancelled {
+            follow(db, order, &after).await;
+        }
+
+        return Ok(());
+    };
+
+    let broker = db
+        .broker(&order.account)
+        .ok_or_else(|| broker::Error::from(format!("unknown account {}", order.account)))?;
+
+    let mut previous = order.status;
+    let pending = db.update_order(client_id, order_id, |order| {
+        previous = order.status;
+        let cancellable = !order.status.is_done() && order.status != Status::PendingCancel;
+        if cancellable {
+            order.status = Status::PendingCancel;
+        }
+        cancellable
+    });
+
+    if pending.is_none() {
+        return Ok(());
+    }
+
+    if let Err(err) = broker.cancel_order(broker_id).await {
+        // Unless the broker reported a change in the meantime.
+        db.update_order(client_id, order_id, |order| {
+            let unchanged = order.status == Status::PendingCancel;
+            if unchanged {
+                order.status = previous;
+            }
+            unchanged
+        });
+
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// React to the change of an order from `before` to `after`.
+///
+/// The broker knows nothing about the relations between orders, so the
+/// connector carries them out: a fill cancels the rest of the order's OCA
+/// group, a filled parent releases its children and a parent that will never
+/// fill takes its children with it.
+fn follow<'a>(db: &'a Db, before: &'a TrackedOrder, after: &'a TrackedOrder) -> BoxFuture<'a, ()> {
+    async move {
+        let (client_id, order_id) = (after.client_id, after.order_id);
+
+        if after.filled > before.filled && !after.order.oca_group.is_empty() {
+            let siblings = db.orders(|other| {
+                other.client_id == client_id
+                    && other.order_id != order_id
+                    && other.order.oca_group == after.order.oca_group
+            });
+
+            for sibling in &siblings {
+                cancel_quietly(db, sibling).await;
+            }
+        }
+
+        if after.status == before.status {
+            return;
+        }
+
+        // Cancelled at the broker to be placed anew.
+        if after.is_held() && !before.is_held() {
+            if is_ready(db, after) {
+                submit(db, after.clone()).await;
+            }
+            return;
+        }
+
+        let children = db.orders(|other| other.client_id == client_id && other.order.parent_id == order_id);
+
+        if after.status == Status::Filled {
+            for child in children {
+                if is_ready(db, &child) {
+                    submit(db, child).await;
+                }
+            }
+        } else if after.status.is_done() {
+            for child in &children {
+                cancel_quietly(db, child).await;
+            }
+        }
+    }
+    .boxed()
+}
+
+/// Cancel an order on the connector's own initiative, only logging failures.
+async fn cancel_quietly(db: &Db, order: &TrackedOrder) {
+    if order.status.is_done() {
+        return;
+    }
+
+    if let Err(err) = cancel(db, order).await {
+        warn!(client_id = order.client_id, order_id = order.order_id, cause = %err, "failed to cancel order");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{
+        Account, Asset, Broker, Error, Order as BrokerOrder, OrderRequest, OrderStatus, OrderType, Position, Side,
+        TimeInForce,
+    };
+    use crate::config::{AccountConfig, BrokerConfig};
+    use crate::Config;
+
+    use async_trait::async_trait;
+    use chrono::{Duration, Utc};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    const ACCOUNT: &str = "DU1";
+
+    /// A broker holding one working order, which answers replacements as
+    /// `answer` says.
+    #[derive(Debug)]
+    struct Replacing {
+        answer: Answer,
+
+        /// The changes asked for, in order.
+        replaced: Mutex<Vec<OrderChanges>>,
+
+        /// Broker ids of the orders asked to cancel.
+        cancelled: Mutex<Vec<String>>,
+
+        /// The orders submitted, in order.
+        submitted: Mutex<Vec<OrderRequest>>,
+
+        updates: broadcast::Sender<OrderUpdate>,
+    }
+
+    #[derive(Debug)]
+    enum Answer {
+        /// With a new, unfilled order.
+        Replace,
+        Reject(&'static str),
+
+        /// As if the order were gone, or the request timed out.
+        Fail,
+        NotSupported,
+    }
+
+    #[async_trait]
+    impl Broker for Replacing {
+        async fn account(&self) -> Result<Account, Error> {
+            Err(Error::NotFound)
+        }
+
+        async fn positions(&self) -> Result<Vec<Position>, Error> {
+            Ok(Vec::new())
+        }
+
+        async fn asset(&self, _: &str) -> Result<Asset, Error> {
+            Err(Error::NotFound)
+        }
+
+        async fn submit_order(&self, order: &OrderRequest) -> Result<BrokerOrder, Error> {
+            self.submitted.lock().unwrap().push(order.clone());
+
+            let submitted = broker_order("broker-3", &order.client_order_id, order.quantity, OrderStatus::New, 0.0);
+            Ok(BrokerOrder {
+                updated_at: Utc::now(),
+                ..submitted
+            })
+        }
+
+        async fn replace_order(&self, id: &str, changes: &OrderChanges) -> Result<BrokerOrder, Error> {
+            self.replaced.lock().unwrap().push(changes.clone());
+
+            match self.answer {
+                Answer::Replace => {}
+                Answer::Reject(reason) => return Err(Error::Rejected(reason.to_string())),
+                Answer::Fail => return Err(Error::NotFound),
+                Answer::NotSupported => return Err(Error::NotSupported),
+            }
+
+            let mut replacement = broker_order(
+                "broker-2",
+                changes.client_order_id.as_deref().unwrap(),
+                changes.quantity.unwrap_or(100.0),
+                OrderStatus::New,
+                0.0,
+            );
+            replacement.limit_price = changes.limit_price.or(replacement.limit_price);
+            replacement.replaces = Some(id.to_string());
+            Ok(replacement)
+        }
+
+        async fn cancel_order(&self, id: &str) -> Result<(), Error> {
+            self.cancelled.lock().unwrap().push(id.to_string());
+            Ok(())
+        }
+
+        async fn orders(&self, _: OrderFilter) -> Result<Vec<BrokerOrder>, Error> {
+            Ok(Vec::new())
+        }
+
+        fn updates(&self) -> broadcast::Receiver<OrderUpdate> {
+            self.updates.subscribe()
+        }
+    }
+
+    /// A limit buy order at 10.00, filled by `filled` shares at that price.
+    fn broker_order(id: &str, client_order_id: &str, quantity: f64, status: OrderStatus, filled: f64) -> BrokerOrder {
+        BrokerOrder {
+            id: id.to_string(),
+            client_order_id: client_order_id.to_string(),
+            symbol: "MSFT".to_string(),
+            side: Side::Buy,
+            quantity,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Day,
+            limit_price: Some(10.0),
+            stop_price: None,
+            trail: None,
+            extended_hours: false,
+            status,
+            filled_quantity: filled,
+            filled_avg_price: (filled > 0.0).then_some(10.0),
+            replaces: None,
+            created_at: Utc::now() - Duration::seconds(1),
+            updated_at: Utc::now() - Duration::seconds(1),
+        }
+    }
+
+    /// Returns a `Db` whose only account is held by `broker`, with order 1 of
+    /// client 1 for 100 shares at 10.00 working at the broker, 40 of them
+    /// filled.
+    async fn open(name: &str, broker: Arc<Replacing>) -> (Db, TrackedOrder) {
+        let data_dir = std::env::temp_dir().join(format!("route_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let config = Config {
+            data_dir,
+            accounts: vec![AccountConfig {
+                id: ACCOUNT.to_string(),
+                currency: "USD".to_string(),
+                broker: BrokerConfig::Paper { cash: 0.0 },
+            }],
+            ..Config::default()
+        };
+
+        let brokers = HashMap::from([(ACCOUNT.to_string(), broker as Arc<dyn Broker>)]);
+        let db = Db::with_brokers(config, brokers).unwrap();
+
+        let contract = Contract {
+            symbol: "MSFT".to_string(),
+            sec_type: "STK".to_string(),
+            ..Contract::default()
+        };
+        let order = Order {
+            action: "BUY".to_string(),
+            total_quantity: 100.0,
+            order_type: "LMT".to_string(),
+            lmt_price: Some(10.0),
+            account: ACCOUNT.to_string(),
+            transmit: true,
+            ..Order::default()
+        };
+
+        let tracked = db.insert_order(TrackedOrder::new(1, 1, ACCOUNT.to_string(), contract, order));
+        let working = broker_order("broker-1", &tracked.client_order_id, 100.0, OrderStatus::PartiallyFilled, 40.0);
+        db.apply_order_update(&OrderUpdate {
+            order: working,
+            fill: None,
+        });
+
+        let current = db.order(1, 1).unwrap();
+        assert_eq!((current.filled, current.status), (40.0, Status::Submitted));
+        (db, current)
+    }
+
+    fn replacing(answer: Answer) -> Arc<Replacing> {
+        Arc::new(Replacing {
+            answer,
+            replaced: Mutex::new(Vec::new()),
+            cancelled: Mutex::new(Vec::new()),
+            submitted: Mutex::new(Vec::new()),
+            updates: broadcast::channel(16).0,
+        })
+    }
+
+    /// Report a change of the order `broker-1`, the one working before the
+    /// modification, through the broker's updates.
+    fn report(broker: &Replacing, client_order_id: &str, status: OrderStatus, filled: f64) {
+        let order = broker_order("broker-1", client_order_id, 100.0, status, filled);
+        let update = OrderUpdate {
+            order: BrokerOrder {
+                updated_at: Utc::now(),
+                ..order
+            },
+            fill: None,
+        };
+        broker.updates.send(update).unwrap();
+    }
+
+    /// Wait for order 1 of client 1 to meet `condition`, which the task
+    /// following the broker's updates brings about.
+    async fn until(db: &Db, condition: impl Fn(&TrackedOrder) -> bool) -> TrackedOrder {
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                match db.order(1, 1) {
+                    Some(order) if condition(&order) => return order,
+                    _ => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                }
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Returns the reason of the first rejection among `events`.
+    fn rejection(events: &mut broadcast::Receiver<OrderEvent>) -> String {
+        loop {
+            if let OrderEvent::Rejected { reason, .. } = events.try_recv().unwrap() {
+                return reason;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn replace_keeps_the_fills_of_the_replaced_order() {
+        let broker = replacing(Answer::Replace);
+        let (db, current) = open("replace", broker.clone()).await;
+
+        let order = Order {
+            lmt_price: Some(10.5),
+            ..current.order.clone()
+        };
+        modify(&db, current.clone(), current.contract.clone(), order).await;
+
+        // The replacement only has to fill the 60 shares left.
+        let replaced = broker.replaced.lock().unwrap().clone();
+        assert_eq!(replaced.len(), 1);
+        assert_eq!((replaced[0].quantity, replaced[0].limit_price), (Some(60.0), Some(10.5)));
+
+        let modified = db.order(1, 1).unwrap();
+        assert_eq!(modified.broker_id.as_deref(), Some("broker-2"));
+        assert_eq!((modified.filled, modified.avg_fill_price), (40.0, 10.0));
+        assert_eq!(modified.remaining(), 60.0);
+
+        let mut filled = broker_order("broker-2", &modified.client_order_id, 60.0, OrderStatus::Filled, 60.0);
+        filled.filled_avg_price = Some(10.5);
+        filled.updated_at = Utc::now();
+        db.apply_order_update(&OrderUpdate {
+            order: filled,
+            fill: None,
+        });
+
+        let done = db.order(1, 1).unwrap();
+        assert_eq!((done.status, done.filled), (Status::Filled, 100.0));
+        assert!((done.avg_fill_price - 10.3).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn rejected_replace_keeps_the_order_as_it_was() {
+        let broker = replacing(Answer::Reject("insufficient buying power"));
+        let (db, current) = open("rejected", broker.clone()).await;
+        let mut events = db.order_events();
+
+        let order = Order {
+            total_quantity: 1000.0,
+            ..current.order.clone()
+        };
+        modify(&db, current.clone(), current.contract.clone(), order).await;
+
+        // No cancel and place anew.
+        assert!(broker.cancelled.lock().unwrap().is_empty());
+
+        let kept = db.order(1, 1).unwrap();
+        assert_eq!(kept.order, current.order);
+        assert_eq!(kept.client_order_id, current.client_order_id);
+        assert_eq!(kept.broker_id.as_deref(), Some("broker-1"));
+        assert_eq!((kept.filled, kept.status), (40.0, Status::Submitted));
+
+        assert_eq!(rejection(&mut events), "insufficient buying power");
+
+        // Updates of the broker order still apply.
+        let mut filled = broker_order("broker-1", &current.client_order_id, 100.0, OrderStatus::Filled, 100.0);
+        filled.updated_at = Utc::now();
+        db.apply_order_update(&OrderUpdate {
+            order: filled,
+            fill: None,
+        });
+        assert_eq!(db.order(1, 1).unwrap().filled, 100.0);
+    }
+
+    #[tokio::test]
+    async fn failed_replace_keeps_the_order_as_it_was() {
+        let broker = replacing(Answer::Fail);
+        let (db, current) = open("failed", broker.clone()).await;
+        let mut events = db.order_events();
+
+        let order = Order {
+            lmt_price: Some(10.5),
+            ..current.order.clone()
+        };
+        modify(&db, current.clone(), current.contract.clone(), order).await;
+
+        // Only a broker that cannot replace the order has it cancelled.
+        assert!(broker.cancelled.lock().unwrap().is_empty());
+        assert!(broker.submitted.lock().unwrap().is_empty());
+
+        let kept = db.order(1, 1).unwrap();
+        assert_eq!(kept.order, current.order);
+        assert_eq!(kept.client_order_id, current.client_order_id);
+        assert_eq!((kept.filled, kept.status), (40.0, Status::Submitted));
+        assert_eq!(rejection(&mut events), "not found");
+    }
+
+    #[tokio::test]
+    async fn fills_of_the_replaced_order_count() {
+        let broker = replacing(Answer::Replace);
+        let (db, current) = open("late_fill", broker.clone()).await;
+
+        let order = Order {
+            lmt_price: Some(10.5),
+            ..current.order.clone()
+        };
+        modify(&db, current.clone(), current.contract.clone(), order).await;
+
+        // The replaced order filled 5 more before the broker replaced it.
+        report(&broker, &current.client_order_id, OrderStatus::Replaced, 45.0);
+
+        let modified = until(&db, |order| order.filled == 45.0).await;
+        assert_eq!(modified.prior_filled, 45.0);
+        assert_eq!(modified.broker_id.as_deref(), Some("broker-2"));
+        assert_eq!(modified.status, Status::Submitted);
+        assert_eq!(modified.remaining(), 55.0);
+    }
+
+    #[tokio::test]
+    async fn unsupported_replace_places_the_rest_anew_once_cancelled() {
+        let broker = replacing(Answer::NotSupported);
+        let (db, current) = open("unsupported", broker.clone()).await;
+
+        let order = Order {
+            lmt_price: Some(10.5),
+            ..current.order.clone()
+        };
+        modify(&db, current.clone(), current.contract.clone(), order).await;
+
+        assert_eq!(*broker.cancelled.lock().unwrap(), vec!["broker-1".to_string()]);
+        assert!(broker.submitted.lock().unwrap().is_empty());
+
+        // Fills until the broker confirms the cancellation count, and only
+        // what is left then is placed anew.
+        report(&broker, &current.client_order_id, OrderStatus::PendingCancel, 45.0);
+        until(&db, |order| order.filled == 45.0).await;
+        assert!(broker.submitted.lock().unwrap().is_empty());
+
+        report(&broker, &current.client_order_id, OrderStatus::Canceled, 50.0);
+        let placed = until(&db, |order| order.broker_id.as_deref() == Some("broker-3")).await;
+
+        let submitted = broker.submitted.lock().unwrap().clone();
+        assert_eq!(submitted.len(), 1);
+        assert_eq!(submitted[0].client_order_id, placed.client_order_id);
+        assert_eq!((submitted[0].quantity, submitted[0].limit_price), (50.0, Some(10.5)));
+
+        assert_eq!((placed.filled, placed.prior_filled), (50.0, 50.0));
+        assert_eq!(placed.remaining(), 50.0);
+    }
+}
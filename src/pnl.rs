@@ -0,0 +1,467 @@
+use crate::broker;
+use crate::executions::Execution;
+use crate::market_data::PriceWatch;
+use crate::orders::con_id;
+use crate::positions::Holding;
+use crate::Db;
+
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::collections::{BTreeMap, HashMap};
+use tokio::time::{self, Duration, Interval};
+use tracing::warn;
+
+/// How often the P&L is recomputed. TWS sends it about once a second.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Profit and loss, as reported in `pnl` and `pnlSingle`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Pnl {
+    /// Change in value since the start of the trading day, fills included.
+    pub(crate) daily: f64,
+
+    /// Gain of the open quantity over its average cost.
+    pub(crate) unrealized: f64,
+
+    /// Gain of the quantity closed during the trading day.
+    pub(crate) realized: f64,
+}
+
+/// The P&L of one position.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PositionPnl {
+    pub(crate) con_id: i32,
+
+    /// Negative for short positions, 0 once closed.
+    pub(crate) quantity: f64,
+
+    /// Market value of the position.
+    pub(crate) value: f64,
+    pub(crate) pnl: Pnl,
+}
+
+/// Follows the P&L of the positions of one account.
+///
+/// The P&L is computed from the fills of the trading day, the previous close
+/// and the last trade price from the market data feed. The trading day starts
+/// at midnight in the configured time zone. Without a feed, positions are
+/// valued at their cost.
+pub(crate) struct PnlWatch {
+    db: Db,
+    account: String,
+    prices: PriceWatch,
+
+    /// Previous close of every followed symbol, with the trading day it
+    /// precedes.
+    closes: HashMap<String, (DateTime<Utc>, Option<f64>)>,
+
+    interval: Interval,
+}
+
+impl PnlWatch {
+    pub(crate) fn new(db: Db, account: String) -> PnlWatch {
+        let prices = PriceWatch::new(db.market_data());
+
+        PnlWatch {
+            db,
+            account,
+            prices,
+            closes: HashMap::new(),
+            interval: time::interval(UPDATE_INTERVAL),
+        }
+    }
+
+    /// Wait until the P&L is due to be recomputed, following the market
+    /// prices meanwhile. Returns right away the first time.
+    pub(crate) async fn tick(&mut self) {
+        loop {
+            tokio::select! {
+                _ = self.interval.tick() => return,
+                _ = self.prices.changed() => {}
+            }
+        }
+    }
+
+    /// Returns the P&L of every position held or traded during the trading
+    /// day, by symbol.
+    pub(crate) async fn positions(&mut self) -> Result<BTreeMap<String, PositionPnl>, broker::Error> {
+        let holdings = self.db.positions(&self.account).await?;
+
+        let start = day_start(self.db.config().time_zone(), Utc::now());
+        let fills = self
+            .db
+            .executions(|execution| execution.account == self.account && execution.time >= start);
+
+        let mut symbols: BTreeMap<&str, (Option<&Holding>, Vec<&Execution>)> = BTreeMap::new();
+        for holding in &holdings {
+            symbols.entry(&holding.contract.symbol).or_default().0 = Some(holding);
+        }
+        for fill in &fills {
+            symbols.entry(&fill.symbol).or_default().1.push(fill);
+        }
+
+        // Follow the prices of the symbols that count today only.
+        let stale: Vec<_> = self
+            .closes
+            .keys()
+            .filter(|symbol| !symbols.contains_key(symbol.as_str()))
+            .cloned()
+            .collect();
+        for symbol in stale {
+            self.prices.unwatch(&symbol);
+            self.closes.remove(&symbol);
+        }
+
+        let mut positions = BTreeMap::new();
+
+        for (symbol, (holding, fills)) in symbols {
+            if self.closes.get(symbol).is_none_or(|(day, _)| *day != start) {
+                self.prices.watch(symbol).await;
+                let close = previous_close(&self.db, symbol).await;
+                self.closes.insert(symbol.to_string(), (start, close));
+            }
+
+            let close = self.closes.get(symbol).and_then(|(_, close)| *close);
+            let pnl = position_pnl(holding, &fills, self.prices.price(symbol), close);
+            positions.insert(symbol.to_string(), pnl);
+        }
+
+        Ok(positions)
+    }
+}
+
+/// Returns the P&L of a position, `holding` now, after `fills` today.
+///
+/// The daily P&L is the value of the position now less its value at the
+/// previous close and the cash paid for the fills. Without a `close`, the
+/// quantity held since before the day is valued at `price`, without a
+/// `price`, at its cost.
+fn position_pnl(
+    holding: Option<&Holding>,
+    fills: &[&Execution],
+    price: Option<f64>,
+    close: Option<f64>,
+) -> PositionPnl {
+    let (symbol, quantity, avg_cost) = match holding {
+        Some(holding) => (
+            holding.contract.symbol.as_str(),
+            holding.quantity,
+            holding.avg_cost,
+        ),
+        None => (fills.first().map_or("", |fill| fill.symbol.as_str()), 0.0, 0.0),
+    };
+
+    let price = price.unwrap_or(avg_cost);
+    let close = close.unwrap_or(price);
+
+    // Summed from 0.0 rather than with `sum`, which starts from -0.0 and
+    // would send `-0` for a day without fills.
+    let traded = fills
+        .iter()
+        .fold(0.0, |traded, fill| traded + fill.signed_shares());
+    let paid = fills
+        .iter()
+        .fold(0.0, |paid, fill| paid + fill.signed_shares() * fill.price);
+    let realized = fills
+        .iter()
+        .filter_map(|fill| fill.realized_pnl)
+        .fold(0.0, |total, pnl| total + pnl);
+    let held = quantity - traded;
+
+    PositionPnl {
+        con_id: con_id(symbol),
+        quantity,
+        value: quantity * price,
+        pnl: Pnl {
+            daily: quantity * price - held * close - paid,
+            unrealized: (price - avg_cost) * quantity,
+            realized,
+        },
+    }
+}
+
+/// Returns the previous close of `symbol`, or `None` if the feed does not
+/// know it.
+async fn previous_close(db: &Db, symbol: &str) -> Option<f64> {
+    let provider = db.market_data()?;
+
+    match provider.previous_close(symbol).await {
+        Ok(bar) => Some(bar.close),
+        Err(err) => {
+            warn!(%symbol, cause = %err, "failed to get previous close");
+            None
+        }
+    }
+}
+
+/// Returns the start of the trading day `now` is in, midnight in `zone`.
+pub(crate) fn day_start(zone: Tz, now: DateTime<Utc>) -> DateTime<Utc> {
+    let midnight = now.with_timezone(&zone).date_naive().and_time(Default::default());
+
+    // Days that start with a daylight saving change have no midnight, they
+    // start an hour later.
+    zone.from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| {
+            zone.from_local_datetime(&(midnight + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .map_or(now, |start| start.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::{
+        Account, Asset, Broker, Error, Fill, Order, OrderChanges, OrderFilter, OrderRequest, OrderStatus,
+        OrderType, OrderUpdate, Position, Side, TimeInForce,
+    };
+    use crate::config::{AccountConfig, BrokerConfig};
+    use crate::orders::{Contract, STOCK};
+    use crate::Config;
+
+    use async_trait::async_trait;
+    use chrono_tz::America::{Havana, New_York};
+    use std::fs;
+    use std::sync::Arc;
+    use tokio::sync::broadcast;
+
+    /// A broker whose account holds 100 MSFT bought at 10.00, which takes a
+    /// while to report it.
+    #[derive(Debug)]
+    struct Holding100 {
+        updates: broadcast::Sender<OrderUpdate>,
+    }
+
+    #[async_trait]
+    impl Broker for Holding100 {
+        async fn account(&self) -> Result<Account, Error> {
+            Err(Error::NotFound)
+        }
+
+        async fn positions(&self) -> Result<Vec<Position>, Error> {
+            time::sleep(Duration::from_millis(100)).await;
+
+            Ok(vec![Position {
+                symbol: "MSFT".to_string(),
+                exchange: "NASDAQ".to_string(),
+                quantity: 100.0,
+                avg_cost: 10.0,
+                market_price: 10.0,
+                market_value: 1000.0,
+                unrealized_pnl: 0.0,
+            }])
+        }
+
+        async fn asset(&self, _: &str) -> Result<Asset, Error> {
+            Err(Error::NotFound)
+        }
+
+        async fn submit_order(&self, _: &OrderRequest) -> Result<Order, Error> {
+            Err(Error::NotSupported)
+        }
+
+        async fn replace_order(&self, _: &str, _: &OrderChanges) -> Result<Order, Error> {
+            Err(Error::NotSupported)
+        }
+
+        async fn cancel_order(&self, _: &str) -> Result<(), Error> {
+            Err(Error::NotSupported)
+        }
+
+        async fn orders(&self, _: OrderFilter) -> Result<Vec<Order>, Error> {
+            Ok(Vec::new())
+        }
+
+        fn updates(&self) -> broadcast::Receiver<OrderUpdate> {
+            self.updates.subscribe()
+        }
+    }
+
+    /// An execution of `shares` MSFT shares at `price`, negative for a sale.
+    fn execution(shares: f64, price: f64, realized_pnl: Option<f64>) -> Execution {
+        Execution {
+            exec_id: String::new(),
+            time: Utc::now(),
+            account: "DU1".to_string(),
+            client_id: 1,
+            order_id: 1,
+            perm_id: 1,
+            symbol: "MSFT".to_string(),
+            sec_type: STOCK.to_string(),
+            exchange: String::new(),
+            currency: "USD".to_string(),
+            local_symbol: "MSFT".to_string(),
+            side: if shares > 0.0 { "BOT" } else { "SLD" }.to_string(),
+            shares: shares.abs(),
+            price,
+            cum_qty: shares.abs(),
+            avg_price: price,
+            order_ref: String::new(),
+            model_code: String::new(),
+            commission: 0.0,
+            realized_pnl,
+        }
+    }
+
+    fn holding(quantity: f64, avg_cost: f64) -> Holding {
+        Holding {
+            account: "DU1".to_string(),
+            contract: Contract {
+                symbol: "MSFT".to_string(),
+                sec_type: STOCK.to_string(),
+                ..Contract::default()
+            },
+            quantity,
+            avg_cost,
+        }
+    }
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn position_held_since_before_the_day() {
+        let held = holding(100.0, 10.0);
+        let pnl = position_pnl(Some(&held), &[], Some(12.0), Some(11.0));
+
+        assert_eq!((pnl.con_id, pnl.quantity, pnl.value), (con_id("MSFT"), 100.0, 1200.0));
+        assert_eq!(
+            pnl.pnl,
+            Pnl {
+                daily: 100.0,
+                unrealized: 200.0,
+                realized: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn position_opened_during_the_day() {
+        let bought = execution(100.0, 10.0, None);
+        let held = holding(100.0, 10.0);
+
+        // The previous close does not count for what was bought today.
+        let pnl = position_pnl(Some(&held), &[&bought], Some(12.0), Some(11.0));
+        assert_eq!((pnl.pnl.daily, pnl.pnl.unrealized, pnl.pnl.realized), (200.0, 200.0, 0.0));
+    }
+
+    #[test]
+    fn partial_close_with_and_without_a_close() {
+        let sold = execution(-40.0, 13.0, Some(120.0));
+        let held = holding(60.0, 10.0);
+
+        let pnl = position_pnl(Some(&held), &[&sold], Some(12.0), Some(11.0));
+        assert_eq!((pnl.pnl.daily, pnl.pnl.unrealized, pnl.pnl.realized), (140.0, 120.0, 120.0));
+
+        // Without a close, what was held is valued at the last price.
+        let pnl = position_pnl(Some(&held), &[&sold], Some(12.0), None);
+        assert_eq!((pnl.pnl.daily, pnl.pnl.unrealized, pnl.pnl.realized), (40.0, 120.0, 120.0));
+
+        // Without a price either, at its cost.
+        let pnl = position_pnl(Some(&held), &[&sold], None, None);
+        assert_eq!((pnl.value, pnl.pnl.daily, pnl.pnl.unrealized), (600.0, 120.0, 0.0));
+    }
+
+    #[test]
+    fn position_closed_during_the_day() {
+        let sold = execution(-100.0, 12.0, Some(200.0));
+        let pnl = position_pnl(None, &[&sold], Some(12.5), Some(11.0));
+
+        assert_eq!((pnl.con_id, pnl.quantity, pnl.value), (con_id("MSFT"), 0.0, 0.0));
+        assert_eq!((pnl.pnl.daily, pnl.pnl.unrealized, pnl.pnl.realized), (100.0, 0.0, 200.0));
+    }
+
+    #[test]
+    fn day_starts_at_midnight_in_the_zone() {
+        // Eastern standard time, and the evening before in New York.
+        assert_eq!(day_start(New_York, utc("2024-03-08T15:00:00Z")), utc("2024-03-08T05:00:00Z"));
+        assert_eq!(day_start(New_York, utc("2024-03-09T04:30:00Z")), utc("2024-03-08T05:00:00Z"));
+
+        // The days clocks change on still start at midnight, at the offset
+        // before the change.
+        assert_eq!(day_start(New_York, utc("2024-03-10T15:00:00Z")), utc("2024-03-10T05:00:00Z"));
+        assert_eq!(day_start(New_York, utc("2024-11-03T15:00:00Z")), utc("2024-11-03T04:00:00Z"));
+        assert_eq!(day_start(New_York, utc("2024-11-04T15:00:00Z")), utc("2024-11-04T05:00:00Z"));
+    }
+
+    #[test]
+    fn day_without_a_midnight_starts_an_hour_later() {
+        // Havana moved its clocks from midnight to 01:00 on 10 March 2024.
+        assert_eq!(day_start(Havana, utc("2024-03-10T15:00:00Z")), utc("2024-03-10T05:00:00Z"));
+        assert_eq!(day_start(Havana, utc("2024-03-09T15:00:00Z")), utc("2024-03-09T05:00:00Z"));
+    }
+
+    #[tokio::test]
+    async fn fill_before_the_first_request_realizes_pnl() {
+        let data_dir = std::env::temp_dir().join(format!("pnl_early_fill_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        fs::create_dir_all(&data_dir).unwrap();
+
+        let config = Config {
+            data_dir,
+            accounts: vec![AccountConfig {
+                id: "DU1".to_string(),
+                currency: "USD".to_string(),
+                broker: BrokerConfig::Paper { cash: 0.0 },
+            }],
+            ..Config::default()
+        };
+
+        let broker = Arc::new(Holding100 {
+            updates: broadcast::channel(16).0,
+        });
+        let brokers = HashMap::from([("DU1".to_string(), broker.clone() as Arc<dyn Broker>)]);
+        let db = Db::with_brokers(config, brokers).unwrap();
+
+        // Sold in another application, while the positions are loading.
+        let now = Utc::now();
+        let sold = Order {
+            id: "broker-1".to_string(),
+            client_order_id: "web-1".to_string(),
+            symbol: "MSFT".to_string(),
+            side: Side::Sell,
+            quantity: 40.0,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Day,
+            limit_price: None,
+            stop_price: None,
+            trail: None,
+            extended_hours: false,
+            status: OrderStatus::Filled,
+            filled_quantity: 40.0,
+            filled_avg_price: Some(12.0),
+            replaces: None,
+            created_at: now,
+            updated_at: now,
+        };
+        let fill = Fill {
+            execution_id: "fill-1".to_string(),
+            price: 12.0,
+            quantity: 40.0,
+            position_quantity: 60.0,
+            timestamp: now,
+        };
+        broker.updates.send(OrderUpdate { order: sold, fill: Some(fill) }).unwrap();
+
+        let executions = time::timeout(Duration::from_secs(5), async {
+            loop {
+                let executions = db.executions(|_| true);
+                if !executions.is_empty() {
+                    return executions;
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(executions[0].realized_pnl, Some(80.0));
+
+        let mut watch = PnlWatch::new(db, "DU1".to_string());
+        let positions = watch.positions().await.unwrap();
+
+        let msft = &positions["MSFT"];
+        assert_eq!((msft.quantity, msft.pnl.realized, msft.pnl.unrealized), (60.0, 80.0, 0.0));
+    }
+}
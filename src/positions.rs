@@ -0,0 +1,254 @@
+use crate::broker;
+use crate::executions::Execution;
+use crate::messages::{Encoder, OutgoingMessage};
+use crate::orders::{con_id, Contract, STOCK};
+use crate::Frame;
+
+use std::collections::{BTreeMap, HashMap};
+
+/// A position in one account, as reported in `position`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Holding {
+    pub(crate) account: String,
+    pub(crate) contract: Contract,
+
+    /// Negative for short positions.
+    pub(crate) quantity: f64,
+
+    /// Average price paid per share.
+    pub(crate) avg_cost: f64,
+}
+
+/// The positions of every account.
+///
+/// The positions of an account are loaded from its broker before its fills
+/// are followed. From then on they are kept up to date from the fills, so they
+/// change as soon as an order fills and the broker is not polled.
+#[derive(Debug, Default)]
+pub(crate) struct Positions {
+    /// Open positions by account and symbol, for the accounts loaded so far.
+    accounts: HashMap<String, BTreeMap<String, Holding>>,
+}
+
+impl Holding {
+    /// Encode the `position` message describing the position.
+    pub(crate) fn position_frame(&self, server_version: u16) -> Frame {
+        let contract = &self.contract;
+
+        Encoder::new(OutgoingMessage::PositionData, server_version)
+            .put("account", &self.account)
+            .put("conId", contract.con_id)
+            .put("symbol", &contract.symbol)
+            .put("secType", &contract.sec_type)
+            .put(
+                "lastTradeDateOrContractMonth",
+                &contract.last_trade_date_or_contract_month,
+            )
+            .put("strike", contract.strike.unwrap_or_default())
+            .put("right", &contract.right)
+            .put("multiplier", &contract.multiplier)
+            .put("exchange", &contract.exchange)
+            .put("currency", &contract.currency)
+            .put("localSymbol", &contract.local_symbol)
+            .put("tradingClass", &contract.trading_class)
+            .put("position", self.quantity)
+            .put("avgCost", self.avg_cost)
+            .into_frame()
+    }
+}
+
+impl Positions {
+    pub(crate) fn new() -> Positions {
+        Positions::default()
+    }
+
+    /// Returns the open positions of `account`, or `None` if they were not
+    /// loaded yet.
+    pub(crate) fn get(&self, account: &str) -> Option<Vec<Holding>> {
+        let holdings = self.accounts.get(account)?;
+        Some(holdings.values().cloned().collect())
+    }
+
+    /// Take the positions the broker of `account`, held in `currency`, reports
+    /// as the starting point, unless they were loaded before.
+    pub(crate) fn load(&mut self, account: &str, currency: &str, positions: &[broker::Position]) {
+        self.accounts.entry(account.to_string()).or_insert_with(|| {
+            positions
+                .iter()
+                .filter(|position| position.quantity != 0.0)
+                .map(|position| {
+                    let holding = Holding {
+                        account: account.to_string(),
+                        contract: contract(&position.symbol, &position.exchange, currency),
+                        quantity: position.quantity,
+                        avg_cost: position.avg_cost,
+                    };
+                    (position.symbol.clone(), holding)
+                })
+                .collect()
+        });
+    }
+
+    /// Returns the profit or loss `execution` realizes, or `None` if it does
+    /// not reduce a position or the positions of the account are not loaded.
+    pub(crate) fn realized_pnl(&self, execution: &Execution) -> Option<f64> {
+        let holding = self.accounts.get(&execution.account)?.get(&execution.symbol)?;
+        let shares = execution.signed_shares();
+
+        if shares.signum() == holding.quantity.signum() {
+            return None;
+        }
+
+        let closed = shares.abs().min(holding.quantity.abs());
+        Some(closed * (execution.price - holding.avg_cost) * holding.quantity.signum())
+    }
+
+    /// Take in `execution`. The average cost only changes when the position
+    /// grows, or when it is reversed, the new position then costing the
+    /// execution price.
+    ///
+    /// Returns the position after the execution, with a quantity of 0 if it
+    /// was closed, or `None` if the positions of the account are not loaded.
+    pub(crate) fn apply(&mut self, execution: &Execution) -> Option<Holding> {
+        let holdings = self.accounts.get_mut(&execution.account)?;
+
+        let holding = holdings
+            .entry(execution.symbol.clone())
+            .or_insert_with(|| Holding {
+                account: execution.account.clone(),
+                contract: contract(&execution.symbol, "", &execution.currency),
+                quantity: 0.0,
+                avg_cost: 0.0,
+            });
+
+        let shares = execution.signed_shares();
+
+        let before = holding.quantity;
+        let after = before + shares;
+
+        if before == 0.0 || (after != 0.0 && after.signum() != before.signum()) {
+            holding.avg_cost = execution.price;
+        } else if after.abs() > before.abs() {
+            holding.avg_cost =
+                (before.abs() * holding.avg_cost + shares.abs() * execution.price) / after.abs();
+        }
+
+        holding.quantity = after;
+
+        if after == 0.0 {
+            let mut closed = holdings.remove(&execution.symbol)?;
+            closed.avg_cost = 0.0;
+            return Some(closed);
+        }
+
+        Some(holding.clone())
+    }
+}
+
+/// Returns the contract of a stock position.
+fn contract(symbol: &str, exchange: &str, currency: &str) -> Contract {
+    Contract {
+        con_id: con_id(symbol),
+        symbol: symbol.to_string(),
+        sec_type: STOCK.to_string(),
+        exchange: exchange.to_string(),
+        currency: currency.to_string(),
+        local_symbol: symbol.to_string(),
+        ..Contract::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Utc;
+
+    const ACCOUNT: &str = "DU1";
+
+    /// An execution of `shares` MSFT shares at `price`, negative for a sale.
+    fn execution(shares: f64, price: f64) -> Execution {
+        Execution {
+            exec_id: String::new(),
+            time: Utc::now(),
+            account: ACCOUNT.to_string(),
+            client_id: 1,
+            order_id: 1,
+            perm_id: 1,
+            symbol: "MSFT".to_string(),
+            sec_type: STOCK.to_string(),
+            exchange: String::new(),
+            currency: "USD".to_string(),
+            local_symbol: "MSFT".to_string(),
+            side: if shares > 0.0 { "BOT" } else { "SLD" }.to_string(),
+            shares: shares.abs(),
+            price,
+            cum_qty: shares.abs(),
+            avg_price: price,
+            order_ref: String::new(),
+            model_code: String::new(),
+            commission: 0.0,
+            realized_pnl: None,
+        }
+    }
+
+    /// Take in `execution`, returning the P&L it realizes and the position
+    /// after it.
+    fn fill(positions: &mut Positions, shares: f64, price: f64) -> (Option<f64>, f64, f64) {
+        let execution = execution(shares, price);
+        let realized = positions.realized_pnl(&execution);
+        let holding = positions.apply(&execution).unwrap();
+
+        (realized, holding.quantity, holding.avg_cost)
+    }
+
+    #[test]
+    fn fills_open_add_reduce_reverse_and_close() {
+        let mut positions = Positions::new();
+        positions.load(ACCOUNT, "USD", &[]);
+
+        // Open, then add at a higher price.
+        assert_eq!(fill(&mut positions, 100.0, 10.0), (None, 100.0, 10.0));
+        assert_eq!(fill(&mut positions, 100.0, 12.0), (None, 200.0, 11.0));
+
+        // A partial close realizes the gain over the average cost, which
+        // stays.
+        assert_eq!(fill(&mut positions, -50.0, 15.0), (Some(200.0), 150.0, 11.0));
+
+        // Reversing realizes the loss on what was held, the short position
+        // costs the execution price.
+        assert_eq!(fill(&mut positions, -250.0, 9.0), (Some(-300.0), -100.0, 9.0));
+
+        // Covering the short below its cost is a gain, and leaves no position.
+        assert_eq!(fill(&mut positions, 100.0, 8.0), (Some(100.0), 0.0, 0.0));
+        assert_eq!(positions.get(ACCOUNT), Some(Vec::new()));
+    }
+
+    #[test]
+    fn positions_start_from_the_broker() {
+        let mut positions = Positions::new();
+        assert_eq!(positions.apply(&execution(100.0, 10.0)), None);
+        assert_eq!(positions.get(ACCOUNT), None);
+
+        let reported = broker::Position {
+            symbol: "MSFT".to_string(),
+            exchange: "NASDAQ".to_string(),
+            quantity: 100.0,
+            avg_cost: 10.0,
+            market_price: 10.0,
+            market_value: 1000.0,
+            unrealized_pnl: 0.0,
+        };
+        positions.load(ACCOUNT, "USD", &[reported]);
+
+        let holdings = positions.get(ACCOUNT).unwrap();
+        assert_eq!(holdings.len(), 1);
+        assert_eq!((holdings[0].quantity, holdings[0].avg_cost), (100.0, 10.0));
+        assert_eq!(holdings[0].contract.exchange, "NASDAQ");
+
+        // Loading again keeps the positions followed from the fills.
+        assert_eq!(fill(&mut positions, -40.0, 12.0), (Some(80.0), 60.0, 10.0));
+        positions.load(ACCOUNT, "USD", &[]);
+        assert_eq!(positions.get(ACCOUNT).unwrap()[0].quantity, 60.0);
+    }
+}
@@ -1,16 +1,16 @@
-//! Minimal Redis server implementation
+//! Minimal TWS API server implementation
 //!
 //! Provides an async `run` function that listens for inbound connections,
 //! spawning a task per connection.
 
-use crate::{Command, Connection, Db, DbDropGuard, Shutdown};
+use crate::{Admission, Command, Config, Connection, Db, Frame, SessionState, Shutdown, Subscriptions};
 
 use std::future::Future;
 use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{broadcast, mpsc, Semaphore};
 use tokio::time::{self, Duration};
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
 /// Server listener state. Created in the `run` call. It includes a `run` method
 /// which performs the TCP listening and initialization of per-connection state.
@@ -18,12 +18,9 @@ use tracing::{debug, error, info, instrument};
 struct Listener {
     /// Shared database handle.
     ///
-    /// Contains the key / value store as well as the broadcast channels for
-    /// pub/sub.
-    ///
-    /// This holds a wrapper around an `Arc`. The internal `Db` can be
-    /// retrieved and passed into the per connection state (`Handler`).
-    db_holder: DbDropGuard,
+    /// Holds the state shared by every connection, such as the client ids in
+    /// use. A clone is passed into the per connection state (`Handler`).
+    db: Db,
 
     /// TCP listener supplied by the `run` caller.
     listener: TcpListener,
@@ -64,17 +61,16 @@ struct Listener {
 }
 
 /// Per-connection handler. Reads requests from `connection` and applies the
-/// commands to `db`.
+/// commands.
 #[derive(Debug)]
 struct Handler {
     /// Shared database handle.
     ///
     /// When a command is received from `connection`, it is applied with `db`.
-    /// The implementation of the command is in the `cmd` module. Each command
-    /// will need to interact with `db` in order to complete the work.
+    /// The implementation of the command is in the `cmd` module.
     db: Db,
 
-    /// The TCP connection decorated with the redis protocol encoder / decoder
+    /// The TCP connection decorated with the TWS protocol encoder / decoder
     /// implemented using a buffered `TcpStream`.
     ///
     /// When `Listener` receives an inbound connection, the `TcpStream` is
@@ -93,11 +89,33 @@ struct Handler {
     /// which point the connection is terminated.
     shutdown: Shutdown,
 
+    /// Where the connection is in the handshake / `startApi` sequence.
+    ///
+    /// Requests are only applied once the session is `Ready`. Anything that
+    /// arrives out of order is answered with the error TWS would send.
+    state: SessionState,
+
+    /// Streams the client subscribed to, such as account summaries.
+    ///
+    /// They are stopped when the handler is dropped.
+    subscriptions: Subscriptions,
+
+    /// Frames produced by the tasks in `subscriptions`, waiting to be written
+    /// to `connection`.
+    updates: mpsc::Receiver<Frame>,
+
+    /// Spaces out requests from clients that connected with `+PACEAPI`.
+    ///
+    /// TWS rejects clients that send more than `MAX_REQUESTS_PER_SECOND`
+    /// messages per second unless they ask to be paced. Paced clients have
+    /// their requests processed at that rate instead.
+    pacer: time::Interval,
+
     /// Not used directly. Instead, when `Handler` is dropped...?
     _shutdown_complete: mpsc::Sender<()>,
 }
 
-/// Maximum number of concurrent connections the redis server will accept.
+/// Maximum number of concurrent connections the server will accept.
 ///
 /// When this limit is reached, the server will stop accepting connections until
 /// an active connection terminates.
@@ -111,7 +129,10 @@ struct Handler {
 /// well).
 const MAX_CONNECTIONS: usize = 250;
 
-/// Run the mini-redis server.
+/// Rate at which requests from a `+PACEAPI` client are processed.
+const MAX_REQUESTS_PER_SECOND: u64 = 50;
+
+/// Run the TWS API server.
 ///
 /// Accepts connections from the supplied listener. For each inbound connection,
 /// a task is spawned to handle that connection. The server runs until the
@@ -120,7 +141,11 @@ const MAX_CONNECTIONS: usize = 250;
 ///
 /// `tokio::signal::ctrl_c()` can be used as the `shutdown` argument. This will
 /// listen for a SIGINT signal.
-pub async fn run(listener: TcpListener, shutdown: impl Future) {
+///
+/// `config` lists the accounts to serve. State that must survive a restart,
+/// such as the order ids each client has used, is kept in its data directory.
+/// An error is returned if that state cannot be loaded.
+pub async fn run(listener: TcpListener, config: Config, shutdown: impl Future) -> crate::Result<()> {
     // When the provided `shutdown` future completes, we must send a shutdown
     // message to all active connections. We use a broadcast channel for this
     // purpose. The call below ignores the receiver of the broadcast pair, and when
@@ -132,7 +157,7 @@ pub async fn run(listener: TcpListener, shutdown: impl Future) {
     // Initialize the listener state
     let mut server = Listener {
         listener,
-        db_holder: DbDropGuard::new(),
+        db: Db::open(config)?,
         limit_connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
         notify_shutdown,
         shutdown_complete_tx,
@@ -195,6 +220,8 @@ pub async fn run(listener: TcpListener, shutdown: impl Future) {
     // `Sender` instances are held by connection handler tasks. When those drop,
     // the `mpsc` channel will close and `recv()` will return `None`.
     let _ = shutdown_complete_rx.recv().await;
+
+    Ok(())
 }
 
 impl Listener {
@@ -237,18 +264,27 @@ impl Listener {
             // error here is non-recoverable.
             let socket = self.accept().await?;
 
+            let (subscriptions, updates) = Subscriptions::new();
+
             // Create the necessary per-connection handler state.
             let mut handler = Handler {
                 // Get a handle to the shared database.
-                db: self.db_holder.db(),
+                db: self.db.clone(),
 
                 // Initialize the connection state. This allocates read/write
-                // buffers to perform redis protocol frame parsing.
+                // buffers to perform TWS protocol frame parsing.
                 connection: Connection::new(socket),
 
                 // Receive shutdown notifications.
                 shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
 
+                state: SessionState::AwaitingHandshake,
+
+                subscriptions,
+                updates,
+
+                pacer: pacer(),
+
                 // Notifies the receiver half once all clones are
                 // dropped.
                 _shutdown_complete: self.shutdown_complete_tx.clone(),
@@ -301,6 +337,13 @@ impl Listener {
     }
 }
 
+/// Returns an interval that ticks `MAX_REQUESTS_PER_SECOND` times a second.
+fn pacer() -> time::Interval {
+    let mut pacer = time::interval(Duration::from_millis(1000 / MAX_REQUESTS_PER_SECOND));
+    pacer.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    pacer
+}
+
 impl Handler {
     /// Process a single connection.
     ///
@@ -316,13 +359,17 @@ impl Handler {
     /// it reaches a safe state, at which point it is terminated.
     #[instrument(skip(self))]
     async fn run(&mut self) -> crate::Result<()> {
-        // As long as the shutdown signal has not been received, try to read a
-        // new request frame.
-        while !self.shutdown.is_shutdown() {
+        // As long as the shutdown signal has not been received and the
+        // session is not being closed, try to read a new request frame.
+        while !self.shutdown.is_shutdown() && self.state != SessionState::Closing {
             // While reading a request frame, also listen for the shutdown
-            // signal.
+            // signal and write out what the subscriptions produce.
             let maybe_frame = tokio::select! {
                 res = self.connection.read_frame() => res?,
+                Some(update) = self.updates.recv() => {
+                    self.connection.write_frame(&update).await?;
+                    continue;
+                }
                 _ = self.shutdown.recv() => {
                     // If a shutdown signal is received, return from `run`.
                     // This will result in the task terminating.
@@ -330,8 +377,6 @@ impl Handler {
                 }
             };
 
-            info!("After let maybe_frame");
-
             // If `None` is returned from `read_frame()` then the peer closed
             // the socket. There is no further work to do and the task can be
             // terminated.
@@ -340,14 +385,13 @@ impl Handler {
                 None => return Ok(()),
             };
 
-            info!("After let frame");
-
-            // Convert the redis frame into a command struct. This returns an
-            // error if the frame is not a valid redis command or it is an
-            // unsupported command.
-            let cmd = Command::from_frame(frame)?;
+            if !self.admit(self.state.admit_frame(&frame)).await? {
+                continue;
+            }
 
-            info!("After let cmd");
+            // Convert the TWS frame into a command struct. This returns an
+            // error if the frame is not a valid TWS request.
+            let cmd = Command::from_frame(frame, self.connection.server_version())?;
 
             // Logs the `cmd` object. The syntax here is a shorthand provided by
             // the `tracing` crate. It can be thought of as similar to:
@@ -360,17 +404,51 @@ impl Handler {
             // as key-value pairs.
             debug!(?cmd);
 
-            // Perform the work needed to apply the command. This may mutate the
-            // database state as a result.
+            if !self.admit(self.state.admit(&cmd)).await? {
+                continue;
+            }
+
+            if self.connection.pace_api() {
+                self.pacer.tick().await;
+            }
+
+            // The transition depends on the command, which `apply` consumes.
+            let next = self.state.next(&cmd);
+
+            // Perform the work needed to apply the command.
             //
             // The connection is passed into the apply function which allows the
-            // command to write response frames directly to the connection. In
-            // the case of pub/sub, multiple frames may be send back to the
-            // peer.
-            cmd.apply(&mut self.connection, &mut self.shutdown)
-                .await?;
+            // command to write response frames directly to the connection. A
+            // single request may produce several response messages.
+            cmd.apply(&self.db, &mut self.connection, &mut self.subscriptions).await?;
+
+            self.state = next.confirm(&self.connection);
         }
 
         Ok(())
     }
+
+    /// Act on the session's decision about an incoming frame or command.
+    ///
+    /// Returns `true` if it should be processed. Otherwise the error has been
+    /// sent to the client and, if the decision was to close, the session moves
+    /// to `Closing`.
+    async fn admit(&mut self, admission: Admission) -> crate::Result<bool> {
+        let (error, close) = match admission {
+            Admission::Apply => return Ok(true),
+            Admission::Reject(error) => (error, false),
+            Admission::Close(error) => (error, true),
+        };
+
+        warn!(code = error.code, state = ?self.state, "request out of sequence");
+
+        let response = error.to_frame(-1, self.connection.server_version());
+        self.connection.write_frame(&response).await?;
+
+        if close {
+            self.state = SessionState::Closing;
+        }
+
+        Ok(false)
+    }
 }
@@ -0,0 +1,167 @@
+use crate::messages::errors::{self, TwsError};
+use crate::{Command, Connection, Frame};
+
+/// Where a connection is in the TWS session lifecycle.
+///
+/// A client first sends the `API\0` handshake, then `startApi` (71), and only
+/// then regular requests. TWS answers requests that arrive out of order with
+/// an error instead of acting on them, and so does the connector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SessionState {
+    /// Waiting for the `API\0` version negotiation.
+    AwaitingHandshake,
+
+    /// A version has been negotiated, waiting for `startApi`.
+    AwaitingStartApi,
+
+    /// The session is established and requests are processed.
+    Ready,
+
+    /// The connection is being closed. No further frames are read.
+    Closing,
+}
+
+/// What to do with a frame or command in the current state.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Admission {
+    /// Process it.
+    Apply,
+
+    /// Report the error to the client and drop the request.
+    Reject(TwsError),
+
+    /// Report the error to the client and close the connection.
+    Close(TwsError),
+}
+
+impl SessionState {
+    /// Decide whether a raw `frame` may be decoded at all.
+    ///
+    /// Before the handshake, anything other than the `API\0` prelude means the
+    /// client is not speaking the TWS protocol, so the connection is closed
+    /// without trying to decode the frame.
+    pub(crate) fn admit_frame(self, frame: &Frame) -> Admission {
+        match (self, frame) {
+            (SessionState::AwaitingHandshake, Frame::Message(_)) => {
+                Admission::Close(errors::NOT_CONNECTED)
+            }
+            _ => Admission::Apply,
+        }
+    }
+
+    /// Decide whether `cmd` may be applied in this state.
+    pub(crate) fn admit(self, cmd: &Command) -> Admission {
+        use SessionState::*;
+
+        match (self, cmd) {
+            (AwaitingHandshake, Command::Api(_)) => Admission::Apply,
+            (AwaitingHandshake, _) => Admission::Close(errors::NOT_CONNECTED),
+            (AwaitingStartApi | Ready, Command::Api(_)) => Admission::Reject(errors::ALREADY_CONNECTED),
+            (AwaitingStartApi, Command::NextValidOrderId(_)) => Admission::Apply,
+            (AwaitingStartApi, _) => Admission::Reject(errors::NOT_CONNECTED),
+            (Ready, Command::NextValidOrderId(_)) => Admission::Reject(errors::ALREADY_CONNECTED),
+            (Ready, _) => Admission::Apply,
+            (Closing, _) => Admission::Reject(errors::NOT_CONNECTED),
+        }
+    }
+
+    /// The state once `cmd` has been applied.
+    pub(crate) fn next(self, cmd: &Command) -> SessionState {
+        match (self, cmd) {
+            (SessionState::AwaitingHandshake, Command::Api(_)) => SessionState::AwaitingStartApi,
+            (SessionState::AwaitingStartApi, Command::NextValidOrderId(_)) => SessionState::Ready,
+            (state, _) => state,
+        }
+    }
+
+    /// Check the state reached by `next` against the outcome on `dst`.
+    ///
+    /// The handshake fails when no common version exists, and `startApi`
+    /// fails when the client id is already in use. In both cases the client
+    /// has already been told so and nothing else can be done with it.
+    pub(crate) fn confirm(self, dst: &Connection) -> SessionState {
+        match self {
+            SessionState::AwaitingHandshake | SessionState::Closing => self,
+            _ if !dst.is_negotiated() => SessionState::Closing,
+            SessionState::Ready if dst.client_id().is_none() => SessionState::Closing,
+            _ => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::{Api, NextValidOrderId};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use SessionState::*;
+
+    fn api() -> Command {
+        Command::Api(Api::new("v100..176"))
+    }
+
+    fn start_api() -> Command {
+        Command::NextValidOrderId(NextValidOrderId::new(2, 1))
+    }
+
+    /// `reqManagedAccts`, a regular request.
+    fn request() -> Command {
+        let mut frame = Frame::message();
+        frame.push_str("17");
+        frame.push_str("1");
+        Command::from_frame(frame, crate::API_VERSION).unwrap()
+    }
+
+    /// Returns the connector's end of a connection.
+    async fn connection() -> Connection {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        Connection::new(listener.accept().await.unwrap().0)
+    }
+
+    #[test]
+    fn messages_before_the_handshake_close_the_connection() {
+        assert_eq!(AwaitingHandshake.admit_frame(&Frame::message()), Admission::Close(errors::NOT_CONNECTED));
+        assert_eq!(AwaitingHandshake.admit(&request()), Admission::Close(errors::NOT_CONNECTED));
+        assert_eq!(AwaitingHandshake.admit(&start_api()), Admission::Close(errors::NOT_CONNECTED));
+
+        let handshake = Frame::Handshake(bytes::Bytes::from_static(b"v100..176"));
+        assert_eq!(AwaitingHandshake.admit_frame(&handshake), Admission::Apply);
+        assert_eq!(AwaitingHandshake.admit(&api()), Admission::Apply);
+        assert_eq!(AwaitingHandshake.next(&api()), AwaitingStartApi);
+    }
+
+    #[test]
+    fn requests_before_start_api_are_not_connected() {
+        assert_eq!(AwaitingStartApi.admit_frame(&Frame::message()), Admission::Apply);
+        assert_eq!(AwaitingStartApi.admit(&request()), Admission::Reject(errors::NOT_CONNECTED));
+        assert_eq!(AwaitingStartApi.next(&request()), AwaitingStartApi);
+        assert_eq!(AwaitingStartApi.admit(&api()), Admission::Reject(errors::ALREADY_CONNECTED));
+
+        assert_eq!(AwaitingStartApi.admit(&start_api()), Admission::Apply);
+        assert_eq!(AwaitingStartApi.next(&start_api()), Ready);
+
+        assert_eq!(Ready.admit(&request()), Admission::Apply);
+        assert_eq!(Ready.admit(&start_api()), Admission::Reject(errors::ALREADY_CONNECTED));
+        assert_eq!(Ready.next(&start_api()), Ready);
+    }
+
+    #[tokio::test]
+    async fn failed_handshake_or_start_api_closes() {
+        let mut dst = connection().await;
+
+        // No common version.
+        assert_eq!(AwaitingStartApi.confirm(&dst), Closing);
+        assert_eq!(AwaitingHandshake.confirm(&dst), AwaitingHandshake);
+
+        dst.set_server_version(crate::API_VERSION);
+        assert_eq!(AwaitingStartApi.confirm(&dst), AwaitingStartApi);
+
+        // The client id is taken.
+        assert_eq!(Ready.confirm(&dst), Closing);
+
+        assert_eq!(Closing.confirm(&dst), Closing);
+        assert_eq!(Closing.admit(&request()), Admission::Reject(errors::NOT_CONNECTED));
+    }
+}
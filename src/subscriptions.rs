@@ -0,0 +1,160 @@
+use crate::ticker::MarketDataType;
+use crate::Frame;
+
+use std::collections::HashMap;
+use std::future::Future;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// Maximum number of frames subscription tasks may queue for one connection
+/// before they have to wait for the handler to write them out.
+const MAX_PENDING_FRAMES: usize = 1024;
+
+/// Kind of stream a client subscribed to.
+///
+/// TWS request ids are only unique per kind of request, e.g. an account
+/// summary and a market data request may both use id 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Topic {
+    AccountSummary,
+
+    /// The values and portfolio of one account, after `reqAccountUpdates`.
+    AccountUpdates,
+
+    /// The changes of the client's orders, for the whole session.
+    Orders,
+
+    /// The changes of the orders placed without going through the connector,
+    /// after `reqAutoOpenOrders`.
+    AutoOpenOrders,
+
+    /// The positions of all accounts, after `reqPositions`.
+    Positions,
+
+    /// The P&L of an account, after `reqPnL`.
+    Pnl,
+
+    /// The P&L of one position, after `reqPnLSingle`.
+    PnlSingle,
+
+    /// The ticks of one contract, after `reqMktData`.
+    MktData,
+
+    /// The order book of one contract, after `reqMktDepth`.
+    MktDepth,
+}
+
+/// The streams a connection is subscribed to.
+///
+/// Each subscription is a task that pushes frames to the connection until it
+/// is cancelled. The frames are not written by the task itself, they are sent
+/// to the connection's `Handler`, which writes them between responses to
+/// requests, so messages never interleave on the socket.
+///
+/// All tasks are stopped when the `Subscriptions` value is dropped, that is
+/// when the connection closes.
+#[derive(Debug)]
+pub(crate) struct Subscriptions {
+    /// Handed to every task to send frames to the handler.
+    sender: mpsc::Sender<Frame>,
+
+    /// Running tasks by topic and request id.
+    tasks: HashMap<(Topic, i64), JoinHandle<()>>,
+
+    /// The kind of market data the client asked for. Market data streams
+    /// follow its changes.
+    market_data_type: watch::Sender<MarketDataType>,
+}
+
+/// Sends frames from a subscription task to its connection.
+pub(crate) type Sink = mpsc::Sender<Frame>;
+
+impl Subscriptions {
+    /// Create an empty set of subscriptions, along with the receiver the
+    /// handler reads their frames from.
+    pub(crate) fn new() -> (Subscriptions, mpsc::Receiver<Frame>) {
+        let (sender, receiver) = mpsc::channel(MAX_PENDING_FRAMES);
+
+        let subscriptions = Subscriptions {
+            sender,
+            tasks: HashMap::new(),
+            market_data_type: watch::channel(MarketDataType::default()).0,
+        };
+
+        (subscriptions, receiver)
+    }
+
+    /// Returns `true` if `req_id` is in use by a running `topic` stream.
+    pub(crate) fn contains(&self, topic: Topic, req_id: i64) -> bool {
+        self.tasks
+            .get(&(topic, req_id))
+            .is_some_and(|task| !task.is_finished())
+    }
+
+    /// Returns the number of running `topic` streams.
+    pub(crate) fn count(&self, topic: Topic) -> usize {
+        self.tasks
+            .iter()
+            .filter(|((t, _), task)| *t == topic && !task.is_finished())
+            .count()
+    }
+
+    /// Start the stream `req_id` of `topic`.
+    ///
+    /// `stream` is called with the sink to write to and runs as its own task.
+    /// A finished stream with the same id is replaced, callers check
+    /// `contains` first to reject duplicate ids of running ones.
+    pub(crate) fn spawn<F, Fut>(&mut self, topic: Topic, req_id: i64, stream: F)
+    where
+        F: FnOnce(Sink) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        debug!(?topic, req_id, "starting subscription");
+
+        self.tasks.retain(|_, task| !task.is_finished());
+
+        let task = tokio::spawn(stream(self.sender.clone()));
+
+        if let Some(previous) = self.tasks.insert((topic, req_id), task) {
+            previous.abort();
+        }
+    }
+
+    /// Returns a receiver of the kind of market data the client asked for,
+    /// marked as seen.
+    pub(crate) fn market_data_type(&self) -> watch::Receiver<MarketDataType> {
+        self.market_data_type.subscribe()
+    }
+
+    /// Switch the market data streams to `data_type`.
+    pub(crate) fn set_market_data_type(&self, data_type: MarketDataType) {
+        self.market_data_type.send_if_modified(|current| {
+            let changed = *current != data_type;
+            *current = data_type;
+            changed
+        });
+    }
+
+    /// Stop the stream `req_id` of `topic`.
+    ///
+    /// Returns `false` if there is no such stream.
+    pub(crate) fn cancel(&mut self, topic: Topic, req_id: i64) -> bool {
+        match self.tasks.remove(&(topic, req_id)) {
+            Some(task) => {
+                debug!(?topic, req_id, "cancelling subscription");
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::market_data::{MarketDataProvider, MarketEvent, Side, Subscription};
+use crate::ticker::DELAY;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::{self, Duration, Instant};
+use tracing::warn;
+
+/// Number of updates a subscriber may fall behind before it misses some.
+const EVENT_CAPACITY: usize = 1024;
+
+/// Most updates held back per symbol. Past it, the oldest second of updates
+/// is dropped.
+const BUFFER_CAPACITY: usize = 65_536;
+
+/// Updates of the market data feed, `DELAY` late.
+///
+/// Every symbol is followed once for all the delayed subscribers, by a task
+/// holding back its updates. The updates are held in one-second steps, in
+/// which a quote replaces the earlier ones, and so does the depth of a
+/// venue, so a busy symbol holds back at most one quote a second besides its
+/// trades and bars.
+#[derive(Debug)]
+pub(crate) struct DelayedFeed {
+    provider: Arc<dyn MarketDataProvider>,
+
+    /// Followed symbols and their subscribers, shared with the release of
+    /// the subscriptions.
+    channels: Arc<Mutex<Channels>>,
+}
+
+#[derive(Debug, Default)]
+struct Channels {
+    by_symbol: HashMap<String, Channel>,
+
+    /// Id of the last channel opened.
+    last_id: u64,
+}
+
+/// Fan-out of the delayed updates of one symbol.
+#[derive(Debug)]
+struct Channel {
+    /// Tells the releases of the subscriptions of an earlier channel of the
+    /// symbol apart.
+    id: u64,
+    sender: broadcast::Sender<MarketEvent>,
+
+    /// Number of live `Subscription` values for the symbol.
+    subscribers: usize,
+
+    /// Dropped with the channel, which stops its task.
+    _stop: oneshot::Sender<()>,
+}
+
+/// What a later update of the same second replaces.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Slot {
+    Quote,
+    Depth(Side, String),
+}
+
+/// The updates that arrived during one second.
+#[derive(Debug)]
+struct Second {
+    started: Instant,
+    events: Vec<MarketEvent>,
+
+    /// Position in `events` of the update holding each slot.
+    slots: HashMap<Slot, usize>,
+}
+
+impl DelayedFeed {
+    pub(crate) fn new(provider: Arc<dyn MarketDataProvider>) -> DelayedFeed {
+        DelayedFeed {
+            provider,
+            channels: Arc::new(Mutex::new(Channels::default())),
+        }
+    }
+
+    /// Subscribe to the updates of `symbol`, `DELAY` late. The first
+    /// subscriber of a symbol subscribes to the feed.
+    pub(crate) async fn subscribe(&self, symbol: &str) -> crate::Result<Subscription> {
+        // The lock cannot be held while subscribing. Unless the symbol has no
+        // channel yet, the subscription is released right away, the feed
+        // already streams the symbol to the channel's task.
+        let live = self.provider.subscribe(symbol).await?;
+
+        let (id, events) = {
+            let mut channels = self.channels.lock().unwrap();
+            let Channels { by_symbol, last_id } = &mut *channels;
+
+            let channel = by_symbol.entry(symbol.to_string()).or_insert_with(|| {
+                *last_id += 1;
+                let sender = broadcast::channel(EVENT_CAPACITY).0;
+                let (stop, stopped) = oneshot::channel();
+
+                let feed = (self.channels.clone(), *last_id);
+                tokio::spawn(hold_back(live, sender.clone(), stopped, feed));
+
+                Channel {
+                    id: *last_id,
+                    sender,
+                    subscribers: 0,
+                    _stop: stop,
+                }
+            });
+
+            channel.subscribers += 1;
+            (channel.id, channel.sender.subscribe())
+        };
+
+        let channels = self.channels.clone();
+        let released = symbol.to_string();
+
+        Ok(Subscription::new(symbol, events, move || {
+            let mut channels = channels.lock().unwrap();
+
+            if let Some(channel) = channels.by_symbol.get_mut(&released).filter(|channel| channel.id == id) {
+                channel.subscribers -= 1;
+
+                if channel.subscribers == 0 {
+                    channels.by_symbol.remove(&released);
+                }
+            }
+        }))
+    }
+}
+
+/// Send the updates of `live` to `sender` `DELAY` after they arrive, until
+/// `stopped` or the feed ends.
+///
+/// When the feed ends, the channel `id` is removed from `channels`, which
+/// ends the subscriptions.
+async fn hold_back(
+    mut live: Subscription,
+    sender: broadcast::Sender<MarketEvent>,
+    mut stopped: oneshot::Receiver<()>,
+    (channels, id): (Arc<Mutex<Channels>>, u64),
+) {
+    let delay = DELAY.to_std().unwrap_or_default();
+    let mut seconds: VecDeque<Second> = VecDeque::new();
+    let mut held = 0;
+
+    loop {
+        let due = seconds.front().map_or_else(Instant::now, |second| second.started + delay);
+
+        tokio::select! {
+            _ = &mut stopped => return,
+            _ = time::sleep_until(due), if !seconds.is_empty() => {
+                if let Some(second) = seconds.pop_front() {
+                    held -= second.events.len();
+
+                    for event in second.events {
+                        // Fails only while no subscriber is left, until the
+                        // last release stops the task.
+                        let _ = sender.send(event);
+                    }
+                }
+            }
+            event = live.recv() => {
+                let Some(event) = event else {
+                    warn!(symbol = live.symbol(), "market data stream ended");
+
+                    let mut channels = channels.lock().unwrap();
+                    if channels.by_symbol.get(live.symbol()).is_some_and(|channel| channel.id == id) {
+                        channels.by_symbol.remove(live.symbol());
+                    }
+                    return;
+                };
+
+                let now = Instant::now();
+                if seconds.back().is_none_or(|second| now >= second.started + Duration::from_secs(1)) {
+                    seconds.push_back(Second {
+                        started: now,
+                        events: Vec::new(),
+                        slots: HashMap::new(),
+                    });
+                }
+
+                if let Some(second) = seconds.back_mut() {
+                    if second.hold(event) {
+                        held += 1;
+                    }
+                }
+
+                if held > BUFFER_CAPACITY {
+                    if let Some(dropped) = seconds.pop_front() {
+                        held -= dropped.events.len();
+                        warn!(
+                            symbol = live.symbol(),
+                            skipped = dropped.events.len(),
+                            "delayed market data is dropping updates"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Second {
+    /// Take in `event`, replacing the update of the same slot if there is one.
+    ///
+    /// Returns `true` if `event` was added.
+    fn hold(&mut self, event: MarketEvent) -> bool {
+        let slot = match &event {
+            MarketEvent::Quote(_) => Slot::Quote,
+            MarketEvent::Depth(depth) => Slot::Depth(depth.side, depth.venue.clone()),
+            MarketEvent::Trade(_) | MarketEvent::Bar(_) => {
+                self.events.push(event);
+                return true;
+            }
+        };
+
+        match self.slots.get(&slot) {
+            Some(position) => {
+                self.events[*position] = event;
+                false
+            }
+            None => {
+                self.slots.insert(slot, self.events.len());
+                self.events.push(event);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_data::{Depth, Quote, Trade};
+
+    use chrono::DateTime;
+
+    fn quote(bid_price: f64) -> MarketEvent {
+        MarketEvent::Quote(Quote {
+            bid_price,
+            ..Quote::default()
+        })
+    }
+
+    fn trade(price: f64) -> MarketEvent {
+        MarketEvent::Trade(Trade {
+            price,
+            ..Trade::default()
+        })
+    }
+
+    fn depth(venue: &str, side: Side, price: f64) -> MarketEvent {
+        MarketEvent::Depth(Depth {
+            venue: venue.to_string(),
+            side,
+            price,
+            size: 100.0,
+            timestamp: DateTime::UNIX_EPOCH,
+        })
+    }
+
+    #[test]
+    fn second_keeps_the_last_quote_and_depth_and_every_trade() {
+        let mut second = Second {
+            started: Instant::now(),
+            events: Vec::new(),
+            slots: HashMap::new(),
+        };
+
+        let held: Vec<_> = [
+            quote(10.0),
+            trade(10.1),
+            depth("ISLAND", Side::Bid, 10.0),
+            quote(10.2),
+            trade(10.1),
+            depth("ARCA", Side::Bid, 10.0),
+            depth("ISLAND", Side::Bid, 10.1),
+            depth("ISLAND", Side::Ask, 10.3),
+        ]
+        .into_iter()
+        .map(|event| second.hold(event))
+        .collect();
+
+        assert_eq!(held, vec![true, true, true, false, true, true, false, true]);
+
+        // A replacing update takes the place of the one it replaces.
+        assert_eq!(
+            second.events,
+            vec![
+                quote(10.2),
+                trade(10.1),
+                depth("ISLAND", Side::Bid, 10.1),
+                trade(10.1),
+                depth("ARCA", Side::Bid, 10.0),
+                depth("ISLAND", Side::Ask, 10.3),
+            ]
+        );
+    }
+}
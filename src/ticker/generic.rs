@@ -0,0 +1,426 @@
+use crate::broker::Asset;
+use crate::market_data::{Bar, Trade};
+use crate::ticker::{Tick, TickType};
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+
+/// A generic tick of `reqMktData`'s `genericTickList` the connector computes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum GenericTick {
+    /// Highs and lows over 13, 26 and 52 weeks and the average volume.
+    MiscStats = 165,
+
+    /// Every trade with the day's volume and VWAP, in one string.
+    RtVolume = 233,
+
+    /// How easily the stock can be sold short.
+    Shortable = 236,
+
+    /// Number of trades of the day.
+    TradeCount = 293,
+
+    /// Trades per minute.
+    TradeRate = 294,
+
+    /// Shares traded per minute.
+    VolumeRate = 295,
+}
+
+/// The generic ticks TWS accepts for stocks, with the names it lists them
+/// under.
+const LEGAL: &[(u16, &str)] = &[
+    (100, "Option Volume"),
+    (101, "Option Open Interest"),
+    (104, "Historical Volatility"),
+    (105, "Average Option Volume"),
+    (106, "impvolat"),
+    (162, "Index Future Premium"),
+    (165, "Misc. Stats"),
+    (221, "Mark Price"),
+    (225, "Auction"),
+    (233, "RTVolume"),
+    (236, "inventory"),
+    (258, "Fundamentals"),
+    (291, "ivclose"),
+    (292, "News"),
+    (293, "TradeCount"),
+    (294, "TradeRate"),
+    (295, "VolumeRate"),
+    (318, "LastRTHTrade"),
+    (375, "RTTrdVolume"),
+    (411, "rthistvol"),
+    (456, "IBDividends"),
+    (460, "Bond Factor Multiplier"),
+    (576, "EtfNavBidAsk"),
+    (577, "EtfNavLast"),
+    (578, "EtfNavClose"),
+    (586, "IPOHLMPRC"),
+    (588, "Futures Open Interest"),
+    (595, "Short-Term Volume X Mins"),
+    (614, "EtfNavMisc"),
+    (619, "Creditman Slow Mark Price"),
+    (623, "EtfFrozenNavLast"),
+];
+
+/// Length of the periods of the misc stats, in weeks, with the tick types of
+/// their low and high.
+const PERIODS: [(i64, TickType, TickType); 3] = [
+    (13, TickType::Low13Week, TickType::High13Week),
+    (26, TickType::Low26Week, TickType::High26Week),
+    (52, TickType::Low52Week, TickType::High52Week),
+];
+
+/// Number of trading days the average volume is taken over.
+const AVERAGE_VOLUME_DAYS: usize = 90;
+
+/// The window trade and volume rates are measured over.
+const RATE_WINDOW: Duration = Duration::minutes(1);
+
+impl GenericTick {
+    fn from_id(id: u16) -> Option<GenericTick> {
+        use GenericTick::*;
+
+        [MiscStats, RtVolume, Shortable, TradeCount, TradeRate, VolumeRate]
+            .into_iter()
+            .find(|tick| *tick as u16 == id)
+    }
+}
+
+/// The generic ticks of a `genericTickList`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct GenericTicks {
+    /// The ones the connector computes.
+    pub(crate) supported: Vec<GenericTick>,
+
+    /// Ids TWS accepts for stocks but the connector has no data for.
+    pub(crate) unsupported: Vec<u16>,
+}
+
+impl GenericTicks {
+    /// Parse a comma separated list of generic tick ids.
+    ///
+    /// Fails with the text of TWS's error if an id is not one TWS accepts
+    /// for stocks.
+    pub(crate) fn parse(list: &str) -> Result<GenericTicks, String> {
+        let mut ticks = GenericTicks::default();
+
+        for item in list.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let id = item
+                .parse()
+                .ok()
+                .filter(|id| LEGAL.iter().any(|(legal, _)| legal == id))
+                .ok_or_else(|| {
+                    let legal: Vec<_> = LEGAL.iter().map(|(id, _)| *id).collect();
+                    format!(
+                        "Incorrect generic tick list of {}.  Legal ones for (STK) are: {}",
+                        item,
+                        describe(&legal)
+                    )
+                })?;
+
+            match GenericTick::from_id(id) {
+                Some(tick) if !ticks.supported.contains(&tick) => ticks.supported.push(tick),
+                Some(_) => {}
+                None if !ticks.unsupported.contains(&id) => ticks.unsupported.push(id),
+                None => {}
+            }
+        }
+
+        Ok(ticks)
+    }
+}
+
+/// Returns `ids` the way TWS lists generic ticks, e.g. `233(RTVolume)`.
+pub(crate) fn describe(ids: &[u16]) -> String {
+    let names: Vec<_> = ids
+        .iter()
+        .map(|id| match LEGAL.iter().find(|(legal, _)| legal == id) {
+            Some((_, name)) => format!("{}({})", id, name),
+            None => id.to_string(),
+        })
+        .collect();
+
+    names.join(",")
+}
+
+/// Returns the value of the shortable tick of `asset`.
+///
+/// TWS sends more than 2.5 when shares are available to borrow, more than
+/// 1.5 when they have to be located first, and less when none are.
+pub(crate) fn shortable(asset: &Asset) -> f64 {
+    match (asset.shortable, asset.easy_to_borrow) {
+        (true, true) => 3.0,
+        (true, false) => 2.0,
+        (false, _) => 1.0,
+    }
+}
+
+/// Highs and lows over 13, 26 and 52 weeks and the average daily volume.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct MiscStats {
+    /// Low and high of each of `PERIODS`.
+    ranges: [Option<(f64, f64)>; 3],
+    average_volume: Option<f64>,
+}
+
+impl MiscStats {
+    /// Compute the stats from the day bars of the last year, before the
+    /// trading day that started at `day_start`.
+    pub(crate) fn new(bars: &[Bar], day_start: DateTime<Utc>) -> MiscStats {
+        let bars: Vec<_> = bars.iter().filter(|bar| bar.start < day_start).collect();
+        let mut stats = MiscStats::default();
+
+        for (range, (weeks, _, _)) in stats.ranges.iter_mut().zip(PERIODS) {
+            let from = day_start - Duration::weeks(weeks);
+
+            *range = bars
+                .iter()
+                .filter(|bar| bar.start >= from)
+                .fold(None, |range, bar| match range {
+                    Some((low, high)) => Some((bar.low.min(low), bar.high.max(high))),
+                    None => Some((bar.low, bar.high)),
+                });
+        }
+
+        let recent: Vec<_> = bars.iter().rev().take(AVERAGE_VOLUME_DAYS).collect();
+        if !recent.is_empty() {
+            let volume = recent.iter().fold(0.0, |volume, bar| volume + bar.volume);
+            stats.average_volume = Some(volume / recent.len() as f64);
+        }
+
+        stats
+    }
+
+    pub(crate) fn ticks(&self) -> Vec<Tick> {
+        let mut ticks = Vec::new();
+
+        for (range, (_, low_type, high_type)) in self.ranges.iter().zip(PERIODS) {
+            if let Some((low, high)) = range {
+                ticks.push(Tick::Price(low_type, *low, 0.0));
+                ticks.push(Tick::Price(high_type, *high, 0.0));
+            }
+        }
+
+        if let Some(volume) = self.average_volume {
+            ticks.push(Tick::Size(TickType::AvgVolume, volume.round()));
+        }
+
+        ticks
+    }
+
+    /// Take in a trade at `price` and return the ticks of the lows and highs
+    /// it moved.
+    pub(crate) fn trade(&mut self, price: f64) -> Vec<Tick> {
+        let mut ticks = Vec::new();
+
+        for (range, (_, low_type, high_type)) in self.ranges.iter_mut().zip(PERIODS) {
+            let Some((low, high)) = range else {
+                *range = Some((price, price));
+                ticks.push(Tick::Price(low_type, price, 0.0));
+                ticks.push(Tick::Price(high_type, price, 0.0));
+                continue;
+            };
+
+            if price < *low {
+                *low = price;
+                ticks.push(Tick::Price(low_type, price, 0.0));
+            }
+            if price > *high {
+                *high = price;
+                ticks.push(Tick::Price(high_type, price, 0.0));
+            }
+        }
+
+        ticks
+    }
+}
+
+/// The day's trades, behind RTVolume and the trade count and rates.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Activity {
+    count: u64,
+
+    /// Value and volume of the trades the VWAP is computed from.
+    turnover: f64,
+    turnover_volume: f64,
+
+    /// Time and size of the trades of the last `RATE_WINDOW`.
+    recent: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl Activity {
+    /// Start from the day's aggregate so far, if any.
+    pub(crate) fn new(today: Option<&Bar>) -> Activity {
+        let mut activity = Activity::default();
+
+        if let Some(bar) = today {
+            activity.count = bar.trades.unwrap_or_default();
+
+            if let Some(vwap) = bar.vwap {
+                activity.turnover = vwap * bar.volume;
+                activity.turnover_volume = bar.volume;
+            }
+        }
+
+        activity
+    }
+
+    pub(crate) fn trade(&mut self, trade: &Trade) {
+        self.count += 1;
+        self.turnover += trade.price * trade.size;
+        self.turnover_volume += trade.size;
+
+        self.recent.push_back((trade.timestamp, trade.size));
+        while self
+            .recent
+            .front()
+            .is_some_and(|(time, _)| *time <= trade.timestamp - RATE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Volume weighted average price of the day.
+    pub(crate) fn vwap(&self) -> Option<f64> {
+        (self.turnover_volume > 0.0).then(|| self.turnover / self.turnover_volume)
+    }
+
+    /// Returns the trades and shares per minute, as of `now`.
+    pub(crate) fn rates(&self, now: DateTime<Utc>) -> (f64, f64) {
+        self.recent
+            .iter()
+            .filter(|(time, _)| *time > now - RATE_WINDOW)
+            .fold((0.0, 0.0), |(trades, shares), (_, size)| (trades + 1.0, shares + size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market_data::MarketEvent;
+    use crate::ticker::Ticker;
+
+    /// The day bar `days` before `day_start`.
+    fn bar(day_start: DateTime<Utc>, days: i64, low: f64, high: f64, volume: f64) -> Bar {
+        Bar {
+            start: day_start - Duration::days(days),
+            low,
+            high,
+            volume,
+            ..Bar::default()
+        }
+    }
+
+    #[test]
+    fn parse_splits_supported_and_unsupported_ticks() {
+        let ticks = GenericTicks::parse("233, 236,293,100,233,100,").unwrap();
+
+        assert_eq!(
+            ticks.supported,
+            vec![GenericTick::RtVolume, GenericTick::Shortable, GenericTick::TradeCount]
+        );
+
+        // Accepted by TWS, answered with error 10090.
+        assert_eq!(ticks.unsupported, vec![100]);
+        assert_eq!(describe(&ticks.unsupported), "100(Option Volume)");
+
+        assert_eq!(GenericTicks::parse("").unwrap(), GenericTicks::default());
+    }
+
+    #[test]
+    fn parse_fails_on_illegal_ticks() {
+        // Answered with error 321.
+        for illegal in ["999", "233,abc"] {
+            let reason = GenericTicks::parse(illegal).unwrap_err();
+            let item = illegal.rsplit(',').next().unwrap();
+
+            assert!(
+                reason.starts_with(&format!(
+                    "Incorrect generic tick list of {}.  Legal ones for (STK) are: 100(Option Volume),101(Option Open Interest),",
+                    item
+                )),
+                "{}",
+                reason
+            );
+            assert!(reason.ends_with(",623(EtfFrozenNavLast)"), "{}", reason);
+        }
+    }
+
+    #[test]
+    fn misc_stats_cover_13_26_and_52_weeks() {
+        let day_start: DateTime<Utc> = "2024-03-08T05:00:00Z".parse().unwrap();
+
+        let bars = [
+            bar(day_start, 400, 1.0, 1000.0, 4000.0),
+            bar(day_start, 300, 30.0, 80.0, 3000.0),
+            bar(day_start, 120, 40.0, 70.0, 2000.0),
+            bar(day_start, 10, 50.0, 60.0, 1000.0),
+            // The day under way does not count.
+            bar(day_start, 0, 0.5, 2000.0, 9000.0),
+        ];
+
+        assert_eq!(
+            MiscStats::new(&bars, day_start).ticks(),
+            vec![
+                Tick::Price(TickType::Low13Week, 50.0, 0.0),
+                Tick::Price(TickType::High13Week, 60.0, 0.0),
+                Tick::Price(TickType::Low26Week, 40.0, 0.0),
+                Tick::Price(TickType::High26Week, 70.0, 0.0),
+                Tick::Price(TickType::Low52Week, 30.0, 0.0),
+                Tick::Price(TickType::High52Week, 80.0, 0.0),
+                Tick::Size(TickType::AvgVolume, 2500.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn average_volume_is_over_the_last_90_days() {
+        let day_start: DateTime<Utc> = "2024-03-08T05:00:00Z".parse().unwrap();
+
+        // Oldest first, the 10 oldest of 100 days trade much more.
+        let bars: Vec<_> = (1..=100)
+            .rev()
+            .map(|days| bar(day_start, days, 10.0, 11.0, if days > 90 { 100_000.0 } else { 500.0 }))
+            .collect();
+
+        let stats = MiscStats::new(&bars, day_start);
+        assert_eq!(stats.ticks().last(), Some(&Tick::Size(TickType::AvgVolume, 500.0)));
+        assert_eq!(MiscStats::new(&[], day_start).ticks(), vec![]);
+    }
+
+    #[test]
+    fn rt_volume_has_the_trade_and_the_days_volume_and_vwap() {
+        let mut ticker = Ticker {
+            generic: vec![GenericTick::RtVolume],
+            ..Ticker::default()
+        };
+
+        let trade = |price, size, millis| Trade {
+            price,
+            size,
+            timestamp: DateTime::from_timestamp_millis(millis).unwrap(),
+            ..Trade::default()
+        };
+
+        let rt_volume = |ticks: Vec<Tick>| {
+            ticks
+                .into_iter()
+                .find_map(|tick| match tick {
+                    Tick::String(TickType::RtVolume, value) => Some(value),
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        let first = ticker.apply(&MarketEvent::Trade(trade(10.0, 100.0, 1_709_906_400_123)));
+        assert_eq!(rt_volume(first), "10;100;1709906400123;100;10;true");
+
+        let second = ticker.apply(&MarketEvent::Trade(trade(11.0, 300.0, 1_709_906_401_456)));
+        assert_eq!(rt_volume(second), "11;300;1709906401456;400;10.75;true");
+    }
+}
@@ -0,0 +1,525 @@
+mod delayed;
+pub(crate) use delayed::DelayedFeed;
+mod generic;
+pub(crate) use generic::{describe, GenericTick, GenericTicks};
+
+use crate::market_data::{Bar, MarketEvent, Quote, Timespan, Trade};
+use crate::pnl::day_start;
+use crate::Db;
+
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use generic::{Activity, MiscStats};
+use tracing::warn;
+
+/// Time zone of the US stock exchanges, the trading days of the market data
+/// start at midnight there.
+const EXCHANGE_ZONE: Tz = chrono_tz::America::New_York;
+
+/// How far delayed market data lags behind.
+pub(crate) const DELAY: Duration = Duration::minutes(15);
+
+/// Kind of market data a connection asked for with `reqMarketDataType`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum MarketDataType {
+    /// Streaming data.
+    #[default]
+    Live = 1,
+
+    /// Streaming data while the market is open, the last values otherwise.
+    Frozen = 2,
+
+    /// Streaming data, `DELAY` late.
+    Delayed = 3,
+
+    /// Delayed data while the market is open, the last delayed values
+    /// otherwise.
+    DelayedFrozen = 4,
+}
+
+/// TWS tick type ids, as sent in `tickPrice`, `tickSize` and `tickString`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TickType {
+    BidSize = 0,
+    Bid = 1,
+    Ask = 2,
+    AskSize = 3,
+    Last = 4,
+    LastSize = 5,
+    High = 6,
+    Low = 7,
+    Volume = 8,
+    Close = 9,
+    Open = 14,
+    Low13Week = 15,
+    High13Week = 16,
+    Low26Week = 17,
+    High26Week = 18,
+    Low52Week = 19,
+    High52Week = 20,
+    AvgVolume = 21,
+    LastTimestamp = 45,
+    Shortable = 46,
+    RtVolume = 48,
+    TradeCount = 54,
+    TradeRate = 55,
+    VolumeRate = 56,
+    DelayedBid = 66,
+    DelayedAsk = 67,
+    DelayedLast = 68,
+    DelayedBidSize = 69,
+    DelayedAskSize = 70,
+    DelayedLastSize = 71,
+    DelayedHigh = 72,
+    DelayedLow = 73,
+    DelayedVolume = 74,
+    DelayedClose = 75,
+    DelayedOpen = 76,
+    DelayedLastTimestamp = 88,
+}
+
+impl MarketDataType {
+    pub(crate) fn from_id(id: i32) -> Option<MarketDataType> {
+        use MarketDataType::*;
+
+        [Live, Frozen, Delayed, DelayedFrozen]
+            .into_iter()
+            .find(|data_type| *data_type as i32 == id)
+    }
+
+    /// Returns the kind of data actually sent, depending on whether the
+    /// market is `open`.
+    pub(crate) fn effective(self, open: bool) -> MarketDataType {
+        match (self, open) {
+            (MarketDataType::Frozen, true) => MarketDataType::Live,
+            (MarketDataType::DelayedFrozen, true) => MarketDataType::Delayed,
+            (data_type, _) => data_type,
+        }
+    }
+
+    pub(crate) fn is_delayed(self) -> bool {
+        matches!(self, MarketDataType::Delayed | MarketDataType::DelayedFrozen)
+    }
+
+    pub(crate) fn is_frozen(self) -> bool {
+        matches!(self, MarketDataType::Frozen | MarketDataType::DelayedFrozen)
+    }
+}
+
+impl TickType {
+    /// Returns the type of the same value in delayed data. Types without a
+    /// delayed counterpart are returned as they are.
+    fn delayed(self) -> TickType {
+        use TickType::*;
+
+        match self {
+            Bid => DelayedBid,
+            Ask => DelayedAsk,
+            Last => DelayedLast,
+            BidSize => DelayedBidSize,
+            AskSize => DelayedAskSize,
+            LastSize => DelayedLastSize,
+            High => DelayedHigh,
+            Low => DelayedLow,
+            Volume => DelayedVolume,
+            Close => DelayedClose,
+            Open => DelayedOpen,
+            LastTimestamp => DelayedLastTimestamp,
+            other => other,
+        }
+    }
+}
+
+/// One value of a ticker.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Tick {
+    /// Sent as `tickPrice`, with the size quoted or traded at the price, 0 if
+    /// the price has none.
+    Price(TickType, f64, f64),
+
+    /// Sent as `tickSize`.
+    Size(TickType, f64),
+
+    /// Sent as `tickString`.
+    String(TickType, String),
+
+    /// Sent as `tickGeneric`.
+    Generic(TickType, f64),
+}
+
+impl Tick {
+    /// Returns the tick with the type it has in delayed data.
+    pub(crate) fn delayed(self) -> Tick {
+        match self {
+            Tick::Price(tick_type, price, size) => Tick::Price(tick_type.delayed(), price, size),
+            Tick::Size(tick_type, size) => Tick::Size(tick_type.delayed(), size),
+            Tick::String(tick_type, value) => Tick::String(tick_type.delayed(), value),
+            Tick::Generic(tick_type, value) => Tick::Generic(tick_type.delayed(), value),
+        }
+    }
+}
+
+/// The market data of one symbol, as TWS reports it.
+///
+/// Starts from the feed's last quote and trade, the previous close and the
+/// day's aggregate, then follows the streamed quotes and trades. Every change
+/// comes out as the ticks that report it.
+///
+/// Generic ticks are computed from the same trades, from the day bars of the
+/// last year and from what the broker tells about the stock.
+///
+/// A delayed ticker starts from the minute bars up to `DELAY` ago instead, and
+/// is fed the updates once they are `DELAY` old.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Ticker {
+    quote: Option<Quote>,
+    last: Option<Trade>,
+    open: Option<f64>,
+    high: Option<f64>,
+    low: Option<f64>,
+    close: Option<f64>,
+    volume: f64,
+
+    /// The generic ticks sent on top of the default ones.
+    generic: Vec<GenericTick>,
+
+    /// Requested generic ticks the ticker could not load the data of.
+    missing: Vec<GenericTick>,
+
+    stats: MiscStats,
+    shortable: Option<f64>,
+    activity: Activity,
+
+    /// Trade and volume rates last sent.
+    rates: (f64, f64),
+}
+
+impl Ticker {
+    /// Load the current values of `symbol` from the market data feed, along
+    /// with the data of the `generic` ticks. A `delayed` ticker holds the
+    /// values of `DELAY` ago.
+    ///
+    /// Returns `None` if the feed does not know the symbol. Other missing
+    /// values are logged and left out.
+    pub(crate) async fn load(db: &Db, symbol: &str, generic: &[GenericTick], delayed: bool) -> Option<Ticker> {
+        let provider = db.market_data()?;
+        let now = Utc::now();
+        let day_start = trading_day(now);
+
+        let mut ticker = Ticker {
+            generic: generic.to_vec(),
+            ..Ticker::default()
+        };
+
+        match provider.previous_close(symbol).await {
+            Ok(bar) => ticker.close = Some(bar.close),
+            Err(err) => warn!(%symbol, cause = %err, "failed to get previous close"),
+        }
+
+        // A year of day bars when the misc stats are wanted, today's only
+        // otherwise.
+        let from = if generic.contains(&GenericTick::MiscStats) {
+            day_start - Duration::weeks(52)
+        } else {
+            day_start
+        };
+
+        let day_bars = match provider.bars(symbol, Timespan::Day, from, now).await {
+            Ok(bars) => {
+                ticker.stats = MiscStats::new(&bars, day_start);
+                bars
+            }
+            Err(err) => {
+                warn!(%symbol, cause = %err, "failed to get day bars");
+                ticker.missing.push(GenericTick::MiscStats);
+                Vec::new()
+            }
+        };
+
+        let today = if delayed {
+            // The day up to `DELAY` ago, from the minute bars that ended by
+            // then.
+            let cutoff = now - DELAY;
+
+            match provider.bars(symbol, Timespan::Minute, day_start, cutoff).await {
+                Ok(bars) => {
+                    let bars: Vec<_> = bars
+                        .into_iter()
+                        .filter(|bar| bar.start + Duration::minutes(1) <= cutoff)
+                        .collect();
+
+                    ticker.last = bars.last().map(|bar| Trade {
+                        price: bar.close,
+                        timestamp: bar.start + Duration::minutes(1),
+                        ..Trade::default()
+                    });
+                    aggregate(&bars)
+                }
+                Err(err) => {
+                    warn!(%symbol, cause = %err, "failed to get minute bars");
+
+                    // The previous close is all that is left to send.
+                    ticker.close?;
+                    None
+                }
+            }
+        } else {
+            match provider.last_quote(symbol).await {
+                Ok(quote) => ticker.quote = Some(quote),
+                Err(err) => warn!(%symbol, cause = %err, "failed to get last quote"),
+            }
+
+            match provider.last_trade(symbol).await {
+                Ok(trade) => ticker.last = Some(trade),
+                Err(err) => warn!(%symbol, cause = %err, "failed to get last trade"),
+            }
+
+            if ticker.quote.is_none() && ticker.last.is_none() {
+                return None;
+            }
+
+            day_bars.into_iter().last().filter(|bar| bar.start >= day_start)
+        };
+
+        if let Some(today) = &today {
+            ticker.open = Some(today.open);
+            ticker.high = Some(today.high);
+            ticker.low = Some(today.low);
+            ticker.volume = today.volume;
+        }
+        ticker.activity = Activity::new(today.as_ref());
+
+        if generic.contains(&GenericTick::Shortable) {
+            // Accounts at the same broker see the same inventory, any one
+            // will do.
+            let broker = db.config().accounts.first().and_then(|account| db.broker(&account.id));
+
+            match broker {
+                Some(broker) => match broker.asset(symbol).await {
+                    Ok(asset) => ticker.shortable = Some(generic::shortable(&asset)),
+                    Err(err) => {
+                        warn!(%symbol, cause = %err, "failed to get asset");
+                        ticker.missing.push(GenericTick::Shortable);
+                    }
+                },
+                None => ticker.missing.push(GenericTick::Shortable),
+            }
+        }
+
+        ticker.missing.retain(|tick| generic.contains(tick));
+        let missing = ticker.missing.clone();
+        ticker.generic.retain(|tick| !missing.contains(tick));
+
+        Some(ticker)
+    }
+
+    /// Returns the requested generic ticks there is no data for.
+    pub(crate) fn missing(&self) -> &[GenericTick] {
+        &self.missing
+    }
+
+    /// Returns the ticks of every known value.
+    pub(crate) fn ticks(&self) -> Vec<Tick> {
+        let mut ticks = Vec::new();
+
+        if let Some(quote) = &self.quote {
+            ticks.push(Tick::Price(TickType::Bid, quote.bid_price, quote.bid_size));
+            ticks.push(Tick::Price(TickType::Ask, quote.ask_price, quote.ask_size));
+        }
+
+        if let Some(trade) = &self.last {
+            ticks.push(Tick::Price(TickType::Last, trade.price, trade.size));
+            ticks.push(last_timestamp(trade));
+        }
+
+        for (tick_type, price) in [
+            (TickType::High, self.high),
+            (TickType::Low, self.low),
+            (TickType::Close, self.close),
+            (TickType::Open, self.open),
+        ] {
+            if let Some(price) = price {
+                ticks.push(Tick::Price(tick_type, price, 0.0));
+            }
+        }
+
+        ticks.push(Tick::Size(TickType::Volume, self.volume));
+
+        for tick in &self.generic {
+            match tick {
+                GenericTick::MiscStats => ticks.extend(self.stats.ticks()),
+                GenericTick::RtVolume => ticks.extend(self.last.as_ref().map(|trade| self.rt_volume(trade))),
+                GenericTick::Shortable => ticks.extend(
+                    self.shortable
+                        .map(|shortable| Tick::Generic(TickType::Shortable, shortable)),
+                ),
+                GenericTick::TradeCount => {
+                    ticks.push(Tick::Generic(TickType::TradeCount, self.activity.count() as f64))
+                }
+                GenericTick::TradeRate => ticks.push(Tick::Generic(TickType::TradeRate, self.rates.0)),
+                GenericTick::VolumeRate => ticks.push(Tick::Generic(TickType::VolumeRate, self.rates.1)),
+            }
+        }
+
+        ticks
+    }
+
+    /// Take in `event` and return the ticks of the values it changed.
+    pub(crate) fn apply(&mut self, event: &MarketEvent) -> Vec<Tick> {
+        match event {
+            MarketEvent::Quote(quote) => self.quote(quote),
+            MarketEvent::Trade(trade) => self.trade(trade),
+
+            // The day's values are kept up to date from the trades, the end
+            // of a minute only moves the rates. They are measured at the end
+            // of the bar, which is `DELAY` ago for delayed data.
+            MarketEvent::Bar(bar) => self.rates(bar.start + Duration::minutes(1)),
+
+            // The depth repeats the quote, venue by venue.
+            MarketEvent::Depth(_) => Vec::new(),
+        }
+    }
+
+    fn quote(&mut self, quote: &Quote) -> Vec<Tick> {
+        let previous = self.quote.replace(quote.clone()).unwrap_or_default();
+        let mut ticks = Vec::new();
+
+        // A new price goes with its size, a size alone is sent when only the
+        // size changed.
+        if quote.bid_price != previous.bid_price {
+            ticks.push(Tick::Price(TickType::Bid, quote.bid_price, quote.bid_size));
+        } else if quote.bid_size != previous.bid_size {
+            ticks.push(Tick::Size(TickType::BidSize, quote.bid_size));
+        }
+
+        if quote.ask_price != previous.ask_price {
+            ticks.push(Tick::Price(TickType::Ask, quote.ask_price, quote.ask_size));
+        } else if quote.ask_size != previous.ask_size {
+            ticks.push(Tick::Size(TickType::AskSize, quote.ask_size));
+        }
+
+        ticks
+    }
+
+    fn trade(&mut self, trade: &Trade) -> Vec<Tick> {
+        let mut ticks = Vec::new();
+
+        match self.last.replace(trade.clone()) {
+            Some(previous) if previous.price == trade.price => {
+                ticks.push(Tick::Size(TickType::LastSize, trade.size));
+            }
+            _ => ticks.push(Tick::Price(TickType::Last, trade.price, trade.size)),
+        }
+        ticks.push(last_timestamp(trade));
+
+        if self.open.is_none() {
+            self.open = Some(trade.price);
+            ticks.push(Tick::Price(TickType::Open, trade.price, 0.0));
+        }
+        if self.high.is_none_or(|high| trade.price > high) {
+            self.high = Some(trade.price);
+            ticks.push(Tick::Price(TickType::High, trade.price, 0.0));
+        }
+        if self.low.is_none_or(|low| trade.price < low) {
+            self.low = Some(trade.price);
+            ticks.push(Tick::Price(TickType::Low, trade.price, 0.0));
+        }
+
+        self.volume += trade.size;
+        ticks.push(Tick::Size(TickType::Volume, self.volume));
+
+        let stats = self.stats.trade(trade.price);
+        if self.generic.contains(&GenericTick::MiscStats) {
+            ticks.extend(stats);
+        }
+
+        self.activity.trade(trade);
+
+        if self.generic.contains(&GenericTick::RtVolume) {
+            ticks.push(self.rt_volume(trade));
+        }
+        if self.generic.contains(&GenericTick::TradeCount) {
+            ticks.push(Tick::Generic(TickType::TradeCount, self.activity.count() as f64));
+        }
+        ticks.extend(self.rates(trade.timestamp));
+
+        ticks
+    }
+
+    /// Returns the ticks of the trade and volume rates that changed as of
+    /// `now`.
+    fn rates(&mut self, now: DateTime<Utc>) -> Vec<Tick> {
+        let (trades, volume) = self.activity.rates(now);
+        let mut ticks = Vec::new();
+
+        if trades != self.rates.0 && self.generic.contains(&GenericTick::TradeRate) {
+            ticks.push(Tick::Generic(TickType::TradeRate, trades));
+        }
+        if volume != self.rates.1 && self.generic.contains(&GenericTick::VolumeRate) {
+            ticks.push(Tick::Generic(TickType::VolumeRate, volume));
+        }
+
+        self.rates = (trades, volume);
+        ticks
+    }
+
+    /// The RTVolume tick of `trade`: its price, size and time in milliseconds,
+    /// the day's volume and VWAP, and whether it was a single trade.
+    fn rt_volume(&self, trade: &Trade) -> Tick {
+        let value = format!(
+            "{};{};{};{};{};true",
+            trade.price,
+            trade.size,
+            trade.timestamp.timestamp_millis(),
+            self.volume,
+            self.activity.vwap().unwrap_or(trade.price),
+        );
+
+        Tick::String(TickType::RtVolume, value)
+    }
+}
+
+/// The time of `trade` in seconds since the epoch, as TWS sends it.
+fn last_timestamp(trade: &Trade) -> Tick {
+    Tick::String(TickType::LastTimestamp, trade.timestamp.timestamp().to_string())
+}
+
+/// Returns the start of the trading day `now` is in, midnight in New York.
+pub(crate) fn trading_day(now: DateTime<Utc>) -> DateTime<Utc> {
+    day_start(EXCHANGE_ZONE, now)
+}
+
+/// Returns `true` during the regular session of the US stock exchanges, 9:30
+/// to 16:00 in New York on weekdays. Holidays are not known.
+pub(crate) fn market_open(now: DateTime<Utc>) -> bool {
+    let local = now.with_timezone(&EXCHANGE_ZONE);
+    let (open, close) = (
+        NaiveTime::from_hms_opt(9, 30, 0).unwrap_or_default(),
+        NaiveTime::from_hms_opt(16, 0, 0).unwrap_or_default(),
+    );
+
+    !matches!(local.weekday(), Weekday::Sat | Weekday::Sun) && (open..close).contains(&local.time())
+}
+
+/// Returns the bar covering all of `bars`, oldest first, or `None` if there
+/// are none.
+fn aggregate(bars: &[Bar]) -> Option<Bar> {
+    let (first, last) = (bars.first()?, bars.last()?);
+    let volume = bars.iter().fold(0.0, |volume, bar| volume + bar.volume);
+
+    // Bars without a VWAP count at their close.
+    let turnover = bars
+        .iter()
+        .fold(0.0, |turnover, bar| turnover + bar.vwap.unwrap_or(bar.close) * bar.volume);
+
+    Some(Bar {
+        start: first.start,
+        open: first.open,
+        high: bars.iter().map(|bar| bar.high).fold(f64::MIN, f64::max),
+        low: bars.iter().map(|bar| bar.low).fold(f64::MAX, f64::min),
+        close: last.close,
+        volume,
+        vwap: (volume > 0.0).then(|| turnover / volume),
+        trades: bars.iter().map(|bar| bar.trades).sum(),
+    })
+}
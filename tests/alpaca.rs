@@ -0,0 +1,260 @@
+//! The Alpaca adapter against the alpaca stand-in.
+
+use tiger_trade_connector::broker::{
+    Alpaca, Broker, Error, OrderChanges, OrderFilter, OrderRequest, OrderStatus, OrderType, OrderUpdate, Side,
+    TimeInForce,
+};
+use tiger_trade_connector::mock;
+
+use chrono::Utc;
+use serde_json::json;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::time::{timeout, Duration};
+
+/// Longest wait for a trade update.
+const WAIT: Duration = Duration::from_secs(10);
+
+/// Start a stand-in on a free port and return an adapter using it, with the
+/// base URL of the stand-in.
+async fn start() -> (Alpaca, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(mock::alpaca::serve(listener));
+
+    let url = format!("http://{}", addr);
+    let alpaca = Alpaca::new("test", "test", &url, &format!("ws://{}/stream", addr)).unwrap();
+
+    (alpaca, url)
+}
+
+fn order(client_order_id: &str, side: Side, quantity: f64, limit_price: Option<f64>) -> OrderRequest {
+    OrderRequest {
+        client_order_id: client_order_id.to_string(),
+        symbol: "MSFT".to_string(),
+        side,
+        quantity,
+        order_type: if limit_price.is_some() {
+            OrderType::Limit
+        } else {
+            OrderType::Market
+        },
+        time_in_force: TimeInForce::Day,
+        limit_price,
+        stop_price: None,
+        trail: None,
+        extended_hours: false,
+    }
+}
+
+/// Wait until the adapter streams trade updates.
+///
+/// Updates sent before the stream listens are lost, so orders that cannot
+/// fill are submitted until one of them is reported.
+async fn wait_for_stream(alpaca: &Alpaca, updates: &mut broadcast::Receiver<OrderUpdate>) {
+    for probe in 0.. {
+        let client_order_id = format!("probe-{}", probe);
+        alpaca
+            .submit_order(&order(&client_order_id, Side::Buy, 1.0, Some(1.0)))
+            .await
+            .unwrap();
+
+        if timeout(Duration::from_millis(100), updates.recv()).await.is_ok() {
+            return;
+        }
+
+        assert!(probe < 100, "the trade updates stream did not connect");
+    }
+}
+
+/// Receive the next update of the order `client_order_id`, skipping the
+/// others.
+async fn next_update(updates: &mut broadcast::Receiver<OrderUpdate>, client_order_id: &str) -> OrderUpdate {
+    loop {
+        let update = timeout(WAIT, updates.recv())
+            .await
+            .expect("no trade update")
+            .unwrap();
+
+        if update.order.client_order_id == client_order_id {
+            return update;
+        }
+    }
+}
+
+#[tokio::test]
+async fn submitted_order_fills_through_trade_updates() {
+    let (alpaca, url) = start().await;
+    let mut updates = alpaca.updates();
+    wait_for_stream(&alpaca, &mut updates).await;
+
+    reqwest::Client::new()
+        .put(format!("{}/mock/prices/MSFT", url))
+        .json(&json!({ "price": 120.0 }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Above 100 shares, the stand-in fills in two executions.
+    let submitted = alpaca.submit_order(&order("fill-1", Side::Buy, 150.0, None)).await.unwrap();
+    assert_eq!(submitted.client_order_id, "fill-1");
+
+    let new = next_update(&mut updates, "fill-1").await;
+    assert_eq!((new.order.status, new.fill), (OrderStatus::New, None));
+
+    let partial = next_update(&mut updates, "fill-1").await;
+    assert_eq!(partial.order.status, OrderStatus::PartiallyFilled);
+    assert_eq!(partial.order.filled_quantity, 75.0);
+    let fill = partial.fill.expect("partial fill without execution");
+    assert_eq!((fill.price, fill.quantity, fill.position_quantity), (120.0, 75.0, 75.0));
+
+    let last = next_update(&mut updates, "fill-1").await;
+    assert_eq!(last.order.status, OrderStatus::Filled);
+    assert_eq!((last.order.filled_quantity, last.order.filled_avg_price), (150.0, Some(120.0)));
+    let second = last.fill.expect("fill without execution");
+    assert_eq!((second.price, second.quantity, second.position_quantity), (120.0, 75.0, 150.0));
+    assert_ne!(fill.execution_id, second.execution_id);
+
+    let positions = alpaca.positions().await.unwrap();
+    assert_eq!(positions.len(), 1);
+    assert_eq!((positions[0].symbol.as_str(), positions[0].quantity), ("MSFT", 150.0));
+}
+
+#[tokio::test]
+async fn replace_then_cancel() {
+    let (alpaca, _) = start().await;
+    let mut updates = alpaca.updates();
+    wait_for_stream(&alpaca, &mut updates).await;
+    let started = Utc::now();
+
+    // Below the default price of the stand-in, the order waits.
+    let working = alpaca
+        .submit_order(&order("replace-1", Side::Buy, 10.0, Some(95.0)))
+        .await
+        .unwrap();
+
+    let changes = OrderChanges {
+        client_order_id: Some("replace-2".to_string()),
+        quantity: Some(20.0),
+        limit_price: Some(96.0),
+        ..OrderChanges::default()
+    };
+    let replacement = alpaca.replace_order(&working.id, &changes).await.unwrap();
+
+    assert_ne!(replacement.id, working.id);
+    assert_eq!(replacement.replaces.as_deref(), Some(working.id.as_str()));
+    assert_eq!(replacement.client_order_id, "replace-2");
+    assert_eq!((replacement.quantity, replacement.limit_price), (20.0, Some(96.0)));
+    assert_eq!(replacement.status, OrderStatus::New);
+
+    next_update(&mut updates, "replace-1").await;
+    let replaced = next_update(&mut updates, "replace-1").await;
+    assert_eq!(replaced.order.status, OrderStatus::Replaced);
+    assert_eq!(next_update(&mut updates, "replace-2").await.order.status, OrderStatus::New);
+
+    // Alpaca answers a cancellation with an empty body.
+    alpaca.cancel_order(&replacement.id).await.unwrap();
+
+    let canceled = next_update(&mut updates, "replace-2").await;
+    assert_eq!((canceled.order.id, canceled.order.status), (replacement.id, OrderStatus::Canceled));
+
+    let open = alpaca.orders(OrderFilter::Open).await.unwrap();
+    assert!(open.iter().all(|order| order.client_order_id.starts_with("probe-")));
+
+    let closed = alpaca.orders(OrderFilter::Closed { after: started }).await.unwrap();
+    let statuses: Vec<_> = closed.iter().map(|order| (order.client_order_id.as_str(), order.status)).collect();
+    assert_eq!(
+        statuses,
+        vec![("replace-1", OrderStatus::Replaced), ("replace-2", OrderStatus::Canceled)]
+    );
+}
+
+#[tokio::test]
+async fn errors_map_to_not_found_and_rejected() {
+    let (alpaca, _) = start().await;
+
+    assert!(matches!(alpaca.cancel_order("no-such-order").await, Err(Error::NotFound)));
+    assert!(matches!(
+        alpaca.replace_order("no-such-order", &OrderChanges::default()).await,
+        Err(Error::NotFound)
+    ));
+
+    // 422
+    match alpaca.submit_order(&order("zero", Side::Buy, 0.0, None)).await {
+        Err(Error::Rejected(reason)) => assert_eq!(reason, "qty must be > 0"),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+
+    let filled = alpaca.submit_order(&order("filled", Side::Buy, 1.0, None)).await.unwrap();
+    match alpaca.cancel_order(&filled.id).await {
+        Err(Error::Rejected(reason)) => assert_eq!(reason, "order is already in \"filled\" state"),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+
+    // 403
+    match alpaca.submit_order(&order("too-large", Side::Buy, 1_000_000.0, None)).await {
+        Err(Error::Rejected(reason)) => assert_eq!(reason, "insufficient buying power"),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn orders_are_paged_by_creation_time() {
+    let (alpaca, _) = start().await;
+
+    // One more than the 500 orders of a page.
+    for index in 0..501 {
+        alpaca
+            .submit_order(&order(&format!("page-{}", index), Side::Buy, 1.0, Some(50.0)))
+            .await
+            .unwrap();
+    }
+
+    let open = alpaca.orders(OrderFilter::Open).await.unwrap();
+    let ids: Vec<_> = open.iter().map(|order| order.client_order_id.clone()).collect();
+    let expected: Vec<_> = (0..501).map(|index| format!("page-{}", index)).collect();
+    assert_eq!(ids, expected);
+}
+
+#[tokio::test]
+async fn fills_missed_while_disconnected_are_caught_up() {
+    let (alpaca, url) = start().await;
+    let mut updates = alpaca.updates();
+    wait_for_stream(&alpaca, &mut updates).await;
+
+    alpaca
+        .submit_order(&order("gap-1", Side::Buy, 150.0, Some(95.0)))
+        .await
+        .unwrap();
+    assert_eq!(next_update(&mut updates, "gap-1").await.order.status, OrderStatus::New);
+
+    let http = reqwest::Client::new();
+    http.post(format!("{}/mock/disconnect", url))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // The adapter waits a second before it reconnects, the order fills in
+    // two executions meanwhile.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    http.put(format!("{}/mock/prices/MSFT", url))
+        .json(&json!({ "price": 90.0 }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let caught_up = next_update(&mut updates, "gap-1").await;
+    assert_eq!(caught_up.order.status, OrderStatus::Filled);
+    assert_eq!(caught_up.order.filled_quantity, 150.0);
+    let fill = caught_up.fill.expect("missed fill not caught up");
+    assert_eq!((fill.price, fill.quantity), (90.0, 150.0));
+
+    // Nothing is forwarded twice.
+    assert!(timeout(Duration::from_millis(200), next_update(&mut updates, "gap-1")).await.is_err());
+}
@@ -0,0 +1,151 @@
+//! Market data requests through the server, fed by the polygon stand-in.
+
+use tiger_trade_connector::config::{AccountConfig, BrokerConfig, MarketDataConfig};
+use tiger_trade_connector::{mock, server, Config, Connection, Frame};
+
+use bytes::Bytes;
+use serde_json::Value;
+use std::future;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+/// Longest wait for a message from the server.
+const WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Start a stand-in and a server fed by it, each on a free port, and return
+/// a client connection that started the API, with the base URL of the
+/// stand-in.
+async fn start(name: &str) -> (Connection, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let polygon = listener.local_addr().unwrap();
+    tokio::spawn(mock::polygon::serve(listener));
+
+    let data_dir = std::env::temp_dir().join(format!("market_data_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    std::fs::create_dir_all(&data_dir).unwrap();
+
+    let config = Config {
+        data_dir,
+        accounts: vec![AccountConfig {
+            id: "DU0000001".to_string(),
+            currency: "USD".to_string(),
+            broker: BrokerConfig::Paper { cash: 100_000.0 },
+        }],
+        market_data: Some(MarketDataConfig::Polygon {
+            api_key: "test".to_string(),
+            rest_url: format!("http://{}", polygon),
+            ws_url: format!("ws://{}/stocks", polygon),
+        }),
+        ..Config::default()
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::run(listener, config, future::pending::<()>()));
+
+    let mut client = Connection::new(TcpStream::connect(addr).await.unwrap());
+    client
+        .write_frame(&Frame::Handshake(Bytes::from_static(b"v100..151")))
+        .await
+        .unwrap();
+
+    // The server version and the connection time.
+    next_message(&mut client).await;
+
+    send(&mut client, &["71", "2", "0", ""]).await;
+
+    (client, format!("http://{}", polygon))
+}
+
+async fn send(client: &mut Connection, fields: &[&str]) {
+    let fields = fields.iter().map(|field| Bytes::copy_from_slice(field.as_bytes())).collect();
+    client.write_frame(&Frame::Message(fields)).await.unwrap();
+}
+
+/// Receive the fields of the next message from the server.
+async fn next_message(client: &mut Connection) -> Vec<String> {
+    let frame = timeout(WAIT, client.read_frame())
+        .await
+        .expect("no message from the server")
+        .unwrap()
+        .expect("the server closed the connection");
+
+    match frame {
+        Frame::Message(fields) => fields
+            .iter()
+            .map(|field| String::from_utf8(field.to_vec()).unwrap())
+            .collect(),
+        Frame::Handshake(_) => panic!("unexpected handshake"),
+    }
+}
+
+/// Returns the connections made to the stand-in so far and the symbols
+/// streamed.
+async fn streams(url: &str) -> (u64, Vec<String>) {
+    let body: Value = reqwest::get(format!("{}/mock/streams", url))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let symbols = body["symbols"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol.as_str().unwrap().to_string())
+        .collect();
+
+    (body["connections"].as_u64().unwrap(), symbols)
+}
+
+#[tokio::test]
+async fn snapshot_sends_the_values_once_without_subscribing() {
+    let (mut client, url) = start("snapshot").await;
+
+    // reqMktData for MSFT with `snapshot` set.
+    send(
+        &mut client,
+        &[
+            "1", "11", "7", "0", "MSFT", "STK", "", "0", "", "", "SMART", "", "USD", "", "", "0", "", "1", "0", "",
+        ],
+    )
+    .await;
+
+    // Skip what the server sends when the API starts.
+    let mut message = next_message(&mut client).await;
+    while message[0] != "58" {
+        message = next_message(&mut client).await;
+    }
+
+    // Live data, the default.
+    assert_eq!(message, vec!["58", "1", "7", "1"]);
+
+    let mut ticks = Vec::new();
+    loop {
+        let message = next_message(&mut client).await;
+        if message[0] == "57" {
+            assert_eq!(message, vec!["57", "1", "7"]);
+            break;
+        }
+
+        assert_eq!(message[2], "7", "{:?}", message);
+        ticks.push(message);
+    }
+
+    // The last quote, trade and previous close of the stand-in.
+    for tick in [
+        vec!["1", "6", "7", "1", "189.85", "2", "0"],
+        vec!["1", "6", "7", "2", "189.87", "3", "0"],
+        vec!["1", "6", "7", "4", "189.86", "25", "0"],
+        vec!["1", "6", "7", "9", "185.92", "0", "0"],
+    ] {
+        assert!(ticks.contains(&tick.iter().map(|field| field.to_string()).collect()), "{:?}", ticks);
+    }
+
+    // The values were read from the REST API, nothing is left streaming and
+    // nothing else is sent.
+    assert_eq!(streams(&url).await, (0, vec![]));
+    assert!(timeout(std::time::Duration::from_millis(200), client.read_frame()).await.is_err());
+    assert_eq!(streams(&url).await, (0, vec![]));
+}
@@ -0,0 +1,194 @@
+//! The polygon.io adapter against the polygon stand-in.
+
+use tiger_trade_connector::market_data::{MarketDataProvider, MarketEvent, Polygon, Subscription, Timespan};
+use tiger_trade_connector::mock;
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio::time::{self, timeout};
+
+/// Longest wait for the stand-in to reach an expected state.
+const WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Start a stand-in on a free port and return an adapter using it, with the
+/// base URL of the stand-in.
+async fn start() -> (Polygon, String) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(mock::polygon::serve(listener));
+
+    let url = format!("http://{}", addr);
+    let polygon = Polygon::new("test", &url, &format!("ws://{}/stocks", addr)).unwrap();
+
+    (polygon, url)
+}
+
+/// Returns the connections made to the stand-in so far and the symbols
+/// streamed.
+async fn streams(url: &str) -> (u64, Vec<String>) {
+    let body: Value = reqwest::get(format!("{}/mock/streams", url))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let symbols = body["symbols"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|symbol| symbol.as_str().unwrap().to_string())
+        .collect();
+
+    (body["connections"].as_u64().unwrap(), symbols)
+}
+
+/// Wait until the stand-in has had `connections` connections and streams
+/// `symbols`.
+async fn wait_for_streams(url: &str, connections: u64, symbols: &[&str]) {
+    let reached = async {
+        while streams(url).await != (connections, symbols.iter().map(|symbol| symbol.to_string()).collect()) {
+            time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+    };
+
+    if timeout(WAIT, reached).await.is_err() {
+        panic!(
+            "expected {} connections streaming {:?}, got {:?}",
+            connections,
+            symbols,
+            streams(url).await
+        );
+    }
+}
+
+/// Receive the next event of `subscription`.
+async fn next_event(subscription: &mut Subscription) -> MarketEvent {
+    timeout(WAIT, subscription.recv())
+        .await
+        .expect("no market data event")
+        .expect("market data stream ended")
+}
+
+#[tokio::test]
+async fn last_quote_trade_and_previous_close() {
+    let (polygon, _) = start().await;
+
+    let quote = polygon.last_quote("MSFT").await.unwrap();
+    assert_eq!((quote.bid_price, quote.bid_size), (189.85, 2.0));
+    assert_eq!((quote.ask_price, quote.ask_size), (189.87, 3.0));
+    assert_eq!(quote.timestamp, DateTime::from_timestamp_nanos(1_705_080_569_478_497_000));
+
+    let trade = polygon.last_trade("MSFT").await.unwrap();
+    assert_eq!((trade.price, trade.size, trade.exchange), (189.86, 25.0, 4));
+    assert_eq!(trade.conditions, vec![37]);
+
+    let close = polygon.previous_close("MSFT").await.unwrap();
+    assert_eq!((close.open, close.high, close.low, close.close), (186.06, 186.74, 185.19, 185.92));
+    assert_eq!(close.start, DateTime::from_timestamp_millis(1_704_920_400_000).unwrap());
+    assert_eq!(close.trades, Some(557_932));
+}
+
+#[tokio::test]
+async fn last_depth_names_the_venues() {
+    let (polygon, _) = start().await;
+
+    let depth = polygon.last_depth("MSFT").await.unwrap();
+    let venues: Vec<_> = depth.iter().map(|depth| (depth.venue.as_str(), depth.price)).collect();
+    assert_eq!(venues, vec![("ISLAND", 189.85), ("ARCA", 189.87)]);
+}
+
+#[tokio::test]
+async fn bars_follow_next_url() {
+    let (polygon, _) = start().await;
+    let now = Utc::now();
+
+    // The stand-in answers two bars a page, the five recorded ones take three
+    // pages.
+    let bars = polygon
+        .bars("MSFT", Timespan::Day, now - Duration::days(7), now)
+        .await
+        .unwrap();
+
+    let closes: Vec<_> = bars.iter().map(|bar| bar.close).collect();
+    assert_eq!(closes, vec![185.14, 181.91, 185.56, 185.14, 186.19]);
+    assert!(bars.windows(2).all(|pair| pair[0].start < pair[1].start));
+}
+
+#[tokio::test]
+async fn ticker_details_per_symbol() {
+    let (polygon, _) = start().await;
+
+    let apple = polygon.ticker_details("AAPL").await.unwrap();
+    assert_eq!((apple.symbol.as_str(), apple.name.as_str()), ("AAPL", "Apple Inc."));
+    assert_eq!((apple.primary_exchange.as_str(), apple.currency.as_str()), ("XNAS", "USD"));
+
+    let microsoft = polygon.ticker_details("MSFT").await.unwrap();
+    assert_eq!(microsoft.name, "Microsoft Corp");
+    assert_eq!(microsoft.list_date, chrono::NaiveDate::from_ymd_opt(1986, 3, 13));
+
+    let spy = polygon.ticker_details("SPY").await.unwrap();
+    assert_eq!((spy.security_type.as_str(), spy.primary_exchange.as_str()), ("ETF", "ARCX"));
+    assert_eq!(spy.market_cap, None);
+
+    assert!(polygon.ticker_details("NOSUCH").await.is_err());
+}
+
+#[tokio::test]
+async fn subscribers_share_one_upstream_subscription() {
+    let (polygon, url) = start().await;
+
+    let mut first = polygon.subscribe("AAPL").await.unwrap();
+    let mut second = polygon.subscribe("AAPL").await.unwrap();
+    wait_for_streams(&url, 1, &["AAPL"]).await;
+
+    assert!(matches!(next_event(&mut first).await, MarketEvent::Quote(_)));
+    assert!(matches!(next_event(&mut second).await, MarketEvent::Quote(_)));
+
+    // The symbol stays subscribed while a subscriber is left.
+    drop(first);
+    next_event(&mut second).await;
+    time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(streams(&url).await, (1, vec!["AAPL".to_string()]));
+
+    // The last one releases it, on the same connection.
+    drop(second);
+    wait_for_streams(&url, 1, &[]).await;
+}
+
+#[tokio::test]
+async fn stream_reconnects_and_subscribes_again() {
+    let (polygon, url) = start().await;
+
+    let mut subscription = polygon.subscribe("MSFT").await.unwrap();
+    wait_for_streams(&url, 1, &["MSFT"]).await;
+    next_event(&mut subscription).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/mock/disconnect", url))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    wait_for_streams(&url, 2, &["MSFT"]).await;
+    let reconnected = Utc::now() - Duration::milliseconds(10);
+
+    // Skip what was received before the connection dropped.
+    let fresh = async {
+        loop {
+            let timestamp = match next_event(&mut subscription).await {
+                MarketEvent::Quote(quote) => quote.timestamp,
+                MarketEvent::Trade(trade) => trade.timestamp,
+                MarketEvent::Bar(_) | MarketEvent::Depth(_) => continue,
+            };
+
+            if timestamp >= reconnected {
+                return;
+            }
+        }
+    };
+
+    timeout(WAIT, fresh).await.expect("no event after reconnecting");
+}